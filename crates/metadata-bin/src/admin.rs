@@ -0,0 +1,46 @@
+use axum::{http::header, routing::get, Router};
+use metadata_data_layer_utils::PoolState;
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use std::time::Duration;
+
+/// The buckets used by the histograms measuring durations, in seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Install the Prometheus recorder as the global [metrics] recorder and
+/// return a handle able to render the collected metrics.
+pub(crate) fn install_recorder() -> Result<PrometheusHandle, BuildError> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_owned()), DURATION_BUCKETS)?
+        .install_recorder()?;
+
+    // The histograms are drained periodically to keep the memory used by
+    // the recorder bounded, even when the endpoint is never scraped.
+    let upkeep = handle.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            ticker.tick().await;
+            upkeep.run_upkeep();
+        }
+    });
+
+    Ok(handle)
+}
+
+/// Build a router serving the collected metrics at `/metrics` in the
+/// Prometheus text format.
+pub(crate) fn metrics_router(handle: PrometheusHandle, pool: PoolState) -> Router {
+    Router::new().route(
+        "/metrics",
+        get(move || async move {
+            pool.record_metrics();
+
+            (
+                [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+                handle.render(),
+            )
+        }),
+    )
+}
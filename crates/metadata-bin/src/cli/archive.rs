@@ -0,0 +1,124 @@
+use super::{
+    output::{self, Output},
+    CommandError,
+};
+use crate::config::Config;
+use clap::ArgMatches;
+use metadata_data_layer::{
+    archive::{Archive, ArchiveFormat, Change, ImportMode, ImportPlan},
+    repositories::ArchiveRepository,
+};
+use metadata_data_layer_utils::{PoolState, Repository};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+async fn run_export(pool: &PoolState, args: &ArgMatches) -> Result<(), CommandError> {
+    let repository = ArchiveRepository::from_ref(pool.session());
+    let name = args.get_one::<String>("domain").unwrap();
+    // The possible values are restricted by the command line parser.
+    let format = args
+        .get_one::<String>("format")
+        .unwrap()
+        .parse::<ArchiveFormat>()
+        .unwrap();
+
+    let archive = repository
+        .export_domain(name)
+        .await?
+        .ok_or_else(|| CommandError::DomainNotFound(name.to_owned()))?;
+
+    match args.get_one::<PathBuf>("file") {
+        Some(path) => {
+            let write = |path: &PathBuf| {
+                let mut writer = BufWriter::new(File::create(path)?);
+                archive.write(format, &mut writer)?;
+                writer.flush()
+            };
+            write(path).map_err(|source| CommandError::Write {
+                path: path.clone(),
+                source,
+            })
+        }
+        None => Ok(archive.write(format, io::stdout().lock())?),
+    }
+}
+
+fn read(path: Option<&PathBuf>) -> Result<Vec<u8>, CommandError> {
+    let mut input = Vec::new();
+    let result = match path.filter(|path| path.as_os_str() != "-") {
+        Some(path) => File::open(path).and_then(|mut file| file.read_to_end(&mut input)),
+        None => io::stdin().lock().read_to_end(&mut input),
+    };
+
+    result.map(|_| input).map_err(|source| CommandError::Read {
+        path: path.cloned().unwrap_or_else(|| PathBuf::from("-")),
+        source,
+    })
+}
+
+fn print_plan(output: Output, plan: &ImportPlan) -> Result<(), CommandError> {
+    match output {
+        Output::Table => {
+            let row = |resource: &str, change: &Change| {
+                [
+                    change.action.to_string(),
+                    resource.to_owned(),
+                    change.id.to_string(),
+                    change.name.clone(),
+                    change
+                        .parent
+                        .map(|parent| parent.to_string())
+                        .unwrap_or_default(),
+                ]
+            };
+            let rows = std::iter::once(row("domain", &plan.domain))
+                .chain(plan.blocks.iter().map(|change| row("block", change)))
+                .collect::<Vec<_>>();
+            output::table(["ACTION", "RESOURCE", "ID", "NAME", "PARENT"], &rows)?
+        }
+        Output::Json => output::json(plan)?,
+    }
+
+    Ok(())
+}
+
+async fn run_import(pool: &PoolState, args: &ArgMatches) -> Result<(), CommandError> {
+    let repository = ArchiveRepository::from_ref(pool.session());
+    // The possible values are restricted by the command line parser.
+    let mode = args
+        .get_one::<String>("mode")
+        .unwrap()
+        .parse::<ImportMode>()
+        .unwrap();
+
+    let archive = Archive::parse(&read(args.get_one::<PathBuf>("file"))?)?;
+    let plan = if args.get_flag("dry_run") {
+        repository.plan_import(&archive, mode).await?
+    } else {
+        repository.import(&archive, mode).await?
+    };
+
+    print_plan(Output::from_args(args), &plan)
+}
+
+#[tokio::main]
+pub(super) async fn export(config: Config, args: &ArgMatches) -> ExitCode {
+    let pool = super::connect(&config.database);
+    let result = run_export(&pool, args).await;
+    pool.close().await;
+
+    super::exit(result)
+}
+
+#[tokio::main]
+pub(super) async fn import(config: Config, args: &ArgMatches) -> ExitCode {
+    let pool = super::connect(&config.database);
+    let result = run_import(&pool, args).await;
+    pool.close().await;
+
+    super::exit(result)
+}
@@ -0,0 +1,221 @@
+use super::{
+    output::{self, Output},
+    CommandError,
+};
+use crate::config::Config;
+use clap::ArgMatches;
+use metadata_data_layer::{
+    models::{Block, Domain, Move, Parent},
+    repositories::{BlockRepository, DomainRepository},
+};
+use metadata_data_layer_utils::{PoolState, Repository};
+use serde::Serialize;
+use std::{collections::HashMap, process::ExitCode};
+use uuid::Uuid;
+
+const HEADERS: [&str; 5] = ["ID", "NAME", "PARENT", "CREATED AT", "UPDATED AT"];
+
+fn print_one(output: Output, block: &Block) -> Result<(), CommandError> {
+    match output {
+        Output::Table => {
+            let parent = match &block.parent {
+                Parent::Domain(uuid) => format!("domain {uuid}"),
+                Parent::Block(uuid) => format!("block {uuid}"),
+            };
+            output::table(
+                HEADERS,
+                &[[
+                    block.id.to_string(),
+                    block.name.clone(),
+                    parent,
+                    output::timestamp(&block.created_at),
+                    output::timestamp(&block.updated_at),
+                ]],
+            )?
+        }
+        Output::Json => output::json(block)?,
+    }
+
+    Ok(())
+}
+
+/// A block of a tree, along with its children.
+#[derive(Serialize)]
+struct Node<'a> {
+    #[serde(flatten)]
+    block: &'a Block,
+    children: Vec<Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn new(block: &'a Block, children: &HashMap<Uuid, Vec<&'a Block>>) -> Self {
+        Self {
+            block,
+            children: children
+                .get(&block.id)
+                .into_iter()
+                .flatten()
+                .map(|child| Self::new(child, children))
+                .collect(),
+        }
+    }
+
+    fn print(&self, prefix: &str, last: bool, lines: &mut Vec<String>) {
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        lines.push(format!(
+            "{prefix}{branch}{} ({})",
+            self.block.name, self.block.id
+        ));
+
+        let prefix = format!("{prefix}{indent}");
+        for (index, child) in self.children.iter().enumerate() {
+            child.print(&prefix, index + 1 == self.children.len(), lines);
+        }
+    }
+}
+
+fn print_tree(output: Output, domain: &Domain, blocks: &[Block]) -> Result<(), CommandError> {
+    let mut roots = Vec::new();
+    let mut children = HashMap::<Uuid, Vec<&Block>>::new();
+    for block in blocks {
+        match block.parent {
+            Parent::Domain(_) => roots.push(block),
+            Parent::Block(parent) => children.entry(parent).or_default().push(block),
+        }
+    }
+    let roots = roots
+        .into_iter()
+        .map(|block| Node::new(block, &children))
+        .collect::<Vec<_>>();
+
+    match output {
+        Output::Table => {
+            let mut lines = vec![domain.name.clone()];
+            for (index, root) in roots.iter().enumerate() {
+                root.print("", index + 1 == roots.len(), &mut lines);
+            }
+            println!("{}", lines.join("\n"));
+        }
+        Output::Json => output::json(&roots)?,
+    }
+
+    Ok(())
+}
+
+async fn find_domain(domains: &DomainRepository, name: &str) -> Result<Domain, CommandError> {
+    domains
+        .get_domain_by_name(name)
+        .await?
+        .ok_or_else(|| CommandError::DomainNotFound(name.to_owned()))
+}
+
+async fn find(blocks: &BlockRepository, id: &Uuid) -> Result<Block, CommandError> {
+    blocks
+        .get_block(id)
+        .await?
+        .ok_or(CommandError::BlockNotFound(*id))
+}
+
+async fn run(pool: &PoolState, args: &ArgMatches) -> Result<(), CommandError> {
+    let domains = DomainRepository::from_ref(pool.session());
+    let blocks = BlockRepository::from_ref(pool.session());
+    let output = Output::from_args(args);
+
+    match args.subcommand() {
+        Some(("tree", args)) => {
+            let domain = find_domain(&domains, args.get_one::<String>("domain").unwrap()).await?;
+
+            print_tree(
+                output,
+                &domain,
+                &blocks.list_domain_blocks(&domain.id).await?,
+            )
+        }
+        Some(("create", args)) => {
+            let domain = find_domain(&domains, args.get_one::<String>("domain").unwrap()).await?;
+            let builder = Block::builder().name(args.get_one::<String>("name").unwrap());
+            let builder = match args.get_one::<Uuid>("parent") {
+                Some(parent) => {
+                    if blocks.get_root_domain_id(parent).await? != Some(domain.id) {
+                        return Err(CommandError::Refused(format!(
+                            "block '{parent}' is not found in domain '{}'",
+                            domain.name
+                        )));
+                    }
+                    builder.block(*parent)
+                }
+                None => builder.domain(domain.id),
+            };
+            let block = builder.finalize().map_err(|errors| CommandError::Invalid {
+                what: "block",
+                errors,
+            })?;
+            blocks.insert_block(&block).await?;
+
+            print_one(output, &block)
+        }
+        Some(("move", args)) => {
+            let block = find(&blocks, args.get_one::<Uuid>("id").unwrap()).await?;
+            let parent = match args.get_one::<Uuid>("parent") {
+                Some(parent) => Parent::Block(*parent),
+                None => {
+                    let name = args.get_one::<String>("domain").unwrap();
+                    Parent::Domain(find_domain(&domains, name).await?.id)
+                }
+            };
+            let block = match blocks.move_block(&block.id, &parent).await? {
+                Move::Moved(block) => block,
+                Move::NotFound => return Err(CommandError::BlockNotFound(block.id)),
+                Move::ParentNotFound => match parent {
+                    Parent::Block(parent) => return Err(CommandError::BlockNotFound(parent)),
+                    Parent::Domain(_) => {
+                        let name = args.get_one::<String>("domain").unwrap();
+                        return Err(CommandError::DomainNotFound(name.clone()));
+                    }
+                },
+                // A block can't become its own ancestor, the tree would be
+                // detached from its domain.
+                Move::Cycle => {
+                    return Err(CommandError::Refused(format!(
+                        "block '{}' can't be moved under itself or one of its descendants",
+                        block.id
+                    )))
+                }
+            };
+
+            print_one(output, &block)
+        }
+        Some(("delete", args)) => {
+            let block = find(&blocks, args.get_one::<Uuid>("id").unwrap()).await?;
+
+            if !args.get_flag("recursive") {
+                let children = blocks.count_children(&Parent::Block(block.id)).await?;
+                if children > 0 {
+                    return Err(CommandError::Refused(format!(
+                        "block '{}' holds {children} child block(s), use --recursive to delete them as well",
+                        block.id
+                    )));
+                }
+            }
+            if !blocks.delete_block(&block.id).await? {
+                return Err(CommandError::BlockNotFound(block.id));
+            }
+
+            print_one(output, &block)
+        }
+        _ => unreachable!("a subcommand is required"),
+    }
+}
+
+#[tokio::main]
+pub(super) async fn entrypoint(config: Config, args: &ArgMatches) -> ExitCode {
+    let pool = super::connect(&config.database);
+    let result = run(&pool, args).await;
+    pool.close().await;
+
+    super::exit(result)
+}
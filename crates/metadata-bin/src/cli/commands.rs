@@ -1,62 +1,313 @@
-use crate::utils::IpAddrParser;
-use clap::{Arg, Command};
+use crate::{
+    config::{self, Config, CONFIG_ENV},
+    utils::IpAddrParser,
+};
+use clap::{Arg, ArgAction, ArgGroup, Command};
+use std::{fmt, path::PathBuf};
+use uuid::Uuid;
+
+/// The help of a flag, completed with the environment variable setting the
+/// same value and with its default value, if any, as the configuration
+/// defines them.
+fn help(text: &str, env: &str, default: Option<&dyn fmt::Display>) -> String {
+    match default {
+        Some(default) => format!("{text} [env: {env}] [default: {default}]"),
+        None => format!("{text} [env: {env}]"),
+    }
+}
+
+/// Add the arguments configuring the HTTP server to a command.
+fn server_args(command: Command) -> Command {
+    let defaults = Config::default();
+
+    command
+        .arg(
+            Arg::new("host")
+                .short('H')
+                .long("host")
+                .value_parser(IpAddrParser::new())
+                .help(help("An IP address mask to use for the port binding", config::HOST_ENV, None))
+        )
+        .arg(
+            Arg::new("port")
+                .short('p')
+                .long("port")
+                .value_parser(clap::value_parser!(u16))
+                .help(help("The port to use on the hosting machine to bind the socket to the process", config::PORT_ENV, Some(&defaults.server.port)))
+        )
+        .arg(
+            Arg::new("tls_cert")
+                .long("tls-cert")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help(help("A PEM file holding the certificate chain used to serve HTTPS", config::TLS_CERT_ENV, None))
+        )
+        .arg(
+            Arg::new("tls_key")
+                .long("tls-key")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help(help("A PEM file holding the private key of the TLS certificate", config::TLS_KEY_ENV, None))
+        )
+        .arg(
+            Arg::new("tls_client_ca")
+                .long("tls-client-ca")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help(help("A PEM file holding the CAs used to verify client certificates, enabling mutual TLS", config::TLS_CLIENT_CA_ENV, None))
+        )
+        .arg(
+            Arg::new("wait_for_database")
+                .long("wait-for-database")
+                .action(ArgAction::SetTrue)
+                .help(help("Wait for the PostgreSQL database to be reachable before accepting requests", config::WAIT_FOR_DATABASE_ENV, None))
+        )
+        .arg(
+            Arg::new("migrate")
+                .long("migrate")
+                .action(ArgAction::SetTrue)
+                .help(help("Apply the pending migrations to the PostgreSQL database at startup", config::MIGRATE_ENV, None))
+        )
+}
+
+/// The argument selecting how the admin commands print their results.
+fn output_arg() -> Arg {
+    Arg::new("output")
+        .short('o')
+        .long("output")
+        .value_parser(["table", "json"])
+        .default_value("table")
+        .help("The format used to print the results")
+        .global(true)
+}
+
+/// A command reading manifests.
+fn manifest_command(name: &'static str) -> Command {
+    Command::new(name)
+        .arg(output_arg())
+        .arg(
+            Arg::new("file")
+                .short('f')
+                .long("file")
+                .required(true)
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("A YAML manifest, or a directory of manifests, can be given more than once"),
+        )
+        .arg(
+            Arg::new("prune")
+                .long("prune")
+                .action(ArgAction::SetTrue)
+                .help(
+                "Delete the blocks of the described domains that are missing from the manifests",
+            ),
+        )
+}
 
 #[inline]
 pub(super) fn cli() -> Command {
-    Command::new(clap::crate_name!())
+    let defaults = Config::default();
+
+    // Without a subcommand, the server is started, so the server
+    // arguments are accepted at the top level as well.
+    server_args(Command::new(clap::crate_name!()))
         .version(clap::crate_version!())
         .author(clap::crate_authors!())
         .about(clap::crate_description!())
         .arg(
-            Arg::new("host")
-            .short('H')
-            .long("host")
-            .value_parser(IpAddrParser::new())
-            .help("An IP address mask to use for the port binding")
-            .required(true)
-        )
-        .arg(
-            Arg::new("port")
-            .short('p')
-            .long("port")
-            .value_parser(clap::value_parser!(u16))
-            .help("The port to use on the hosting machine to bind the socket to the process")
-            .default_value("80")
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .env(CONFIG_ENV)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("A TOML file holding the configuration of the application")
+                .global(true)
         )
         .arg(
             Arg::new("postgres_host")
                 .long("postgres-host")
-                .env("POSTGRES_HOST")
-                .help("The hostname of the PostgreSQL database to use")
-                .default_value("localhost")
+                .help(help("The hostname of the PostgreSQL database to use", config::POSTGRES_HOST_ENV, Some(&defaults.database.host)))
+                .global(true)
         )
         .arg(
             Arg::new("postgres_port")
                 .long("postgres-port")
-                .env("POSTGRES_PORT")
                 .value_parser(clap::value_parser!(u16))
-                .help("The port to use to connect with the PostgreSQL database")
-                .default_value("5432")
+                .help(help("The port to use to connect with the PostgreSQL database", config::POSTGRES_PORT_ENV, Some(&defaults.database.port)))
+                .global(true)
         )
         .arg(
             Arg::new("postgres_user")
                 .long("postgres-user")
-                .env("POSTGRES_USER")
-                .required(true)
-                .help("The username to use for the authentication to the PostgreSQL database")
+                .help(help("The username to use for the authentication to the PostgreSQL database", config::POSTGRES_USER_ENV, None))
+                .global(true)
         )
         .arg(
             Arg::new("postgres_password")
                 .long("postgres-password")
-                .env("POSTGRES_PASSWORD")
-                .required(true)
-                .help("The password to use for the authentication to the PostgreSQL database")
+                .help(help("The password to use for the authentication to the PostgreSQL database", config::POSTGRES_PASSWORD_ENV, None))
+                .global(true)
         )
         .arg(
             Arg::new("postgres_database")
                 .long("postgres-database")
-                .env("POSTGRES_DATABASE")
-                .required(true)
-                .help("The database to use once the connection is established with the PostgreSQL database"),
+                .help(help("The database to use once the connection is established with the PostgreSQL database", config::POSTGRES_DATABASE_ENV, None))
+                .global(true)
+        )
+        .arg(
+            Arg::new("log_level")
+                .long("log-level")
+                .help(help("The default log level or directive, used when RUST_LOG is not set", config::LOG_LEVEL_ENV, Some(&defaults.logging.level)))
+                .global(true)
+        )
+        .arg(
+            Arg::new("log_format")
+                .long("log-format")
+                .value_parser(["json", "pretty", "compact"])
+                .help(help("The format of the log lines", config::LOG_FORMAT_ENV, Some(&defaults.logging.format)))
+                .global(true)
+        )
+        .subcommand(
+            server_args(Command::new("serve").about("Serve the HTTP API (the default command)"))
+        )
+        .subcommand(
+            Command::new("domain")
+                .about("Manage the domains stored in the database")
+                .subcommand_required(true)
+                .arg(output_arg())
+                .subcommand(Command::new("list").about("List every domain"))
+                .subcommand(
+                    Command::new("create")
+                        .about("Create a domain")
+                        .arg(Arg::new("name").required(true).help("The name of the domain"))
+                )
+                .subcommand(
+                    Command::new("rename")
+                        .about("Rename a domain")
+                        .arg(Arg::new("name").required(true).help("The current name of the domain"))
+                        .arg(Arg::new("new_name").value_name("NEW_NAME").required(true).help("The new name of the domain"))
+                )
+                .subcommand(
+                    Command::new("delete")
+                        .about("Delete a domain")
+                        .arg(Arg::new("name").required(true).help("The name of the domain"))
+                        .arg(
+                            Arg::new("recursive")
+                                .short('r')
+                                .long("recursive")
+                                .action(ArgAction::SetTrue)
+                                .help("Delete the blocks of the domain as well, rather than refusing to delete a non-empty domain")
+                        )
+                )
+        )
+        .subcommand(
+            Command::new("block")
+                .about("Manage the blocks stored in the database")
+                .subcommand_required(true)
+                .arg(output_arg())
+                .subcommand(
+                    Command::new("tree")
+                        .about("Print the tree of blocks of a domain")
+                        .arg(Arg::new("domain").required(true).help("The name of the domain"))
+                )
+                .subcommand(
+                    Command::new("create")
+                        .about("Create a block, at the root of a domain or under another block")
+                        .arg(Arg::new("domain").required(true).help("The name of the domain"))
+                        .arg(Arg::new("name").required(true).help("The name of the block"))
+                        .arg(
+                            Arg::new("parent")
+                                .long("parent")
+                                .value_parser(clap::value_parser!(Uuid))
+                                .help("The ID of the parent block, which must belong to the domain")
+                        )
+                )
+                .subcommand(
+                    Command::new("move")
+                        .about("Move a block, with its descendants, under another parent")
+                        .arg(Arg::new("id").required(true).value_parser(clap::value_parser!(Uuid)).help("The ID of the block"))
+                        .arg(
+                            Arg::new("parent")
+                                .long("parent")
+                                .value_parser(clap::value_parser!(Uuid))
+                                .help("The ID of the new parent block")
+                        )
+                        .arg(
+                            Arg::new("domain")
+                                .long("domain")
+                                .help("The name of the domain the block becomes a root of")
+                        )
+                        .group(ArgGroup::new("target").args(["parent", "domain"]).required(true))
+                )
+                .subcommand(
+                    Command::new("delete")
+                        .about("Delete a block")
+                        .arg(Arg::new("id").required(true).value_parser(clap::value_parser!(Uuid)).help("The ID of the block"))
+                        .arg(
+                            Arg::new("recursive")
+                                .short('r')
+                                .long("recursive")
+                                .action(ArgAction::SetTrue)
+                                .help("Delete the descendants of the block as well, rather than refusing to delete a block with children")
+                        )
+                )
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export a domain, with its whole tree of blocks, as an archive")
+                .arg(Arg::new("domain").required(true).help("The name of the domain"))
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_parser(["json", "ndjson"])
+                        .default_value("json")
+                        .help("The format of the archive")
+                )
+                .arg(
+                    Arg::new("file")
+                        .short('f')
+                        .long("file")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("The file to write the archive to, instead of the standard output")
+                )
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Import a domain, with its whole tree of blocks, from an archive")
+                .arg(output_arg())
+                .arg(
+                    Arg::new("file")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("The JSON or NDJSON archive to import, read from the standard input when missing or '-'")
+                )
+                .arg(
+                    Arg::new("mode")
+                        .long("mode")
+                        .value_parser(["new-ids", "preserve-ids"])
+                        .default_value("new-ids")
+                        .help("Whether the resources are created with new IDs, or keep the IDs of the archive and update the resources already stored")
+                )
+                .arg(
+                    Arg::new("dry_run")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Only print the changes the import would make")
+                )
+        )
+        .subcommand(manifest_command("plan").about(
+            "Print the changes reconciling the database with YAML manifests, without applying them",
+        ))
+        .subcommand(manifest_command("apply").about(
+            "Reconcile the database with YAML manifests, applying all the changes in a single transaction",
+        ))
+        .subcommand(
+            Command::new("config")
+                .about("Inspect the effective configuration of the application")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("check")
+                        .about("Validate the configuration and exit with a non-zero status if it is invalid")
+                )
+                .subcommand(
+                    Command::new("print")
+                        .about("Print the effective configuration as TOML, with secrets redacted")
+                )
         )
 }
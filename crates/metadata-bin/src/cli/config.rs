@@ -0,0 +1,20 @@
+use crate::config::Config;
+use std::process::ExitCode;
+
+pub(super) fn entrypoint(config: Config, args: &clap::ArgMatches) -> ExitCode {
+    match args.subcommand() {
+        Some(("check", _)) => {
+            println!("The configuration is valid.");
+        }
+        Some(("print", _)) => match config.to_redacted_toml() {
+            Ok(toml) => print!("{toml}"),
+            Err(error) => {
+                eprintln!("error: {error}");
+                return ExitCode::FAILURE;
+            }
+        },
+        _ => unreachable!("a subcommand is required"),
+    }
+
+    ExitCode::SUCCESS
+}
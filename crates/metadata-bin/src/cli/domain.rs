@@ -0,0 +1,117 @@
+use super::{
+    output::{self, Output},
+    CommandError,
+};
+use crate::config::Config;
+use clap::ArgMatches;
+use metadata_data_layer::{
+    models::{Domain, Parent},
+    repositories::{BlockRepository, DomainRepository},
+    validation::{self, ValidationErrors},
+};
+use metadata_data_layer_utils::{PoolState, Repository};
+use std::process::ExitCode;
+
+const HEADERS: [&str; 4] = ["ID", "NAME", "CREATED AT", "UPDATED AT"];
+
+fn row(domain: &Domain) -> [String; 4] {
+    [
+        domain.id.to_string(),
+        domain.name.clone(),
+        output::timestamp(&domain.created_at),
+        output::timestamp(&domain.updated_at),
+    ]
+}
+
+fn print(output: Output, domains: &[Domain]) -> Result<(), CommandError> {
+    match output {
+        Output::Table => output::table(HEADERS, &domains.iter().map(row).collect::<Vec<_>>())?,
+        Output::Json => output::json(&domains)?,
+    }
+
+    Ok(())
+}
+
+fn print_one(output: Output, domain: &Domain) -> Result<(), CommandError> {
+    match output {
+        Output::Table => output::table(HEADERS, &[row(domain)])?,
+        Output::Json => output::json(domain)?,
+    }
+
+    Ok(())
+}
+
+async fn find(domains: &DomainRepository, name: &str) -> Result<Domain, CommandError> {
+    domains
+        .get_domain_by_name(name)
+        .await?
+        .ok_or_else(|| CommandError::DomainNotFound(name.to_owned()))
+}
+
+async fn run(pool: &PoolState, args: &ArgMatches) -> Result<(), CommandError> {
+    let domains = DomainRepository::from_ref(pool.session());
+    let output = Output::from_args(args);
+
+    match args.subcommand() {
+        Some(("list", _)) => print(output, &domains.list_domains().await?),
+        Some(("create", args)) => {
+            let name = args.get_one::<String>("name").unwrap();
+            let domain = Domain::new(name).map_err(|errors| CommandError::Invalid {
+                what: "domain",
+                errors,
+            })?;
+            domains.insert_domain(&domain).await?;
+
+            print_one(output, &domain)
+        }
+        Some(("rename", args)) => {
+            let domain = find(&domains, args.get_one::<String>("name").unwrap()).await?;
+            let name = args.get_one::<String>("new_name").unwrap().to_lowercase();
+
+            let mut errors = ValidationErrors::new();
+            validation::validate_domain_name("name", &name, &mut errors);
+            errors
+                .into_result(|| ())
+                .map_err(|errors| CommandError::Invalid {
+                    what: "domain",
+                    errors,
+                })?;
+
+            let domain = domains
+                .rename_domain(&domain.id, &name)
+                .await?
+                .ok_or(CommandError::DomainNotFound(domain.name))?;
+
+            print_one(output, &domain)
+        }
+        Some(("delete", args)) => {
+            let domain = find(&domains, args.get_one::<String>("name").unwrap()).await?;
+
+            if !args.get_flag("recursive") {
+                let blocks = BlockRepository::from_ref(pool.session());
+                let children = blocks.count_children(&Parent::Domain(domain.id)).await?;
+                if children > 0 {
+                    return Err(CommandError::Refused(format!(
+                        "domain '{}' holds {children} root block(s), use --recursive to delete them as well",
+                        domain.name
+                    )));
+                }
+            }
+            if !domains.delete_domain(&domain.id).await? {
+                return Err(CommandError::DomainNotFound(domain.name));
+            }
+
+            print_one(output, &domain)
+        }
+        _ => unreachable!("a subcommand is required"),
+    }
+}
+
+#[tokio::main]
+pub(super) async fn entrypoint(config: Config, args: &ArgMatches) -> ExitCode {
+    let pool = super::connect(&config.database);
+    let result = run(&pool, args).await;
+    pool.close().await;
+
+    super::exit(result)
+}
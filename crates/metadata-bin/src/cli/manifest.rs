@@ -0,0 +1,126 @@
+use super::{
+    output::{self, Output},
+    CommandError,
+};
+use crate::config::Config;
+use clap::ArgMatches;
+use metadata_data_layer::{
+    manifest::{Manifest, Plan},
+    repositories::ManifestRepository,
+};
+use metadata_data_layer_utils::{PoolState, Repository};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// List the manifests of a path, recursively when it's a directory, in
+/// a stable order.
+fn files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), CommandError> {
+    let read = |source| CommandError::Read {
+        path: path.to_owned(),
+        source,
+    };
+
+    if !path.is_dir() {
+        files.push(path.to_owned());
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(read)?;
+    entries.sort();
+
+    for entry in entries {
+        let is_manifest = entry
+            .extension()
+            .is_some_and(|extension| extension == "yaml" || extension == "yml");
+        if entry.is_dir() || is_manifest {
+            self::files(&entry, files)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the manifests of the given paths, a file holding any number of
+/// YAML documents.
+fn load<'a>(paths: impl Iterator<Item = &'a PathBuf>) -> Result<Vec<Manifest>, CommandError> {
+    let mut all = Vec::new();
+    for path in paths {
+        files(path, &mut all)?;
+    }
+
+    let mut manifests = Vec::new();
+    for path in all {
+        let text = fs::read_to_string(&path).map_err(|source| CommandError::Read {
+            path: path.clone(),
+            source,
+        })?;
+
+        for document in serde_yaml::Deserializer::from_str(&text) {
+            let manifest =
+                Manifest::deserialize(document).map_err(|error| CommandError::Parse {
+                    path: path.clone(),
+                    reason: error.to_string(),
+                })?;
+            manifests.push(manifest);
+        }
+    }
+
+    Ok(manifests)
+}
+
+fn print(output: Output, plan: &Plan) -> Result<(), CommandError> {
+    match output {
+        Output::Table => {
+            let rows = plan
+                .changes
+                .iter()
+                .map(|change| {
+                    [
+                        change.action.to_string(),
+                        change.resource.to_string(),
+                        change.path.clone(),
+                        change.id.to_string(),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            output::table(["ACTION", "RESOURCE", "PATH", "ID"], &rows)?;
+            println!("\nPlan: {plan}.");
+        }
+        Output::Json => output::json(plan)?,
+    }
+
+    Ok(())
+}
+
+async fn run(pool: &PoolState, args: &ArgMatches, apply: bool) -> Result<(), CommandError> {
+    let repository = ManifestRepository::from_ref(pool.session());
+
+    let mut manifests = load(args.get_many::<PathBuf>("file").unwrap_or_default())?;
+    let prune = args.get_flag("prune");
+    let plan = if apply {
+        repository.apply(&mut manifests, prune).await?
+    } else {
+        repository.plan(&mut manifests, prune).await?
+    };
+
+    print(Output::from_args(args), &plan)
+}
+
+#[tokio::main]
+pub(super) async fn entrypoint(config: Config, args: &ArgMatches, apply: bool) -> ExitCode {
+    let pool = super::connect(&config.database);
+    let result = run(&pool, args, apply).await;
+    pool.close().await;
+
+    super::exit(result)
+}
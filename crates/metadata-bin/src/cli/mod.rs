@@ -1,8 +1,119 @@
+use crate::config::{Config, DatabaseConfig};
+use metadata_data_layer::{
+    archive::ArchiveError, manifest::ManifestError, validation::ValidationErrors,
+};
+use metadata_data_layer_utils::{PoolState, PoolStateBuilder};
+use std::{path::PathBuf, process::ExitCode};
+use thiserror::Error;
+use uuid::Uuid;
+
+mod archive;
+mod block;
 mod commands;
+mod config;
+mod domain;
+mod manifest;
+mod output;
 mod serve;
 
-pub(crate) fn main() {
+/// An error ending an admin command.
+#[derive(Debug, Error)]
+enum CommandError {
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error("invalid {what}: {errors}")]
+    Invalid {
+        what: &'static str,
+        errors: ValidationErrors,
+    },
+    #[error("domain '{0}' is not found")]
+    DomainNotFound(String),
+    #[error("block '{0}' is not found")]
+    BlockNotFound(Uuid),
+    #[error("{0}")]
+    Refused(String),
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+    #[error(transparent)]
+    Manifest(#[from] ManifestError),
+    #[error("unable to parse {path}: {reason}")]
+    Parse { path: PathBuf, reason: String },
+    #[error("unable to print the result: {0}")]
+    Output(#[from] std::io::Error),
+    #[error("unable to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("unable to write {path}: {source}")]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// Build the pool of connections to the database from the configuration.
+/// The admin commands only use the primary, so they always read what
+/// they wrote.
+fn connect(database: &DatabaseConfig) -> PoolState {
+    builder(database).finalize()
+}
+
+/// Configure the pool of connections to the primary from the
+/// configuration.
+fn builder(database: &DatabaseConfig) -> PoolStateBuilder<'_> {
+    let mut pool = PoolState::builder()
+        .application_name(&database.application_name)
+        .host(&database.host)
+        .port(database.port);
+    if let Some(user) = &database.user {
+        pool = pool.user(user);
+    }
+    if let Some(password) = &database.password {
+        pool = pool.password(password.expose());
+    }
+    if let Some(dbname) = &database.database {
+        pool = pool.dbname(dbname);
+    }
+
+    pool
+}
+
+/// Report the outcome of an admin command as the exit code of the
+/// process.
+fn exit(result: Result<(), CommandError>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+pub(crate) fn main() -> ExitCode {
     let args = commands::cli().get_matches();
+    // The admin commands only reach the database.
+    let serves = !matches!(
+        args.subcommand_name(),
+        Some("domain" | "block" | "export" | "import" | "plan" | "apply")
+    );
+    let config = match Config::load(&args, serves) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
 
-    serve::entrypoint(args)
+    match args.subcommand() {
+        Some(("config", args)) => config::entrypoint(config, args),
+        Some(("domain", args)) => domain::entrypoint(config, args),
+        Some(("block", args)) => block::entrypoint(config, args),
+        Some(("export", args)) => archive::export(config, args),
+        Some(("import", args)) => archive::import(config, args),
+        Some(("plan", args)) => manifest::entrypoint(config, args, false),
+        Some(("apply", args)) => manifest::entrypoint(config, args, true),
+        _ => serve::entrypoint(config),
+    }
 }
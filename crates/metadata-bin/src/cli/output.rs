@@ -0,0 +1,60 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ArgMatches;
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// How the admin commands print their results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Output {
+    Table,
+    Json,
+}
+
+impl Output {
+    pub(super) fn from_args(args: &ArgMatches) -> Self {
+        match args.get_one::<String>("output").map(String::as_str) {
+            Some("json") => Self::Json,
+            _ => Self::Table,
+        }
+    }
+}
+
+/// Format a timestamp for a table cell.
+pub(super) fn timestamp(value: &DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Print a value as pretty JSON.
+pub(super) fn json<T: Serialize>(value: &T) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    writeln!(stdout)
+}
+
+/// Print rows as a table, whose columns are aligned on their widest
+/// cell.
+pub(super) fn table<const N: usize>(headers: [&str; N], rows: &[[String; N]]) -> io::Result<()> {
+    let mut widths = headers.map(|header| header.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut stdout = io::stdout().lock();
+    let mut line = |cells: &mut dyn Iterator<Item = &str>| {
+        let line = cells
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(stdout, "{}", line.trim_end())
+    };
+
+    line(&mut headers.into_iter())?;
+    for row in rows {
+        line(&mut row.iter().map(String::as_str))?;
+    }
+
+    Ok(())
+}
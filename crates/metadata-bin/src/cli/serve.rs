@@ -1,62 +1,437 @@
-use http::Method;
-use metadata_data_layer_utils::PoolState;
-use metadata_http::{init_router, AppState};
-use std::net::{IpAddr, SocketAddr};
-use tokio::net::TcpListener;
+use crate::{
+    admin,
+    config::{Config, CorsConfig, LimitsConfig, LogFormat, RateLimitConfig, ReplicasConfig},
+    telemetry, tls,
+};
+use http::{HeaderName, HeaderValue, Method};
+use metadata_data_layer::{migrations::MIGRATOR, repositories::HealthRepository};
+use metadata_data_layer_utils::{PoolState, Repository};
+use metadata_http::{
+    init_router, ApiKeys, AppState, Quota, RateLimits, RequestLimits, RouteClass, Shutdown,
+    CONSISTENCY_TOKEN,
+};
+use metadata_http_utils::context;
+use std::{
+    future::Future,
+    net::SocketAddr,
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+use tokio::{net::TcpListener, time::MissedTickBehavior};
+use tokio_stream::wrappers::TcpListenerStream;
 use tower::ServiceBuilder;
 use tower_http::{
     compression::CompressionLayer,
-    cors::{self, CorsLayer},
+    cors::{self, AllowOrigin, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
     trace::{DefaultOnRequest, DefaultOnResponse, TraceLayer},
 };
-use tracing::{level_filters::LevelFilter, Level};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing::Level;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// Build the CORS layer from the configuration. The values are expected
+/// to be validated beforehand by [Config::validate].
+fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    let origins = if config.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            config
+                .allowed_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
+    let methods = config
+        .allowed_methods
+        .iter()
+        .filter_map(|method| method.parse::<Method>().ok())
+        .collect::<Vec<_>>();
+    let headers = config
+        .allowed_headers
+        .iter()
+        .filter_map(|header| header.parse::<HeaderName>().ok())
+        .collect::<Vec<_>>();
+
+    let layer = CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .expose_headers([CONSISTENCY_TOKEN]);
+    let layer = if headers.is_empty() {
+        layer
+    } else {
+        layer.allow_headers(cors::AllowHeaders::list(headers))
+    };
+
+    match config.max_age {
+        Some(max_age) => layer.max_age(Duration::from_secs(max_age)),
+        None => layer,
+    }
+}
+
+/// Build the rate limits of the routes from the configuration.
+fn rate_limits(config: &RateLimitConfig) -> RateLimits {
+    [
+        (RouteClass::Read, &config.reads),
+        (RouteClass::Write, &config.writes),
+        (RouteClass::Search, &config.search),
+    ]
+    .into_iter()
+    .fold(RateLimits::new(config.key), |limits, (class, quota)| {
+        limits.with_quota(class, Quota::new(quota.burst, quota.per_second))
+    })
+    .with_ip_quota(Quota::new(config.ip.burst, config.ip.per_second))
+}
+
+/// Build the limits of the requests from the configuration.
+fn request_limits(config: &LimitsConfig) -> RequestLimits {
+    let mut limits = RequestLimits::new()
+        .with_stream_timeout(Duration::from_secs(config.stream_timeout))
+        .with_max_body_size(config.max_body_size)
+        .with_load_shedding(config.load_shedding);
+    if let Some(timeout) = config.request_timeout {
+        limits = limits.with_timeout(Duration::from_secs(timeout));
+    }
+    if let Some(max_in_flight) = config.max_in_flight {
+        limits = limits.with_max_in_flight(max_in_flight);
+    }
+
+    limits
+}
+
+/// Check the lag of the replicas periodically, until the shutdown. The
+/// replicas serve the reads once first checked.
+async fn check_replicas(pool: PoolState, config: ReplicasConfig, shutdown: Shutdown) {
+    let max_lag = Duration::from_secs(config.max_lag);
+    let mut ticker = tokio::time::interval(Duration::from_secs(config.check_interval));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // The check in progress is abandoned on shutdown, as the pools are
+    // about to be closed.
+    let checks = async {
+        loop {
+            ticker.tick().await;
+            pool.check_replicas(max_lag).await;
+        }
+    };
+    tokio::select! {
+        () = shutdown.triggered() => {}
+        () = checks => {}
+    }
+}
+
+/// Wait for the database to be reachable, retrying with an exponential
+/// backoff until the timeout elapses.
+async fn wait_for_database(pool: &PoolState, timeout: Duration) -> Result<(), sqlx::Error> {
+    const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(5);
+    const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+    let repository = HealthRepository::from_ref(pool.session());
+    let deadline = Instant::now() + timeout;
+    let mut backoff = Duration::from_millis(250);
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let error =
+            match tokio::time::timeout(remaining.min(ATTEMPT_TIMEOUT), repository.ping()).await {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(error)) => error,
+                Err(_) => sqlx::Error::PoolTimedOut,
+            };
+
+        if Instant::now() + backoff >= deadline {
+            return Err(error);
+        }
+        tracing::warn!(%error, "database is not reachable yet, retrying in {backoff:?}");
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Install the handlers of `SIGINT` and, on Unix platforms, `SIGTERM`,
+/// returning a future triggering the shutdown signal once the process
+/// receives one of them.
+#[cfg(unix)]
+fn shutdown_on_signal(shutdown: Shutdown) -> std::io::Result<impl Future<Output = ()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    Ok(async move {
+        tokio::select! {
+            _ = interrupt.recv() => tracing::info!("SIGINT received, shutting down"),
+            _ = terminate.recv() => tracing::info!("SIGTERM received, shutting down"),
+        }
+
+        shutdown.trigger();
+    })
+}
+
+#[cfg(not(unix))]
+fn shutdown_on_signal(shutdown: Shutdown) -> std::io::Result<impl Future<Output = ()>> {
+    Ok(async move {
+        match tokio::signal::ctrl_c().await {
+            Ok(()) => {
+                tracing::info!("SIGINT received, shutting down");
+                shutdown.trigger();
+            }
+            Err(error) => tracing::error!(%error, "unable to listen for SIGINT"),
+        }
+    })
+}
 
 #[tokio::main]
-pub(super) async fn entrypoint(args: clap::ArgMatches) {
+pub(super) async fn entrypoint(config: Config) -> ExitCode {
+    let tracer_provider = match &config.telemetry.endpoint {
+        Some(endpoint) => match telemetry::tracer_provider(&config.telemetry, endpoint) {
+            Ok(provider) => Some(provider),
+            Err(error) => {
+                eprintln!("error: unable to build the OTLP exporter: {error}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    let fmt_layer = match config.logging.format {
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+        LogFormat::Pretty => tracing_subscriber::fmt::layer().pretty().boxed(),
+        LogFormat::Compact => tracing_subscriber::fmt::layer().compact().boxed(),
+    };
     tracing_subscriber::registry()
         .with(
             EnvFilter::builder()
-                .with_default_directive(LevelFilter::TRACE.into())
+                .with_default_directive(config.logging.level.parse().unwrap())
                 .from_env_lossy(),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(fmt_layer)
+        .with(tracer_provider.as_ref().map(telemetry::layer))
         .init();
 
-    let state = AppState::new(
-        PoolState::builder()
-            .application_name(clap::crate_name!())
-            .host(args.get_one::<String>("postgres_host").unwrap())
-            .port(*args.get_one("postgres_port").unwrap())
-            .user(args.get_one::<String>("postgres_user").unwrap())
-            .password(args.get_one::<String>("postgres_password").unwrap())
-            .dbname(args.get_one::<String>("postgres_database").unwrap())
-            .finalize(),
+    metadata_http_utils::problems::set_base_uri(&config.problems.base_uri);
+
+    // The TLS material is loaded before anything else is started, so a
+    // misconfigured server fails fast.
+    let acceptor = if config.server.tls.is_enabled() {
+        match tls::acceptor(&config.server.tls) {
+            Ok(acceptor) => Some(acceptor),
+            Err(error) => {
+                tracing::error!(%error, "unable to set up TLS");
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        None
+    };
+
+    let database = &config.database;
+    let pool = database
+        .replicas
+        .addresses(database.port)
+        .into_iter()
+        .flatten()
+        .fold(super::builder(database), |pool, (host, port)| {
+            pool.replica(host, port)
+        })
+        .finalize();
+    if database.wait_for_startup {
+        let timeout = Duration::from_secs(database.startup_timeout);
+        if let Err(error) = wait_for_database(&pool, timeout).await {
+            tracing::error!(%error, "database is not reachable, giving up");
+            return ExitCode::FAILURE;
+        }
+        tracing::info!("Database is reachable");
+    }
+    if database.migrate {
+        if let Err(error) = MIGRATOR.run(pool.downcast_ref().as_ref()).await {
+            tracing::error!(%error, "unable to apply the database migrations");
+            return ExitCode::FAILURE;
+        }
+        tracing::info!("Database migrations applied");
+    }
+
+    let shutdown = Shutdown::new();
+    if pool.has_replicas() {
+        tokio::spawn(check_replicas(
+            pool.clone(),
+            database.replicas.clone(),
+            shutdown.clone(),
+        ));
+    }
+    match shutdown_on_signal(shutdown.clone()) {
+        Ok(signal) => _ = tokio::spawn(signal),
+        Err(error) => {
+            tracing::error!(%error, "unable to listen for the shutdown signals");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let api_keys = ApiKeys::new(
+        config
+            .auth
+            .api_keys
+            .iter()
+            .map(|(name, key)| (name.as_str(), key.expose())),
     );
-    let app = init_router(state)
-        .layer(
-            // TODO(rigma): CORS parameters should be configurable
-            CorsLayer::new().allow_origin(cors::Any).allow_methods([
-                Method::GET,
-                Method::HEAD,
-                Method::OPTIONS,
-            ]),
-        )
-        .layer(
-            ServiceBuilder::new().layer(CompressionLayer::new()).layer(
+    let mut state = AppState::new(pool.clone())
+        .with_api_keys(api_keys.clone())
+        .with_shutdown(shutdown.clone())
+        .with_openapi_ui(config.openapi.ui)
+        .with_graphql_limits(config.graphql.max_depth, config.graphql.max_complexity)
+        .with_max_batch_size(config.blocks.max_batch_size)
+        .with_request_limits(request_limits(&config.limits));
+    if let Some(integrity) = &config.openapi.redoc_integrity {
+        state = state.with_redoc_integrity(integrity);
+    }
+    if config.rate_limit.enabled {
+        state = state.with_rate_limits(rate_limits(&config.rate_limit));
+    }
+    let mut app = init_router(state);
+    if config.metrics.enabled {
+        let handle = match admin::install_recorder() {
+            Ok(handle) => handle,
+            Err(error) => {
+                tracing::error!(%error, "unable to install the metrics recorder");
+                return ExitCode::FAILURE;
+            }
+        };
+        let metrics = admin::metrics_router(handle, pool.clone());
+
+        match config.metrics.listen {
+            Some(address) => {
+                let listener = match TcpListener::bind(address).await {
+                    Ok(listener) => listener,
+                    Err(error) => {
+                        tracing::error!(%error, %address, "unable to listen for the metrics");
+                        return ExitCode::FAILURE;
+                    }
+                };
+                let shutdown = shutdown.clone();
+
+                tracing::info!("Serving metrics on http://{address}");
+                tokio::spawn(async move {
+                    if let Err(error) = axum::serve(listener, metrics)
+                        .with_graceful_shutdown(shutdown.triggered_owned())
+                        .await
+                    {
+                        tracing::error!(%error, "the metrics listener failed");
+                    }
+                });
+            }
+            None => app = app.merge(metrics),
+        }
+    }
+    if let Some(address) = config.grpc.listen {
+        let listener = match TcpListener::bind(address).await {
+            Ok(listener) => listener,
+            Err(error) => {
+                tracing::error!(%error, %address, "unable to listen for the gRPC API");
+                return ExitCode::FAILURE;
+            }
+        };
+        let service = metadata_grpc::service(
+            pool.clone(),
+            api_keys,
+            config.grpc.max_watchers,
+            Duration::from_secs(config.grpc.watch_timeout),
+            shutdown.clone(),
+        );
+        let shutdown = shutdown.clone();
+
+        // The calls carry the API keys, so they are encrypted with the
+        // same certificate as the HTTP API whenever it's configured.
+        let server = match &acceptor {
+            Some(acceptor) => {
+                tracing::info!("Serving gRPC with TLS on {address}");
+                let incoming = tls::incoming(listener, acceptor.clone());
+                tokio::spawn(metadata_grpc::serve(incoming, service, shutdown))
+            }
+            None => {
+                tracing::info!("Serving gRPC on {address}");
+                let incoming = TcpListenerStream::new(listener);
+                tokio::spawn(metadata_grpc::serve(incoming, service, shutdown))
+            }
+        };
+        tokio::spawn(async move {
+            if let Ok(Err(error)) = server.await {
+                tracing::error!(%error, "the gRPC server failed");
+            }
+        });
+    }
+    let app = app.layer(cors_layer(&config.cors)).layer(
+        ServiceBuilder::new()
+            .map_request(context::sanitize_request_id)
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(CompressionLayer::new())
+            .layer(
                 TraceLayer::new_for_http()
+                    .make_span_with(|request: &http::Request<_>| telemetry::request_span(request))
                     .on_request(DefaultOnRequest::new().level(Level::TRACE))
                     .on_response(DefaultOnResponse::new().level(Level::TRACE)),
             ),
-        );
+    );
+
+    let Some(address) = config.server.address() else {
+        tracing::error!("server.host is required to serve the API");
+        return ExitCode::FAILURE;
+    };
+    let listener = match TcpListener::bind(address).await {
+        Ok(listener) => listener,
+        Err(error) => {
+            tracing::error!(%error, %address, "unable to listen for the HTTP API");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let drain_timeout = Duration::from_secs(config.server.drain_timeout);
+    let mut exit_code = ExitCode::SUCCESS;
+    if let Some(acceptor) = acceptor {
+        tracing::info!("Listening on https://{}", listener.local_addr().unwrap());
+        tls::serve(listener, acceptor, app, shutdown, drain_timeout).await;
+    } else {
+        tracing::info!("Listening on http://{}", listener.local_addr().unwrap());
+        let server = axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown.triggered_owned());
+        let drain = async {
+            shutdown.triggered().await;
+            tokio::time::sleep(drain_timeout).await;
+        };
+
+        tokio::select! {
+            result = server => if let Err(error) = result {
+                tracing::error!(%error, "the HTTP server failed");
+                exit_code = ExitCode::FAILURE;
+            },
+            _ = drain => tracing::warn!("drain timeout elapsed, closing the remaining connections"),
+        }
+    }
+
+    // The pool is closed last, once no more requests can use it. Closing
+    // it waits for the connections in use to be returned, which a stream
+    // cut short by the drain timeout may never do.
+    const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+    if tokio::time::timeout(CLOSE_TIMEOUT, pool.close())
+        .await
+        .is_err()
+    {
+        tracing::warn!(timeout = ?CLOSE_TIMEOUT, "the database connections didn't close in time");
+    }
+    tracing::info!("Server stopped");
 
-    let listener = TcpListener::bind(SocketAddr::new(
-        *args.get_one::<IpAddr>("host").unwrap(),
-        *args.get_one("port").unwrap(),
-    ))
-    .await
-    .unwrap();
+    if let Some(provider) = tracer_provider {
+        if let Err(error) = provider.shutdown() {
+            eprintln!("error: unable to flush the pending traces: {error}");
+        }
+    }
 
-    tracing::info!("Listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    exit_code
 }
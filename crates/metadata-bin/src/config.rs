@@ -0,0 +1,1156 @@
+use clap::{parser::ValueSource, ArgMatches};
+use http::{HeaderValue, Method};
+use metadata_http::RateLimitKey;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use tracing_subscriber::filter::Directive;
+
+/// The environment variable that can be used instead of the `--config`
+/// flag to point to a configuration file.
+pub(crate) const CONFIG_ENV: &str = "METADATA_CONFIG";
+
+// The environment variables setting the same values as the flags, named
+// in the help of the flags.
+pub(crate) const HOST_ENV: &str = "METADATA_HOST";
+pub(crate) const PORT_ENV: &str = "METADATA_PORT";
+pub(crate) const TLS_CERT_ENV: &str = "METADATA_TLS_CERT";
+pub(crate) const TLS_KEY_ENV: &str = "METADATA_TLS_KEY";
+pub(crate) const TLS_CLIENT_CA_ENV: &str = "METADATA_TLS_CLIENT_CA";
+pub(crate) const WAIT_FOR_DATABASE_ENV: &str = "METADATA_WAIT_FOR_DATABASE";
+pub(crate) const MIGRATE_ENV: &str = "METADATA_MIGRATE";
+pub(crate) const POSTGRES_HOST_ENV: &str = "POSTGRES_HOST";
+pub(crate) const POSTGRES_PORT_ENV: &str = "POSTGRES_PORT";
+pub(crate) const POSTGRES_USER_ENV: &str = "POSTGRES_USER";
+pub(crate) const POSTGRES_PASSWORD_ENV: &str = "POSTGRES_PASSWORD";
+pub(crate) const POSTGRES_DATABASE_ENV: &str = "POSTGRES_DATABASE";
+pub(crate) const LOG_LEVEL_ENV: &str = "METADATA_LOG_LEVEL";
+pub(crate) const LOG_FORMAT_ENV: &str = "METADATA_LOG_FORMAT";
+
+/// An error emitted while the effective configuration of the application
+/// is being resolved.
+#[derive(Debug, Error)]
+pub(crate) enum ConfigError {
+    #[error("unable to read configuration file '{}': {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("unable to parse configuration file '{}': {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid value '{value}' for environment variable {name}")]
+    Env { name: &'static str, value: String },
+    #[error("unable to render the configuration: {0}")]
+    Render(toml::ser::Error),
+    #[error("invalid configuration:\n{}", .0.iter().map(|error| format!("  - {error}")).collect::<Vec<_>>().join("\n"))]
+    Invalid(Vec<String>),
+}
+
+/// A string value that must never be displayed as is, like a password or
+/// an API key. It is redacted when it is formatted or serialized.
+#[derive(Clone, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub(crate) struct Secret(String);
+
+impl Secret {
+    const REDACTED: &'static str = "<redacted>";
+
+    /// Returns the secret value in clear text.
+    #[inline]
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(Self::REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(Self::REDACTED)
+    }
+}
+
+/// The effective configuration of `backbone-metadata`.
+///
+/// It is resolved by layering the following sources, each one taking
+/// precedence over the previous ones:
+///
+/// 1. the default values;
+/// 2. the TOML file given by `--config` or `METADATA_CONFIG`;
+/// 3. the environment variables;
+/// 4. the command line flags.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub cors: CorsConfig,
+    pub logging: LoggingConfig,
+    pub metrics: MetricsConfig,
+    pub telemetry: TelemetryConfig,
+    pub auth: AuthConfig,
+    pub problems: ProblemsConfig,
+    pub openapi: OpenApiConfig,
+    pub graphql: GraphqlConfig,
+    pub grpc: GrpcConfig,
+    pub blocks: BlocksConfig,
+    pub rate_limit: RateLimitConfig,
+    pub limits: LimitsConfig,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ServerConfig {
+    /// The IP address mask to use for the port binding, required to
+    /// serve the API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<IpAddr>,
+    /// The port to use on the hosting machine to bind the socket.
+    pub port: u16,
+    /// How long, in seconds, the in-flight requests are given to complete
+    /// once a shutdown has been requested.
+    pub drain_timeout: u64,
+    pub tls: TlsConfig,
+}
+
+impl ServerConfig {
+    /// The address the API is served on, once the host is known.
+    #[inline]
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.host.map(|host| SocketAddr::new(host, self.port))
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: 80,
+            drain_timeout: 30,
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TlsConfig {
+    /// The PEM file holding the certificate chain of the server. HTTPS is
+    /// enabled when it is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert: Option<PathBuf>,
+    /// The PEM file holding the private key of the server certificate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<PathBuf>,
+    /// The PEM file holding the certificate authorities used to verify
+    /// the client certificates. Mutual TLS is enabled when it is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ca: Option<PathBuf>,
+    /// Whether the clients must present a certificate when mutual TLS
+    /// is enabled.
+    pub client_auth: ClientAuth,
+    /// How often, in seconds, the certificate and key files are checked
+    /// for changes.
+    pub watch_interval: u64,
+}
+
+impl TlsConfig {
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.cert.is_some()
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert: None,
+            key: None,
+            client_ca: None,
+            client_auth: ClientAuth::Required,
+            watch_interval: 30,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ClientAuth {
+    Required,
+    Optional,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DatabaseConfig {
+    /// The name used to identify the application on the PostgreSQL side.
+    pub application_name: String,
+    pub host: String,
+    pub port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<Secret>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
+    /// Whether the server should wait for the database to be reachable
+    /// before accepting requests.
+    pub wait_for_startup: bool,
+    /// How long, in seconds, the server waits for the database when
+    /// `wait_for_startup` is enabled.
+    pub startup_timeout: u64,
+    /// Whether the pending migrations are applied when the server starts.
+    pub migrate: bool,
+    pub replicas: ReplicasConfig,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            application_name: clap::crate_name!().to_owned(),
+            host: "localhost".to_owned(),
+            port: 5432,
+            user: None,
+            password: None,
+            database: None,
+            wait_for_startup: false,
+            startup_timeout: 60,
+            migrate: false,
+            replicas: ReplicasConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ReplicasConfig {
+    /// The read replicas of the database, as `host` or `host:port`, the
+    /// port being the one of the primary by default. They're reached with
+    /// the credentials of the primary, and only by the server.
+    pub hosts: Vec<String>,
+    /// How far behind the primary, in seconds, a replica can be and still
+    /// serve the reads.
+    pub max_lag: u64,
+    /// How often, in seconds, the lag of the replicas is checked.
+    pub check_interval: u64,
+}
+
+impl Default for ReplicasConfig {
+    fn default() -> Self {
+        Self {
+            hosts: Vec::new(),
+            max_lag: 5,
+            check_interval: 5,
+        }
+    }
+}
+
+impl ReplicasConfig {
+    /// The host and the port of every replica, `None` for the addresses
+    /// which can't be parsed.
+    pub fn addresses(&self, default_port: u16) -> Vec<Option<(&str, u16)>> {
+        self.hosts
+            .iter()
+            .map(|address| match address.rsplit_once(':') {
+                // A bare IPv6 address has colons but no port.
+                Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+                    let host = host.trim_start_matches('[').trim_end_matches(']');
+                    Some((host, port.parse().ok()?)).filter(|(host, _)| !host.is_empty())
+                }
+                _ => Some((address.as_str(), default_port)).filter(|(host, _)| !host.is_empty()),
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CorsConfig {
+    /// The origins allowed to do cross-origin requests. `"*"` allows
+    /// any origin.
+    pub allowed_origins: Vec<String>,
+    /// The HTTP methods allowed for cross-origin requests.
+    pub allowed_methods: Vec<String>,
+    /// The HTTP headers allowed for cross-origin requests.
+    pub allowed_headers: Vec<String>,
+    /// How long, in seconds, the results of a preflight request can be
+    /// cached by the clients.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec!["*".to_owned()],
+            allowed_methods: vec!["GET".to_owned(), "HEAD".to_owned(), "OPTIONS".to_owned()],
+            allowed_headers: Vec::new(),
+            max_age: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct LoggingConfig {
+    /// The default [tracing] directive, used when `RUST_LOG` is not set.
+    pub level: String,
+    /// How the log lines are formatted.
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "trace".to_owned(),
+            format: LogFormat::Compact,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogFormat {
+    /// One JSON object per line, for log collectors.
+    Json,
+    /// Multi-line human-readable output, for local development.
+    Pretty,
+    /// One human-readable line per event.
+    Compact,
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Json => "json",
+            Self::Pretty => "pretty",
+            Self::Compact => "compact",
+        })
+    }
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "json" => Ok(Self::Json),
+            "pretty" => Ok(Self::Pretty),
+            "compact" => Ok(Self::Compact),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MetricsConfig {
+    /// Whether the Prometheus metrics are collected and served at
+    /// `/metrics`.
+    pub enabled: bool,
+    /// The address of a separate admin listener serving the metrics. When
+    /// it's not set, the metrics are served by the main listener.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen: Option<SocketAddr>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            listen: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TelemetryConfig {
+    /// The endpoint of the OpenTelemetry collector receiving the traces.
+    /// The traces are not exported when it's not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// The OTLP transport to use to export the traces.
+    pub protocol: OtlpProtocol,
+    /// The name identifying the service in the exported traces.
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            protocol: OtlpProtocol::Grpc,
+            service_name: clap::crate_name!().to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OtlpProtocol {
+    Grpc,
+    Http,
+}
+
+impl std::str::FromStr for OtlpProtocol {
+    type Err = ();
+
+    /// Parse the values allowed by `OTEL_EXPORTER_OTLP_PROTOCOL`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "grpc" => Ok(Self::Grpc),
+            "http" | "http/protobuf" => Ok(Self::Http),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AuthConfig {
+    /// The API keys allowed to query the service, indexed by the name
+    /// of their owner. When it's empty, the authentication is disabled.
+    pub api_keys: BTreeMap<String, Secret>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ProblemsConfig {
+    /// The base URI of the problem types of the error responses, e.g.
+    /// `https://metadata.example.com/problems` to point to the pages
+    /// describing them served by the service.
+    pub base_uri: String,
+}
+
+impl Default for ProblemsConfig {
+    fn default() -> Self {
+        Self {
+            base_uri: metadata_http_utils::problems::DEFAULT_BASE_URI.to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct OpenApiConfig {
+    /// Whether a page rendering the OpenAPI document is served at
+    /// `/docs`. The document itself is always served at `/openapi.json`.
+    pub ui: bool,
+    /// The subresource integrity of the Redoc script loaded by the page,
+    /// required to serve it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redoc_integrity: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct GraphqlConfig {
+    /// The maximum depth of the GraphQL queries.
+    pub max_depth: usize,
+    /// The maximum complexity of the GraphQL queries, each field counting
+    /// for one, multiplied by the size of the pages of the paginated ones.
+    pub max_complexity: usize,
+}
+
+impl Default for GraphqlConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: metadata_http::graphql::DEFAULT_MAX_DEPTH,
+            max_complexity: metadata_http::graphql::DEFAULT_MAX_COMPLEXITY,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct GrpcConfig {
+    /// The address of the listener serving the gRPC API. It's not served
+    /// when it's not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen: Option<SocketAddr>,
+    /// The maximum number of `WatchChanges` streams open at once.
+    pub max_watchers: usize,
+    /// The number of seconds after which a `WatchChanges` stream ends, the
+    /// client having to call again to keep watching.
+    pub watch_timeout: u64,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            listen: None,
+            max_watchers: metadata_grpc::DEFAULT_MAX_WATCHERS,
+            watch_timeout: 3600,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct BlocksConfig {
+    /// The maximum number of blocks read by a request to
+    /// `/blocks:batchGet`.
+    pub max_batch_size: usize,
+}
+
+impl Default for BlocksConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: metadata_http::DEFAULT_MAX_BATCH_SIZE,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RateLimitConfig {
+    /// Whether the rate of the requests to the API is limited per client.
+    pub enabled: bool,
+    /// What identifies a client: `principal`, `api-key` or `ip`. The
+    /// requests which are not authenticated are identified by their IP.
+    pub key: RateLimitKey,
+    /// The quota of the routes reading a single resource.
+    pub reads: QuotaConfig,
+    /// The quota of the routes creating or changing resources.
+    pub writes: QuotaConfig,
+    /// The quota of the routes reading many resources at once: GraphQL,
+    /// the batches and the trees of blocks.
+    pub search: QuotaConfig,
+    /// The quota of each IP address, counting its requests to every route
+    /// of the API before they are authenticated, so the requests with
+    /// invalid or missing keys are limited too.
+    pub ip: QuotaConfig,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key: RateLimitKey::default(),
+            reads: QuotaConfig {
+                burst: 100,
+                per_second: 50.0,
+            },
+            writes: QuotaConfig {
+                burst: 20,
+                per_second: 10.0,
+            },
+            search: QuotaConfig {
+                burst: 10,
+                per_second: 2.0,
+            },
+            ip: QuotaConfig {
+                burst: 200,
+                per_second: 100.0,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct QuotaConfig {
+    /// The number of requests a client can send at once.
+    pub burst: u32,
+    /// The number of requests a client is given back every second.
+    pub per_second: f64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct LimitsConfig {
+    /// How long, in seconds, a request to the API is given to respond
+    /// before it's answered with a `504 Gateway Timeout` problem. The
+    /// requests are not timed out when it's not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_timeout: Option<u64>,
+    /// How long, in seconds, a streamed response is given to complete
+    /// before it's aborted, for a slow client not to hold a connection to
+    /// the database.
+    pub stream_timeout: u64,
+    /// The maximum size, in bytes, of the request bodies.
+    pub max_body_size: usize,
+    /// The maximum number of requests to the API handled at once. The
+    /// number of requests is not limited when it's not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_in_flight: Option<usize>,
+    /// Whether the requests above `max_in_flight` are rejected with a
+    /// `503 Service Unavailable` problem, rather than waiting.
+    pub load_shedding: bool,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: None,
+            stream_timeout: 300,
+            max_body_size: metadata_http::DEFAULT_MAX_BODY_SIZE,
+            max_in_flight: None,
+            load_shedding: false,
+        }
+    }
+}
+
+impl Config {
+    /// Resolve the effective configuration from the default values, the
+    /// configuration file, the environment and the parsed command line.
+    ///
+    /// The host of the server is only required when `serves` is set, i.e.
+    /// when the command serves the API or checks its configuration.
+    pub fn load(args: &ArgMatches, serves: bool) -> Result<Self, ConfigError> {
+        let mut config = match args.get_one::<PathBuf>("config") {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        // The flags may be given to the command or to any of its
+        // subcommands, the innermost one winning.
+        let mut args = Some(args);
+        while let Some(matches) = args {
+            config.apply_args(matches);
+            args = matches.subcommand().map(|(_, matches)| matches);
+        }
+        config.validate(serves)?;
+
+        Ok(config)
+    }
+
+    /// Read and parse a TOML configuration file. Missing values are
+    /// filled with their default.
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let raw = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+
+        toml::from_str(&raw).map_err(|source| ConfigError::Parse {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    /// Override the configuration values with the ones available in the
+    /// environment.
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(host) = env(HOST_ENV) {
+            self.server.host = Some(parse_env(HOST_ENV, host)?);
+        }
+        if let Some(port) = env(PORT_ENV) {
+            self.server.port = parse_env(PORT_ENV, port)?;
+        }
+        if let Some(drain_timeout) = env("METADATA_DRAIN_TIMEOUT") {
+            self.server.drain_timeout = parse_env("METADATA_DRAIN_TIMEOUT", drain_timeout)?;
+        }
+        if let Some(cert) = env(TLS_CERT_ENV) {
+            self.server.tls.cert = Some(cert.into());
+        }
+        if let Some(key) = env(TLS_KEY_ENV) {
+            self.server.tls.key = Some(key.into());
+        }
+        if let Some(client_ca) = env(TLS_CLIENT_CA_ENV) {
+            self.server.tls.client_ca = Some(client_ca.into());
+        }
+
+        if let Some(application_name) = env("POSTGRES_APPNAME") {
+            self.database.application_name = application_name;
+        }
+        if let Some(host) = env(POSTGRES_HOST_ENV) {
+            self.database.host = host;
+        }
+        if let Some(port) = env(POSTGRES_PORT_ENV) {
+            self.database.port = parse_env(POSTGRES_PORT_ENV, port)?;
+        }
+        if let Some(user) = env(POSTGRES_USER_ENV) {
+            self.database.user = Some(user);
+        }
+        if let Some(password) = env(POSTGRES_PASSWORD_ENV) {
+            self.database.password = Some(password.into());
+        }
+        if let Some(database) = env(POSTGRES_DATABASE_ENV) {
+            self.database.database = Some(database);
+        }
+        if let Some(wait_for_startup) = env(WAIT_FOR_DATABASE_ENV) {
+            self.database.wait_for_startup = parse_env(WAIT_FOR_DATABASE_ENV, wait_for_startup)?;
+        }
+        if let Some(startup_timeout) = env("METADATA_DATABASE_STARTUP_TIMEOUT") {
+            self.database.startup_timeout =
+                parse_env("METADATA_DATABASE_STARTUP_TIMEOUT", startup_timeout)?;
+        }
+        if let Some(migrate) = env(MIGRATE_ENV) {
+            self.database.migrate = parse_env(MIGRATE_ENV, migrate)?;
+        }
+        if let Some(hosts) = env("METADATA_DATABASE_REPLICAS") {
+            self.database.replicas.hosts = split_list(&hosts);
+        }
+        if let Some(max_lag) = env("METADATA_DATABASE_REPLICA_MAX_LAG") {
+            self.database.replicas.max_lag =
+                parse_env("METADATA_DATABASE_REPLICA_MAX_LAG", max_lag)?;
+        }
+        if let Some(check_interval) = env("METADATA_DATABASE_REPLICA_CHECK_INTERVAL") {
+            self.database.replicas.check_interval =
+                parse_env("METADATA_DATABASE_REPLICA_CHECK_INTERVAL", check_interval)?;
+        }
+
+        if let Some(origins) = env("METADATA_CORS_ALLOWED_ORIGINS") {
+            self.cors.allowed_origins = split_list(&origins);
+        }
+        if let Some(methods) = env("METADATA_CORS_ALLOWED_METHODS") {
+            self.cors.allowed_methods = split_list(&methods);
+        }
+        if let Some(headers) = env("METADATA_CORS_ALLOWED_HEADERS") {
+            self.cors.allowed_headers = split_list(&headers);
+        }
+        if let Some(max_age) = env("METADATA_CORS_MAX_AGE") {
+            self.cors.max_age = Some(parse_env("METADATA_CORS_MAX_AGE", max_age)?);
+        }
+
+        if let Some(level) = env(LOG_LEVEL_ENV) {
+            self.logging.level = level;
+        }
+        if let Some(format) = env(LOG_FORMAT_ENV) {
+            self.logging.format = parse_env(LOG_FORMAT_ENV, format)?;
+        }
+
+        if let Some(enabled) = env("METADATA_METRICS_ENABLED") {
+            self.metrics.enabled = parse_env("METADATA_METRICS_ENABLED", enabled)?;
+        }
+        if let Some(listen) = env("METADATA_METRICS_LISTEN") {
+            self.metrics.listen = Some(parse_env("METADATA_METRICS_LISTEN", listen)?);
+        }
+
+        if let Some(endpoint) = env("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            self.telemetry.endpoint = Some(endpoint);
+        }
+        if let Some(protocol) = env("OTEL_EXPORTER_OTLP_PROTOCOL") {
+            self.telemetry.protocol = parse_env("OTEL_EXPORTER_OTLP_PROTOCOL", protocol)?;
+        }
+        if let Some(service_name) = env("OTEL_SERVICE_NAME") {
+            self.telemetry.service_name = service_name;
+        }
+
+        if let Some(api_keys) = env("METADATA_API_KEYS") {
+            self.auth.api_keys = split_list(&api_keys)
+                .into_iter()
+                .map(|entry| match entry.split_once('=') {
+                    Some((name, key)) => Ok((name.to_owned(), key.to_owned().into())),
+                    None => Err(ConfigError::Env {
+                        name: "METADATA_API_KEYS",
+                        value: "<redacted>".to_owned(),
+                    }),
+                })
+                .collect::<Result<_, _>>()?;
+        }
+
+        if let Some(base_uri) = env("METADATA_PROBLEMS_BASE_URI") {
+            self.problems.base_uri = base_uri;
+        }
+        if let Some(ui) = env("METADATA_OPENAPI_UI") {
+            self.openapi.ui = parse_env("METADATA_OPENAPI_UI", ui)?;
+        }
+        if let Some(integrity) = env("METADATA_OPENAPI_REDOC_INTEGRITY") {
+            self.openapi.redoc_integrity = Some(integrity);
+        }
+        if let Some(max_depth) = env("METADATA_GRAPHQL_MAX_DEPTH") {
+            self.graphql.max_depth = parse_env("METADATA_GRAPHQL_MAX_DEPTH", max_depth)?;
+        }
+        if let Some(max_complexity) = env("METADATA_GRAPHQL_MAX_COMPLEXITY") {
+            self.graphql.max_complexity =
+                parse_env("METADATA_GRAPHQL_MAX_COMPLEXITY", max_complexity)?;
+        }
+        if let Some(max_batch_size) = env("METADATA_BLOCKS_MAX_BATCH_SIZE") {
+            self.blocks.max_batch_size =
+                parse_env("METADATA_BLOCKS_MAX_BATCH_SIZE", max_batch_size)?;
+        }
+        if let Some(listen) = env("METADATA_GRPC_LISTEN") {
+            self.grpc.listen = Some(parse_env("METADATA_GRPC_LISTEN", listen)?);
+        }
+        if let Some(max_watchers) = env("METADATA_GRPC_MAX_WATCHERS") {
+            self.grpc.max_watchers = parse_env("METADATA_GRPC_MAX_WATCHERS", max_watchers)?;
+        }
+        if let Some(watch_timeout) = env("METADATA_GRPC_WATCH_TIMEOUT") {
+            self.grpc.watch_timeout = parse_env("METADATA_GRPC_WATCH_TIMEOUT", watch_timeout)?;
+        }
+
+        if let Some(enabled) = env("METADATA_RATE_LIMIT_ENABLED") {
+            self.rate_limit.enabled = parse_env("METADATA_RATE_LIMIT_ENABLED", enabled)?;
+        }
+        if let Some(key) = env("METADATA_RATE_LIMIT_KEY") {
+            self.rate_limit.key = parse_env("METADATA_RATE_LIMIT_KEY", key)?;
+        }
+        for (quota, burst_name, per_second_name) in [
+            (
+                &mut self.rate_limit.reads,
+                "METADATA_RATE_LIMIT_READS_BURST",
+                "METADATA_RATE_LIMIT_READS_PER_SECOND",
+            ),
+            (
+                &mut self.rate_limit.writes,
+                "METADATA_RATE_LIMIT_WRITES_BURST",
+                "METADATA_RATE_LIMIT_WRITES_PER_SECOND",
+            ),
+            (
+                &mut self.rate_limit.search,
+                "METADATA_RATE_LIMIT_SEARCH_BURST",
+                "METADATA_RATE_LIMIT_SEARCH_PER_SECOND",
+            ),
+            (
+                &mut self.rate_limit.ip,
+                "METADATA_RATE_LIMIT_IP_BURST",
+                "METADATA_RATE_LIMIT_IP_PER_SECOND",
+            ),
+        ] {
+            if let Some(burst) = env(burst_name) {
+                quota.burst = parse_env(burst_name, burst)?;
+            }
+            if let Some(per_second) = env(per_second_name) {
+                quota.per_second = parse_env(per_second_name, per_second)?;
+            }
+        }
+
+        if let Some(timeout) = env("METADATA_REQUEST_TIMEOUT") {
+            self.limits.request_timeout = Some(parse_env("METADATA_REQUEST_TIMEOUT", timeout)?);
+        }
+        if let Some(timeout) = env("METADATA_STREAM_TIMEOUT") {
+            self.limits.stream_timeout = parse_env("METADATA_STREAM_TIMEOUT", timeout)?;
+        }
+        if let Some(max_body_size) = env("METADATA_MAX_BODY_SIZE") {
+            self.limits.max_body_size = parse_env("METADATA_MAX_BODY_SIZE", max_body_size)?;
+        }
+        if let Some(max_in_flight) = env("METADATA_MAX_IN_FLIGHT") {
+            self.limits.max_in_flight = Some(parse_env("METADATA_MAX_IN_FLIGHT", max_in_flight)?);
+        }
+        if let Some(load_shedding) = env("METADATA_LOAD_SHEDDING") {
+            self.limits.load_shedding = parse_env("METADATA_LOAD_SHEDDING", load_shedding)?;
+        }
+
+        Ok(())
+    }
+
+    /// Override the configuration values with the flags explicitly given
+    /// on the command line.
+    fn apply_args(&mut self, args: &ArgMatches) {
+        if let Some(host) = flag::<IpAddr>(args, "host") {
+            self.server.host = Some(host);
+        }
+        if let Some(port) = flag::<u16>(args, "port") {
+            self.server.port = port;
+        }
+        if let Some(cert) = flag::<PathBuf>(args, "tls_cert") {
+            self.server.tls.cert = Some(cert);
+        }
+        if let Some(key) = flag::<PathBuf>(args, "tls_key") {
+            self.server.tls.key = Some(key);
+        }
+        if let Some(client_ca) = flag::<PathBuf>(args, "tls_client_ca") {
+            self.server.tls.client_ca = Some(client_ca);
+        }
+        if let Some(host) = flag::<String>(args, "postgres_host") {
+            self.database.host = host;
+        }
+        if let Some(port) = flag::<u16>(args, "postgres_port") {
+            self.database.port = port;
+        }
+        if let Some(user) = flag::<String>(args, "postgres_user") {
+            self.database.user = Some(user);
+        }
+        if let Some(password) = flag::<String>(args, "postgres_password") {
+            self.database.password = Some(password.into());
+        }
+        if let Some(database) = flag::<String>(args, "postgres_database") {
+            self.database.database = Some(database);
+        }
+        if let Some(level) = flag::<String>(args, "log_level") {
+            self.logging.level = level;
+        }
+        if let Some(format) = flag::<String>(args, "log_format") {
+            // The possible values are restricted by the command line parser.
+            self.logging.format = format.parse().unwrap();
+        }
+        if let Some(true) = flag::<bool>(args, "wait_for_database") {
+            self.database.wait_for_startup = true;
+        }
+        if let Some(true) = flag::<bool>(args, "migrate") {
+            self.database.migrate = true;
+        }
+    }
+
+    /// Check that every value of the configuration can be used by the
+    /// application, collecting every problem found.
+    pub fn validate(&self, serves: bool) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+
+        if serves && self.server.host.is_none() {
+            errors.push("server.host is required, set it with --host or METADATA_HOST".to_owned());
+        }
+
+        let tls = &self.server.tls;
+        match (&tls.cert, &tls.key) {
+            (Some(_), None) => {
+                errors.push("server.tls.key is required with server.tls.cert".to_owned())
+            }
+            (None, Some(_)) => {
+                errors.push("server.tls.cert is required with server.tls.key".to_owned())
+            }
+            _ => {}
+        }
+        if tls.client_ca.is_some() && !tls.is_enabled() {
+            errors.push("server.tls.client_ca requires server.tls.cert".to_owned());
+        }
+        for (name, path) in [
+            ("cert", &tls.cert),
+            ("key", &tls.key),
+            ("client_ca", &tls.client_ca),
+        ] {
+            if let Some(path) = path {
+                if !path.is_file() {
+                    errors.push(format!(
+                        "server.tls.{name}: '{}' is not a readable file",
+                        path.display()
+                    ));
+                }
+            }
+        }
+        if tls.watch_interval == 0 {
+            errors.push("server.tls.watch_interval must be greater than zero".to_owned());
+        }
+
+        if self.database.host.is_empty() {
+            errors.push("database.host must not be empty".to_owned());
+        }
+        for (name, value, source) in [
+            (
+                "database.user",
+                self.database.user.as_deref(),
+                "--postgres-user or POSTGRES_USER",
+            ),
+            (
+                "database.password",
+                self.database.password.as_ref().map(Secret::expose),
+                "--postgres-password or POSTGRES_PASSWORD",
+            ),
+            (
+                "database.database",
+                self.database.database.as_deref(),
+                "--postgres-database or POSTGRES_DATABASE",
+            ),
+        ] {
+            match value {
+                None => errors.push(format!("{name} is required, set it with {source}")),
+                Some("") => errors.push(format!("{name} must not be empty")),
+                Some(_) => {}
+            }
+        }
+        let replicas = &self.database.replicas;
+        for (address, parsed) in replicas
+            .hosts
+            .iter()
+            .zip(replicas.addresses(self.database.port))
+        {
+            if parsed.is_none() {
+                errors.push(format!(
+                    "database.replicas.hosts: '{address}' is not a valid host or host:port"
+                ));
+            }
+        }
+        if replicas.check_interval == 0 {
+            errors.push("database.replicas.check_interval must be greater than zero".to_owned());
+        }
+
+        for origin in &self.cors.allowed_origins {
+            if origin != "*" && HeaderValue::from_str(origin).is_err() {
+                errors.push(format!(
+                    "cors.allowed_origins: '{origin}' is not a valid origin"
+                ));
+            }
+        }
+        if self.cors.allowed_origins.len() > 1 && self.cors.allowed_origins.iter().any(|o| o == "*")
+        {
+            errors.push("cors.allowed_origins: '*' cannot be mixed with other origins".to_owned());
+        }
+        for method in &self.cors.allowed_methods {
+            if method.parse::<Method>().is_err() {
+                errors.push(format!(
+                    "cors.allowed_methods: '{method}' is not a valid HTTP method"
+                ));
+            }
+        }
+        for header in &self.cors.allowed_headers {
+            if header.parse::<http::HeaderName>().is_err() {
+                errors.push(format!(
+                    "cors.allowed_headers: '{header}' is not a valid HTTP header"
+                ));
+            }
+        }
+
+        if let Err(error) = self.logging.level.parse::<Directive>() {
+            errors.push(format!("logging.level: {error}"));
+        }
+
+        if let Some(listen) = self.metrics.listen.filter(|_| self.metrics.enabled) {
+            if Some(listen) == self.server.address() {
+                errors.push("metrics.listen must differ from the server address".to_owned());
+            }
+        }
+
+        if self.blocks.max_batch_size == 0 {
+            errors.push("blocks.max_batch_size must be greater than zero".to_owned());
+        }
+        if self.grpc.max_watchers == 0 {
+            errors.push("grpc.max_watchers must be greater than zero".to_owned());
+        }
+        if self.grpc.watch_timeout == 0 {
+            errors.push("grpc.watch_timeout must be greater than zero".to_owned());
+        }
+
+        if let Some(listen) = self.grpc.listen {
+            if Some(listen) == self.server.address() {
+                errors.push("grpc.listen must differ from the server address".to_owned());
+            }
+            if self.metrics.enabled && self.metrics.listen == Some(listen) {
+                errors.push("grpc.listen must differ from metrics.listen".to_owned());
+            }
+        }
+
+        for (name, quota) in [
+            ("reads", &self.rate_limit.reads),
+            ("writes", &self.rate_limit.writes),
+            ("search", &self.rate_limit.search),
+            ("ip", &self.rate_limit.ip),
+        ] {
+            if quota.burst == 0 {
+                errors.push(format!("rate_limit.{name}.burst must be greater than zero"));
+            }
+            if !(quota.per_second.is_finite() && quota.per_second > 0.0) {
+                errors.push(format!(
+                    "rate_limit.{name}.per_second must be a positive number"
+                ));
+            }
+        }
+
+        if self.limits.request_timeout == Some(0) {
+            errors.push("limits.request_timeout must be greater than zero".to_owned());
+        }
+        if self.limits.stream_timeout == 0 {
+            errors.push("limits.stream_timeout must be greater than zero".to_owned());
+        }
+        if self.limits.max_body_size == 0 {
+            errors.push("limits.max_body_size must be greater than zero".to_owned());
+        }
+        if self.limits.max_in_flight == Some(0) {
+            errors.push("limits.max_in_flight must be greater than zero".to_owned());
+        }
+        if self.limits.load_shedding && self.limits.max_in_flight.is_none() {
+            errors.push("limits.max_in_flight is required with limits.load_shedding".to_owned());
+        }
+
+        match &self.openapi.redoc_integrity {
+            None if self.openapi.ui => {
+                errors.push("openapi.redoc_integrity is required with openapi.ui".to_owned());
+            }
+            Some(integrity)
+                if !["sha256-", "sha384-", "sha512-"]
+                    .iter()
+                    .any(|prefix| integrity.starts_with(prefix)) =>
+            {
+                errors.push(
+                    "openapi.redoc_integrity must be a sha256, sha384 or sha512 digest".to_owned(),
+                );
+            }
+            _ => {}
+        }
+
+        if self.graphql.max_depth == 0 {
+            errors.push("graphql.max_depth must be greater than zero".to_owned());
+        }
+        if self.graphql.max_complexity == 0 {
+            errors.push("graphql.max_complexity must be greater than zero".to_owned());
+        }
+
+        if let Some(endpoint) = &self.telemetry.endpoint {
+            if endpoint.parse::<http::Uri>().is_err() {
+                errors.push(format!(
+                    "telemetry.endpoint: '{endpoint}' is not a valid URI"
+                ));
+            }
+        }
+
+        for (name, key) in &self.auth.api_keys {
+            if key.expose().is_empty() {
+                errors.push(format!("auth.api_keys.{name} must not be empty"));
+            }
+        }
+
+        match self.problems.base_uri.parse::<http::Uri>() {
+            Ok(uri) if uri.scheme().is_some() && uri.query().is_none() => {}
+            _ => errors.push(format!(
+                "problems.base_uri: '{}' is not an absolute URI",
+                self.problems.base_uri
+            )),
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+
+    /// Render the configuration as a TOML document, with its secrets
+    /// redacted. It fails on the integers TOML can't represent, i.e. the
+    /// ones above [i64::MAX], which can only come from the environment
+    /// or the flags.
+    pub fn to_redacted_toml(&self) -> Result<String, ConfigError> {
+        toml::to_string_pretty(self).map_err(ConfigError::Render)
+    }
+}
+
+#[inline]
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn parse_env<T: std::str::FromStr>(name: &'static str, value: String) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError::Env { name, value })
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Returns the value of a flag only if it was explicitly given on the
+/// command line, the other sources being handled by [Config] itself.
+///
+/// The flags are not defined by every subcommand, e.g. the server flags
+/// are unknown to the admin commands, in which case `None` is returned.
+fn flag<T: Clone + Send + Sync + 'static>(args: &ArgMatches, id: &str) -> Option<T> {
+    let value = args.try_get_one::<T>(id).ok()??.clone();
+
+    match args.value_source(id) {
+        Some(ValueSource::CommandLine) => Some(value),
+        _ => None,
+    }
+}
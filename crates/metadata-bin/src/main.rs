@@ -1,4 +1,10 @@
+use std::process::ExitCode;
+
+mod admin;
 mod cli;
+mod config;
+mod telemetry;
+mod tls;
 mod utils;
 
 #[cfg(any(
@@ -9,14 +15,20 @@ mod utils;
 static GLOBAL_ALLOCATOR: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 #[cfg(not(debug_assertions))]
-fn main() {
-    cli::main();
+fn main() -> ExitCode {
+    cli::main()
 }
 
 #[cfg(debug_assertions)]
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    dotenvy::dotenv()?;
-    cli::main();
+fn main() -> ExitCode {
+    // A missing `.env` file is fine, the configuration can come from
+    // elsewhere, but a malformed one is not.
+    if let Err(error) = dotenvy::dotenv() {
+        if !error.not_found() {
+            eprintln!("error: unable to load .env file: {error}");
+            return ExitCode::FAILURE;
+        }
+    }
 
-    Ok(())
+    cli::main()
 }
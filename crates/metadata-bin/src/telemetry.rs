@@ -0,0 +1,97 @@
+use crate::config::{OtlpProtocol, TelemetryConfig};
+use http::{HeaderMap, Request};
+use metadata_http_utils::context::REQUEST_ID_HEADER;
+use opentelemetry::{propagation::Extractor, trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::{ExporterBuildError, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{span_processor_with_async_runtime::BatchSpanProcessor, SdkTracerProvider},
+    Resource,
+};
+use tracing::Span;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Build the OTLP exporter and the tracer provider described by the
+/// configuration, and register the W3C trace context propagator.
+pub(crate) fn tracer_provider(
+    config: &TelemetryConfig,
+    endpoint: &str,
+) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = match config.protocol {
+        OtlpProtocol::Grpc => opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()?,
+        OtlpProtocol::Http => opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint)
+            .build()?,
+    };
+    let resource = Resource::builder()
+        .with_service_name(config.service_name.clone())
+        .with_attribute(KeyValue::new("service.version", clap::crate_version!()))
+        .build();
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    // The exporters are driven by the Tokio runtime of the server, as
+    // both the gRPC and HTTP transports need its reactor.
+    let processor = BatchSpanProcessor::builder(exporter, runtime::Tokio).build();
+
+    Ok(SdkTracerProvider::builder()
+        .with_span_processor(processor)
+        .with_resource(resource)
+        .build())
+}
+
+/// Build the [tracing_subscriber] layer exporting the spans with the
+/// given provider.
+pub(crate) fn layer<S>(
+    provider: &SdkTracerProvider,
+) -> OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(clap::crate_name!()))
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Create the span of an incoming request, using the `traceparent` and
+/// `tracestate` headers of the request, if any, as its remote parent.
+///
+/// The request identifier is attached to the span, so every event and
+/// span emitted while processing the request can be correlated with it.
+pub(crate) fn request_span<B>(request: &Request<B>) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id,
+    );
+
+    let context = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    let _ = span.set_parent(context);
+
+    span
+}
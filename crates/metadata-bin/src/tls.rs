@@ -0,0 +1,337 @@
+use crate::config::{ClientAuth, TlsConfig};
+use axum::{
+    extract::{ConnectInfo, Request},
+    Router,
+};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use metadata_http::{ClientIdentity, Shutdown};
+use rustls::{
+    crypto::ring::{default_provider, sign::any_supported_type},
+    pki_types::CertificateDer,
+    server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier},
+    sign::CertifiedKey,
+    RootCertStore, ServerConfig,
+};
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use tokio_stream::wrappers::ReceiverStream;
+use tower::ServiceExt;
+
+/// An error emitted while the TLS material of the server is loaded.
+#[derive(Debug, Error)]
+pub(crate) enum TlsError {
+    #[error("unable to read '{}': {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("no certificate found in '{}'", .0.display())]
+    NoCertificate(PathBuf),
+    #[error("no private key found in '{}'", .0.display())]
+    NoPrivateKey(PathBuf),
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+    #[error("invalid client CA bundle: {0}")]
+    ClientVerifier(#[from] rustls::server::VerifierBuilderError),
+    #[cfg(unix)]
+    #[error("unable to listen for SIGHUP: {0}")]
+    Signal(std::io::Error),
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, TlsError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|source| TlsError::Read {
+            path: path.to_owned(),
+            source,
+        })
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certificates = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|source| TlsError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+
+    if certificates.is_empty() {
+        Err(TlsError::NoCertificate(path.to_owned()))
+    } else {
+        Ok(certificates)
+    }
+}
+
+/// Load a certificate chain and its private key from PEM files.
+fn load_certified_key(cert: &Path, key: &Path) -> Result<CertifiedKey, TlsError> {
+    let certificates = load_certificates(cert)?;
+    let private_key = rustls_pemfile::private_key(&mut open(key)?)
+        .map_err(|source| TlsError::Read {
+            path: key.to_owned(),
+            source,
+        })?
+        .ok_or_else(|| TlsError::NoPrivateKey(key.to_owned()))?;
+
+    Ok(CertifiedKey::new(
+        certificates,
+        any_supported_type(&private_key)?,
+    ))
+}
+
+/// A certificate resolver whose certificate can be swapped at runtime,
+/// without restarting the server nor dropping the open connections.
+#[derive(Debug)]
+struct ReloadableCertificate {
+    cert: PathBuf,
+    key: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadableCertificate {
+    fn new(cert: PathBuf, key: PathBuf) -> Result<Self, TlsError> {
+        let current = load_certified_key(&cert, &key)?;
+
+        Ok(Self {
+            cert,
+            key,
+            current: RwLock::new(Arc::new(current)),
+        })
+    }
+
+    /// Read the certificate and its key again from the disk. The current
+    /// certificate is kept if the new one can't be loaded.
+    fn reload(&self) {
+        match load_certified_key(&self.cert, &self.key) {
+            Ok(certified_key) => {
+                *self.current.write().unwrap() = Arc::new(certified_key);
+                tracing::info!(cert = %self.cert.display(), "TLS certificate reloaded");
+            }
+            Err(error) => {
+                tracing::error!(%error, "unable to reload TLS certificate, keeping the current one");
+            }
+        }
+    }
+
+    fn modified_at(&self) -> Option<(SystemTime, SystemTime)> {
+        let modified_at = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+        Some((modified_at(&self.cert)?, modified_at(&self.key)?))
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertificate {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.current.read().unwrap()))
+    }
+}
+
+/// Reload the certificate when the process receives `SIGHUP` or when the
+/// certificate or key files are modified on the disk. The handler of
+/// `SIGHUP` is installed before the reloads are watched, so it can't fail
+/// unnoticed.
+fn watch(certificate: Arc<ReloadableCertificate>, interval: Duration) -> Result<(), TlsError> {
+    #[cfg(unix)]
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .map_err(TlsError::Signal)?;
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut last_modified_at = certificate.modified_at();
+
+        loop {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = hangup.recv() => {
+                    tracing::info!("SIGHUP received, reloading TLS certificate");
+                    certificate.reload();
+                    last_modified_at = certificate.modified_at();
+                    continue;
+                }
+                _ = ticker.tick() => {}
+            }
+            #[cfg(not(unix))]
+            ticker.tick().await;
+
+            let modified_at = certificate.modified_at();
+            if modified_at.is_some() && modified_at != last_modified_at {
+                certificate.reload();
+                last_modified_at = modified_at;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Build a TLS acceptor from the configuration and start watching the
+/// certificate for changes.
+pub(crate) fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor, TlsError> {
+    let (Some(cert), Some(key)) = (&config.cert, &config.key) else {
+        unreachable!("TLS configuration is validated beforehand");
+    };
+    let certificate = Arc::new(ReloadableCertificate::new(cert.clone(), key.clone())?);
+    watch(
+        Arc::clone(&certificate),
+        Duration::from_secs(config.watch_interval),
+    )?;
+
+    let provider = Arc::new(default_provider());
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()?;
+    let builder = match &config.client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in load_certificates(client_ca)? {
+                roots.add(certificate)?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = match config.client_auth {
+                ClientAuth::Required => verifier.build()?,
+                ClientAuth::Optional => verifier.allow_unauthenticated().build()?,
+            };
+
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder.with_cert_resolver(certificate);
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Extract the subject of the certificate presented by the client, if
+/// any. The certificate chain has already been verified by rustls.
+fn client_identity(certificates: Option<&[CertificateDer<'_>]>) -> Option<ClientIdentity> {
+    let certificate = certificates?.first()?;
+    let (_, certificate) = x509_parser::parse_x509_certificate(certificate).ok()?;
+
+    Some(ClientIdentity {
+        subject: certificate.subject().to_string(),
+    })
+}
+
+/// Accept TLS connections on the listener, e.g. for the gRPC server,
+/// until the returned stream is dropped. The handshakes are completed
+/// concurrently, so a slow client doesn't delay the others.
+pub(crate) fn incoming(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+) -> ReceiverStream<std::io::Result<TlsStream<TcpStream>>> {
+    let (sender, receiver) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, remote_addr) = tokio::select! {
+                connection = listener.accept() => match connection {
+                    Ok(connection) => connection,
+                    Err(error) => {
+                        tracing::error!(%error, "unable to accept connection");
+                        continue;
+                    }
+                },
+                () = sender.closed() => break,
+            };
+            let acceptor = acceptor.clone();
+            let sender = sender.clone();
+
+            tokio::spawn(async move {
+                match acceptor.accept(stream).await {
+                    Ok(stream) => _ = sender.send(Ok(stream)).await,
+                    Err(error) => tracing::debug!(%error, %remote_addr, "TLS handshake failed"),
+                }
+            });
+        }
+    });
+
+    ReceiverStream::new(receiver)
+}
+
+/// Accept TLS connections on the listener and serve the application on
+/// them. The subject of the client certificate, when one is presented,
+/// is made available to the handlers as a [ClientIdentity], and the
+/// address of the client as a [ConnectInfo].
+///
+/// Once the shutdown signal is triggered, no more connections are
+/// accepted and this function returns when the open connections are
+/// closed or when the drain timeout has elapsed.
+pub(crate) async fn serve(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    app: Router,
+    shutdown: Shutdown,
+    drain_timeout: Duration,
+) {
+    let graceful = GracefulShutdown::new();
+
+    loop {
+        let (stream, remote_addr) = tokio::select! {
+            connection = listener.accept() => match connection {
+                Ok(connection) => connection,
+                Err(error) => {
+                    tracing::error!(%error, "unable to accept connection");
+                    continue;
+                }
+            },
+            _ = shutdown.triggered() => break,
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        let watcher = graceful.watcher();
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(error) => {
+                    tracing::debug!(%error, %remote_addr, "TLS handshake failed");
+                    return;
+                }
+            };
+            let identity = client_identity(stream.get_ref().1.peer_certificates());
+
+            let service = app.map_request(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(remote_addr));
+                if let Some(identity) = &identity {
+                    request.extensions_mut().insert(identity.clone());
+                }
+                request
+            });
+
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(
+                TokioIo::new(stream),
+                TowerToHyperService::new(service),
+            );
+
+            if let Err(error) = watcher.watch(connection.into_owned()).await {
+                tracing::debug!(%error, %remote_addr, "connection closed with an error");
+            }
+        });
+    }
+
+    drop(listener);
+    if tokio::time::timeout(drain_timeout, graceful.shutdown())
+        .await
+        .is_err()
+    {
+        tracing::warn!("drain timeout elapsed, closing the remaining connections");
+    }
+}
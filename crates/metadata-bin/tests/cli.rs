@@ -0,0 +1,134 @@
+use std::process::{Command, Output};
+use uuid::Uuid;
+
+/// Run an administration command against the database configured with
+/// the `POSTGRES_*` variables.
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_backbone-metadata"))
+        .args(args)
+        .env("METADATA_LOG_LEVEL", "error")
+        .output()
+        .expect("the command to run")
+}
+
+/// Run a command which must succeed, returning what it printed.
+fn stdout(args: &[&str]) -> String {
+    let output = run(args);
+    assert!(
+        output.status.success(),
+        "{args:?}: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8(output.stdout).expect("UTF-8 output")
+}
+
+/// Create a block, returning its identifier.
+fn create_block(domain: &str, name: &str, parent: Option<Uuid>) -> Uuid {
+    let parent = parent.map(|parent| parent.to_string());
+    let mut args = vec!["block", "-o", "json", "create", domain, name];
+    if let Some(parent) = &parent {
+        args.extend(["--parent", parent]);
+    }
+    let block: serde_json::Value = serde_json::from_str(&stdout(&args)).expect("a JSON block");
+
+    block["id"].as_str().unwrap().parse().unwrap()
+}
+
+#[test]
+#[ignore = "requires a PostgreSQL database, configured with the POSTGRES_* variables"]
+fn prints_the_tables_aligned_on_their_widest_cells() {
+    let name = format!("cli-{}-with-a-long-name", Uuid::now_v7().simple());
+    stdout(&["domain", "create", &name]);
+
+    let table = stdout(&["domain", "list"]);
+    let mut lines = table.lines();
+    let header = lines.next().expect("a header");
+    let row = lines
+        .find(|line| line.contains(&name))
+        .expect("the row of the domain");
+
+    // Every column starts at the same offset in the header and in the
+    // rows, two spaces after the widest cell of the previous one, and the
+    // lines have no trailing spaces.
+    let columns = |line: &str| {
+        (2..line.len())
+            .filter(|&index| line[..index].ends_with("  ") && !line[index..].starts_with(' '))
+            .collect::<Vec<_>>()
+    };
+    assert!(header.starts_with("ID "), "{header}");
+    assert_eq!(columns(header), columns(row), "\n{header}\n{row}");
+    assert!(table.lines().all(|line| line == line.trim_end()));
+
+    stdout(&["domain", "delete", &name]);
+}
+
+#[test]
+#[ignore = "requires a PostgreSQL database, configured with the POSTGRES_* variables"]
+fn prints_the_blocks_under_their_parents() {
+    let name = format!("cli-{}", Uuid::now_v7().simple());
+    stdout(&["domain", "create", &name]);
+    let a = create_block(&name, "a", None);
+    let b = create_block(&name, "b", Some(a));
+    let c = create_block(&name, "c", Some(a));
+    let d = create_block(&name, "d", Some(b));
+    let e = create_block(&name, "e", None);
+
+    assert_eq!(
+        stdout(&["block", "tree", &name]),
+        [
+            name.clone(),
+            format!("├── a ({a})"),
+            format!("│   ├── b ({b})"),
+            format!("│   │   └── d ({d})"),
+            format!("│   └── c ({c})"),
+            format!("└── e ({e})"),
+            String::new(),
+        ]
+        .join("\n")
+    );
+
+    stdout(&["domain", "delete", "--recursive", &name]);
+}
+
+#[test]
+#[ignore = "requires a PostgreSQL database, configured with the POSTGRES_* variables"]
+fn refuses_to_move_a_block_under_its_descendants() {
+    let name = format!("cli-{}", Uuid::now_v7().simple());
+    stdout(&["domain", "create", &name]);
+    let a = create_block(&name, "a", None);
+    let b = create_block(&name, "b", Some(a));
+    let c = create_block(&name, "c", Some(b));
+
+    for parent in [a, c] {
+        let output = run(&[
+            "block",
+            "move",
+            &a.to_string(),
+            "--parent",
+            &parent.to_string(),
+        ]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("can't be moved under itself or one of its descendants"));
+    }
+
+    let output = run(&[
+        "block",
+        "move",
+        &a.to_string(),
+        "--parent",
+        &Uuid::now_v7().to_string(),
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not found"));
+
+    stdout(&["block", "move", &c.to_string(), "--domain", &name]);
+    stdout(&["block", "move", &a.to_string(), "--parent", &c.to_string()]);
+    assert_eq!(
+        stdout(&["block", "tree", &name]),
+        format!("{name}\n└── c ({c})\n    └── a ({a})\n        └── b ({b})\n")
+    );
+
+    stdout(&["domain", "delete", "--recursive", &name]);
+}
@@ -0,0 +1,206 @@
+use std::{
+    path::PathBuf,
+    process::{Command, Output},
+};
+use uuid::Uuid;
+
+/// A configuration file, removed once dropped.
+struct ConfigFile(PathBuf);
+
+impl ConfigFile {
+    fn new(content: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("metadata-{}.toml", Uuid::now_v7()));
+        std::fs::write(&path, content).expect("the configuration file to be written");
+
+        Self(path)
+    }
+}
+
+impl Drop for ConfigFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// The values which have no default, given to every command unless the
+/// tests override them.
+const REQUIRED: [(&str, &str); 4] = [
+    ("METADATA_HOST", "127.0.0.1"),
+    ("POSTGRES_USER", "metadata"),
+    ("POSTGRES_PASSWORD", "metadata"),
+    ("POSTGRES_DATABASE", "metadata"),
+];
+
+/// Run a command with the given environment only, so the configuration
+/// of the machine running the tests is ignored.
+fn run(args: &[&str], env: &[(&str, &str)]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_backbone-metadata"))
+        .args(args)
+        .env_clear()
+        .envs(REQUIRED)
+        .envs(env.iter().copied())
+        .output()
+        .expect("the command to run")
+}
+
+/// Print the effective configuration, parsed.
+fn print(args: &[&str], env: &[(&str, &str)]) -> toml::Value {
+    let args = [args, &["config", "print"]].concat();
+    let output = run(&args, env);
+    assert!(
+        output.status.success(),
+        "{args:?}: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    toml::from_str(&String::from_utf8(output.stdout).unwrap()).expect("a TOML configuration")
+}
+
+/// Check the configuration, returning what was reported on failure.
+fn check_errors(args: &[&str], env: &[(&str, &str)]) -> String {
+    let args = [args, &["config", "check"]].concat();
+    let output = run(&args, env);
+    assert!(!output.status.success(), "{args:?} succeeded");
+
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn the_flags_override_the_environment_which_overrides_the_file() {
+    let file = ConfigFile::new(
+        r#"
+        [server]
+        port = 81
+
+        [logging]
+        level = "warn"
+        "#,
+    );
+    let path = file.0.to_str().unwrap();
+
+    let config = print(&[], &[]);
+    assert_eq!(config["server"]["port"].as_integer(), Some(80));
+    assert_eq!(config["logging"]["level"].as_str(), Some("trace"));
+
+    let config = print(&["--config", path], &[]);
+    assert_eq!(config["server"]["port"].as_integer(), Some(81));
+    assert_eq!(config["logging"]["level"].as_str(), Some("warn"));
+
+    // The file can also be given by the environment.
+    let config = print(&[], &[("METADATA_CONFIG", path)]);
+    assert_eq!(config["server"]["port"].as_integer(), Some(81));
+
+    let env = [("METADATA_CONFIG", path), ("METADATA_PORT", "82")];
+    let config = print(&[], &env);
+    assert_eq!(config["server"]["port"].as_integer(), Some(82));
+    assert_eq!(config["logging"]["level"].as_str(), Some("warn"));
+
+    let config = print(&["--port", "83"], &env);
+    assert_eq!(config["server"]["port"].as_integer(), Some(83));
+    assert_eq!(config["logging"]["level"].as_str(), Some("warn"));
+}
+
+#[test]
+fn the_secrets_are_redacted() {
+    let config = print(&[], &[("POSTGRES_PASSWORD", "hunter2")]);
+
+    assert_eq!(config["database"]["password"].as_str(), Some("<redacted>"));
+}
+
+#[test]
+fn reports_the_invalid_sources() {
+    let errors = check_errors(&[], &[("METADATA_PORT", "eighty")]);
+    assert!(
+        errors.contains("invalid value 'eighty' for environment variable METADATA_PORT"),
+        "{errors}"
+    );
+
+    let file = ConfigFile::new("[server]\nunknown = 1\n");
+    let errors = check_errors(&["--config", file.0.to_str().unwrap()], &[]);
+    assert!(
+        errors.contains("unable to parse configuration file"),
+        "{errors}"
+    );
+
+    let errors = check_errors(&["--config", "/nonexistent/metadata.toml"], &[]);
+    assert!(
+        errors.contains("unable to read configuration file"),
+        "{errors}"
+    );
+}
+
+#[test]
+fn reports_the_values_which_cant_be_printed() {
+    let env = [("METADATA_DRAIN_TIMEOUT", "18446744073709551615")];
+    let output = run(&["config", "print"], &env);
+
+    assert!(!output.status.success());
+    let errors = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        errors.starts_with("error: unable to render the configuration: "),
+        "{errors}"
+    );
+}
+
+#[test]
+fn requires_the_address_and_the_credentials() {
+    let output = Command::new(env!("CARGO_BIN_EXE_backbone-metadata"))
+        .args(["config", "check"])
+        .env_clear()
+        .output()
+        .expect("the command to run");
+    assert!(!output.status.success());
+
+    let errors = String::from_utf8(output.stderr).unwrap();
+    for error in [
+        "server.host is required",
+        "database.user is required",
+        "database.password is required",
+        "database.database is required",
+    ] {
+        assert!(errors.contains(error), "{error} is not in {errors}");
+    }
+
+    let errors = check_errors(&[], &[("POSTGRES_USER", "")]);
+    assert!(errors.contains("database.user is required"), "{errors}");
+}
+
+#[test]
+fn reports_every_invalid_value_at_once() {
+    let file = ConfigFile::new(
+        r#"
+        [server.tls]
+        cert = "server.pem"
+
+        [blocks]
+        max_batch_size = 0
+
+        [grpc]
+        watch_timeout = 0
+        "#,
+    );
+
+    let errors = check_errors(&["--config", file.0.to_str().unwrap()], &[]);
+    for error in [
+        "server.tls",
+        "blocks.max_batch_size must be greater than zero",
+        "grpc.watch_timeout must be greater than zero",
+    ] {
+        assert!(errors.contains(error), "{error} is not in {errors}");
+    }
+}
+
+#[test]
+fn a_valid_configuration_is_accepted() {
+    let output = run(&["config", "check"], &[]);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "The configuration is valid.\n"
+    );
+}
@@ -0,0 +1,179 @@
+use http::StatusCode;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The service answered with a problem details, as defined by
+    /// [RFC 9457](https://datatracker.ietf.org/doc/html/rfc9457).
+    #[error("{0}")]
+    Problem(Box<ProblemDetails>),
+    /// The service answered with an error which is not described by a
+    /// problem details, e.g. from a proxy in front of it.
+    #[error("the service answered with the status {status}")]
+    Status { status: StatusCode, body: String },
+    /// The GraphQL query failed with errors which are not described by a
+    /// problem type, e.g. a syntax error.
+    #[error("the GraphQL query failed: {}", .0.join(", "))]
+    Graphql(Vec<String>),
+    /// The paginated resource doesn't exist.
+    #[error("the {resource} '{name}' is not found")]
+    NotFound {
+        resource: &'static str,
+        name: String,
+    },
+    /// The body of a response couldn't be decoded, e.g. a line of a
+    /// streamed tree.
+    #[error("the response of the service is malformed: {0}")]
+    Malformed(serde_json::Error),
+    #[error("the base URL is invalid: {0}")]
+    InvalidUrl(String),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+impl ClientError {
+    /// The problem details the service answered with, if any.
+    pub fn problem(&self) -> Option<&ProblemDetails> {
+        match self {
+            Self::Problem(problem) => Some(problem),
+            _ => None,
+        }
+    }
+
+    /// Whether the requested resource doesn't exist.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Self::Problem(problem) => problem.status == Some(StatusCode::NOT_FOUND.as_u16()),
+            Self::NotFound { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<ProblemDetails> for ClientError {
+    fn from(problem: ProblemDetails) -> Self {
+        Self::Problem(Box::new(problem))
+    }
+}
+
+/// The known problem types, named after the path of their URI relative
+/// to the base URI of the problem types of the service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProblemKind {
+    DomainNotFound,
+    BlockNotFound,
+    ParentNotFound,
+    BatchTooLarge,
+    InvalidResource,
+    AlreadyExists,
+    MissingApiKey,
+    InvalidApiKey,
+    TooManyRequests,
+    Timeout,
+    Overloaded,
+    /// A problem type this release of the client doesn't know about.
+    Other,
+}
+
+impl ProblemKind {
+    const NAMES: [(&'static str, Self); 11] = [
+        ("domains/not-found", Self::DomainNotFound),
+        ("blocks/not-found", Self::BlockNotFound),
+        ("blocks/parent-not-found", Self::ParentNotFound),
+        ("blocks/batch-too-large", Self::BatchTooLarge),
+        ("validation/invalid-resource", Self::InvalidResource),
+        ("sql/unique-violation", Self::AlreadyExists),
+        ("auth/missing-api-key", Self::MissingApiKey),
+        ("auth/invalid-api-key", Self::InvalidApiKey),
+        ("rate-limit/too-many-requests", Self::TooManyRequests),
+        ("server/timeout", Self::Timeout),
+        ("server/overloaded", Self::Overloaded),
+    ];
+}
+
+/// A parameter of a request reported as invalid by a problem.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct InvalidParam {
+    pub name: String,
+    pub reason: String,
+}
+
+/// A problem details sent by the service. Its members which are not
+/// standard are kept as extensions.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type", default = "about_blank")]
+    pub ty: String,
+    #[serde(default)]
+    pub title: String,
+    pub status: Option<u16>,
+    pub detail: Option<String>,
+    pub instance: Option<String>,
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+fn about_blank() -> String {
+    "about:blank".to_owned()
+}
+
+impl ProblemDetails {
+    /// Whether the problem is of the type with the given name, e.g.
+    /// `domains/not-found`, whatever the base URI of the problem types.
+    pub fn is(&self, name: &str) -> bool {
+        self.ty
+            .strip_suffix(name)
+            .is_some_and(|base| base.ends_with('/'))
+    }
+
+    pub fn kind(&self) -> ProblemKind {
+        ProblemKind::NAMES
+            .into_iter()
+            .find(|(name, _)| self.is(name))
+            .map_or(ProblemKind::Other, |(_, kind)| kind)
+    }
+
+    /// The parameters reported as invalid, by the validation problems.
+    pub fn invalid_params(&self) -> Vec<InvalidParam> {
+        self.extensions
+            .get("invalid_params")
+            .cloned()
+            .and_then(|params| serde_json::from_value(params).ok())
+            .unwrap_or_default()
+    }
+
+    /// Build a problem from the extensions of a GraphQL error, which
+    /// carry the members of the problem when it has a type.
+    pub(crate) fn from_graphql(message: &str, extensions: &Map<String, Value>) -> Option<Self> {
+        let ty = extensions.get("type")?.as_str()?;
+
+        Some(Self {
+            ty: ty.to_owned(),
+            title: extensions
+                .get("title")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_owned(),
+            status: extensions
+                .get("status")
+                .and_then(Value::as_u64)
+                .and_then(|status| u16::try_from(status).ok()),
+            detail: Some(message.to_owned()),
+            instance: None,
+            extensions: Map::new(),
+        })
+    }
+}
+
+impl fmt::Display for ProblemDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.detail {
+            Some(detail) => f.write_str(detail),
+            None => f.write_str(&self.title),
+        }
+    }
+}
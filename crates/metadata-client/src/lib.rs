@@ -0,0 +1,580 @@
+//! A typed client of the HTTP API of the service, for the Rust services
+//! reading and writing domains and blocks.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), metadata_client::ClientError> {
+//! use futures_util::TryStreamExt;
+//! use metadata_client::Client;
+//!
+//! let client = Client::builder("http://localhost:8080")
+//!     .api_key("secret")
+//!     .finalize()?;
+//! let domain = client.create_domain("billing").await?;
+//! let root = client.create_block(&domain.name, "root", None).await?;
+//! client.create_block(&domain.name, "child", Some(root.id)).await?;
+//!
+//! let mut children = Box::pin(client.children(root.id));
+//! while let Some(block) = children.try_next().await? {
+//!     println!("{block}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The errors of the service are decoded into [ProblemDetails], and the
+//! failed requests are retried according to a [RetryPolicy]. A client,
+//! and its clones, read their own writes: the consistency token of the
+//! latest one is sent with every request, so the service doesn't read
+//! from replicas which didn't replay it. The client
+//! speaks plain HTTP unless a TLS feature of `reqwest` is enabled, and a
+//! client configured with it is given to [ClientBuilder::http_client].
+
+mod error;
+mod pages;
+mod retry;
+mod trees;
+
+pub use error::{ClientError, InvalidParam, ProblemDetails, ProblemKind};
+pub use metadata_data_layer::{
+    archive::{Archive, ImportMode, ImportPlan},
+    models::{Block, Domain, Parent, TreeEntry},
+};
+pub use retry::RetryPolicy;
+
+use futures_util::Stream;
+use http::{header, HeaderValue, Method, StatusCode};
+use pages::{Blocks, Children, Domains};
+use reqwest::{RequestBuilder, Response, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// The header carrying the consistency tokens.
+const CONSISTENCY_TOKEN: &str = "x-consistency-token";
+
+/// The number of items fetched per page by the paginated lists, by
+/// default.
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+
+/// Whether the service is ready to handle requests, along with the
+/// checks of its dependencies.
+#[derive(Clone, Debug)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Value,
+}
+
+/// The blocks read by a batch, in the order of the request, and the
+/// identifiers of the blocks which don't exist.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockBatch {
+    pub blocks: Vec<Block>,
+    pub missing: Vec<Uuid>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: Url,
+    api_key: Option<String>,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+    page_size: u32,
+    /// The consistency token of the latest write, shared by the clones.
+    token: Arc<Mutex<Option<Token>>>,
+}
+
+/// A consistency token, ordered by the position of the write-ahead log it
+/// holds, e.g. `16/B374D848`.
+#[derive(Clone, Debug)]
+struct Token {
+    position: u64,
+    value: HeaderValue,
+}
+
+impl Token {
+    fn parse(value: &HeaderValue) -> Option<Self> {
+        let (high, low) = value.to_str().ok()?.split_once('/')?;
+        let high = u32::from_str_radix(high, 16).ok()?;
+        let low = u32::from_str_radix(low, 16).ok()?;
+
+        Some(Self {
+            position: u64::from(high) << 32 | u64::from(low),
+            value: value.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ClientBuilder {
+    base_url: String,
+    api_key: Option<String>,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+    page_size: u32,
+    http: Option<reqwest::Client>,
+}
+
+impl ClientBuilder {
+    /// The API key sent as a bearer token with every request.
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// The timeout of each attempt of a request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The number of items fetched per page by the paginated lists, at
+    /// most 100.
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Send the requests with this client, e.g. to use TLS or a proxy.
+    pub fn http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    pub fn finalize(self) -> Result<Client, ClientError> {
+        let base_url = Url::parse(&self.base_url)
+            .map_err(|error| ClientError::InvalidUrl(error.to_string()))?;
+        if base_url.cannot_be_a_base() {
+            return Err(ClientError::InvalidUrl(format!(
+                "'{base_url}' can't be a base"
+            )));
+        }
+
+        Ok(Client {
+            http: self.http.unwrap_or_default(),
+            base_url,
+            api_key: self.api_key,
+            retry: self.retry,
+            timeout: self.timeout,
+            page_size: self.page_size,
+            token: Arc::default(),
+        })
+    }
+}
+
+/// A request to the service, which can be sent more than once.
+struct Call<'a> {
+    method: Method,
+    path: &'a [&'a str],
+    query: &'a [(&'a str, String)],
+    body: Option<Value>,
+    idempotent: bool,
+    retry: bool,
+}
+
+impl<'a> Call<'a> {
+    fn get(path: &'a [&'a str]) -> Self {
+        Self {
+            method: Method::GET,
+            path,
+            query: &[],
+            body: None,
+            idempotent: true,
+            retry: true,
+        }
+    }
+
+    fn post(path: &'a [&'a str], body: Value) -> Self {
+        Self {
+            method: Method::POST,
+            path,
+            query: &[],
+            body: Some(body),
+            idempotent: false,
+            retry: true,
+        }
+    }
+}
+
+impl Client {
+    /// Build a client of the service served at the base URL.
+    pub fn builder(base_url: impl Into<String>) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.into(),
+            api_key: None,
+            retry: RetryPolicy::default(),
+            timeout: None,
+            page_size: DEFAULT_PAGE_SIZE,
+            http: None,
+        }
+    }
+
+    fn url(&self, path: &[&str]) -> Url {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("the base URL is checked by the builder")
+            .pop_if_empty()
+            .extend(path);
+
+        url
+    }
+
+    fn request(&self, call: &Call<'_>) -> RequestBuilder {
+        let mut request = self
+            .http
+            .request(call.method.clone(), self.url(call.path))
+            .header(header::ACCEPT, HeaderValue::from_static("application/json"));
+        if !call.query.is_empty() {
+            request = request.query(call.query);
+        }
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        if let Some(token) = &*self.token.lock().unwrap() {
+            request = request.header(CONSISTENCY_TOKEN, token.value.clone());
+        }
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        if let Some(body) = &call.body {
+            request = request.json(body);
+        }
+
+        request
+    }
+
+    /// Send a request, retrying it according to the policy, and decode
+    /// the problem of the failed ones.
+    async fn send(&self, call: Call<'_>) -> Result<Response, ClientError> {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let can_retry = call.retry && self.retry.allows(attempts);
+
+            let response = match self.request(&call).send().await {
+                Ok(response) => response,
+                Err(error) if can_retry && retry::retryable_error(&error, call.idempotent) => {
+                    let backoff = self.retry.backoff(attempts, None);
+                    tracing::debug!(%error, ?backoff, "retrying the request");
+                    tokio::time::sleep(backoff).await;
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+
+            let status = response.status();
+            if status.is_success() {
+                self.keep_token(&response);
+                return Ok(response);
+            }
+            if can_retry && retry::retryable(status, call.idempotent) {
+                let backoff = self.retry.backoff(attempts, Some(response.headers()));
+                tracing::debug!(%status, ?backoff, "retrying the request");
+                tokio::time::sleep(backoff).await;
+                continue;
+            }
+
+            return Err(Self::error(response).await);
+        }
+    }
+
+    /// Keep the consistency token of a response, unless the one of a
+    /// later write is already kept.
+    fn keep_token(&self, response: &Response) {
+        let Some(token) = response
+            .headers()
+            .get(CONSISTENCY_TOKEN)
+            .and_then(Token::parse)
+        else {
+            return;
+        };
+
+        let mut kept = self.token.lock().unwrap();
+        if kept
+            .as_ref()
+            .map_or(true, |kept| kept.position < token.position)
+        {
+            *kept = Some(token);
+        }
+    }
+
+    /// Decode the error the service answered with.
+    async fn error(response: Response) -> ClientError {
+        let status = response.status();
+        let is_problem = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("application/problem+json"));
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(error) => return error.into(),
+        };
+
+        match serde_json::from_str::<ProblemDetails>(&body) {
+            Ok(problem) if is_problem => problem.into(),
+            _ => ClientError::Status { status, body },
+        }
+    }
+
+    async fn json<T: DeserializeOwned>(&self, call: Call<'_>) -> Result<T, ClientError> {
+        Ok(self.send(call).await?.json().await?)
+    }
+
+    /// Check that the service is alive.
+    pub async fn health(&self) -> Result<(), ClientError> {
+        self.send(Call::get(&["healthz"])).await?;
+
+        Ok(())
+    }
+
+    /// Check whether the service is ready to handle requests. It's not
+    /// retried, as a service which is not ready answers with a `503`.
+    pub async fn readiness(&self) -> Result<Readiness, ClientError> {
+        #[derive(Deserialize)]
+        struct Report {
+            status: String,
+            checks: Value,
+        }
+
+        let call = Call {
+            retry: false,
+            ..Call::get(&["readyz"])
+        };
+        let response = match self.send(call).await {
+            Ok(response) => response,
+            Err(ClientError::Status { status, body })
+                if status == StatusCode::SERVICE_UNAVAILABLE =>
+            {
+                let report = serde_json::from_str::<Report>(&body)
+                    .map_err(|_| ClientError::Status { status, body })?;
+                return Ok(Readiness {
+                    ready: false,
+                    checks: report.checks,
+                });
+            }
+            Err(error) => return Err(error),
+        };
+        let report = response.json::<Report>().await?;
+
+        Ok(Readiness {
+            ready: report.status == "up",
+            checks: report.checks,
+        })
+    }
+
+    /// The OpenAPI document describing the API.
+    pub async fn openapi(&self) -> Result<Value, ClientError> {
+        self.json(Call::get(&["openapi.json"])).await
+    }
+
+    pub async fn get_domain(&self, domain_name: &str) -> Result<Domain, ClientError> {
+        self.json(Call::get(&[domain_name])).await
+    }
+
+    pub async fn create_domain(&self, domain_name: &str) -> Result<Domain, ClientError> {
+        self.json(Call::post(&[], json!({ "name": domain_name })))
+            .await
+    }
+
+    pub async fn get_block(
+        &self,
+        domain_name: &str,
+        block_name: &str,
+    ) -> Result<Block, ClientError> {
+        self.json(Call::get(&[domain_name, block_name])).await
+    }
+
+    /// Read many blocks at once, by their identifiers.
+    pub async fn batch_get_blocks(&self, block_ids: &[Uuid]) -> Result<BlockBatch, ClientError> {
+        self.json(Call {
+            // Reading the blocks doesn't change anything.
+            idempotent: true,
+            ..Call::post(&["blocks:batchGet"], json!({ "ids": block_ids }))
+        })
+        .await
+    }
+
+    /// Create a block in a domain, under the parent block when there's
+    /// one, or else at the root of the domain.
+    pub async fn create_block(
+        &self,
+        domain_name: &str,
+        block_name: &str,
+        parent: Option<Uuid>,
+    ) -> Result<Block, ClientError> {
+        let body = json!({ "name": block_name, "parent": parent });
+
+        self.json(Call::post(&[domain_name], body)).await
+    }
+
+    /// Export a domain along with its whole tree of blocks.
+    pub async fn export_domain(&self, domain_name: &str) -> Result<Archive, ClientError> {
+        self.json(Call::get(&["archives", domain_name])).await
+    }
+
+    /// Stream the blocks of the tree of a domain, or of the subtree of
+    /// the block named `root`, walked depth-first: every block comes right
+    /// before its descendants, and the siblings are ordered by name.
+    ///
+    /// The blocks are decoded as they are received, so trees of any size
+    /// can be read. The stream fails, without being retried, when the
+    /// service can't send the tree to its end. The timeout of the client
+    /// covers the whole stream.
+    pub async fn tree(
+        &self,
+        domain_name: &str,
+        root: Option<&str>,
+    ) -> Result<impl Stream<Item = Result<TreeEntry, ClientError>>, ClientError> {
+        let query = root
+            .map(|root| ("root", root.to_owned()))
+            .into_iter()
+            .collect::<Vec<_>>();
+        let response = self
+            .send(Call {
+                query: &query,
+                ..Call::get(&["trees", domain_name])
+            })
+            .await?;
+
+        Ok(trees::entries(response))
+    }
+
+    /// Import an archive, returning the changes it made.
+    pub async fn import_archive(
+        &self,
+        archive: &Archive,
+        mode: ImportMode,
+    ) -> Result<ImportPlan, ClientError> {
+        self.import(archive, mode, false).await
+    }
+
+    /// Report the changes an import of the archive would make, without
+    /// making them.
+    pub async fn plan_import(
+        &self,
+        archive: &Archive,
+        mode: ImportMode,
+    ) -> Result<ImportPlan, ClientError> {
+        self.import(archive, mode, true).await
+    }
+
+    async fn import(
+        &self,
+        archive: &Archive,
+        mode: ImportMode,
+        dry_run: bool,
+    ) -> Result<ImportPlan, ClientError> {
+        let body = serde_json::to_value(archive).expect("an archive is always serializable");
+        let query = [("mode", mode.to_string()), ("dry_run", dry_run.to_string())];
+
+        self.json(Call {
+            query: &query,
+            // A dry run doesn't change anything.
+            idempotent: dry_run,
+            ..Call::post(&["archives"], body)
+        })
+        .await
+    }
+
+    /// Execute a GraphQL query, returning its data.
+    ///
+    /// When an error of the query is described by a problem type, it's
+    /// returned as [ClientError::Problem].
+    pub async fn graphql<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        variables: Value,
+    ) -> Result<T, ClientError> {
+        #[derive(Deserialize)]
+        struct GraphqlError {
+            message: String,
+            #[serde(default)]
+            extensions: serde_json::Map<String, Value>,
+        }
+
+        #[derive(Deserialize)]
+        struct GraphqlResponse<T> {
+            data: Option<T>,
+            #[serde(default)]
+            errors: Vec<GraphqlError>,
+        }
+
+        let body = json!({ "query": query, "variables": variables });
+        let response = self
+            .json::<GraphqlResponse<T>>(Call {
+                // The queries don't change anything.
+                idempotent: true,
+                ..Call::post(&["graphql"], body)
+            })
+            .await?;
+
+        if let Some(problem) = response
+            .errors
+            .iter()
+            .find_map(|error| ProblemDetails::from_graphql(&error.message, &error.extensions))
+        {
+            return Err(problem.into());
+        }
+        match response.data {
+            Some(data) if response.errors.is_empty() => Ok(data),
+            _ => Err(ClientError::Graphql(
+                response
+                    .errors
+                    .into_iter()
+                    .map(|error| error.message)
+                    .collect(),
+            )),
+        }
+    }
+
+    /// Stream the domains, ordered by name.
+    pub fn domains(&self) -> impl Stream<Item = Result<Domain, ClientError>> + '_ {
+        pages::paginate(move |after| async move {
+            let variables = json!({ "first": self.page_size, "after": after });
+            let data = self.graphql::<Domains>(pages::DOMAINS, variables).await?;
+
+            Ok(data.domains.into_page())
+        })
+    }
+
+    /// Stream the blocks at the root of a domain, ordered by name.
+    pub fn blocks<'a>(
+        &'a self,
+        domain_name: &'a str,
+    ) -> impl Stream<Item = Result<Block, ClientError>> + 'a {
+        pages::paginate(move |after| async move {
+            let variables = json!({ "name": domain_name, "first": self.page_size, "after": after });
+            let data = self.graphql::<Blocks>(pages::BLOCKS, variables).await?;
+            let domain = data.domain.ok_or_else(|| ClientError::NotFound {
+                resource: "domain",
+                name: domain_name.to_owned(),
+            })?;
+
+            Ok(domain.blocks.into_page())
+        })
+    }
+
+    /// Stream the direct children of a block, ordered by name.
+    pub fn children(&self, block_id: Uuid) -> impl Stream<Item = Result<Block, ClientError>> + '_ {
+        pages::paginate(move |after| async move {
+            let variables = json!({ "id": block_id, "first": self.page_size, "after": after });
+            let data = self.graphql::<Children>(pages::CHILDREN, variables).await?;
+            let block = data.block.ok_or_else(|| ClientError::NotFound {
+                resource: "block",
+                name: block_id.to_string(),
+            })?;
+
+            Ok(block.children.into_page())
+        })
+    }
+}
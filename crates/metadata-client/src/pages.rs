@@ -0,0 +1,183 @@
+//! The lists of domains and of blocks, paginated by the connections of
+//! the GraphQL API: the REST API has no listing route.
+
+use crate::ClientError;
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream, TryStreamExt};
+use metadata_data_layer::models::{Block, Domain, Parent};
+use serde::Deserialize;
+use std::future::Future;
+use uuid::Uuid;
+
+pub(crate) const DOMAINS: &str = r#"
+query Domains($first: Int, $after: String) {
+  domains(first: $first, after: $after) {
+    pageInfo { hasNextPage endCursor }
+    edges { node { id name createdAt updatedAt } }
+  }
+}
+"#;
+
+pub(crate) const BLOCKS: &str = r#"
+query Blocks($name: String!, $first: Int, $after: String) {
+  domain(name: $name) {
+    blocks(first: $first, after: $after) {
+      pageInfo { hasNextPage endCursor }
+      edges { node { id name createdAt updatedAt parent { id } domain { id } } }
+    }
+  }
+}
+"#;
+
+pub(crate) const CHILDREN: &str = r#"
+query Children($id: UUID!, $first: Int, $after: String) {
+  block(id: $id) {
+    children(first: $first, after: $after) {
+      pageInfo { hasNextPage endCursor }
+      edges { node { id name createdAt updatedAt parent { id } domain { id } } }
+    }
+  }
+}
+"#;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Edge<T> {
+    node: T,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Connection<T> {
+    page_info: PageInfo,
+    edges: Vec<Edge<T>>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Domains {
+    pub domains: Connection<DomainNode>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Blocks {
+    pub domain: Option<WithBlocks>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct WithBlocks {
+    pub blocks: Connection<BlockNode>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Children {
+    pub block: Option<WithChildren>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct WithChildren {
+    pub children: Connection<BlockNode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DomainNode {
+    id: Uuid,
+    name: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct Reference {
+    id: Uuid,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockNode {
+    id: Uuid,
+    name: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    parent: Option<Reference>,
+    domain: Reference,
+}
+
+impl From<DomainNode> for Domain {
+    fn from(node: DomainNode) -> Self {
+        Self {
+            id: node.id,
+            name: node.name,
+            created_at: node.created_at,
+            updated_at: node.updated_at,
+        }
+    }
+}
+
+impl From<BlockNode> for Block {
+    fn from(node: BlockNode) -> Self {
+        let parent = match node.parent {
+            Some(parent) => Parent::Block(parent.id),
+            None => Parent::Domain(node.domain.id),
+        };
+
+        Self {
+            id: node.id,
+            parent,
+            name: node.name,
+            created_at: node.created_at,
+            updated_at: node.updated_at,
+        }
+    }
+}
+
+/// A page of items, along with the cursor of the next one.
+pub(crate) struct Page<T> {
+    items: Vec<T>,
+    next: Option<String>,
+}
+
+impl<N> Connection<N> {
+    pub(crate) fn into_page<T: From<N>>(self) -> Page<T> {
+        let next = self
+            .page_info
+            .end_cursor
+            .filter(|_| self.page_info.has_next_page);
+
+        Page {
+            items: self
+                .edges
+                .into_iter()
+                .map(|edge| edge.node.into())
+                .collect(),
+            next,
+        }
+    }
+}
+
+/// Stream the items of the pages fetched one after the other, the next
+/// page being fetched once the items of the previous one are consumed.
+pub(crate) fn paginate<T, F, Fut>(fetch: F) -> impl Stream<Item = Result<T, ClientError>>
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = Result<Page<T>, ClientError>>,
+{
+    // The state is the cursor of the next page, `None` once the last page
+    // is fetched.
+    stream::try_unfold((Some(None), fetch), |(after, mut fetch)| async move {
+        let Some(after) = after else {
+            return Ok::<_, ClientError>(None);
+        };
+        let page = fetch(after).await?;
+
+        Ok(Some((page.items, (page.next.map(Some), fetch))))
+    })
+    .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+    .try_flatten()
+}
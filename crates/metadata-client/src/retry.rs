@@ -0,0 +1,84 @@
+use http::{header, HeaderMap, StatusCode};
+use std::time::Duration;
+
+/// How the failed requests are retried, with an exponential backoff.
+///
+/// The requests reading resources are retried when the service is
+/// unreachable, overloaded or timed out. The ones creating resources are
+/// only retried when they were not processed: when the connection could
+/// not be established, or when the service rejected them with a `429` or
+/// a `503` status. The delay of the `Retry-After` header is honored, up
+/// to the maximum backoff.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy never retrying the requests.
+    pub fn none() -> Self {
+        Self::default().max_retries(0)
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// The delay before the first retry, doubled for each next one.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Whether the request can be attempted again after `attempts` ones.
+    pub(crate) fn allows(&self, attempts: u32) -> bool {
+        attempts <= self.max_retries
+    }
+
+    /// The delay before the retry following `attempts` attempts.
+    pub(crate) fn backoff(&self, attempts: u32, headers: Option<&HeaderMap>) -> Duration {
+        let retry_after = headers
+            .and_then(|headers| headers.get(header::RETRY_AFTER))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let backoff = retry_after.unwrap_or_else(|| {
+            self.initial_backoff
+                .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        });
+
+        backoff.min(self.max_backoff)
+    }
+}
+
+/// Whether a request answered with the status can be retried.
+pub(crate) fn retryable(status: StatusCode, idempotent: bool) -> bool {
+    match status {
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => true,
+        StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT => idempotent,
+        _ => false,
+    }
+}
+
+/// Whether a request which failed with the error can be retried.
+pub(crate) fn retryable_error(error: &reqwest::Error, idempotent: bool) -> bool {
+    error.is_connect() || (idempotent && error.is_timeout())
+}
@@ -0,0 +1,46 @@
+//! The trees of blocks, streamed by the service as newline-delimited
+//! JSON and decoded as their chunks are received.
+
+use crate::ClientError;
+use futures_util::{stream, Stream, TryStreamExt};
+use metadata_data_layer::models::TreeEntry;
+use reqwest::Response;
+
+/// Decode the entries of a tree from the body of the response, keeping
+/// the last line of a chunk until it's completed by the next ones.
+pub(crate) fn entries(response: Response) -> impl Stream<Item = Result<TreeEntry, ClientError>> {
+    // The state is the response, `None` once its body is read, and the
+    // bytes of the incomplete line.
+    stream::try_unfold(
+        (Some(response), Vec::new()),
+        |(response, mut pending)| async move {
+            let Some(mut response) = response else {
+                return Ok::<_, ClientError>(None);
+            };
+
+            match response.chunk().await? {
+                Some(chunk) => {
+                    pending.extend_from_slice(&chunk);
+                    let end = pending
+                        .iter()
+                        .rposition(|&byte| byte == b'\n')
+                        .map_or(0, |position| position + 1);
+                    let lines = pending.drain(..end).collect::<Vec<_>>();
+
+                    Ok(Some((parse(&lines)?, (Some(response), pending))))
+                }
+                None => Ok(Some((parse(&pending)?, (None, Vec::new())))),
+            }
+        },
+    )
+    .map_ok(|entries| stream::iter(entries.into_iter().map(Ok)))
+    .try_flatten()
+}
+
+fn parse(lines: &[u8]) -> Result<Vec<TreeEntry>, ClientError> {
+    lines
+        .split(|&byte| byte == b'\n')
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|line| serde_json::from_slice(line).map_err(ClientError::Malformed))
+        .collect()
+}
@@ -0,0 +1,606 @@
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use futures_util::TryStreamExt;
+use metadata_client::{Client, ClientError, ImportMode, Parent, ProblemKind, RetryPolicy};
+use metadata_data_layer_utils::PoolState;
+use metadata_http::{
+    init_router, ApiKeys, AppState, Quota, RateLimitKey, RateLimits, RequestLimits, RouteClass,
+};
+use std::{
+    ops::Not,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+/// Serve the router on a random local port, returning its base URL.
+async fn spawn(router: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    format!("http://{address}")
+}
+
+/// The pool connects lazily, so the routes which don't query the
+/// database can be served without one.
+async fn service(state: AppState) -> Client {
+    let base_url = spawn(init_router(state)).await;
+
+    Client::builder(base_url).finalize().unwrap()
+}
+
+fn fast_retries() -> RetryPolicy {
+    RetryPolicy::default()
+        .max_retries(2)
+        .initial_backoff(Duration::from_millis(1))
+}
+
+/// A service answering with the status until the number of failures is
+/// reached, and with a domain afterwards. It counts the requests.
+async fn flaky(status: StatusCode, failures: u32) -> (String, Arc<AtomicU32>) {
+    let requests = Arc::new(AtomicU32::new(0));
+    let counter = requests.clone();
+    let handler = move || {
+        let counter = counter.clone();
+        async move {
+            if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                let problem = serde_json::json!({
+                    "type": "https://errors.example.com/sql/serialization-failure",
+                    "title": "Concurrent Update.",
+                    "status": status.as_u16(),
+                    "detail": "The query conflicted with a concurrent transaction.",
+                });
+                return (
+                    status,
+                    [
+                        (header::CONTENT_TYPE, "application/problem+json"),
+                        (header::RETRY_AFTER, "0"),
+                    ],
+                    problem.to_string(),
+                )
+                    .into_response();
+            }
+
+            axum::Json(serde_json::json!({
+                "id": Uuid::now_v7(),
+                "name": "billing",
+                "created_at": "2024-10-19T00:00:00Z",
+                "updated_at": "2024-10-19T00:00:00Z",
+            }))
+            .into_response()
+        }
+    };
+    let router = Router::new().route("/:domain_name", get(handler.clone()).post(handler));
+
+    (spawn(router).await, requests)
+}
+
+#[tokio::test]
+async fn checks_health_and_reads_the_openapi_document() {
+    let client = service(AppState::new(PoolState::builder().finalize())).await;
+
+    client.health().await.unwrap();
+    let document = client.openapi().await.unwrap();
+    assert!(document["openapi"].as_str().unwrap().starts_with("3.1"));
+}
+
+#[tokio::test]
+async fn decodes_validation_problems() {
+    let client = service(AppState::new(PoolState::builder().finalize())).await;
+
+    let error = client.create_domain("not a name").await.unwrap_err();
+    let problem = error.problem().expect("a problem");
+    assert_eq!(problem.kind(), ProblemKind::InvalidResource);
+    assert_eq!(problem.status, Some(422));
+    assert!(problem.is("validation/invalid-resource"));
+    assert_eq!(problem.invalid_params()[0].name, "name");
+}
+
+#[tokio::test]
+async fn rejects_the_batches_above_the_maximum() {
+    let state = AppState::new(PoolState::builder().finalize()).with_max_batch_size(2);
+    let client = service(state).await;
+
+    let ids = [Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7()];
+    let error = client.batch_get_blocks(&ids).await.unwrap_err();
+    assert_eq!(error.problem().unwrap().kind(), ProblemKind::BatchTooLarge);
+}
+
+#[tokio::test]
+async fn decodes_authentication_problems() {
+    let state = AppState::new(PoolState::builder().finalize())
+        .with_api_keys(ApiKeys::new([("ci", "secret")]));
+    let base_url = spawn(init_router(state)).await;
+
+    let anonymous = Client::builder(&base_url).finalize().unwrap();
+    let error = anonymous.get_domain("billing").await.unwrap_err();
+    assert_eq!(error.problem().unwrap().kind(), ProblemKind::MissingApiKey);
+
+    let impostor = Client::builder(&base_url)
+        .api_key("guess")
+        .finalize()
+        .unwrap();
+    let error = impostor.get_domain("billing").await.unwrap_err();
+    assert_eq!(error.problem().unwrap().kind(), ProblemKind::InvalidApiKey);
+    assert!(!error.is_not_found());
+}
+
+#[tokio::test]
+async fn decodes_rate_limiting_problems() {
+    let limits =
+        RateLimits::new(RateLimitKey::Ip).with_quota(RouteClass::Write, Quota::new(1, 0.001));
+    let state = AppState::new(PoolState::builder().finalize()).with_rate_limits(limits);
+    let base_url = spawn(init_router(state)).await;
+    let client = Client::builder(base_url)
+        .retry(RetryPolicy::none())
+        .finalize()
+        .unwrap();
+
+    // The invalid domain is rejected before reaching the database, but
+    // still counted.
+    let error = client.create_domain("not a name").await.unwrap_err();
+    assert_eq!(
+        error.problem().unwrap().kind(),
+        ProblemKind::InvalidResource
+    );
+    let error = client.create_domain("not a name").await.unwrap_err();
+    let problem = error.problem().expect("a problem");
+    assert_eq!(problem.kind(), ProblemKind::TooManyRequests);
+    assert_eq!(problem.status, Some(429));
+
+    // The probes are never limited.
+    client.health().await.unwrap();
+}
+
+/// The port of a database accepting the connections without ever
+/// answering, so the queries hang until they're timed out.
+async fn stalled_database() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((connection, _)) = listener.accept().await {
+            connections.push(connection);
+        }
+    });
+
+    port
+}
+
+#[tokio::test]
+async fn decodes_load_problems() {
+    let limits = RequestLimits::new()
+        .with_timeout(Duration::from_millis(300))
+        .with_max_in_flight(1)
+        .with_load_shedding(true);
+    let pool = PoolState::builder()
+        .host("127.0.0.1")
+        .port(stalled_database().await)
+        .finalize();
+    let state = AppState::new(pool).with_request_limits(limits);
+    let base_url = spawn(init_router(state)).await;
+    let client = Client::builder(base_url)
+        .retry(RetryPolicy::none())
+        .finalize()
+        .unwrap();
+
+    // The second request is shed while the first one waits for the
+    // database, until it's timed out.
+    let (first, second) = tokio::join!(client.get_domain("billing"), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        client.get_domain("billing").await
+    });
+    let problem = first.unwrap_err().problem().cloned().expect("a problem");
+    assert_eq!(problem.kind(), ProblemKind::Timeout);
+    assert_eq!(problem.status, Some(504));
+    let problem = second.unwrap_err().problem().cloned().expect("a problem");
+    assert_eq!(problem.kind(), ProblemKind::Overloaded);
+    assert_eq!(problem.status, Some(503));
+
+    // The probes are never shed.
+    client.health().await.unwrap();
+}
+
+#[tokio::test]
+async fn retries_the_unavailable_service() {
+    let (base_url, requests) = flaky(StatusCode::SERVICE_UNAVAILABLE, 2).await;
+    let client = Client::builder(base_url)
+        .retry(fast_retries())
+        .finalize()
+        .unwrap();
+
+    let domain = client.get_domain("billing").await.unwrap();
+    assert_eq!(domain.name, "billing");
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn gives_up_after_the_last_retry() {
+    let (base_url, requests) = flaky(StatusCode::SERVICE_UNAVAILABLE, 5).await;
+    let client = Client::builder(base_url)
+        .retry(fast_retries())
+        .finalize()
+        .unwrap();
+
+    let error = client.get_domain("billing").await.unwrap_err();
+    let problem = error.problem().expect("a problem");
+    assert_eq!(problem.status, Some(503));
+    assert_eq!(problem.kind(), ProblemKind::Other);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn does_not_retry_the_creations_which_may_be_processed() {
+    let (base_url, requests) = flaky(StatusCode::GATEWAY_TIMEOUT, 1).await;
+    let client = Client::builder(base_url)
+        .retry(fast_retries())
+        .finalize()
+        .unwrap();
+
+    let error = client
+        .create_block("billing", "root", None)
+        .await
+        .unwrap_err();
+    assert_eq!(error.problem().unwrap().status, Some(504));
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    // Reading is retried on the same status.
+    client.get_domain("billing").await.unwrap();
+}
+
+#[tokio::test]
+async fn reports_the_errors_which_are_not_problems() {
+    let router = Router::new().route(
+        "/:domain_name",
+        get(|| async { (StatusCode::BAD_GATEWAY, "upstream is down") }),
+    );
+    let client = Client::builder(spawn(router).await)
+        .retry(RetryPolicy::none())
+        .finalize()
+        .unwrap();
+
+    match client.get_domain("billing").await.unwrap_err() {
+        ClientError::Status { status, body } => {
+            assert_eq!(status, StatusCode::BAD_GATEWAY);
+            assert_eq!(body, "upstream is down");
+        }
+        error => panic!("unexpected error: {error}"),
+    }
+}
+
+/// A client of a service backed by the database configured with the
+/// `POSTGRES_*` variables, whose migrations are applied.
+async fn database_service() -> Client {
+    let base_url = spawn(init_router(AppState::new(PoolState::from_env().finalize()))).await;
+
+    Client::builder(base_url).page_size(2).finalize().unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires a PostgreSQL database, configured with the POSTGRES_* variables"]
+async fn creates_reads_and_lists_domains_and_blocks() {
+    let client = database_service().await;
+    let name = format!("client-{}", Uuid::now_v7().simple());
+
+    let domain = client.create_domain(&name).await.unwrap();
+    assert_eq!(client.get_domain(&name).await.unwrap().id, domain.id);
+
+    let root = client.create_block(&name, &name, None).await.unwrap();
+    assert_eq!(root.parent, Parent::Domain(domain.id));
+    for child in ["c", "a", "b"] {
+        client
+            .create_block(&name, child, Some(root.id))
+            .await
+            .unwrap();
+    }
+    assert_eq!(client.get_block(&name, &name).await.unwrap().id, root.id);
+
+    // The children span two pages of two blocks.
+    let children = client
+        .children(root.id)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    let names = children
+        .iter()
+        .map(|block| block.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["a", "b", "c"]);
+    assert!(children
+        .iter()
+        .all(|block| block.parent == Parent::Block(root.id)));
+
+    // The batches keep the order of the request, and read the repeated
+    // identifiers once.
+    let unknown = Uuid::now_v7();
+    let ids = [children[2].id, unknown, root.id, children[2].id];
+    let batch = client.batch_get_blocks(&ids).await.unwrap();
+    let found = batch
+        .blocks
+        .iter()
+        .map(|block| block.id)
+        .collect::<Vec<_>>();
+    assert_eq!(found, [children[2].id, root.id]);
+    assert_eq!(batch.missing, [unknown]);
+
+    let roots = client.blocks(&name).try_collect::<Vec<_>>().await.unwrap();
+    assert_eq!(roots.len(), 1);
+    assert_eq!(roots[0].id, root.id);
+
+    let domains = client.domains().try_collect::<Vec<_>>().await.unwrap();
+    assert!(domains.iter().any(|listed| listed.id == domain.id));
+
+    // The tree is walked depth-first, the siblings ordered by name.
+    let nested = client
+        .create_block(&name, &format!("{name}-b"), Some(children[1].id))
+        .await
+        .unwrap();
+    client
+        .create_block(&name, "leaf", Some(nested.id))
+        .await
+        .unwrap();
+    let tree = client
+        .tree(&name, None)
+        .await
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    let walk = tree
+        .iter()
+        .map(|entry| (entry.depth, entry.block.name.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        walk,
+        [
+            (0, name.clone()),
+            (1, "a".to_owned()),
+            (1, "b".to_owned()),
+            (2, format!("{name}-b")),
+            (3, "leaf".to_owned()),
+            (1, "c".to_owned()),
+        ]
+    );
+    let subtree = client
+        .tree(&name, Some(&nested.name))
+        .await
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(subtree.len(), 2);
+    assert_eq!((subtree[0].depth, subtree[0].block.id), (0, nested.id));
+
+    let archive = client.export_domain(&name).await.unwrap();
+    assert_eq!(archive.blocks.len(), 6);
+    let plan = client
+        .plan_import(&archive, ImportMode::PreserveIds)
+        .await
+        .unwrap();
+    assert!(plan.is_noop());
+}
+
+#[tokio::test]
+#[ignore = "requires a PostgreSQL database, configured with the POSTGRES_* variables"]
+async fn streams_the_trees_spanning_several_pages_of_siblings() {
+    let client = database_service().await;
+    let name = format!("client-{}", Uuid::now_v7().simple());
+    client.create_domain(&name).await.unwrap();
+
+    // More roots, and more children of the first root, than the pages of
+    // siblings the tree is read by.
+    let mut expected = Vec::new();
+    for root in 0..100 {
+        let root_name = format!("{name}-{root:03}");
+        let block = client.create_block(&name, &root_name, None).await.unwrap();
+        expected.push((0, root_name));
+        if root == 0 {
+            for child in 0..100 {
+                let child_name = format!("{child:03}");
+                client
+                    .create_block(&name, &child_name, Some(block.id))
+                    .await
+                    .unwrap();
+                expected.push((1, child_name));
+            }
+        }
+    }
+
+    let walk = client
+        .tree(&name, None)
+        .await
+        .unwrap()
+        .map_ok(|entry| (entry.depth, entry.block.name))
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(walk, expected);
+
+    let subtree = client
+        .tree(&name, Some(&format!("{name}-000")))
+        .await
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(subtree.len(), 101);
+    assert!(subtree[1..].iter().all(|entry| entry.depth == 1));
+}
+
+#[tokio::test]
+#[ignore = "requires a PostgreSQL database, configured with the POSTGRES_* variables"]
+async fn reads_from_the_healthy_replicas() {
+    // The database is its own replica, never lagging, next to a replica
+    // which never answers and must be left aside.
+    let host = std::env::var("POSTGRES_HOST").unwrap_or("localhost".to_owned());
+    let port = std::env::var("POSTGRES_PORT").map_or(5432, |port| port.parse().unwrap());
+    let pool = PoolState::from_env()
+        .replica(host, port)
+        .replica("127.0.0.1", stalled_database().await)
+        .finalize();
+    pool.check_replicas(Duration::from_secs(5)).await;
+
+    let base_url = spawn(init_router(AppState::new(pool))).await;
+    let client = Client::builder(base_url).finalize().unwrap();
+    let name = format!("client-{}", Uuid::now_v7().simple());
+
+    let domain = client.create_domain(&name).await.unwrap();
+    for _ in 0..4 {
+        let read = tokio::time::timeout(Duration::from_secs(1), client.get_domain(&name));
+        assert_eq!(read.await.unwrap().unwrap().id, domain.id);
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires a PostgreSQL database and a streaming replica of it, listening on the \
+            POSTGRES_REPLICA_PORT port"]
+async fn reads_its_writes_from_lagging_replicas() {
+    let host = std::env::var("POSTGRES_HOST").unwrap_or("localhost".to_owned());
+    let replica_port = std::env::var("POSTGRES_REPLICA_PORT")
+        .expect("the port of the replica")
+        .parse::<u16>()
+        .unwrap();
+    let replica = PoolState::from_env().port(replica_port).finalize();
+    let pool = PoolState::from_env()
+        .replica(host.clone(), replica_port)
+        .finalize();
+    let base_url = spawn(init_router(AppState::new(pool.clone()))).await;
+
+    // The replica replays a write, so its lag is known, before it stops
+    // replaying the next ones while staying within the maximum lag.
+    let writer = Client::builder(&base_url).finalize().unwrap();
+    let name = format!("client-{}", Uuid::now_v7().simple());
+    writer
+        .create_domain(&format!("{name}-before"))
+        .await
+        .unwrap();
+    while sqlx::query_scalar::<_, bool>("SELECT pg_last_wal_replay_lsn() >= $1::pg_lsn")
+        .bind(pool_position(&pool).await)
+        .fetch_one(replica.downcast_ref().as_ref())
+        .await
+        .unwrap()
+        .not()
+    {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    sqlx::query("SELECT pg_wal_replay_pause()")
+        .execute(replica.downcast_ref().as_ref())
+        .await
+        .unwrap();
+    pool.check_replicas(Duration::from_secs(3600)).await;
+
+    let domain = writer.create_domain(&name).await;
+    let read = writer.get_domain(&name).await;
+    let stale = Client::builder(&base_url)
+        .finalize()
+        .unwrap()
+        .get_domain(&name)
+        .await;
+
+    sqlx::query("SELECT pg_wal_replay_resume()")
+        .execute(replica.downcast_ref().as_ref())
+        .await
+        .unwrap();
+    // The writer reads its write from the primary, as the replica didn't
+    // replay its token, while a client without it reads from the replica.
+    assert_eq!(read.unwrap().id, domain.unwrap().id);
+    assert!(stale.unwrap_err().is_not_found());
+}
+
+/// The current position of the write-ahead log of the primary.
+async fn pool_position(pool: &PoolState) -> String {
+    sqlx::query_scalar("SELECT pg_current_wal_lsn()::text")
+        .fetch_one(pool.downcast_ref().as_ref())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires a PostgreSQL database, configured with the POSTGRES_* variables"]
+async fn looks_the_blocks_up_by_name_within_their_domain() {
+    let client = database_service().await;
+    let (first, second) = (
+        format!("client-{}", Uuid::now_v7().simple()),
+        format!("client-{}", Uuid::now_v7().simple()),
+    );
+    client.create_domain(&first).await.unwrap();
+    client.create_domain(&second).await.unwrap();
+
+    // The names are only unique among siblings: each domain reads its own
+    // block, the one nearest to its root.
+    let parent = client.create_block(&first, "parent", None).await.unwrap();
+    let nested = client
+        .create_block(&first, "shared", Some(parent.id))
+        .await
+        .unwrap();
+    assert_eq!(
+        client.get_block(&first, "shared").await.unwrap().id,
+        nested.id
+    );
+    let root = client.create_block(&first, "shared", None).await.unwrap();
+    let other = client.create_block(&second, "shared", None).await.unwrap();
+    assert_eq!(
+        client.get_block(&first, "shared").await.unwrap().id,
+        root.id
+    );
+    assert_eq!(
+        client.get_block(&second, "shared").await.unwrap().id,
+        other.id
+    );
+
+    // The subtrees are looked up within the domain too.
+    let tree = client
+        .tree(&second, Some("shared"))
+        .await
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(tree.len(), 1);
+    assert_eq!(tree[0].block.id, other.id);
+
+    let error = client.get_block(&second, "parent").await.unwrap_err();
+    assert_eq!(error.problem().unwrap().kind(), ProblemKind::BlockNotFound);
+}
+
+#[tokio::test]
+#[ignore = "requires a PostgreSQL database, configured with the POSTGRES_* variables"]
+async fn reports_the_missing_resources() {
+    let client = database_service().await;
+    let name = format!("missing-{}", Uuid::now_v7().simple());
+
+    let error = client.get_domain(&name).await.unwrap_err();
+    assert!(error.is_not_found());
+    assert_eq!(error.problem().unwrap().kind(), ProblemKind::DomainNotFound);
+
+    let error = client
+        .blocks(&name)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        ClientError::NotFound {
+            resource: "domain",
+            ..
+        }
+    ));
+
+    let error = client.tree(&name, None).await.err().unwrap();
+    assert_eq!(error.problem().unwrap().kind(), ProblemKind::DomainNotFound);
+
+    let error = client
+        .create_block("billing", "orphan", Some(Uuid::now_v7()))
+        .await
+        .unwrap_err();
+    assert_eq!(error.problem().unwrap().kind(), ProblemKind::ParentNotFound);
+}
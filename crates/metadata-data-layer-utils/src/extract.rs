@@ -1,8 +1,7 @@
-use super::{repository, PoolState};
+use super::{repository, PoolState, Session};
 use async_trait::async_trait;
 use axum_core::extract::{FromRef, FromRequestParts};
 use http::request::Parts;
-use sqlx::postgres::Postgres;
 use std::{convert::Infallible, sync::Arc};
 
 /// An [axum_core] extractor that is instanciating a structure
@@ -10,6 +9,10 @@ use std::{convert::Infallible, sync::Arc};
 /// state that is able to provide an atomic reference to a
 /// [PoolState] structure.
 ///
+/// The repositories extracted for the same request share a [Session],
+/// so a handler reads what it wrote even when the reads are sent to
+/// replicas.
+///
 /// # Example usage in an application
 ///
 /// ```ignore
@@ -28,13 +31,11 @@ use std::{convert::Infallible, sync::Arc};
 /// # }
 ///
 /// struct FooRepository {
-///     pool: Arc<Pool<Postgres>>,
+///     pool: Session,
 /// }
 ///
 /// impl Repository for FooRepository {
-///     type DB = Postgres;
-///
-///     fn from_ref(pool: Arc<Pool<Self::DB>>) -> Self {
+///     fn from_ref(pool: Session) -> Self {
 ///         Self { pool }
 ///     }
 /// }
@@ -63,14 +64,21 @@ pub struct Repository<T>(pub T);
 impl<T, S> FromRequestParts<S> for Repository<T>
 where
     Arc<PoolState>: FromRef<S>,
-    T: repository::Repository<DB = Postgres>,
+    T: repository::Repository,
     S: Send + Sync,
 {
     type Rejection = Infallible;
 
-    async fn from_request_parts(_: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let pool_state = Arc::from_ref(state);
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let session = match parts.extensions.get::<Session>() {
+            Some(session) => session.clone(),
+            None => {
+                let session = Arc::<PoolState>::from_ref(state).session();
+                parts.extensions.insert(session.clone());
+                session
+            }
+        };
 
-        Ok(Self(T::from_ref(pool_state.downcast_ref())))
+        Ok(Self(T::from_ref(session)))
     }
 }
@@ -1,6 +1,10 @@
 pub mod extract;
+pub mod metrics;
 mod repository;
+mod session;
 mod state;
+pub mod trace;
 
 pub use repository::Repository;
-pub use state::PoolState;
+pub use session::{ConsistencyToken, InvalidToken, Session};
+pub use state::{PoolState, PoolStateBuilder};
@@ -0,0 +1,47 @@
+//! Helpers recording the activity of the data layer with the [metrics]
+//! facade. They are no-ops until a recorder is installed by the
+//! application.
+
+use sqlx::{
+    pool::{Pool, PoolConnection},
+    Database,
+};
+use std::{future::Future, time::Instant};
+
+/// Acquire a connection from the pool, recording how long the caller had
+/// to wait for it in the `db_pool_acquire_duration_seconds` histogram.
+pub async fn acquire<DB: Database>(pool: &Pool<DB>) -> Result<PoolConnection<DB>, sqlx::Error> {
+    let start = Instant::now();
+    let connection = pool.acquire().await;
+    let outcome = if connection.is_ok() { "ok" } else { "error" };
+
+    metrics::histogram!("db_pool_acquire_duration_seconds", "outcome" => outcome)
+        .record(start.elapsed());
+
+    connection
+}
+
+/// Run a query, recording its duration in the `db_query_duration_seconds`
+/// histogram labelled by repository, query name and outcome.
+pub async fn observe<F, T>(
+    repository: &'static str,
+    query: &'static str,
+    future: F,
+) -> Result<T, sqlx::Error>
+where
+    F: Future<Output = Result<T, sqlx::Error>>,
+{
+    let start = Instant::now();
+    let result = future.await;
+    let outcome = if result.is_ok() { "ok" } else { "error" };
+
+    metrics::histogram!(
+        "db_query_duration_seconds",
+        "repository" => repository,
+        "query" => query,
+        "outcome" => outcome,
+    )
+    .record(start.elapsed());
+
+    result
+}
@@ -1,11 +1,10 @@
-use std::sync::Arc;
+use super::Session;
 
 /// A trait to tag a structure as a repository that can be
 /// extracted thanks to [Repository](metadata_data_layer_utils::extract::Repository).
+///
+/// The repositories built from the same [Session] share it, so their
+/// reads are sent to the primary once one of them wrote.
 pub trait Repository {
-    type DB;
-
-    fn from_ref(pool: Arc<sqlx::Pool<Self::DB>>) -> Self
-    where
-        <Self as Repository>::DB: sqlx::Database;
+    fn from_ref(session: Session) -> Self;
 }
@@ -0,0 +1,283 @@
+use sqlx::{
+    pool::{Pool, PoolConnection},
+    postgres::Postgres,
+};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use thiserror::Error;
+
+/// The statement reading the position of the primary in its write-ahead
+/// log.
+pub(crate) const POSITION_STATEMENT: &str = "SELECT pg_current_wal_lsn()::text";
+
+/// The statement measuring how far a replica is behind its primary, in
+/// seconds, and reading the position it replayed. A replica which
+/// replayed the position of the primary, given as `$1`, is up to date
+/// however old its last transaction is. A replica whose WAL receiver is
+/// disconnected is arbitrarily stale otherwise, and its lag, like the
+/// one of a replica which never replayed a transaction, is unknown.
+const LAG_STATEMENT: &str = r#"
+    SELECT
+        CASE
+            WHEN NOT pg_is_in_recovery() THEN 0
+            WHEN pg_last_wal_replay_lsn() >= $1::pg_lsn THEN 0
+            WHEN NOT EXISTS (SELECT FROM pg_stat_wal_receiver) THEN NULL
+            ELSE extract(epoch FROM now() - pg_last_xact_replay_timestamp())
+        END::float8,
+        CASE
+            WHEN pg_is_in_recovery() THEN pg_last_wal_replay_lsn()
+            ELSE pg_current_wal_lsn()
+        END::text
+"#;
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+#[error("a consistency token is a position of the write-ahead log, e.g. `16/B374D848`")]
+pub struct InvalidToken;
+
+/// A position in the write-ahead log of the primary, given to a client
+/// once its request wrote, so its next requests read from the replicas
+/// which replayed the write only.
+///
+/// It's formatted like the `pg_lsn` values of PostgreSQL, e.g.
+/// `16/B374D848`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ConsistencyToken(u64);
+
+impl fmt::Display for ConsistencyToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}/{:X}", self.0 >> 32, self.0 & u64::from(u32::MAX))
+    }
+}
+
+impl FromStr for ConsistencyToken {
+    type Err = InvalidToken;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (high, low) = value.split_once('/').ok_or(InvalidToken)?;
+        let high = u32::from_str_radix(high, 16).map_err(|_| InvalidToken)?;
+        let low = u32::from_str_radix(low, 16).map_err(|_| InvalidToken)?;
+
+        Ok(Self(u64::from(high) << 32 | u64::from(low)))
+    }
+}
+
+/// A pool of connections to a read replica, which serves the reads only
+/// while it's healthy.
+#[derive(Debug)]
+pub(crate) struct Replica {
+    /// The address of the replica, identifying it in the logs and the
+    /// metrics.
+    name: String,
+    pool: Pool<Postgres>,
+    healthy: AtomicBool,
+    /// The position the replica replayed when it was last checked, which
+    /// it has replayed since for sure.
+    replayed: AtomicU64,
+}
+
+impl Replica {
+    pub(crate) fn new(name: String, pool: Pool<Postgres>) -> Self {
+        Self {
+            name,
+            pool,
+            // A replica is only trusted once checked.
+            healthy: AtomicBool::new(false),
+            replayed: AtomicU64::new(0),
+        }
+    }
+
+    fn set_healthy(&self, healthy: bool) {
+        if self.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+            if healthy {
+                tracing::info!(replica = %self.name, "the replica serves the reads");
+            } else {
+                tracing::warn!(replica = %self.name, "the reads fall back to the primary");
+            }
+        }
+        metrics::gauge!("db_replica_healthy", "replica" => self.name.clone())
+            .set(f64::from(u8::from(healthy)));
+    }
+
+    /// Measure the lag of the replica behind the `primary` position,
+    /// unknown when the primary is unreachable, marking the replica
+    /// unhealthy when it's unreachable, when its lag is unknown or when
+    /// it's further behind than `max_lag`.
+    pub(crate) async fn check(&self, max_lag: Duration, primary: Option<ConsistencyToken>) {
+        let checked = sqlx::query_as::<_, (Option<f64>, Option<String>)>(LAG_STATEMENT)
+            .bind(primary.map(|position| position.to_string()))
+            .fetch_one(&self.pool)
+            .await;
+
+        let (lag, replayed) = match checked {
+            Ok(checked) => checked,
+            Err(error) => {
+                tracing::debug!(replica = %self.name, %error, "unable to check the replica");
+                return self.set_healthy(false);
+            }
+        };
+        if let Some(ConsistencyToken(replayed)) = replayed.and_then(|lsn| lsn.parse().ok()) {
+            self.replayed.fetch_max(replayed, Ordering::Relaxed);
+        }
+        match lag {
+            Some(lag) => {
+                metrics::gauge!("db_replica_lag_seconds", "replica" => self.name.clone()).set(lag);
+                self.set_healthy(lag <= max_lag.as_secs_f64());
+            }
+            None => self.set_healthy(false),
+        }
+    }
+
+    pub(crate) fn timed_out(&self) {
+        tracing::debug!(replica = %self.name, "the check of the replica timed out");
+        self.set_healthy(false);
+    }
+
+    /// Whether the replica replayed the position of a token.
+    fn replayed(&self, token: ConsistencyToken) -> bool {
+        self.replayed.load(Ordering::Relaxed) >= token.0
+    }
+
+    pub(crate) async fn close(&self) {
+        self.pool.close().await
+    }
+}
+
+/// The replicas of a primary, serving the reads in turn.
+#[derive(Debug, Default)]
+pub(crate) struct Replicas {
+    pub(crate) replicas: Vec<Replica>,
+    next: AtomicUsize,
+}
+
+impl Replicas {
+    pub(crate) fn new(replicas: Vec<Replica>) -> Self {
+        Self {
+            replicas,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pick the next healthy replica which replayed the position of the
+    /// token, if any.
+    fn pick(&self, token: Option<ConsistencyToken>) -> Option<&Replica> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+
+        (0..self.replicas.len())
+            .map(|offset| &self.replicas[(start + offset) % self.replicas.len()])
+            .filter(|replica| token.map_or(true, |token| replica.replayed(token)))
+            .find(|replica| replica.healthy.load(Ordering::Relaxed))
+    }
+}
+
+/// The connections used by the repositories serving a single request,
+/// e.g. an HTTP request or a gRPC call.
+///
+/// The read-only queries are sent to the healthy replicas and the others
+/// to the primary. Once a session wrote, its reads are sent to the
+/// primary too, so it reads its own writes whatever the lag of the
+/// replicas. The following requests of the client read its writes too
+/// when they carry the [ConsistencyToken] of the session, as only the
+/// replicas which replayed it serve their reads.
+#[derive(Clone, Debug)]
+pub struct Session {
+    primary: Arc<Pool<Postgres>>,
+    replicas: Arc<Replicas>,
+    pinned: Arc<AtomicBool>,
+    wrote: Arc<AtomicBool>,
+    token: Option<ConsistencyToken>,
+}
+
+impl Session {
+    pub(crate) fn new(primary: Arc<Pool<Postgres>>, replicas: Arc<Replicas>) -> Self {
+        Self {
+            primary,
+            replicas,
+            pinned: Arc::default(),
+            wrote: Arc::default(),
+            token: None,
+        }
+    }
+
+    /// Read from the replicas which replayed the position of the token
+    /// only, e.g. the one given to the client by its previous request.
+    pub fn with_token(mut self, token: ConsistencyToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// The pool of connections to the primary.
+    #[inline]
+    pub fn primary(&self) -> &Pool<Postgres> {
+        &self.primary
+    }
+
+    /// The pool a read-only query should be sent to: a healthy replica,
+    /// unless the session is pinned to the primary.
+    pub fn reader(&self) -> &Pool<Postgres> {
+        match self.replica() {
+            Some(replica) => &replica.pool,
+            None => &self.primary,
+        }
+    }
+
+    fn replica(&self) -> Option<&Replica> {
+        if self.pinned.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        self.replicas.pick(self.token)
+    }
+
+    /// Acquire a connection for read-only queries. The primary is used
+    /// when the replica fails to give one, the replica being then left
+    /// aside until it's checked again.
+    pub async fn read(&self) -> Result<PoolConnection<Postgres>, sqlx::Error> {
+        if let Some(replica) = self.replica() {
+            match crate::metrics::acquire(&replica.pool).await {
+                Ok(connection) => return Ok(connection),
+                Err(error) => {
+                    tracing::debug!(replica = %replica.name, %error, "unable to reach the replica");
+                    replica.set_healthy(false);
+                }
+            }
+        }
+
+        crate::metrics::acquire(&self.primary).await
+    }
+
+    /// Acquire a connection to the primary for queries changing the
+    /// database, pinning the session to the primary.
+    pub async fn write(&self) -> Result<PoolConnection<Postgres>, sqlx::Error> {
+        self.pin();
+        self.wrote.store(true, Ordering::Relaxed);
+        crate::metrics::acquire(&self.primary).await
+    }
+
+    /// The token to give to the client once the session wrote, read once
+    /// its writes are committed, so its next requests read them. There's
+    /// none when the session only read.
+    pub async fn token(&self) -> Result<Option<ConsistencyToken>, sqlx::Error> {
+        if !self.wrote.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+
+        let position = sqlx::query_scalar::<_, String>(POSITION_STATEMENT)
+            .fetch_one(self.primary.as_ref())
+            .await?;
+
+        Ok(position.parse().ok())
+    }
+
+    /// Send every following query of the session to the primary, e.g.
+    /// before reading what decides the changes to apply.
+    pub fn pin(&self) {
+        self.pinned.store(true, Ordering::Relaxed);
+    }
+}
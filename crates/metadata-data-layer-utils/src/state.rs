@@ -1,8 +1,18 @@
+use super::session::{ConsistencyToken, Replica, Replicas, Session, POSITION_STATEMENT};
 use sqlx::{
-    pool::Pool,
+    pool::{Pool, PoolOptions},
     postgres::{PgConnectOptions, Postgres},
 };
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, sync::Arc, time::Duration};
+use tokio::task::JoinSet;
+
+/// How long the reads wait for a connection to a replica before falling
+/// back to the primary.
+const REPLICA_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the check of a replica, or the read of the position of the
+/// primary it's compared to, can take.
+const REPLICA_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// A structure that is holding an atomic reference count to a SQLx
 /// [Pool](sqlx::pool::Pool) for a [Postgres](sqlx::postgres::Postgres)
@@ -10,9 +20,14 @@ use std::{borrow::Cow, sync::Arc};
 ///
 /// You can either use as a state of your application or embed it into
 /// another application state.
+///
+/// The pool is the one of the primary, and the state may also hold pools
+/// of read replicas, to which the repositories send their reads through
+/// a [Session].
 #[derive(Clone, Debug)]
 pub struct PoolState {
     inner: Arc<Pool<Postgres>>,
+    replicas: Arc<Replicas>,
 }
 
 impl PoolState {
@@ -29,29 +44,101 @@ impl PoolState {
     /// It will use the following variables:
     ///
     /// - `POSTGRES_APPNAME` will be used to identify the application
-    /// in the context of the PostgreSQL connection.
+    ///   in the context of the PostgreSQL connection.
     /// - `POSTGRES_HOST` will be used to get the PostgreSQL database
-    /// hostname. The default value is `"localhost"`
+    ///   hostname. The default value is `"localhost"`
     /// - `POSTGRES_PORT` will be used to get the network port to use
-    /// to connect with the database. The default value is `5432`
+    ///   to connect with the database. The default value is `5432`
     /// - `POSTGRES_USER` will be used as the username for the
-    /// authentication with the PostgreSQL database. The default value
-    /// is `postgres`.
+    ///   authentication with the PostgreSQL database. The default value
+    ///   is `postgres`.
     /// - `POSTGRES_PASSWORD` will be used as the password for the
-    /// authentication process with the PostgreSQL database.
+    ///   authentication process with the PostgreSQL database.
     /// - `POSTGRES_DATABASE` will be used as the database name
-    /// to use once connected to PostgreSQL database.
+    ///   to use once connected to PostgreSQL database.
     #[inline]
     pub fn from_env<'a>() -> PoolStateBuilder<'a> {
         Default::default()
     }
 
     /// Retrieve an immutable clone of the inner atomic reference
-    /// count stored in the structure.
+    /// count stored in the structure, the pool of the primary.
     #[inline]
     pub fn downcast_ref(&self) -> Arc<Pool<Postgres>> {
         Arc::clone(&self.inner)
     }
+
+    /// Start a new session, whose reads are sent to the replicas until it
+    /// writes.
+    #[inline]
+    pub fn session(&self) -> Session {
+        Session::new(Arc::clone(&self.inner), Arc::clone(&self.replicas))
+    }
+
+    /// Whether the state holds pools of read replicas.
+    #[inline]
+    pub fn has_replicas(&self) -> bool {
+        !self.replicas.replicas.is_empty()
+    }
+
+    /// Measure the lag of every replica: the ones which are unreachable or
+    /// behind the primary by more than `max_lag` stop serving the reads
+    /// until they're checked again. The replicas serve no reads before
+    /// their first check.
+    ///
+    /// The replicas are checked concurrently, and the ones whose check
+    /// doesn't complete in time are marked unhealthy too.
+    pub async fn check_replicas(&self, max_lag: Duration) {
+        let primary = tokio::time::timeout(
+            REPLICA_CHECK_TIMEOUT,
+            sqlx::query_scalar::<_, String>(POSITION_STATEMENT).fetch_one(self.inner.as_ref()),
+        )
+        .await;
+        let primary = match primary {
+            Ok(Ok(position)) => position.parse::<ConsistencyToken>().ok(),
+            Ok(Err(error)) => {
+                tracing::debug!(%error, "unable to read the position of the primary");
+                None
+            }
+            Err(_) => None,
+        };
+
+        let mut checks = JoinSet::new();
+        for index in 0..self.replicas.replicas.len() {
+            let replicas = Arc::clone(&self.replicas);
+            checks.spawn(async move {
+                let replica = &replicas.replicas[index];
+                let check = replica.check(max_lag, primary);
+                if tokio::time::timeout(REPLICA_CHECK_TIMEOUT, check)
+                    .await
+                    .is_err()
+                {
+                    replica.timed_out();
+                }
+            });
+        }
+        while checks.join_next().await.is_some() {}
+    }
+
+    /// Record the current usage of the connection pool in the
+    /// `db_pool_connections` and `db_pool_max_connections` gauges.
+    pub fn record_metrics(&self) {
+        let size = self.inner.size();
+        let idle = u32::try_from(self.inner.num_idle()).unwrap_or(u32::MAX);
+
+        metrics::gauge!("db_pool_connections", "state" => "idle").set(idle);
+        metrics::gauge!("db_pool_connections", "state" => "in_use").set(size.saturating_sub(idle));
+        metrics::gauge!("db_pool_max_connections").set(self.inner.options().get_max_connections());
+    }
+
+    /// Close the connection pool, waiting for the connections that are
+    /// currently in use to be released.
+    pub async fn close(&self) {
+        self.inner.close().await;
+        for replica in &self.replicas.replicas {
+            replica.close().await;
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -62,6 +149,7 @@ pub struct PoolStateBuilder<'a> {
     user: Cow<'a, str>,
     password: Option<Cow<'a, str>>,
     dbname: Option<Cow<'a, str>>,
+    replicas: Vec<(Cow<'a, str>, u16)>,
 }
 
 impl<'a> PoolStateBuilder<'a> {
@@ -75,6 +163,7 @@ impl<'a> PoolStateBuilder<'a> {
             user: "postgres".into(),
             password: None,
             dbname: None,
+            replicas: Vec::new(),
         }
     }
 
@@ -146,6 +235,17 @@ impl<'a> PoolStateBuilder<'a> {
         self
     }
 
+    /// Add a read replica of the PostgreSQL database, reached with the
+    /// same credentials and database name as the primary.
+    #[inline]
+    pub fn replica<S>(mut self, host: S, port: u16) -> Self
+    where
+        S: AsRef<str> + Into<Cow<'a, str>>,
+    {
+        self.replicas.push((host.into(), port));
+        self
+    }
+
     /// Consume the current instance of the builder and use the values
     /// collected in it to instanciate a new [PoolState] instance that
     /// is holding connection pool to a PostgreSQL database.
@@ -165,8 +265,21 @@ impl<'a> PoolStateBuilder<'a> {
             options = options.password(&password);
         }
 
+        let replicas = self
+            .replicas
+            .iter()
+            .map(|(host, port)| {
+                let pool = PoolOptions::new()
+                    .acquire_timeout(REPLICA_ACQUIRE_TIMEOUT)
+                    .connect_lazy_with(options.clone().host(host).port(*port));
+
+                Replica::new(format!("{host}:{port}"), pool)
+            })
+            .collect();
+
         PoolState {
             inner: Arc::new(Pool::connect_lazy_with(options)),
+            replicas: Arc::new(Replicas::new(replicas)),
         }
     }
 }
@@ -203,6 +316,7 @@ impl<'a> Default for PoolStateBuilder<'a> {
             user,
             password,
             dbname,
+            replicas: Vec::new(),
         }
     }
 }
@@ -0,0 +1,21 @@
+//! Helpers attaching database attributes to the [tracing] spans of the
+//! repositories, following the OpenTelemetry semantic conventions.
+
+/// Record a SQL statement as the `db.statement` attribute of the current
+/// span and return it unchanged, so it can wrap the statement given to
+/// SQLx.
+///
+/// The span must declare the `db.statement` field, e.g. with
+/// `#[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]`.
+/// Only the statement text is recorded: the parameter values are bound
+/// separately and never appear in the span, the arguments of the
+/// instrumented function being skipped for the same reason.
+pub fn statement(sql: &'static str) -> &'static str {
+    let span = tracing::Span::current();
+    if !span.is_disabled() {
+        let normalized = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+        span.record("db.statement", normalized);
+    }
+
+    sql
+}
@@ -0,0 +1,44 @@
+-- The schema may predate the migrations, when it was created by hand, so
+-- this first migration only creates what's missing from it.
+CREATE TABLE IF NOT EXISTS domains (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- A block is either the child of a domain or the child of another block,
+-- never both at the same time.
+CREATE TABLE IF NOT EXISTS blocks (
+    id UUID PRIMARY KEY,
+    domain_id UUID REFERENCES domains (id) ON DELETE CASCADE,
+    block_id UUID REFERENCES blocks (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- PostgreSQL has no IF NOT EXISTS for the constraints, so they're looked up
+-- by name before being added.
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT FROM pg_constraint
+        WHERE conrelid = 'domains'::regclass AND conname = 'domains_name_key'
+    ) THEN
+        ALTER TABLE domains ADD CONSTRAINT domains_name_key UNIQUE (name);
+    END IF;
+
+    IF NOT EXISTS (
+        SELECT FROM pg_constraint
+        WHERE conrelid = 'blocks'::regclass AND conname = 'blocks_parent_check'
+    ) THEN
+        ALTER TABLE blocks ADD CONSTRAINT blocks_parent_check
+            CHECK ((domain_id IS NULL) <> (block_id IS NULL));
+    END IF;
+END
+$$;
+
+CREATE INDEX IF NOT EXISTS blocks_domain_id_idx ON blocks (domain_id);
+CREATE INDEX IF NOT EXISTS blocks_block_id_idx ON blocks (block_id);
+CREATE INDEX IF NOT EXISTS blocks_name_idx ON blocks (name);
@@ -0,0 +1,33 @@
+-- Publish every change of the domains and of the blocks on the
+-- `metadata_changes` channel, whatever the client making it, so the
+-- change streams can follow them with LISTEN.
+CREATE FUNCTION notify_metadata_change() RETURNS trigger AS $$
+DECLARE
+    row RECORD;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        row := OLD;
+    ELSE
+        row := NEW;
+    END IF;
+
+    PERFORM pg_notify(
+        'metadata_changes',
+        json_build_object(
+            'operation', lower(TG_OP),
+            'resource', TG_TABLE_NAME,
+            'row', row_to_json(row)
+        )::text
+    );
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER domains_notify_change
+AFTER INSERT OR UPDATE OR DELETE ON domains
+FOR EACH ROW EXECUTE FUNCTION notify_metadata_change();
+
+CREATE TRIGGER blocks_notify_change
+AFTER INSERT OR UPDATE OR DELETE ON blocks
+FOR EACH ROW EXECUTE FUNCTION notify_metadata_change();
@@ -0,0 +1,8 @@
+-- The pages of children are read in the order of these indexes, from the
+-- position of their cursor. They also serve the lookups by parent alone,
+-- which the previous indexes were for.
+CREATE INDEX blocks_domain_id_name_id_idx ON blocks (domain_id, name, id);
+CREATE INDEX blocks_block_id_name_id_idx ON blocks (block_id, name, id);
+
+DROP INDEX blocks_domain_id_idx;
+DROP INDEX blocks_block_id_idx;
@@ -0,0 +1,370 @@
+//! Portable archives of a domain along with its whole tree of blocks,
+//! used to move a domain between environments.
+//!
+//! An archive is either a single JSON document, or NDJSON: a first line
+//! holding the version and the domain, then one line per block. In both
+//! cases the blocks are listed parents first, the siblings ordered by
+//! name, so an archive can be imported in a single pass.
+
+use crate::{
+    models::{Block, Domain, Parent},
+    validation::{self, ValidationErrors},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// The version of the archives written by this release, which is also
+/// the only one it reads.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("the archive is malformed: {0}")]
+    Malformed(String),
+    #[error("the archive version {0} is not supported, expected {ARCHIVE_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("the archived domain is invalid: {0}")]
+    InvalidDomain(ValidationErrors),
+    #[error("the archived block '{id}' is invalid: {errors}")]
+    InvalidBlock { id: Uuid, errors: ValidationErrors },
+    #[error("the block '{0}' is archived more than once")]
+    DuplicateBlock(Uuid),
+    #[error("the parent '{parent}' of the block '{id}' is not in the archive")]
+    UnknownParent { id: Uuid, parent: Uuid },
+    #[error("the blocks of the archive don't form a tree")]
+    Cycle,
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedDomain {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedBlock {
+    pub id: Uuid,
+    /// The parent block, or `None` for the roots of the domain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Uuid>,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The first line of an NDJSON archive.
+#[derive(Serialize, Deserialize)]
+struct Header<D> {
+    version: u32,
+    domain: D,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Archive {
+    pub version: u32,
+    pub domain: ArchivedDomain,
+    pub blocks: Vec<ArchivedBlock>,
+}
+
+/// The serializations of an archive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    #[default]
+    Json,
+    Ndjson,
+}
+
+impl ArchiveFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Ndjson => "ndjson",
+        }
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            _ => Err(format!("unknown archive format '{value}'")),
+        }
+    }
+}
+
+impl Archive {
+    /// Archive a domain with its blocks, given parents first.
+    pub fn new(domain: &Domain, blocks: &[Block]) -> Self {
+        Self {
+            version: ARCHIVE_VERSION,
+            domain: ArchivedDomain {
+                id: domain.id,
+                name: domain.name.clone(),
+                created_at: domain.created_at,
+                updated_at: domain.updated_at,
+            },
+            blocks: blocks
+                .iter()
+                .map(|block| ArchivedBlock {
+                    id: block.id,
+                    parent: match block.parent {
+                        Parent::Domain(_) => None,
+                        Parent::Block(uuid) => Some(uuid),
+                    },
+                    name: block.name.clone(),
+                    created_at: block.created_at,
+                    updated_at: block.updated_at,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn write(&self, format: ArchiveFormat, mut writer: impl Write) -> io::Result<()> {
+        match format {
+            ArchiveFormat::Json => serde_json::to_writer_pretty(&mut writer, self)?,
+            ArchiveFormat::Ndjson => {
+                let header = Header {
+                    version: self.version,
+                    domain: &self.domain,
+                };
+                serde_json::to_writer(&mut writer, &header)?;
+                for block in &self.blocks {
+                    writer.write_all(b"\n")?;
+                    serde_json::to_writer(&mut writer, block)?;
+                }
+            }
+        }
+
+        writer.write_all(b"\n")
+    }
+
+    /// Parse an archive, whatever its format, and check that it can be
+    /// imported.
+    pub fn parse(input: &[u8]) -> Result<Self, ArchiveError> {
+        // The version is checked first, as the other fields of the
+        // archives of another version may not be understood.
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        let first_line = input
+            .split(|byte| *byte == b'\n')
+            .next()
+            .unwrap_or_default();
+        if let Ok(Version { version }) =
+            serde_json::from_slice(input).or_else(|_| serde_json::from_slice(first_line))
+        {
+            if version != ARCHIVE_VERSION {
+                return Err(ArchiveError::UnsupportedVersion(version));
+            }
+        }
+
+        let archive = match serde_json::from_slice::<Self>(input) {
+            Ok(archive) => archive,
+            Err(error) => {
+                let mut lines = input
+                    .split(|byte| *byte == b'\n')
+                    .enumerate()
+                    .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace));
+
+                // The input is read as NDJSON when it's not a JSON
+                // document, but the error of the JSON parser is reported
+                // when it doesn't start with an NDJSON header either.
+                let header = match lines.next() {
+                    Some((_, line)) => serde_json::from_slice::<Header<ArchivedDomain>>(line)
+                        .map_err(|_| ArchiveError::Malformed(error.to_string()))?,
+                    None => return Err(ArchiveError::Malformed(error.to_string())),
+                };
+                let blocks = lines
+                    .map(|(index, line)| {
+                        serde_json::from_slice(line).map_err(|error| {
+                            ArchiveError::Malformed(format!("line {}: {error}", index + 1))
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                Self {
+                    version: header.version,
+                    domain: header.domain,
+                    blocks,
+                }
+            }
+        };
+
+        archive.validate()
+    }
+
+    /// Check the names and the structure of the archive,
+    /// and order its blocks parents first.
+    fn validate(mut self) -> Result<Self, ArchiveError> {
+        self.domain.name = self.domain.name.to_lowercase();
+        let mut errors = ValidationErrors::new();
+        validation::validate_domain_name("name", &self.domain.name, &mut errors);
+        errors
+            .into_result(|| ())
+            .map_err(ArchiveError::InvalidDomain)?;
+
+        let mut ids = HashSet::new();
+        for block in &self.blocks {
+            let mut errors = ValidationErrors::new();
+            validation::validate_block_name("name", &block.name, &mut errors);
+            errors
+                .into_result(|| ())
+                .map_err(|errors| ArchiveError::InvalidBlock {
+                    id: block.id,
+                    errors,
+                })?;
+            if !ids.insert(block.id) {
+                return Err(ArchiveError::DuplicateBlock(block.id));
+            }
+        }
+
+        let mut children = HashMap::<Option<Uuid>, Vec<ArchivedBlock>>::new();
+        for block in std::mem::take(&mut self.blocks) {
+            if let Some(parent) = block.parent.filter(|parent| !ids.contains(parent)) {
+                return Err(ArchiveError::UnknownParent {
+                    id: block.id,
+                    parent,
+                });
+            }
+            children.entry(block.parent).or_default().push(block);
+        }
+
+        // Walk the tree from its roots, one level at a time, so the
+        // blocks that can't be reached are part of a cycle.
+        let mut level = vec![None];
+        while !level.is_empty() {
+            let mut next = Vec::new();
+            for parent in level {
+                let mut siblings = children.remove(&parent).unwrap_or_default();
+                siblings.sort_by(|a, b| a.name.cmp(&b.name));
+                next.extend(siblings.iter().map(|block| Some(block.id)));
+                self.blocks.extend(siblings);
+            }
+            level = next;
+        }
+        if !children.is_empty() {
+            return Err(ArchiveError::Cycle);
+        }
+
+        Ok(self)
+    }
+}
+
+/// How the identifiers of an archive are handled by an import.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImportMode {
+    /// The domain and its blocks are created with new identifiers, e.g.
+    /// to copy a domain within an environment.
+    #[default]
+    NewIds,
+    /// The identifiers are kept, the resources already stored with the
+    /// same identifiers being updated.
+    PreserveIds,
+}
+
+impl fmt::Display for ImportMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NewIds => "new-ids",
+            Self::PreserveIds => "preserve-ids",
+        })
+    }
+}
+
+impl FromStr for ImportMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "new-ids" => Ok(Self::NewIds),
+            "preserve-ids" => Ok(Self::PreserveIds),
+            _ => Err(format!("unknown import mode '{value}'")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Update,
+    Unchanged,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Unchanged => "unchanged",
+        })
+    }
+}
+
+/// The stored state of a resource updated by an import.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Previous {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Uuid>,
+}
+
+/// What an import does to a resource.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Change {
+    pub action: Action,
+    pub id: Uuid,
+    pub name: String,
+    /// The parent block, or `None` for the domain and its roots.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous: Option<Previous>,
+    #[serde(skip)]
+    pub created_at: DateTime<Utc>,
+    #[serde(skip)]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The changes an import makes, the blocks being listed parents first.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImportPlan {
+    pub mode: ImportMode,
+    pub domain: Change,
+    pub blocks: Vec<Change>,
+}
+
+impl ImportPlan {
+    /// Whether applying the plan changes anything.
+    pub fn is_noop(&self) -> bool {
+        std::iter::once(&self.domain)
+            .chain(&self.blocks)
+            .all(|change| change.action == Action::Unchanged)
+    }
+}
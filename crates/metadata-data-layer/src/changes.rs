@@ -0,0 +1,265 @@
+//! The changes of the domains and of the blocks, published by the
+//! database on the [CHANNEL] notification channel whatever the client
+//! making them, so they can be streamed to the clients following them.
+
+use crate::models::{Block, Domain, Parent};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::{
+    pool::PoolOptions,
+    postgres::{PgListener, Postgres},
+    Pool,
+};
+use std::{future::Future, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::sync::{broadcast, OwnedSemaphorePermit, Semaphore};
+use uuid::Uuid;
+
+/// The channel on which the database notifies the changes.
+pub const CHANNEL: &str = "metadata_changes";
+
+#[derive(Debug, Error)]
+pub enum ChangeError {
+    #[error("the change notification is malformed: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// The changed resource, as it was after the change, or before it for
+/// the deletions.
+#[derive(Clone, Debug)]
+pub enum Resource {
+    Domain(Domain),
+    Block(Block),
+}
+
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub operation: Operation,
+    pub resource: Resource,
+}
+
+#[derive(Deserialize)]
+struct DomainRow {
+    id: Uuid,
+    name: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct BlockRow {
+    id: Uuid,
+    domain_id: Option<Uuid>,
+    block_id: Option<Uuid>,
+    name: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+/// The payload of a notification, as built by the
+/// `notify_metadata_change` trigger.
+#[derive(Deserialize)]
+struct Payload {
+    operation: Operation,
+    #[serde(flatten)]
+    row: Row,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "resource", content = "row", rename_all = "lowercase")]
+enum Row {
+    Domains(DomainRow),
+    Blocks(BlockRow),
+}
+
+impl Change {
+    /// Parse the payload of a notification of the [CHANNEL].
+    pub fn parse(payload: &str) -> Result<Self, ChangeError> {
+        let payload = serde_json::from_str::<Payload>(payload)?;
+        let resource = match payload.row {
+            Row::Domains(row) => Resource::Domain(Domain {
+                id: row.id,
+                name: row.name,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            }),
+            Row::Blocks(row) => {
+                let parent = match (row.domain_id, row.block_id) {
+                    (Some(uuid), None) => Parent::Domain(uuid),
+                    (None, Some(uuid)) => Parent::Block(uuid),
+                    _ => {
+                        return Err(ChangeError::Malformed(serde::de::Error::custom(
+                            "a block must have exactly one parent",
+                        )))
+                    }
+                };
+
+                Resource::Block(Block {
+                    id: row.id,
+                    parent,
+                    name: row.name,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                })
+            }
+        };
+
+        Ok(Self {
+            operation: payload.operation,
+            resource,
+        })
+    }
+}
+
+/// The delay before connecting again to the database, once the listener
+/// lost its connection and failed to re-establish it.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Receives the changes notified by the database.
+///
+/// The connection is re-established when it's lost, but the changes made
+/// in the meantime are not received.
+struct ChangeListener {
+    listener: PgListener,
+}
+
+impl ChangeListener {
+    async fn connect(pool: &Pool<Postgres>) -> Result<Self, sqlx::Error> {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(CHANNEL).await?;
+
+        Ok(Self { listener })
+    }
+
+    /// Wait for the next change.
+    async fn recv(&mut self) -> Result<Change, ChangeError> {
+        let notification = self.listener.recv().await?;
+
+        Change::parse(notification.payload())
+    }
+}
+
+/// Why a subscription to the [ChangeFeed] missed changes or ended.
+#[derive(Clone, Debug, Error)]
+pub enum FeedError {
+    /// The connection to the database was lost, and the changes made
+    /// until it's re-established are not received.
+    #[error(transparent)]
+    Interrupted(Arc<sqlx::Error>),
+    /// The subscriber didn't keep up with the changes, and missed some.
+    #[error("{0} changes were missed by a subscriber reading them too slowly")]
+    Lagged(u64),
+    /// The feed stopped, e.g. on shutdown.
+    #[error("the change feed stopped")]
+    Closed,
+}
+
+type Notification = Result<Change, Arc<sqlx::Error>>;
+
+/// Receives the changes notified by the database on a connection of its
+/// own, which is never taken from the pool serving the requests, and
+/// broadcasts them to every subscriber.
+#[derive(Clone, Debug)]
+pub struct ChangeFeed {
+    sender: broadcast::Sender<Notification>,
+    subscribers: Arc<Semaphore>,
+}
+
+impl ChangeFeed {
+    /// Start listening to the changes with the options of the connections
+    /// of `pool`, until `shutdown` completes. The feed accepts at most
+    /// `max_subscribers` subscribers at once, each buffering `capacity`
+    /// changes before missing the next ones.
+    pub fn spawn<F>(
+        pool: &Pool<Postgres>,
+        capacity: usize,
+        max_subscribers: usize,
+        shutdown: F,
+    ) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (sender, _) = broadcast::channel(capacity);
+        let pool = PoolOptions::new()
+            .max_connections(1)
+            .connect_lazy_with((*pool.connect_options()).clone());
+
+        let feed = sender.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                () = shutdown => {}
+                () = listen(&pool, &feed) => {}
+            }
+            pool.close().await;
+        });
+
+        Self {
+            sender,
+            subscribers: Arc::new(Semaphore::new(max_subscribers)),
+        }
+    }
+
+    /// Subscribe to the changes notified from now on, unless the feed has
+    /// as many subscribers as it accepts.
+    pub fn subscribe(&self) -> Option<Subscription> {
+        let permit = Arc::clone(&self.subscribers).try_acquire_owned().ok()?;
+
+        Some(Subscription {
+            receiver: self.sender.subscribe(),
+            _permit: permit,
+        })
+    }
+}
+
+/// Broadcast the changes received by a listener, connecting it again when
+/// it fails.
+async fn listen(pool: &Pool<Postgres>, sender: &broadcast::Sender<Notification>) {
+    loop {
+        let error = match ChangeListener::connect(pool).await {
+            Ok(mut listener) => loop {
+                match listener.recv().await {
+                    // The changes are dropped while there is no subscriber.
+                    Ok(change) => _ = sender.send(Ok(change)),
+                    Err(ChangeError::Malformed(error)) => {
+                        tracing::warn!(%error, "ignoring a malformed change notification");
+                    }
+                    Err(ChangeError::Database(error)) => break error,
+                }
+            },
+            Err(error) => error,
+        };
+
+        tracing::warn!(%error, "the change listener lost its connection, reconnecting in {RECONNECT_DELAY:?}");
+        _ = sender.send(Err(Arc::new(error)));
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// The changes notified since a subscriber subscribed to the [ChangeFeed].
+#[derive(Debug)]
+pub struct Subscription {
+    receiver: broadcast::Receiver<Notification>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Subscription {
+    /// Wait for the next change.
+    pub async fn recv(&mut self) -> Result<Change, FeedError> {
+        match self.receiver.recv().await {
+            Ok(Ok(change)) => Ok(change),
+            Ok(Err(error)) => Err(FeedError::Interrupted(error)),
+            Err(broadcast::error::RecvError::Lagged(missed)) => Err(FeedError::Lagged(missed)),
+            Err(broadcast::error::RecvError::Closed) => Err(FeedError::Closed),
+        }
+    }
+}
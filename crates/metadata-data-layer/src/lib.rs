@@ -1,2 +1,7 @@
+pub mod archive;
+pub mod changes;
+pub mod manifest;
+pub mod migrations;
 pub mod models;
 pub mod repositories;
+pub mod validation;
@@ -0,0 +1,338 @@
+//! Declarative descriptions of domains and of their trees of blocks, to
+//! be reconciled with the stored resources.
+//!
+//! The blocks of a manifest have no identifier: they are matched with
+//! the stored blocks by name, among the children of the same parent.
+
+use crate::{
+    models::{Block, Domain, Parent},
+    validation::{self, ValidationErrors},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The name of the domain.
+    pub domain: String,
+    #[serde(default)]
+    pub blocks: Vec<ManifestBlock>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestBlock {
+    pub name: String,
+    #[serde(default)]
+    pub blocks: Vec<ManifestBlock>,
+}
+
+#[derive(Debug, Error)]
+pub enum ManifestError {
+    #[error("the domain '{0}' is described more than once")]
+    DuplicateDomain(String),
+    #[error("the block '{0}' is described more than once")]
+    DuplicateBlock(String),
+    #[error("'{path}' is invalid: {errors}")]
+    Invalid {
+        path: String,
+        errors: ValidationErrors,
+    },
+    #[error("the block '{0}' is ambiguous, several stored blocks have this path")]
+    Ambiguous(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// Normalize the domain names of manifests, and check that they
+/// describe each domain and each block only once, with valid names.
+pub fn validate(manifests: &mut [Manifest]) -> Result<(), ManifestError> {
+    fn validate_blocks(path: &str, blocks: &[ManifestBlock]) -> Result<(), ManifestError> {
+        let mut names = HashSet::new();
+        for block in blocks {
+            let path = format!("{path}/{}", block.name);
+            let mut errors = ValidationErrors::new();
+            validation::validate_block_name("name", &block.name, &mut errors);
+            errors
+                .into_result(|| ())
+                .map_err(|errors| ManifestError::Invalid {
+                    path: path.clone(),
+                    errors,
+                })?;
+            if !names.insert(&block.name) {
+                return Err(ManifestError::DuplicateBlock(path));
+            }
+
+            validate_blocks(&path, &block.blocks)?;
+        }
+
+        Ok(())
+    }
+
+    let mut domains = HashSet::new();
+    for manifest in manifests {
+        manifest.domain = manifest.domain.to_lowercase();
+        let mut errors = ValidationErrors::new();
+        validation::validate_domain_name("domain", &manifest.domain, &mut errors);
+        errors
+            .into_result(|| ())
+            .map_err(|errors| ManifestError::Invalid {
+                path: manifest.domain.clone(),
+                errors,
+            })?;
+        if !domains.insert(manifest.domain.clone()) {
+            return Err(ManifestError::DuplicateDomain(manifest.domain.clone()));
+        }
+
+        validate_blocks(&manifest.domain, &manifest.blocks)?;
+    }
+
+    Ok(())
+}
+
+/// The stored blocks of a domain, grouped by parent block, the roots
+/// being grouped under `None`.
+type Children<'a> = HashMap<Option<Uuid>, Vec<&'a Block>>;
+
+/// Plan the creation of blocks missing from the database, along with
+/// all their descendants.
+fn create(changes: &mut Vec<Change>, path: &str, parent: Parent, blocks: &[ManifestBlock]) {
+    for block in blocks {
+        let id = Uuid::now_v7();
+        let path = format!("{path}/{}", block.name);
+        changes.push(Change {
+            action: Action::Create,
+            resource: Resource::Block,
+            path: path.clone(),
+            id,
+            parent: Some(parent.clone()),
+        });
+
+        create(changes, &path, Parent::Block(id), &block.blocks);
+    }
+}
+
+/// Plan the reconciliation of the stored children of a parent with the
+/// blocks a manifest describes for it.
+fn reconcile_children(
+    changes: &mut Vec<Change>,
+    children: &Children<'_>,
+    path: &str,
+    parent: Parent,
+    blocks: &[ManifestBlock],
+    prune: bool,
+) -> Result<(), ManifestError> {
+    let key = match parent {
+        Parent::Domain(_) => None,
+        Parent::Block(uuid) => Some(uuid),
+    };
+    let stored = children.get(&key).map(Vec::as_slice).unwrap_or_default();
+
+    for block in blocks {
+        let mut matches = stored.iter().filter(|stored| stored.name == block.name);
+
+        match (matches.next(), matches.next()) {
+            (None, _) => create(changes, path, parent.clone(), std::slice::from_ref(block)),
+            (Some(stored), None) => {
+                let path = format!("{path}/{}", block.name);
+                changes.push(Change {
+                    action: Action::Unchanged,
+                    resource: Resource::Block,
+                    path: path.clone(),
+                    id: stored.id,
+                    parent: None,
+                });
+                reconcile_children(
+                    changes,
+                    children,
+                    &path,
+                    Parent::Block(stored.id),
+                    &block.blocks,
+                    prune,
+                )?;
+            }
+            (Some(_), Some(_)) => {
+                return Err(ManifestError::Ambiguous(format!("{path}/{}", block.name)))
+            }
+        }
+    }
+
+    for stored in stored
+        .iter()
+        .filter(|stored| !blocks.iter().any(|block| block.name == stored.name))
+    {
+        changes.push(Change {
+            action: if prune {
+                Action::Delete
+            } else {
+                Action::Unmanaged
+            },
+            resource: Resource::Block,
+            path: format!("{path}/{}", stored.name),
+            id: stored.id,
+            parent: None,
+        });
+    }
+
+    Ok(())
+}
+
+/// Plan the reconciliation of the stored resources with a validated
+/// manifest, given the domain it describes and the blocks of its tree when
+/// the domain is stored. With `prune`, the stored blocks missing from the
+/// manifest are deleted.
+pub fn reconcile(
+    manifest: &Manifest,
+    stored: Option<(&Domain, &[Block])>,
+    prune: bool,
+) -> Result<Vec<Change>, ManifestError> {
+    let mut changes = Vec::new();
+    let Some((domain, blocks)) = stored else {
+        let id = Uuid::now_v7();
+        changes.push(Change {
+            action: Action::Create,
+            resource: Resource::Domain,
+            path: manifest.domain.clone(),
+            id,
+            parent: None,
+        });
+        create(
+            &mut changes,
+            &manifest.domain,
+            Parent::Domain(id),
+            &manifest.blocks,
+        );
+        return Ok(changes);
+    };
+
+    changes.push(Change {
+        action: Action::Unchanged,
+        resource: Resource::Domain,
+        path: domain.name.clone(),
+        id: domain.id,
+        parent: None,
+    });
+
+    let mut children = Children::new();
+    for block in blocks {
+        let key = match block.parent {
+            Parent::Domain(_) => None,
+            Parent::Block(uuid) => Some(uuid),
+        };
+        children.entry(key).or_default().push(block);
+    }
+
+    reconcile_children(
+        &mut changes,
+        &children,
+        &domain.name,
+        Parent::Domain(domain.id),
+        &manifest.blocks,
+        prune,
+    )?;
+
+    Ok(changes)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Delete,
+    Unchanged,
+    /// A stored block missing from the manifests, left untouched as
+    /// pruning is disabled.
+    Unmanaged,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Create => "create",
+            Self::Delete => "delete",
+            Self::Unchanged => "unchanged",
+            Self::Unmanaged => "unmanaged",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resource {
+    Domain,
+    Block,
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Domain => "domain",
+            Self::Block => "block",
+        })
+    }
+}
+
+/// What the reconciliation does to a resource, identified by the names
+/// leading to it, e.g. `billing/invoices`.
+///
+/// The descendants of a deleted or unmanaged block are not listed, they
+/// share the fate of their ancestor.
+#[derive(Clone, Debug, Serialize)]
+pub struct Change {
+    pub action: Action,
+    pub resource: Resource,
+    pub path: String,
+    pub id: Uuid,
+    /// The parent of a created block.
+    #[serde(skip)]
+    pub parent: Option<Parent>,
+}
+
+impl Change {
+    /// The name of the resource, the last segment of its path.
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+}
+
+/// The changes reconciling the stored resources with manifests, the
+/// parents being listed before their children.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+}
+
+impl Plan {
+    pub fn count(&self, action: Action) -> usize {
+        self.changes
+            .iter()
+            .filter(|change| change.action == action)
+            .count()
+    }
+
+    /// Whether applying the plan writes anything.
+    pub fn has_changes(&self) -> bool {
+        self.changes
+            .iter()
+            .any(|change| matches!(change.action, Action::Create | Action::Delete))
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} to create, {} to delete, {} unchanged, {} unmanaged",
+            self.count(Action::Create),
+            self.count(Action::Delete),
+            self.count(Action::Unchanged),
+            self.count(Action::Unmanaged),
+        )
+    }
+}
@@ -0,0 +1,11 @@
+use sqlx::migrate::Migrator;
+
+/// The migrations of the database schema, embedded at compile time from
+/// the `migrations` directory of this crate.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Returns the version of the latest migration known by this build,
+/// which is the schema version expected to be found in the database.
+pub fn expected_version() -> Option<i64> {
+    MIGRATOR.iter().map(|migration| migration.version).max()
+}
@@ -1,10 +1,14 @@
+use crate::validation::{self, ValidationError, ValidationErrors};
 use chrono::{DateTime, Utc};
-use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde::{
+    de::{Deserialize, Deserializer},
+    ser::{Serialize, SerializeStruct, Serializer},
+};
 use sqlx::{postgres::PgRow, FromRow, Row};
 use std::fmt;
 use uuid::Uuid;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Parent {
     Block(Uuid),
     Domain(Uuid),
@@ -29,7 +33,23 @@ impl Serialize for Parent {
     }
 }
 
-#[derive(Clone, Debug)]
+impl<'de> Deserialize<'de> for Parent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(tag = "type", rename_all = "lowercase")]
+        enum Tagged {
+            Block { block_uuid: Uuid },
+            Domain { domain_uuid: Uuid },
+        }
+
+        Ok(match Tagged::deserialize(deserializer)? {
+            Tagged::Block { block_uuid } => Self::Block(block_uuid),
+            Tagged::Domain { domain_uuid } => Self::Domain(domain_uuid),
+        })
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
 pub struct Block {
     pub id: Uuid,
     pub parent: Parent,
@@ -94,6 +114,47 @@ impl fmt::Display for Block {
     }
 }
 
+/// A page of the direct children of a parent, ordered by name then by
+/// identifier, made of at most `limit` children coming after the child
+/// whose name and identifier are `after`, if any.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChildrenPage {
+    pub parent: Parent,
+    pub after: Option<(String, Uuid)>,
+    pub limit: i64,
+}
+
+/// The outcome of moving a block under another parent.
+#[derive(Clone, Debug)]
+pub enum Move {
+    /// The block has been moved.
+    Moved(Block),
+    /// The block to move doesn't exist.
+    NotFound,
+    /// The new parent of the block doesn't exist.
+    ParentNotFound,
+    /// The new parent is the block itself or one of its descendants, so
+    /// the tree would be detached from its domain.
+    Cycle,
+}
+
+/// A block of a tree walked depth-first, along with its depth: the root
+/// of the walk is at the depth 0, its children at the depth 1, etc.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct TreeEntry {
+    pub depth: i32,
+    pub block: Block,
+}
+
+impl FromRow<'_, PgRow> for TreeEntry {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            depth: row.try_get("depth")?,
+            block: Block::from_row(row)?,
+        })
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct BlockBuilder {
     parent: Option<Parent>,
@@ -116,18 +177,30 @@ impl BlockBuilder {
         self
     }
 
-    pub fn finalize(self) -> Result<Block, ()> {
-        // TODO(rigma): add an error type to indicate what is missing
-        let name = self.name.ok_or(())?;
-        let parent = self.parent.ok_or(())?;
-        let now = Utc::now();
+    /// Build the block, or list every field that is missing or invalid.
+    pub fn finalize(self) -> Result<Block, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        match &self.name {
+            Some(name) => validation::validate_block_name("name", name, &mut errors),
+            None => errors.push("name", ValidationError::Missing),
+        }
+        if self.parent.is_none() {
+            errors.push("parent", ValidationError::Missing);
+        }
 
-        Ok(Block {
-            id: Uuid::now_v7(),
-            parent,
-            name,
-            created_at: now,
-            updated_at: now,
+        let (Some(name), Some(parent)) = (self.name, self.parent) else {
+            return Err(errors);
+        };
+        errors.into_result(|| {
+            let now = Utc::now();
+
+            Block {
+                id: Uuid::now_v7(),
+                parent,
+                name,
+                created_at: now,
+                updated_at: now,
+            }
         })
     }
 }
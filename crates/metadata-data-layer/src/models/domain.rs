@@ -1,8 +1,9 @@
+use crate::validation::{self, ValidationErrors};
 use chrono::{DateTime, Utc};
 use std::fmt;
 use uuid::Uuid;
 
-#[derive(Clone, Debug, serde::Serialize, sqlx::FromRow)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
 pub struct Domain {
     pub id: Uuid,
     pub name: String,
@@ -11,15 +12,23 @@ pub struct Domain {
 }
 
 impl Domain {
-    pub fn new(name: impl ToString) -> Self {
-        let now = Utc::now();
+    /// Create a domain, whose name is normalized to lowercase, or list
+    /// the reasons why the name is invalid.
+    pub fn new(name: impl ToString) -> Result<Self, ValidationErrors> {
+        let name = name.to_string().to_lowercase();
+        let mut errors = ValidationErrors::new();
+        validation::validate_domain_name("name", &name, &mut errors);
 
-        Self {
-            id: Uuid::now_v7(),
-            name: name.to_string().to_lowercase(),
-            created_at: now,
-            updated_at: now,
-        }
+        errors.into_result(|| {
+            let now = Utc::now();
+
+            Self {
+                id: Uuid::now_v7(),
+                name,
+                created_at: now,
+                updated_at: now,
+            }
+        })
     }
 }
 
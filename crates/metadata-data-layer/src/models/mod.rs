@@ -1,5 +1,5 @@
 mod block;
 mod domain;
 
-pub use block::Block;
+pub use block::{Block, BlockBuilder, ChildrenPage, Move, Parent, TreeEntry};
 pub use domain::Domain;
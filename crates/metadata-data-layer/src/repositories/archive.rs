@@ -0,0 +1,345 @@
+use crate::{
+    archive::{Action, Archive, ArchiveError, Change, ImportMode, ImportPlan, Previous},
+    models::Parent,
+    repositories::{
+        block::domain_blocks,
+        domain::{find_domain, find_domain_by_name},
+        BlockRepository, DomainRepository,
+    },
+};
+use chrono::Utc;
+use metadata_data_layer_utils::{metrics, trace, Repository, Session};
+use sqlx::{postgres::PgConnection, Connection};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct ArchiveRepository {
+    pool: Session,
+}
+
+impl ArchiveRepository {
+    /// Archive a domain with its whole tree of blocks, or return `None`
+    /// if it doesn't exist.
+    pub async fn export_domain(&self, domain_name: &str) -> Result<Option<Archive>, sqlx::Error> {
+        let domains = DomainRepository::from_ref(self.pool.clone());
+        let blocks = BlockRepository::from_ref(self.pool.clone());
+
+        let Some(domain) = domains.get_domain_by_name(domain_name).await? else {
+            return Ok(None);
+        };
+        let blocks = blocks.list_domain_blocks(&domain.id).await?;
+
+        Ok(Some(Archive::new(&domain, &blocks)))
+    }
+
+    /// Compare an archive with the stored resources, and list what
+    /// importing it would change. The resources are read from the primary,
+    /// the replicas possibly lagging behind the changes to come.
+    pub async fn plan_import(
+        &self,
+        archive: &Archive,
+        mode: ImportMode,
+    ) -> Result<ImportPlan, ArchiveError> {
+        self.pool.pin();
+        let mut connection = self.pool.read().await?;
+
+        plan(&mut connection, archive, mode).await
+    }
+
+    /// Import an archive, returning the plan of the changes applied. The
+    /// plan is made and applied all at once within a serializable
+    /// transaction, so the changes made meanwhile can't be overwritten:
+    /// the import fails with a serialization failure instead, and can be
+    /// retried.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn import(
+        &self,
+        archive: &Archive,
+        mode: ImportMode,
+    ) -> Result<ImportPlan, ArchiveError> {
+        let mut connection = self.pool.write().await?;
+        let mut transaction = connection.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+            .execute(&mut *transaction)
+            .await?;
+
+        let plan = plan(&mut transaction, archive, mode).await?;
+        apply(&mut transaction, &plan).await?;
+        transaction.commit().await?;
+
+        Ok(plan)
+    }
+}
+
+impl Repository for ArchiveRepository {
+    fn from_ref(pool: Session) -> Self {
+        Self { pool }
+    }
+}
+
+/// Returns the identifiers, among the given ones, of the stored blocks.
+async fn existing_block_ids(
+    connection: &mut PgConnection,
+    block_ids: &[Uuid],
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    metrics::observe(
+        "archives",
+        "existing_block_ids",
+        sqlx::query_scalar(trace::statement(
+            r#"
+            SELECT blocks.id
+            FROM blocks
+            WHERE blocks.id = ANY($1)
+            "#,
+        ))
+        .bind(block_ids)
+        .fetch_all(connection),
+    )
+    .await
+}
+
+/// Compare an archive with the resources stored in the database of a
+/// connection, and list what importing it would change.
+async fn plan(
+    connection: &mut PgConnection,
+    archive: &Archive,
+    mode: ImportMode,
+) -> Result<ImportPlan, ArchiveError> {
+    let now = Utc::now();
+
+    if mode == ImportMode::NewIds {
+        if find_domain_by_name(&mut *connection, &archive.domain.name)
+            .await?
+            .is_some()
+        {
+            return Err(ArchiveError::Conflict(format!(
+                "the domain '{}' already exists",
+                archive.domain.name
+            )));
+        }
+
+        let ids = archive
+            .blocks
+            .iter()
+            .map(|block| (block.id, Uuid::now_v7()))
+            .collect::<HashMap<_, _>>();
+        let create = |id, name: &str, parent| Change {
+            action: Action::Create,
+            id,
+            name: name.to_owned(),
+            parent,
+            previous: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        return Ok(ImportPlan {
+            mode,
+            domain: create(Uuid::now_v7(), &archive.domain.name, None),
+            blocks: archive
+                .blocks
+                .iter()
+                .map(|block| {
+                    create(
+                        ids[&block.id],
+                        &block.name,
+                        block.parent.map(|parent| ids[&parent]),
+                    )
+                })
+                .collect(),
+        });
+    }
+
+    let domain = &archive.domain;
+    let stored = find_domain(&mut *connection, &domain.id).await?;
+    if let Some(other) = find_domain_by_name(&mut *connection, &domain.name)
+        .await?
+        .filter(|other| other.id != domain.id)
+    {
+        return Err(ArchiveError::Conflict(format!(
+            "the domain '{}' already exists with the ID '{}'",
+            other.name, other.id
+        )));
+    }
+
+    // The blocks of the domain are updated in place, but the blocks
+    // of other domains are never taken over.
+    let tree = match &stored {
+        Some(stored) => domain_blocks(&mut *connection, &stored.id).await?,
+        None => Vec::new(),
+    };
+    let tree = tree
+        .into_iter()
+        .map(|block| (block.id, block))
+        .collect::<HashMap<_, _>>();
+    let ids = archive
+        .blocks
+        .iter()
+        .map(|block| block.id)
+        .collect::<Vec<_>>();
+    if let Some(id) = existing_block_ids(&mut *connection, &ids)
+        .await?
+        .into_iter()
+        .find(|id| !tree.contains_key(id))
+    {
+        return Err(ArchiveError::Conflict(format!(
+            "the block '{id}' already exists in another domain"
+        )));
+    }
+
+    let domain = Change {
+        action: match &stored {
+            None => Action::Create,
+            Some(stored) if stored.name == domain.name => Action::Unchanged,
+            Some(_) => Action::Update,
+        },
+        id: domain.id,
+        name: domain.name.clone(),
+        parent: None,
+        previous: stored
+            .filter(|stored| stored.name != domain.name)
+            .map(|stored| Previous {
+                name: stored.name,
+                parent: None,
+            }),
+        created_at: domain.created_at,
+        updated_at: domain.updated_at,
+    };
+    let blocks = archive
+        .blocks
+        .iter()
+        .map(|block| {
+            let previous = tree.get(&block.id).map(|stored| Previous {
+                name: stored.name.clone(),
+                parent: match stored.parent {
+                    Parent::Domain(_) => None,
+                    Parent::Block(uuid) => Some(uuid),
+                },
+            });
+            let action = match &previous {
+                None => Action::Create,
+                Some(previous)
+                    if previous.name == block.name && previous.parent == block.parent =>
+                {
+                    Action::Unchanged
+                }
+                Some(_) => Action::Update,
+            };
+
+            Change {
+                action,
+                id: block.id,
+                name: block.name.clone(),
+                parent: block.parent,
+                previous: previous.filter(|_| action == Action::Update),
+                created_at: block.created_at,
+                updated_at: block.updated_at,
+            }
+        })
+        .collect();
+
+    Ok(ImportPlan {
+        mode,
+        domain,
+        blocks,
+    })
+}
+
+/// Apply the changes of an import plan through a connection, within
+/// the transaction the plan was made in.
+async fn apply(connection: &mut PgConnection, plan: &ImportPlan) -> Result<(), sqlx::Error> {
+    let domain = &plan.domain;
+    match domain.action {
+        Action::Create => {
+            metrics::observe(
+                "archives",
+                "insert_domain",
+                sqlx::query(trace::statement(
+                    r#"
+                    INSERT INTO domains (id, name, created_at, updated_at)
+                    VALUES ($1, $2, $3, $4)
+                    "#,
+                ))
+                .bind(domain.id)
+                .bind(&domain.name)
+                .bind(domain.created_at)
+                .bind(domain.updated_at)
+                .execute(&mut *connection),
+            )
+            .await?;
+        }
+        Action::Update => {
+            metrics::observe(
+                "archives",
+                "update_domain",
+                sqlx::query(trace::statement(
+                    r#"
+                    UPDATE domains
+                    SET name = $2, updated_at = now()
+                    WHERE domains.id = $1
+                    "#,
+                ))
+                .bind(domain.id)
+                .bind(&domain.name)
+                .execute(&mut *connection),
+            )
+            .await?;
+        }
+        Action::Unchanged => {}
+    }
+
+    // The blocks are listed parents first, so a parent always exists
+    // by the time its children are written.
+    for block in &plan.blocks {
+        let (domain_id, parent_id) = match block.parent {
+            Some(parent) => (None, Some(parent)),
+            None => (Some(domain.id), None),
+        };
+
+        match block.action {
+            Action::Create => {
+                metrics::observe(
+                    "archives",
+                    "insert_block",
+                    sqlx::query(trace::statement(
+                        r#"
+                        INSERT INTO blocks (id, domain_id, block_id, name, created_at, updated_at)
+                        VALUES ($1, $2, $3, $4, $5, $6)
+                        "#,
+                    ))
+                    .bind(block.id)
+                    .bind(domain_id)
+                    .bind(parent_id)
+                    .bind(&block.name)
+                    .bind(block.created_at)
+                    .bind(block.updated_at)
+                    .execute(&mut *connection),
+                )
+                .await?;
+            }
+            Action::Update => {
+                metrics::observe(
+                    "archives",
+                    "update_block",
+                    sqlx::query(trace::statement(
+                        r#"
+                        UPDATE blocks
+                        SET domain_id = $2, block_id = $3, name = $4, updated_at = now()
+                        WHERE blocks.id = $1
+                        "#,
+                    ))
+                    .bind(block.id)
+                    .bind(domain_id)
+                    .bind(parent_id)
+                    .bind(&block.name)
+                    .execute(&mut *connection),
+                )
+                .await?;
+            }
+            Action::Unchanged => {}
+        }
+    }
+
+    Ok(())
+}
@@ -1,60 +1,614 @@
-use crate::models::Block;
-use metadata_data_layer_utils::Repository;
-use sqlx::{postgres::Postgres, Pool};
-use std::sync::Arc;
+use crate::models::{Block, ChildrenPage, Move, Parent, TreeEntry};
+use futures_core::stream::BoxStream;
+use metadata_data_layer_utils::{metrics, trace, Repository, Session};
+use sqlx::{postgres::PgConnection, Connection, FromRow, Row};
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub struct BlockRepository {
-    pool: Arc<Pool<Postgres>>,
+    pool: Session,
 }
 
 impl BlockRepository {
-    #[tracing::instrument]
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
     pub async fn get_block(&self, block_id: &Uuid) -> Result<Option<Block>, sqlx::Error> {
-        sqlx::query_as::<_, Block>(
+        let mut connection = self.pool.read().await?;
+
+        metrics::observe(
+            "blocks",
+            "get_block",
+            sqlx::query_as::<_, Block>(trace::statement(
+                r#"
+                SELECT
+                    blocks.id,
+                    blocks.domain_id,
+                    blocks.block_id,
+                    blocks.name,
+                    blocks.created_at,
+                    blocks.updated_at
+                FROM blocks
+                WHERE blocks.id = $1
+                "#,
+            ))
+            .bind(block_id)
+            .fetch_optional(&mut *connection),
+        )
+        .await
+    }
+
+    /// Returns the block with the given name in the tree of a domain. The
+    /// names are only unique among siblings, so the block nearest to the
+    /// root of the domain is returned, the ones at the same depth being
+    /// ordered by identifier.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn get_block_by_name(
+        &self,
+        domain_id: &Uuid,
+        block_name: &str,
+    ) -> Result<Option<Block>, sqlx::Error> {
+        let mut connection = self.pool.read().await?;
+
+        // The tree is walked up from the blocks with the name, rather than
+        // down from the domain, to find the domain of each of them.
+        metrics::observe(
+            "blocks",
+            "get_block_by_name",
+            sqlx::query_as(trace::statement(
+                r#"
+                WITH RECURSIVE ancestors AS (
+                    SELECT
+                        blocks.id AS origin,
+                        blocks.domain_id,
+                        blocks.block_id,
+                        0 AS depth,
+                        ARRAY[blocks.id] AS visited
+                    FROM blocks
+                    WHERE blocks.name = $2
+                    UNION ALL
+                    SELECT
+                        ancestors.origin,
+                        blocks.domain_id,
+                        blocks.block_id,
+                        ancestors.depth + 1,
+                        ancestors.visited || blocks.id
+                    FROM blocks
+                    JOIN ancestors ON blocks.id = ancestors.block_id
+                    WHERE NOT blocks.id = ANY(ancestors.visited)
+                )
+                SELECT
+                    blocks.id,
+                    blocks.domain_id,
+                    blocks.block_id,
+                    blocks.name,
+                    blocks.created_at,
+                    blocks.updated_at
+                FROM ancestors
+                JOIN blocks ON blocks.id = ancestors.origin
+                WHERE ancestors.domain_id = $1
+                ORDER BY ancestors.depth, blocks.id
+                LIMIT 1
+                "#,
+            ))
+            .bind(domain_id)
+            .bind(block_name)
+            .fetch_optional(&mut *connection),
+        )
+        .await
+    }
+
+    /// Returns the blocks with the given identifiers, in no particular
+    /// order, the missing ones being left out.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn get_blocks(&self, block_ids: &[Uuid]) -> Result<Vec<Block>, sqlx::Error> {
+        let mut connection = self.pool.read().await?;
+
+        metrics::observe(
+            "blocks",
+            "get_blocks",
+            sqlx::query_as::<_, Block>(trace::statement(
+                r#"
+                SELECT
+                    blocks.id,
+                    blocks.domain_id,
+                    blocks.block_id,
+                    blocks.name,
+                    blocks.created_at,
+                    blocks.updated_at
+                FROM blocks
+                WHERE blocks.id = ANY($1)
+                "#,
+            ))
+            .bind(block_ids)
+            .fetch_all(&mut *connection),
+        )
+        .await
+    }
+
+    /// Returns the direct children of several blocks or domains at once,
+    /// the siblings being ordered by name.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn list_children(&self, parents: &[Parent]) -> Result<Vec<Block>, sqlx::Error> {
+        let mut connection = self.pool.read().await?;
+        let (mut domain_ids, mut block_ids) = (Vec::new(), Vec::new());
+        for parent in parents {
+            match parent {
+                Parent::Domain(uuid) => domain_ids.push(*uuid),
+                Parent::Block(uuid) => block_ids.push(*uuid),
+            }
+        }
+
+        metrics::observe(
+            "blocks",
+            "list_children",
+            sqlx::query_as::<_, Block>(trace::statement(
+                r#"
+                SELECT
+                    blocks.id,
+                    blocks.domain_id,
+                    blocks.block_id,
+                    blocks.name,
+                    blocks.created_at,
+                    blocks.updated_at
+                FROM blocks
+                WHERE blocks.domain_id = ANY($1) OR blocks.block_id = ANY($2)
+                ORDER BY blocks.name, blocks.id
+                "#,
+            ))
+            .bind(domain_ids)
+            .bind(block_ids)
+            .fetch_all(&mut *connection),
+        )
+        .await
+    }
+
+    /// Returns several pages of children at once, as pairs of the index of
+    /// a page in `pages` and of one of its children. Each page is read
+    /// from the index of its parent, so the siblings beyond its limit are
+    /// never loaded.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn list_children_pages(
+        &self,
+        pages: &[ChildrenPage],
+    ) -> Result<Vec<(usize, Block)>, sqlx::Error> {
+        let mut connection = self.pool.read().await?;
+
+        children_pages(&mut connection, "list_children_pages", pages).await
+    }
+
+    /// Returns the ancestors of several blocks at once, as pairs of the
+    /// identifier of a block and of one of its ancestors, the nearest
+    /// ancestors coming first.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn list_ancestors(
+        &self,
+        block_ids: &[Uuid],
+    ) -> Result<Vec<(Uuid, Block)>, sqlx::Error> {
+        let mut connection = self.pool.read().await?;
+
+        let rows = metrics::observe(
+            "blocks",
+            "list_ancestors",
+            sqlx::query(trace::statement(
+                r#"
+                WITH RECURSIVE ancestors AS (
+                    SELECT
+                        blocks.id AS origin,
+                        blocks.block_id AS id,
+                        1 AS depth,
+                        ARRAY[blocks.id, blocks.block_id] AS visited
+                    FROM blocks
+                    WHERE blocks.id = ANY($1) AND blocks.block_id IS NOT NULL
+                    UNION ALL
+                    SELECT
+                        ancestors.origin,
+                        blocks.block_id,
+                        ancestors.depth + 1,
+                        ancestors.visited || blocks.block_id
+                    FROM blocks
+                    JOIN ancestors ON blocks.id = ancestors.id
+                    WHERE blocks.block_id IS NOT NULL
+                        AND NOT blocks.block_id = ANY(ancestors.visited)
+                )
+                SELECT
+                    ancestors.origin,
+                    blocks.id,
+                    blocks.domain_id,
+                    blocks.block_id,
+                    blocks.name,
+                    blocks.created_at,
+                    blocks.updated_at
+                FROM ancestors
+                JOIN blocks ON blocks.id = ancestors.id
+                ORDER BY ancestors.origin, ancestors.depth
+                "#,
+            ))
+            .bind(block_ids)
+            .fetch_all(&mut *connection),
+        )
+        .await?;
+
+        rows.iter()
+            .map(|row| Ok((row.try_get("origin")?, Block::from_row(row)?)))
+            .collect()
+    }
+
+    /// Returns the identifier of the domain at the root of the tree of a
+    /// block, or `None` if the block doesn't exist.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn get_root_domain_id(&self, block_id: &Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+        let mut connection = self.pool.read().await?;
+
+        metrics::observe(
+            "blocks",
+            "get_root_domain_id",
+            sqlx::query_scalar(trace::statement(
+                r#"
+                WITH RECURSIVE ancestors AS (
+                    SELECT blocks.domain_id, blocks.block_id, ARRAY[blocks.id] AS visited
+                    FROM blocks
+                    WHERE blocks.id = $1
+                    UNION ALL
+                    SELECT blocks.domain_id, blocks.block_id, ancestors.visited || blocks.id
+                    FROM blocks
+                    JOIN ancestors ON blocks.id = ancestors.block_id
+                    WHERE NOT blocks.id = ANY(ancestors.visited)
+                )
+                SELECT ancestors.domain_id
+                FROM ancestors
+                WHERE ancestors.domain_id IS NOT NULL
+                "#,
+            ))
+            .bind(block_id)
+            .fetch_optional(&mut *connection),
+        )
+        .await
+    }
+
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn insert_block(&self, block: &Block) -> Result<(), sqlx::Error> {
+        let mut connection = self.pool.write().await?;
+        let (domain_id, block_id) = match block.parent {
+            Parent::Domain(uuid) => (Some(uuid), None),
+            Parent::Block(uuid) => (None, Some(uuid)),
+        };
+
+        metrics::observe(
+            "blocks",
+            "insert_block",
+            sqlx::query(trace::statement(
+                r#"
+                INSERT INTO blocks (id, domain_id, block_id, name, created_at, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                "#,
+            ))
+            .bind(block.id)
+            .bind(domain_id)
+            .bind(block_id)
+            .bind(&block.name)
+            .bind(block.created_at)
+            .bind(block.updated_at)
+            .execute(&mut *connection),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Returns every block of the tree of a domain, the parents always
+    /// coming before their children.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn list_domain_blocks(&self, domain_id: &Uuid) -> Result<Vec<Block>, sqlx::Error> {
+        domain_blocks(&mut *self.pool.read().await?, domain_id).await
+    }
+
+    /// Streams the blocks of the tree of a domain, or of the subtree of
+    /// one of its blocks, walked depth-first: every block comes right
+    /// before its descendants, and the siblings are ordered by name.
+    ///
+    /// The rows of a single query are read from a cursor as the stream
+    /// is polled, so the tree comes from one snapshot and is never held
+    /// in memory by the service whatever its size, the database sorting
+    /// it on disk when it's too large. The stream holds a connection of
+    /// the pool until it's exhausted or dropped.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub fn stream_tree(
+        &self,
+        domain_id: &Uuid,
+        root_id: Option<&Uuid>,
+    ) -> BoxStream<'_, Result<TreeEntry, sqlx::Error>> {
+        // The path of a block lists the names and the identifiers of its
+        // ancestors and its own, so sorting the paths sorts the siblings
+        // by name and puts the parents before their children.
+        sqlx::query_as::<_, TreeEntry>(trace::statement(
             r#"
+            WITH RECURSIVE tree AS (
+                SELECT
+                    blocks.*,
+                    0 AS depth,
+                    ARRAY[blocks.name::text, blocks.id::text] AS path,
+                    ARRAY[blocks.id] AS visited
+                FROM blocks
+                WHERE CASE
+                    WHEN $2::uuid IS NULL THEN blocks.domain_id = $1
+                    ELSE blocks.id = $2
+                END
+                UNION ALL
+                SELECT
+                    blocks.*,
+                    tree.depth + 1,
+                    tree.path || ARRAY[blocks.name::text, blocks.id::text],
+                    tree.visited || blocks.id
+                FROM blocks
+                JOIN tree ON blocks.block_id = tree.id
+                WHERE NOT blocks.id = ANY(tree.visited)
+            )
             SELECT
-                blocks.id,
-                blocks.domain_id,
-                blocks.block_id,
-                blocks.name,
-                blocks.created_at,
-                blocks.updated_at
-            FROM blocks
-            WHERE blocks.id = $1
+                tree.id,
+                tree.domain_id,
+                tree.block_id,
+                tree.name,
+                tree.created_at,
+                tree.updated_at,
+                tree.depth
+            FROM tree
+            ORDER BY tree.path
             "#,
+        ))
+        .bind(*domain_id)
+        .bind(root_id.copied())
+        .fetch(self.pool.reader())
+    }
+
+    /// Returns the number of direct children of a block or of a domain.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn count_children(&self, parent: &Parent) -> Result<i64, sqlx::Error> {
+        let mut connection = self.pool.read().await?;
+        let (domain_id, block_id) = match parent {
+            Parent::Domain(uuid) => (Some(uuid), None),
+            Parent::Block(uuid) => (None, Some(uuid)),
+        };
+
+        metrics::observe(
+            "blocks",
+            "count_children",
+            sqlx::query_scalar(trace::statement(
+                r#"
+                SELECT count(*)
+                FROM blocks
+                WHERE blocks.domain_id = $1 OR blocks.block_id = $2
+                "#,
+            ))
+            .bind(domain_id)
+            .bind(block_id)
+            .fetch_one(&mut *connection),
+        )
+        .await
+    }
+
+    /// Move a block under another parent, unless the block or the parent
+    /// doesn't exist, or the parent is the block itself or one of its
+    /// descendants.
+    ///
+    /// The parent is checked and the block moved by a single statement of
+    /// a serializable transaction, so concurrent moves can't build a cycle
+    /// together: one of them fails with a serialization failure instead.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn move_block(&self, block_id: &Uuid, parent: &Parent) -> Result<Move, sqlx::Error> {
+        let mut connection = self.pool.write().await?;
+        let mut transaction = connection.begin().await?;
+        let (domain_id, parent_id) = match parent {
+            Parent::Domain(uuid) => (Some(uuid), None),
+            Parent::Block(uuid) => (None, Some(uuid)),
+        };
+
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+            .execute(&mut *transaction)
+            .await?;
+        let row = metrics::observe(
+            "blocks",
+            "move_block",
+            sqlx::query(trace::statement(
+                r#"
+                WITH RECURSIVE ancestors AS (
+                    SELECT blocks.id, blocks.block_id, ARRAY[blocks.id] AS visited
+                    FROM blocks
+                    WHERE blocks.id = $3
+                    UNION ALL
+                    SELECT blocks.id, blocks.block_id, ancestors.visited || blocks.id
+                    FROM blocks
+                    JOIN ancestors ON blocks.id = ancestors.block_id
+                    WHERE NOT blocks.id = ANY(ancestors.visited)
+                ),
+                checks AS (
+                    SELECT
+                        EXISTS (SELECT FROM blocks WHERE blocks.id = $1) AS found,
+                        CASE
+                            WHEN $3::uuid IS NULL
+                                THEN EXISTS (SELECT FROM domains WHERE domains.id = $2)
+                            ELSE EXISTS (SELECT FROM ancestors)
+                        END AS parent_found,
+                        EXISTS (SELECT FROM ancestors WHERE ancestors.id = $1) AS cycle
+                ),
+                moved AS (
+                    UPDATE blocks
+                    SET domain_id = $2, block_id = $3, updated_at = now()
+                    FROM checks
+                    WHERE blocks.id = $1 AND checks.parent_found AND NOT checks.cycle
+                    RETURNING
+                        blocks.id,
+                        blocks.domain_id,
+                        blocks.block_id,
+                        blocks.name,
+                        blocks.created_at,
+                        blocks.updated_at
+                )
+                SELECT checks.found, checks.parent_found, checks.cycle, moved.*
+                FROM checks
+                LEFT JOIN moved ON TRUE
+                "#,
+            ))
+            .bind(block_id)
+            .bind(domain_id)
+            .bind(parent_id)
+            .fetch_one(&mut *transaction),
+        )
+        .await?;
+        transaction.commit().await?;
+
+        if !row.try_get::<bool, _>("found")? {
+            Ok(Move::NotFound)
+        } else if !row.try_get::<bool, _>("parent_found")? {
+            Ok(Move::ParentNotFound)
+        } else if row.try_get::<bool, _>("cycle")? {
+            Ok(Move::Cycle)
+        } else {
+            Ok(Move::Moved(Block::from_row(&row)?))
+        }
+    }
+
+    /// Delete a block along with all its descendants, returning whether
+    /// the block existed.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn delete_block(&self, block_id: &Uuid) -> Result<bool, sqlx::Error> {
+        let mut connection = self.pool.write().await?;
+
+        metrics::observe(
+            "blocks",
+            "delete_block",
+            sqlx::query(trace::statement(
+                r#"
+                DELETE FROM blocks
+                WHERE blocks.id = $1
+                "#,
+            ))
+            .bind(block_id)
+            .execute(&mut *connection),
         )
-        .bind(block_id)
-        .fetch_optional(self.pool.as_ref())
         .await
+        .map(|result| result.rows_affected() > 0)
     }
+}
 
-    #[tracing::instrument]
-    pub async fn get_block_by_name(&self, block_name: &str) -> Result<Option<Block>, sqlx::Error> {
-        sqlx::query_as(
+/// Read every block of the tree of a domain through a connection, e.g.
+/// within a transaction, the parents always coming before their
+/// children.
+pub(crate) async fn domain_blocks(
+    connection: &mut PgConnection,
+    domain_id: &Uuid,
+) -> Result<Vec<Block>, sqlx::Error> {
+    metrics::observe(
+        "blocks",
+        "list_domain_blocks",
+        sqlx::query_as::<_, Block>(trace::statement(
             r#"
+            WITH RECURSIVE tree AS (
+                SELECT blocks.*, 0 AS depth, ARRAY[blocks.id] AS visited
+                FROM blocks
+                WHERE blocks.domain_id = $1
+                UNION ALL
+                SELECT blocks.*, tree.depth + 1, tree.visited || blocks.id
+                FROM blocks
+                JOIN tree ON blocks.block_id = tree.id
+                WHERE NOT blocks.id = ANY(tree.visited)
+            )
             SELECT
-                blocks.id,
-                blocks.domain_id,
-                blocks.block_id,
-                blocks.name,
-                blocks.created_at,
-                blocks.updated_at
-            FROM blocks
-            WHERE blocks.name = $1
+                tree.id,
+                tree.domain_id,
+                tree.block_id,
+                tree.name,
+                tree.created_at,
+                tree.updated_at
+            FROM tree
+            ORDER BY tree.depth, tree.name
             "#,
-        )
-        .bind(block_name)
-        .fetch_optional(self.pool.as_ref())
-        .await
+        ))
+        .bind(domain_id)
+        .fetch_all(connection),
+    )
+    .await
+}
+
+/// Read several pages of children at once, as pairs of the index of a
+/// page in `pages` and of one of its children.
+async fn children_pages(
+    connection: &mut PgConnection,
+    query: &'static str,
+    pages: &[ChildrenPage],
+) -> Result<Vec<(usize, Block)>, sqlx::Error> {
+    let (mut domain_ids, mut block_ids) = (Vec::new(), Vec::new());
+    let (mut after_names, mut after_ids, mut limits) = (Vec::new(), Vec::new(), Vec::new());
+    for page in pages {
+        let (domain_id, block_id) = match page.parent {
+            Parent::Domain(uuid) => (Some(uuid), None),
+            Parent::Block(uuid) => (None, Some(uuid)),
+        };
+        domain_ids.push(domain_id);
+        block_ids.push(block_id);
+        after_names.push(page.after.as_ref().map(|(name, _)| name.clone()));
+        after_ids.push(page.after.as_ref().map(|(_, id)| *id));
+        limits.push(page.limit);
     }
+
+    // Only one of the branches of a page matches its parent, each one
+    // walking an index in the order of the page.
+    let rows = metrics::observe(
+        "blocks",
+        query,
+        sqlx::query(trace::statement(
+            r#"
+            SELECT
+                pages.ordinality - 1 AS page,
+                children.id,
+                children.domain_id,
+                children.block_id,
+                children.name,
+                children.created_at,
+                children.updated_at
+            FROM unnest($1::uuid[], $2::uuid[], $3::text[], $4::uuid[], $5::int8[])
+                WITH ORDINALITY AS pages (domain_id, block_id, after_name, after_id, size, ordinality)
+            CROSS JOIN LATERAL (
+                (
+                    SELECT blocks.*
+                    FROM blocks
+                    WHERE blocks.domain_id = pages.domain_id
+                        AND (pages.after_name IS NULL
+                            OR (blocks.name, blocks.id) > (pages.after_name, pages.after_id))
+                    ORDER BY blocks.name, blocks.id
+                    LIMIT pages.size
+                )
+                UNION ALL
+                (
+                    SELECT blocks.*
+                    FROM blocks
+                    WHERE blocks.block_id = pages.block_id
+                        AND (pages.after_name IS NULL
+                            OR (blocks.name, blocks.id) > (pages.after_name, pages.after_id))
+                    ORDER BY blocks.name, blocks.id
+                    LIMIT pages.size
+                )
+            ) AS children
+            ORDER BY pages.ordinality, children.name, children.id
+            "#,
+        ))
+        .bind(domain_ids)
+        .bind(block_ids)
+        .bind(after_names)
+        .bind(after_ids)
+        .bind(limits)
+        .fetch_all(&mut *connection),
+    )
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            let page = row.try_get::<i64, _>("page")?;
+
+            Ok((page as usize, Block::from_row(row)?))
+        })
+        .collect()
 }
 
 impl Repository for BlockRepository {
-    type DB = Postgres;
-
-    fn from_ref(pool: Arc<sqlx::Pool<Self::DB>>) -> Self {
+    fn from_ref(pool: Session) -> Self {
         Self { pool }
     }
 }
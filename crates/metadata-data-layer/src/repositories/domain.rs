@@ -1,18 +1,196 @@
 use crate::models::Domain;
-use metadata_data_layer_utils::Repository;
-use sqlx::{postgres::Postgres, Pool};
-use std::sync::Arc;
+use metadata_data_layer_utils::{metrics, trace, Repository, Session};
+use sqlx::postgres::PgConnection;
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub struct DomainRepository {
-    pool: Arc<Pool<Postgres>>,
+    pool: Session,
 }
 
 impl DomainRepository {
-    #[tracing::instrument]
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
     pub async fn get_domain(&self, domain_id: &Uuid) -> Result<Option<Domain>, sqlx::Error> {
-        sqlx::query_as::<_, Domain>(
+        find_domain(&mut *self.pool.read().await?, domain_id).await
+    }
+
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn get_domain_by_name(
+        &self,
+        domain_name: &str,
+    ) -> Result<Option<Domain>, sqlx::Error> {
+        find_domain_by_name(&mut *self.pool.read().await?, domain_name).await
+    }
+
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn insert_domain(&self, domain: &Domain) -> Result<(), sqlx::Error> {
+        let mut connection = self.pool.write().await?;
+
+        metrics::observe(
+            "domains",
+            "insert_domain",
+            sqlx::query(trace::statement(
+                r#"
+                INSERT INTO domains (id, name, created_at, updated_at)
+                VALUES ($1, $2, $3, $4)
+                "#,
+            ))
+            .bind(domain.id)
+            .bind(&domain.name)
+            .bind(domain.created_at)
+            .bind(domain.updated_at)
+            .execute(&mut *connection),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn list_domains(&self) -> Result<Vec<Domain>, sqlx::Error> {
+        let mut connection = self.pool.read().await?;
+
+        metrics::observe(
+            "domains",
+            "list_domains",
+            sqlx::query_as::<_, Domain>(trace::statement(
+                r#"
+                SELECT
+                    domains.id,
+                    domains.name,
+                    domains.created_at,
+                    domains.updated_at
+                FROM domains
+                ORDER BY domains.name
+                "#,
+            ))
+            .fetch_all(&mut *connection),
+        )
+        .await
+    }
+
+    /// Returns the domains with the given identifiers, in no particular
+    /// order, the missing ones being left out.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn get_domains(&self, domain_ids: &[Uuid]) -> Result<Vec<Domain>, sqlx::Error> {
+        let mut connection = self.pool.read().await?;
+
+        metrics::observe(
+            "domains",
+            "get_domains",
+            sqlx::query_as::<_, Domain>(trace::statement(
+                r#"
+                SELECT
+                    domains.id,
+                    domains.name,
+                    domains.created_at,
+                    domains.updated_at
+                FROM domains
+                WHERE domains.id = ANY($1)
+                "#,
+            ))
+            .bind(domain_ids)
+            .fetch_all(&mut *connection),
+        )
+        .await
+    }
+
+    /// Returns a page of at most `limit` domains, ordered by name, coming
+    /// after the domain named `after` if any.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn list_domains_page(
+        &self,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<Domain>, sqlx::Error> {
+        let mut connection = self.pool.read().await?;
+
+        metrics::observe(
+            "domains",
+            "list_domains_page",
+            sqlx::query_as::<_, Domain>(trace::statement(
+                r#"
+                SELECT
+                    domains.id,
+                    domains.name,
+                    domains.created_at,
+                    domains.updated_at
+                FROM domains
+                WHERE $1::text IS NULL OR domains.name > $1
+                ORDER BY domains.name
+                LIMIT $2
+                "#,
+            ))
+            .bind(after)
+            .bind(limit)
+            .fetch_all(&mut *connection),
+        )
+        .await
+    }
+
+    /// Rename a domain, returning the renamed domain or `None` if it
+    /// doesn't exist.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn rename_domain(
+        &self,
+        domain_id: &Uuid,
+        name: &str,
+    ) -> Result<Option<Domain>, sqlx::Error> {
+        let mut connection = self.pool.write().await?;
+
+        metrics::observe(
+            "domains",
+            "rename_domain",
+            sqlx::query_as::<_, Domain>(trace::statement(
+                r#"
+                UPDATE domains
+                SET name = $2, updated_at = now()
+                WHERE domains.id = $1
+                RETURNING
+                    domains.id,
+                    domains.name,
+                    domains.created_at,
+                    domains.updated_at
+                "#,
+            ))
+            .bind(domain_id)
+            .bind(name)
+            .fetch_optional(&mut *connection),
+        )
+        .await
+    }
+
+    /// Delete a domain along with all its blocks, returning whether the
+    /// domain existed.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn delete_domain(&self, domain_id: &Uuid) -> Result<bool, sqlx::Error> {
+        let mut connection = self.pool.write().await?;
+
+        metrics::observe(
+            "domains",
+            "delete_domain",
+            sqlx::query(trace::statement(
+                r#"
+                DELETE FROM domains
+                WHERE domains.id = $1
+                "#,
+            ))
+            .bind(domain_id)
+            .execute(&mut *connection),
+        )
+        .await
+        .map(|result| result.rows_affected() > 0)
+    }
+}
+
+/// Read a domain through a connection, e.g. within a transaction.
+pub(crate) async fn find_domain(
+    connection: &mut PgConnection,
+    domain_id: &Uuid,
+) -> Result<Option<Domain>, sqlx::Error> {
+    metrics::observe(
+        "domains",
+        "get_domain",
+        sqlx::query_as::<_, Domain>(trace::statement(
             r#"
             SELECT
                 domains.id,
@@ -22,18 +200,22 @@ impl DomainRepository {
             FROM domains
             WHERE domains.id = $1
             "#,
-        )
+        ))
         .bind(domain_id)
-        .fetch_optional(self.pool.as_ref())
-        .await
-    }
+        .fetch_optional(connection),
+    )
+    .await
+}
 
-    #[tracing::instrument]
-    pub async fn get_domain_by_name(
-        &self,
-        domain_name: &str,
-    ) -> Result<Option<Domain>, sqlx::Error> {
-        sqlx::query_as::<_, Domain>(
+/// Read a domain by name through a connection, e.g. within a transaction.
+pub(crate) async fn find_domain_by_name(
+    connection: &mut PgConnection,
+    domain_name: &str,
+) -> Result<Option<Domain>, sqlx::Error> {
+    metrics::observe(
+        "domains",
+        "get_domain_by_name",
+        sqlx::query_as::<_, Domain>(trace::statement(
             r#"
             SELECT
                 domains.id,
@@ -43,17 +225,15 @@ impl DomainRepository {
             FROM domains
             WHERE domains.name = $1
             "#,
-        )
+        ))
         .bind(domain_name)
-        .fetch_optional(self.pool.as_ref())
-        .await
-    }
+        .fetch_optional(connection),
+    )
+    .await
 }
 
 impl Repository for DomainRepository {
-    type DB = Postgres;
-
-    fn from_ref(pool: Arc<Pool<Self::DB>>) -> Self {
+    fn from_ref(pool: Session) -> Self {
         Self { pool }
     }
 }
@@ -0,0 +1,49 @@
+use metadata_data_layer_utils::{trace, Repository, Session};
+
+#[derive(Debug)]
+pub struct HealthRepository {
+    pool: Session,
+}
+
+impl HealthRepository {
+    /// Run a trivial query to check that the database is reachable.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(trace::statement("SELECT 1"))
+            .execute(self.pool.primary())
+            .await
+            .map(|_| ())
+    }
+
+    /// Returns the version of the latest migration successfully applied
+    /// to the database, if any.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn schema_version(&self) -> Result<Option<i64>, sqlx::Error> {
+        let applied = sqlx::query_scalar::<_, bool>(trace::statement(
+            r#"
+            SELECT to_regclass('_sqlx_migrations') IS NOT NULL
+            "#,
+        ))
+        .fetch_one(self.pool.primary())
+        .await?;
+        if !applied {
+            return Ok(None);
+        }
+
+        sqlx::query_scalar(trace::statement(
+            r#"
+            SELECT max(_sqlx_migrations.version)
+            FROM _sqlx_migrations
+            WHERE _sqlx_migrations.success
+            "#,
+        ))
+        .fetch_one(self.pool.primary())
+        .await
+    }
+}
+
+impl Repository for HealthRepository {
+    fn from_ref(pool: Session) -> Self {
+        Self { pool }
+    }
+}
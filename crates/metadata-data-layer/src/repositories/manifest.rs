@@ -0,0 +1,160 @@
+use crate::{
+    manifest::{self, Action, Manifest, ManifestError, Plan, Resource},
+    models::Parent,
+    repositories::{block::domain_blocks, domain::find_domain_by_name},
+};
+use metadata_data_layer_utils::{metrics, trace, Repository, Session};
+use sqlx::{postgres::PgConnection, Connection};
+
+#[derive(Debug)]
+pub struct ManifestRepository {
+    pool: Session,
+}
+
+impl ManifestRepository {
+    /// Compare manifests with the stored resources, and list the changes
+    /// reconciling them. With `prune`, the stored blocks of the described
+    /// domains missing from the manifests are deleted. The resources are
+    /// read from the primary, the replicas possibly lagging behind the
+    /// changes to come.
+    pub async fn plan(
+        &self,
+        manifests: &mut [Manifest],
+        prune: bool,
+    ) -> Result<Plan, ManifestError> {
+        manifest::validate(manifests)?;
+        self.pool.pin();
+        let mut connection = self.pool.read().await?;
+
+        plan(&mut connection, manifests, prune).await
+    }
+
+    /// Reconcile the stored resources with manifests, returning the plan
+    /// of the changes applied. The plan is made and applied all at once
+    /// within a serializable transaction, so the changes made meanwhile
+    /// can't be overwritten: the reconciliation fails with a serialization
+    /// failure instead, and can be retried.
+    #[tracing::instrument(skip_all, fields(db.system = "postgresql", db.statement))]
+    pub async fn apply(
+        &self,
+        manifests: &mut [Manifest],
+        prune: bool,
+    ) -> Result<Plan, ManifestError> {
+        manifest::validate(manifests)?;
+        let mut connection = self.pool.write().await?;
+        let mut transaction = connection.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+            .execute(&mut *transaction)
+            .await?;
+
+        let plan = plan(&mut transaction, manifests, prune).await?;
+        if plan.has_changes() {
+            apply(&mut transaction, &plan).await?;
+        }
+        transaction.commit().await?;
+
+        Ok(plan)
+    }
+}
+
+impl Repository for ManifestRepository {
+    fn from_ref(pool: Session) -> Self {
+        Self { pool }
+    }
+}
+
+/// Plan the reconciliation of validated manifests with the resources
+/// stored in the database of a connection.
+async fn plan(
+    connection: &mut PgConnection,
+    manifests: &[Manifest],
+    prune: bool,
+) -> Result<Plan, ManifestError> {
+    let mut changes = Vec::new();
+    for manifest in manifests {
+        let stored = match find_domain_by_name(&mut *connection, &manifest.domain).await? {
+            Some(domain) => {
+                let blocks = domain_blocks(&mut *connection, &domain.id).await?;
+                Some((domain, blocks))
+            }
+            None => None,
+        };
+        changes.extend(manifest::reconcile(
+            manifest,
+            stored
+                .as_ref()
+                .map(|(domain, blocks)| (domain, blocks.as_slice())),
+            prune,
+        )?);
+    }
+
+    Ok(Plan { changes })
+}
+
+/// Apply the changes of a plan through a connection, within the
+/// transaction the plan was made in.
+async fn apply(connection: &mut PgConnection, plan: &Plan) -> Result<(), sqlx::Error> {
+    // The parents are listed before their children, so they always
+    // exist by the time their children are created.
+    for change in &plan.changes {
+        match (change.action, change.resource, &change.parent) {
+            (Action::Create, Resource::Domain, _) => {
+                metrics::observe(
+                    "manifests",
+                    "insert_domain",
+                    sqlx::query(trace::statement(
+                        r#"
+                        INSERT INTO domains (id, name)
+                        VALUES ($1, $2)
+                        "#,
+                    ))
+                    .bind(change.id)
+                    .bind(change.name())
+                    .execute(&mut *connection),
+                )
+                .await?;
+            }
+            (Action::Create, Resource::Block, Some(parent)) => {
+                let (domain_id, parent_id) = match parent {
+                    Parent::Domain(uuid) => (Some(uuid), None),
+                    Parent::Block(uuid) => (None, Some(uuid)),
+                };
+
+                metrics::observe(
+                    "manifests",
+                    "insert_block",
+                    sqlx::query(trace::statement(
+                        r#"
+                        INSERT INTO blocks (id, domain_id, block_id, name)
+                        VALUES ($1, $2, $3, $4)
+                        "#,
+                    ))
+                    .bind(change.id)
+                    .bind(domain_id)
+                    .bind(parent_id)
+                    .bind(change.name())
+                    .execute(&mut *connection),
+                )
+                .await?;
+            }
+            (Action::Delete, Resource::Block, _) => {
+                metrics::observe(
+                    "manifests",
+                    "delete_block",
+                    sqlx::query(trace::statement(
+                        r#"
+                        DELETE FROM blocks
+                        WHERE blocks.id = $1
+                        "#,
+                    ))
+                    .bind(change.id)
+                    .execute(&mut *connection),
+                )
+                .await?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
@@ -1,5 +1,11 @@
+mod archive;
 mod block;
 mod domain;
+mod health;
+mod manifest;
 
+pub use archive::ArchiveRepository;
 pub use block::BlockRepository;
 pub use domain::DomainRepository;
+pub use health::HealthRepository;
+pub use manifest::ManifestRepository;
@@ -0,0 +1,134 @@
+//! Validation of the values of the models, before they are stored.
+//!
+//! The names of the domains and of the blocks are used as segments of
+//! the URLs of the service, so they are restricted to a URL-safe subset
+//! of ASCII: letters, digits, `-`, `_` and `.`, starting with a letter
+//! or a digit.
+
+use std::fmt;
+use thiserror::Error;
+
+/// The maximum length, in characters, of the names of the domains and
+/// of the blocks.
+pub const MAX_NAME_LENGTH: usize = 64;
+
+/// The names a domain can't take, as they are used by the top-level
+/// routes of the service.
+pub const RESERVED_DOMAIN_NAMES: &[&str] = &[
+    "archives",
+    "blocks",
+    "docs",
+    "graphql",
+    "healthz",
+    "metrics",
+    "openapi.json",
+    "problems",
+    "readyz",
+    "trees",
+];
+
+/// Why the value of a field is invalid.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ValidationError {
+    #[error("is required")]
+    Missing,
+    #[error("must not be empty")]
+    Empty,
+    #[error("must be at most {max} characters long")]
+    TooLong { max: usize },
+    #[error("must start with a letter or a digit")]
+    InvalidStart,
+    #[error("contains the forbidden character {0:?}")]
+    ForbiddenCharacter(char),
+    #[error("'{0}' is reserved")]
+    Reserved(String),
+    #[error("must be a UUID")]
+    NotUuid,
+}
+
+/// The invalid fields of a value, each one with the reasons why it's
+/// invalid.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Error)]
+pub struct ValidationErrors {
+    errors: Vec<(&'static str, ValidationError)>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, field: &'static str, error: ValidationError) {
+        self.errors.push((field, error));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Iterate over the invalid fields, in the order they were
+    /// validated.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &ValidationError)> {
+        self.errors.iter().map(|(field, error)| (*field, error))
+    }
+
+    /// Returns the value if no error was found.
+    pub fn into_result<T>(self, value: impl FnOnce() -> T) -> Result<T, Self> {
+        if self.is_empty() {
+            Ok(value())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (field, error)) in self.errors.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{field} {error}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Validate a name shared by the domains and the blocks.
+fn validate_name(field: &'static str, name: &str, errors: &mut ValidationErrors) {
+    let Some(first) = name.chars().next() else {
+        errors.push(field, ValidationError::Empty);
+        return;
+    };
+
+    if name.chars().count() > MAX_NAME_LENGTH {
+        errors.push(
+            field,
+            ValidationError::TooLong {
+                max: MAX_NAME_LENGTH,
+            },
+        );
+    }
+    if let Some(character) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+    {
+        errors.push(field, ValidationError::ForbiddenCharacter(character));
+    } else if !first.is_ascii_alphanumeric() {
+        errors.push(field, ValidationError::InvalidStart);
+    }
+}
+
+/// Validate the name of a domain, once normalized to lowercase.
+pub fn validate_domain_name(field: &'static str, name: &str, errors: &mut ValidationErrors) {
+    validate_name(field, name, errors);
+    if RESERVED_DOMAIN_NAMES.contains(&name) {
+        errors.push(field, ValidationError::Reserved(name.to_owned()));
+    }
+}
+
+/// Validate the name of a block.
+pub fn validate_block_name(field: &'static str, name: &str, errors: &mut ValidationErrors) {
+    validate_name(field, name, errors);
+}
@@ -0,0 +1,152 @@
+use metadata_data_layer::archive::{Archive, ArchiveError, ArchiveFormat, ARCHIVE_VERSION};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+const TIMESTAMP: &str = "2024-10-19T12:00:00Z";
+
+fn block(id: Uuid, parent: Option<Uuid>, name: &str) -> Value {
+    json!({
+        "id": id,
+        "parent": parent,
+        "name": name,
+        "created_at": TIMESTAMP,
+        "updated_at": TIMESTAMP,
+    })
+}
+
+fn archive(blocks: Vec<Value>) -> Value {
+    json!({
+        "version": ARCHIVE_VERSION,
+        "domain": {
+            "id": Uuid::now_v7(),
+            "name": "Billing",
+            "created_at": TIMESTAMP,
+            "updated_at": TIMESTAMP,
+        },
+        "blocks": blocks,
+    })
+}
+
+fn parse(archive: &Value) -> Result<Archive, ArchiveError> {
+    Archive::parse(archive.to_string().as_bytes())
+}
+
+#[test]
+fn orders_the_blocks_parents_first() {
+    let (a, b, c, d) = (
+        Uuid::now_v7(),
+        Uuid::now_v7(),
+        Uuid::now_v7(),
+        Uuid::now_v7(),
+    );
+    let archive = parse(&archive(vec![
+        block(d, Some(b), "d"),
+        block(c, None, "c"),
+        block(b, Some(a), "b"),
+        block(a, None, "a"),
+    ]))
+    .unwrap();
+
+    assert_eq!(archive.domain.name, "billing");
+    let ids = archive
+        .blocks
+        .iter()
+        .map(|block| block.id)
+        .collect::<Vec<_>>();
+    assert_eq!(ids, [a, c, b, d]);
+}
+
+#[test]
+fn reads_the_json_and_ndjson_archives_alike() {
+    let (a, b) = (Uuid::now_v7(), Uuid::now_v7());
+    let archive = parse(&archive(vec![block(a, None, "a"), block(b, Some(a), "b")])).unwrap();
+
+    for format in [ArchiveFormat::Json, ArchiveFormat::Ndjson] {
+        let mut bytes = Vec::new();
+        archive.write(format, &mut bytes).unwrap();
+
+        assert_eq!(Archive::parse(&bytes).unwrap(), archive, "{format:?}");
+    }
+}
+
+#[test]
+fn refuses_the_other_versions() {
+    let mut json = archive(Vec::new());
+    json["version"] = json!(ARCHIVE_VERSION + 1);
+    assert!(matches!(
+        parse(&json),
+        Err(ArchiveError::UnsupportedVersion(version)) if version == ARCHIVE_VERSION + 1
+    ));
+
+    // The version of an NDJSON archive is read from its first line, even
+    // when the blocks are malformed.
+    let ndjson = format!(
+        "{}\n{{\"id\": 1}}\n",
+        json!({"version": ARCHIVE_VERSION + 1, "domain": json["domain"]})
+    );
+    assert!(matches!(
+        Archive::parse(ndjson.as_bytes()),
+        Err(ArchiveError::UnsupportedVersion(_))
+    ));
+}
+
+#[test]
+fn reports_the_malformed_lines() {
+    let json = archive(Vec::new());
+    let ndjson = format!(
+        "{}\n{}\n{{\"id\": 1}}\n",
+        json!({"version": ARCHIVE_VERSION, "domain": json["domain"]}),
+        block(Uuid::now_v7(), None, "a"),
+    );
+
+    let Err(ArchiveError::Malformed(message)) = Archive::parse(ndjson.as_bytes()) else {
+        panic!("the archive was expected to be malformed");
+    };
+    assert!(message.starts_with("line 3: "), "{message}");
+    assert!(matches!(
+        Archive::parse(b"not an archive"),
+        Err(ArchiveError::Malformed(_))
+    ));
+}
+
+#[test]
+fn refuses_the_invalid_names_and_duplicates() {
+    let mut json = archive(Vec::new());
+    json["domain"]["name"] = json!("-billing");
+    assert!(matches!(parse(&json), Err(ArchiveError::InvalidDomain(_))));
+
+    let id = Uuid::now_v7();
+    assert!(matches!(
+        parse(&archive(vec![block(id, None, "a/b")])),
+        Err(ArchiveError::InvalidBlock { id: invalid, .. }) if invalid == id
+    ));
+    assert!(matches!(
+        parse(&archive(vec![block(id, None, "a"), block(id, None, "b")])),
+        Err(ArchiveError::DuplicateBlock(duplicate)) if duplicate == id
+    ));
+}
+
+#[test]
+fn refuses_the_blocks_outside_of_the_tree() {
+    let (a, b, c) = (Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7());
+
+    let missing = Uuid::now_v7();
+    assert!(matches!(
+        parse(&archive(vec![block(a, Some(missing), "a")])),
+        Err(ArchiveError::UnknownParent { id, parent }) if id == a && parent == missing
+    ));
+
+    // The blocks of a cycle all have a parent within the archive, but
+    // can't be reached from the roots.
+    let cycles = [
+        vec![block(a, Some(a), "a")],
+        vec![
+            block(a, None, "a"),
+            block(b, Some(c), "b"),
+            block(c, Some(b), "c"),
+        ],
+    ];
+    for blocks in cycles {
+        assert!(matches!(parse(&archive(blocks)), Err(ArchiveError::Cycle)));
+    }
+}
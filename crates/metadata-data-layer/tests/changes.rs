@@ -0,0 +1,83 @@
+use metadata_data_layer::{
+    changes::{Change, ChangeError, Operation, Resource},
+    models::Parent,
+};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// The payload of a notification, as built by the trigger from a row.
+fn payload(operation: &str, resource: &str, row: Value) -> String {
+    json!({"operation": operation, "resource": resource, "row": row}).to_string()
+}
+
+fn block_row(domain_id: Option<Uuid>, block_id: Option<Uuid>) -> Value {
+    json!({
+        "id": Uuid::nil(),
+        "domain_id": domain_id,
+        "block_id": block_id,
+        "name": "root",
+        "created_at": "2024-10-19T12:00:00.123456+00:00",
+        "updated_at": "2024-10-19T12:00:00.123456+00:00",
+    })
+}
+
+#[test]
+fn parses_the_changes_of_the_domains() {
+    let id = Uuid::now_v7();
+    let row = json!({
+        "id": id,
+        "name": "billing",
+        "created_at": "2024-10-19T12:00:00+00:00",
+        "updated_at": "2024-10-19T12:30:00+00:00",
+    });
+
+    let change = Change::parse(&payload("update", "domains", row)).unwrap();
+    assert_eq!(change.operation, Operation::Update);
+    let Resource::Domain(domain) = change.resource else {
+        panic!("a domain was expected");
+    };
+    assert_eq!((domain.id, domain.name.as_str()), (id, "billing"));
+    assert!(domain.updated_at > domain.created_at);
+}
+
+#[test]
+fn parses_the_parent_of_the_blocks() {
+    let parent = Uuid::now_v7();
+
+    for (operation, row, expected) in [
+        (
+            "insert",
+            block_row(Some(parent), None),
+            Parent::Domain(parent),
+        ),
+        (
+            "delete",
+            block_row(None, Some(parent)),
+            Parent::Block(parent),
+        ),
+    ] {
+        let change = Change::parse(&payload(operation, "blocks", row)).unwrap();
+        let Resource::Block(block) = change.resource else {
+            panic!("a block was expected");
+        };
+        assert_eq!(block.parent, expected);
+    }
+}
+
+#[test]
+fn rejects_the_malformed_notifications() {
+    let parent = Some(Uuid::now_v7());
+
+    for payload in [
+        payload("insert", "blocks", block_row(parent, parent)),
+        payload("insert", "blocks", block_row(None, None)),
+        payload("insert", "archives", json!({})),
+        payload("truncate", "domains", json!({})),
+        "not json".to_owned(),
+    ] {
+        assert!(
+            matches!(Change::parse(&payload), Err(ChangeError::Malformed(_))),
+            "{payload} was parsed"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use metadata_data_layer::{
+    manifest::{self, Action, Change, Manifest, ManifestError, Resource},
+    models::{Block, Domain, Parent},
+};
+use serde_json::json;
+
+fn manifest(value: serde_json::Value) -> Manifest {
+    serde_json::from_value(value).expect("a manifest")
+}
+
+fn block(parent: Parent, name: &str) -> Block {
+    let builder = match parent {
+        Parent::Domain(uuid) => Block::builder().domain(uuid),
+        Parent::Block(uuid) => Block::builder().block(uuid),
+    };
+
+    builder.name(&name).finalize().unwrap()
+}
+
+/// The action, resource and path of each change.
+fn summary(changes: &[Change]) -> Vec<(Action, Resource, &str)> {
+    changes
+        .iter()
+        .map(|change| (change.action, change.resource, change.path.as_str()))
+        .collect()
+}
+
+#[test]
+fn normalizes_and_validates_the_manifests() {
+    let mut manifests = [
+        manifest(json!({"domain": "Billing", "blocks": [
+            {"name": "invoices", "blocks": [{"name": "lines"}]},
+            {"name": "payments", "blocks": [{"name": "lines"}]},
+        ]})),
+        manifest(json!({"domain": "shipping"})),
+    ];
+    manifest::validate(&mut manifests).unwrap();
+    assert_eq!(manifests[0].domain, "billing");
+
+    let mut manifests = [
+        manifest(json!({"domain": "Billing"})),
+        manifest(json!({"domain": "billing"})),
+    ];
+    assert!(matches!(
+        manifest::validate(&mut manifests),
+        Err(ManifestError::DuplicateDomain(domain)) if domain == "billing"
+    ));
+
+    let mut manifests = [manifest(json!({"domain": "billing", "blocks": [
+        {"name": "invoices", "blocks": [{"name": "lines"}, {"name": "lines"}]},
+    ]}))];
+    assert!(matches!(
+        manifest::validate(&mut manifests),
+        Err(ManifestError::DuplicateBlock(path)) if path == "billing/invoices/lines"
+    ));
+
+    let mut manifests = [manifest(json!({"domain": "billing", "blocks": [
+        {"name": "invoices", "blocks": [{"name": "-lines"}]},
+    ]}))];
+    assert!(matches!(
+        manifest::validate(&mut manifests),
+        Err(ManifestError::Invalid { path, .. }) if path == "billing/invoices/-lines"
+    ));
+}
+
+#[test]
+fn creates_the_missing_domains_with_their_trees() {
+    let manifest = manifest(json!({"domain": "billing", "blocks": [
+        {"name": "invoices", "blocks": [{"name": "lines"}]},
+    ]}));
+
+    let changes = manifest::reconcile(&manifest, None, false).unwrap();
+    assert_eq!(
+        summary(&changes),
+        [
+            (Action::Create, Resource::Domain, "billing"),
+            (Action::Create, Resource::Block, "billing/invoices"),
+            (Action::Create, Resource::Block, "billing/invoices/lines"),
+        ]
+    );
+    assert_eq!(changes[1].parent, Some(Parent::Domain(changes[0].id)));
+    assert_eq!(changes[2].parent, Some(Parent::Block(changes[1].id)));
+}
+
+#[test]
+fn reconciles_the_stored_trees_by_name() {
+    let domain = Domain::new("billing").unwrap();
+    let invoices = block(Parent::Domain(domain.id), "invoices");
+    let lines = block(Parent::Block(invoices.id), "lines");
+    let archived = block(Parent::Block(invoices.id), "archived");
+    let payments = block(Parent::Domain(domain.id), "payments");
+    let refunds = block(Parent::Block(payments.id), "refunds");
+    let stored = [
+        invoices.clone(),
+        payments.clone(),
+        archived.clone(),
+        lines.clone(),
+        refunds,
+    ];
+
+    let manifest = manifest(json!({"domain": "billing", "blocks": [
+        {"name": "invoices", "blocks": [{"name": "lines"}, {"name": "taxes"}]},
+    ]}));
+
+    let changes = manifest::reconcile(&manifest, Some((&domain, &stored)), false).unwrap();
+    assert_eq!(
+        summary(&changes),
+        [
+            (Action::Unchanged, Resource::Domain, "billing"),
+            (Action::Unchanged, Resource::Block, "billing/invoices"),
+            (Action::Unchanged, Resource::Block, "billing/invoices/lines"),
+            (Action::Create, Resource::Block, "billing/invoices/taxes"),
+            (
+                Action::Unmanaged,
+                Resource::Block,
+                "billing/invoices/archived"
+            ),
+            (Action::Unmanaged, Resource::Block, "billing/payments"),
+        ]
+    );
+    assert_eq!(changes[1].id, invoices.id);
+    assert_eq!(changes[2].id, lines.id);
+    assert_eq!(changes[3].parent, Some(Parent::Block(invoices.id)));
+
+    // The blocks missing from the manifest are deleted with the pruning,
+    // their descendants sharing their fate without being listed.
+    let changes = manifest::reconcile(&manifest, Some((&domain, &stored)), true).unwrap();
+    let deleted = changes
+        .iter()
+        .filter(|change| change.action == Action::Delete)
+        .map(|change| change.id)
+        .collect::<Vec<_>>();
+    assert_eq!(deleted, [archived.id, payments.id]);
+}
+
+#[test]
+fn refuses_the_ambiguous_blocks() {
+    let domain = Domain::new("billing").unwrap();
+    let stored = [
+        block(Parent::Domain(domain.id), "invoices"),
+        block(Parent::Domain(domain.id), "invoices"),
+    ];
+    let manifest = manifest(json!({"domain": "billing", "blocks": [{"name": "invoices"}]}));
+
+    assert!(matches!(
+        manifest::reconcile(&manifest, Some((&domain, &stored)), false),
+        Err(ManifestError::Ambiguous(path)) if path == "billing/invoices"
+    ));
+}
@@ -0,0 +1,49 @@
+#!/bin/sh
+# Regenerate `src/pb/backbone.metadata.v1.rs` from the definition of the
+# service, with `tonic-prost-build` and `protoc`, which building the crate
+# doesn't require. The hash of the definition is recorded in the generated
+# code, so `cargo test` fails until it's regenerated after a change.
+set -eu
+
+crate=$(cd "$(dirname "$0")" && pwd)
+proto=proto/backbone/metadata/v1/metadata.proto
+generated=src/pb/backbone.metadata.v1.rs
+
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+mkdir "$work/src"
+
+cat > "$work/Cargo.toml" <<'TOML'
+[package]
+name = "generate"
+version = "0.0.0"
+edition = "2021"
+
+[dependencies]
+tonic-prost-build = "^0.14.2"
+TOML
+
+cat > "$work/src/main.rs" <<'RUST'
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = std::env::args().nth(1).expect("the output directory");
+
+    tonic_prost_build::configure()
+        .build_client(false)
+        .out_dir(out_dir)
+        .compile_protos(&["proto/backbone/metadata/v1/metadata.proto"], &["proto"])?;
+
+    Ok(())
+}
+RUST
+
+cd "$crate"
+cargo run --quiet --manifest-path "$work/Cargo.toml" -- "$work"
+
+{
+    echo "// The code generated by \`tonic-prost-build\` from"
+    echo "// \`$proto\`. It's checked in so building"
+    echo "// the crate doesn't require \`protoc\`: regenerate it with \`generate.sh\`"
+    echo "// when the definition changes."
+    echo "// proto-sha256: $(sha256sum "$proto" | cut -d ' ' -f 1)"
+    cat "$work/backbone.metadata.v1.rs"
+} > "$generated"
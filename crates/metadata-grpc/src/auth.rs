@@ -0,0 +1,48 @@
+use crate::status;
+use metadata_http::{ApiKeys, AuthError, Principal};
+use tonic::{service::Interceptor, Request, Status};
+
+/// Rejects the calls which are not carrying one of the API keys of the
+/// service, either as a bearer token in the `authorization` metadata or
+/// in the `x-api-key` one, like the HTTP requests.
+#[derive(Clone, Debug)]
+pub struct Authenticator {
+    api_keys: ApiKeys,
+}
+
+impl Authenticator {
+    pub fn new(api_keys: ApiKeys) -> Self {
+        Self { api_keys }
+    }
+}
+
+impl Interceptor for Authenticator {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if self.api_keys.is_empty() {
+            return Ok(request);
+        }
+
+        let metadata = request.metadata();
+        let bearer = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let key = bearer
+            .or_else(|| {
+                metadata
+                    .get("x-api-key")
+                    .and_then(|value| value.to_str().ok())
+            })
+            .ok_or_else(|| status::problem(&AuthError::MissingApiKey))?;
+        let owner = self
+            .api_keys
+            .owner(key)
+            .ok_or_else(|| status::problem(&AuthError::InvalidApiKey))?;
+
+        let principal = Principal(owner.to_owned());
+        tracing::debug!(principal = %principal.0, "call authenticated");
+        request.extensions_mut().insert(principal);
+
+        Ok(request)
+    }
+}
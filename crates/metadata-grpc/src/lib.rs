@@ -0,0 +1,60 @@
+//! The gRPC API of the service, served on a port of its own for the
+//! clients speaking gRPC only.
+//!
+//! It's defined by `proto/backbone/metadata/v1/metadata.proto`, and its
+//! errors carry the same problem types as the HTTP API.
+
+mod auth;
+mod service;
+mod status;
+
+pub mod pb {
+    #![allow(clippy::all)]
+    include!("pb/backbone.metadata.v1.rs");
+}
+
+pub use auth::Authenticator;
+pub use service::{Metadata, DEFAULT_MAX_WATCHERS};
+
+use metadata_data_layer_utils::PoolState;
+use metadata_http::{ApiKeys, Shutdown};
+use pb::metadata_service_server::MetadataServiceServer;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_stream::Stream;
+use tonic::{service::interceptor::InterceptedService, transport::server::Connected};
+
+/// Build the service, authenticating the calls with the API keys when
+/// any is given, and streaming the changes to at most `max_watchers`
+/// clients at once, for `watch_timeout` each.
+pub fn service(
+    pool: PoolState,
+    api_keys: ApiKeys,
+    max_watchers: usize,
+    watch_timeout: Duration,
+    shutdown: Shutdown,
+) -> InterceptedService<MetadataServiceServer<Metadata>, Authenticator> {
+    MetadataServiceServer::with_interceptor(
+        Metadata::new(pool, max_watchers, shutdown).with_watch_timeout(watch_timeout),
+        Authenticator::new(api_keys),
+    )
+}
+
+/// Serve the service on the incoming connections, e.g. a
+/// [tokio_stream::wrappers::TcpListenerStream] or a stream of TLS
+/// connections, until the shutdown is triggered. The change streams end
+/// with it, so the pending calls can complete.
+pub async fn serve<I, IO, IE>(
+    incoming: I,
+    service: InterceptedService<MetadataServiceServer<Metadata>, Authenticator>,
+    shutdown: Shutdown,
+) -> Result<(), tonic::transport::Error>
+where
+    I: Stream<Item = Result<IO, IE>>,
+    IO: AsyncRead + AsyncWrite + Connected + Unpin + Send + 'static,
+    IE: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    tonic::transport::Server::builder()
+        .serve_with_incoming_shutdown(service, incoming, shutdown.triggered_owned())
+        .await
+}
@@ -0,0 +1,393 @@
+use crate::{
+    pb::{self, metadata_service_server::MetadataService},
+    status,
+};
+use chrono::{DateTime, Utc};
+use metadata_data_layer::{
+    changes::{Change, ChangeFeed, FeedError, Operation, Resource},
+    models::{Block, Domain, Parent},
+    repositories::{BlockRepository, DomainRepository},
+    validation::{ValidationError, ValidationErrors},
+};
+use metadata_data_layer_utils::{ConsistencyToken, PoolState, Repository, Session};
+use metadata_http::{BlockError, DomainError, LoadError, Shutdown, ValidationProblem};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{metadata::MetadataValue, Request, Response, Status};
+use uuid::Uuid;
+
+/// The number of domains of a page when the `page_size` is missing.
+const DEFAULT_PAGE_SIZE: i64 = 20;
+/// The maximum `page_size`, the larger ones being lowered to it.
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// The number of changes buffered for a client which is not reading its
+/// stream fast enough, before it misses the newer ones.
+const CHANGES_BUFFER: usize = 64;
+
+/// The maximum number of `WatchChanges` streams open at once, by default.
+pub const DEFAULT_MAX_WATCHERS: usize = 100;
+
+/// The implementation of the service, on top of the same repositories as
+/// the HTTP API.
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    pool: PoolState,
+    changes: ChangeFeed,
+    shutdown: Shutdown,
+    watch_timeout: Option<Duration>,
+}
+
+impl Metadata {
+    /// Build the service, listening to the changes of the database for at
+    /// most `max_watchers` streams at once until the shutdown.
+    pub fn new(pool: PoolState, max_watchers: usize, shutdown: Shutdown) -> Self {
+        let changes = ChangeFeed::spawn(
+            &pool.downcast_ref(),
+            CHANGES_BUFFER,
+            max_watchers,
+            shutdown.triggered_owned(),
+        );
+
+        Self {
+            pool,
+            changes,
+            shutdown,
+            watch_timeout: None,
+        }
+    }
+
+    /// End the `WatchChanges` streams with a `DEADLINE_EXCEEDED` error
+    /// after `timeout`, so a client holds a slot of the watchers only as
+    /// long as it keeps calling again.
+    pub fn with_watch_timeout(mut self, timeout: Duration) -> Self {
+        self.watch_timeout = Some(timeout);
+        self
+    }
+
+    /// The session of a call, reading from the replicas which replayed
+    /// the consistency token of its `x-consistency-token` metadata only,
+    /// and from the primary when the token is invalid.
+    fn session<T>(&self, request: &Request<T>) -> Session {
+        let session = self.pool.session();
+        let Some(value) = request.metadata().get(CONSISTENCY_TOKEN) else {
+            return session;
+        };
+
+        match value.to_str().ok().map(str::parse::<ConsistencyToken>) {
+            Some(Ok(token)) => session.with_token(token),
+            _ => {
+                session.pin();
+                session
+            }
+        }
+    }
+}
+
+/// The metadata carrying the consistency token, in the responses to the
+/// calls which wrote and in the following calls of the client.
+const CONSISTENCY_TOKEN: &str = "x-consistency-token";
+
+/// Respond to a call which wrote with the consistency token of its
+/// session, so the following calls of the client read its writes.
+async fn respond<T>(session: &Session, message: T) -> Response<T> {
+    let mut response = Response::new(message);
+    match session.token().await {
+        Ok(Some(token)) => {
+            if let Ok(value) = MetadataValue::try_from(token.to_string()) {
+                response.metadata_mut().insert(CONSISTENCY_TOKEN, value);
+            }
+        }
+        Ok(None) => {}
+        Err(error) => tracing::warn!(%error, "unable to read the consistency token"),
+    }
+
+    response
+}
+
+/// Parse the identifier sent in a field of a request.
+fn uuid(field: &'static str, value: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(value).map_err(|_| {
+        let mut errors = ValidationErrors::new();
+        errors.push(field, ValidationError::NotUuid);
+
+        status::problem(&ValidationProblem::from(errors))
+    })
+}
+
+fn timestamp(datetime: DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: datetime.timestamp(),
+        nanos: datetime.timestamp_subsec_nanos() as i32,
+    }
+}
+
+impl From<Domain> for pb::Domain {
+    fn from(domain: Domain) -> Self {
+        Self {
+            id: domain.id.to_string(),
+            name: domain.name,
+            created_at: Some(timestamp(domain.created_at)),
+            updated_at: Some(timestamp(domain.updated_at)),
+        }
+    }
+}
+
+impl From<Block> for pb::Block {
+    fn from(block: Block) -> Self {
+        let parent = match block.parent {
+            Parent::Domain(uuid) => pb::block::Parent::DomainId(uuid.to_string()),
+            Parent::Block(uuid) => pb::block::Parent::ParentBlockId(uuid.to_string()),
+        };
+
+        Self {
+            id: block.id.to_string(),
+            parent: Some(parent),
+            name: block.name,
+            created_at: Some(timestamp(block.created_at)),
+            updated_at: Some(timestamp(block.updated_at)),
+        }
+    }
+}
+
+impl From<Change> for pb::Change {
+    fn from(change: Change) -> Self {
+        let operation = match change.operation {
+            Operation::Insert => pb::change::Operation::Created,
+            Operation::Update => pb::change::Operation::Updated,
+            Operation::Delete => pb::change::Operation::Deleted,
+        };
+        let resource = match change.resource {
+            Resource::Domain(domain) => pb::change::Resource::Domain(domain.into()),
+            Resource::Block(block) => pb::change::Resource::Block(block.into()),
+        };
+
+        Self {
+            operation: operation.into(),
+            resource: Some(resource),
+        }
+    }
+}
+
+impl Metadata {
+    async fn find_domain(&self, session: &Session, domain_name: String) -> Result<Domain, Status> {
+        let domain = DomainRepository::from_ref(session.clone())
+            .get_domain_by_name(&domain_name)
+            .await
+            .map_err(|error| status::database(&error))?;
+
+        domain.ok_or_else(|| status::problem(&DomainError::NotFoundByName(domain_name)))
+    }
+
+    async fn find_block(&self, session: &Session, block_id: &str) -> Result<Block, Status> {
+        let uuid = uuid("id", block_id)?;
+        let block = BlockRepository::from_ref(session.clone())
+            .get_block(&uuid)
+            .await
+            .map_err(|error| status::database(&error))?;
+
+        block.ok_or_else(|| status::problem(&BlockError::NotFoundById(uuid)))
+    }
+}
+
+#[tonic::async_trait]
+impl MetadataService for Metadata {
+    #[tracing::instrument(name = "grpc.get_domain", skip_all)]
+    async fn get_domain(
+        &self,
+        request: Request<pb::GetDomainRequest>,
+    ) -> Result<Response<pb::Domain>, Status> {
+        let session = self.session(&request);
+        let domain = self
+            .find_domain(&session, request.into_inner().name)
+            .await?;
+
+        Ok(Response::new(domain.into()))
+    }
+
+    #[tracing::instrument(name = "grpc.list_domains", skip_all)]
+    async fn list_domains(
+        &self,
+        request: Request<pb::ListDomainsRequest>,
+    ) -> Result<Response<pb::ListDomainsResponse>, Status> {
+        let session = self.session(&request);
+        let request = request.into_inner();
+        let page_size = match i64::from(request.page_size) {
+            size if size <= 0 => DEFAULT_PAGE_SIZE,
+            size => size.min(MAX_PAGE_SIZE),
+        };
+        let after = Some(request.page_token.as_str()).filter(|token| !token.is_empty());
+
+        // One more domain is fetched to know whether a next page exists.
+        let mut domains = DomainRepository::from_ref(session)
+            .list_domains_page(after, page_size + 1)
+            .await
+            .map_err(|error| status::database(&error))?;
+        let next_page_token = if domains.len() as i64 > page_size {
+            domains.truncate(page_size as usize);
+            domains
+                .last()
+                .map(|domain| domain.name.clone())
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        Ok(Response::new(pb::ListDomainsResponse {
+            domains: domains.into_iter().map(Into::into).collect(),
+            next_page_token,
+        }))
+    }
+
+    #[tracing::instrument(name = "grpc.create_domain", skip_all)]
+    async fn create_domain(
+        &self,
+        request: Request<pb::CreateDomainRequest>,
+    ) -> Result<Response<pb::Domain>, Status> {
+        let session = self.session(&request);
+        let domain = Domain::new(request.into_inner().name)
+            .map_err(|errors| status::problem(&ValidationProblem::from(errors)))?;
+        DomainRepository::from_ref(session.clone())
+            .insert_domain(&domain)
+            .await
+            .map_err(|error| status::database(&error))?;
+
+        Ok(respond(&session, domain.into()).await)
+    }
+
+    #[tracing::instrument(name = "grpc.get_block", skip_all)]
+    async fn get_block(
+        &self,
+        request: Request<pb::GetBlockRequest>,
+    ) -> Result<Response<pb::Block>, Status> {
+        let session = self.session(&request);
+        let block = self.find_block(&session, &request.into_inner().id).await?;
+
+        Ok(Response::new(block.into()))
+    }
+
+    #[tracing::instrument(name = "grpc.list_blocks", skip_all)]
+    async fn list_blocks(
+        &self,
+        request: Request<pb::ListBlocksRequest>,
+    ) -> Result<Response<pb::ListBlocksResponse>, Status> {
+        use pb::list_blocks_request::Parent as Requested;
+
+        let session = self.session(&request);
+        let parent = match request.into_inner().parent {
+            Some(Requested::DomainName(domain_name)) => {
+                Parent::Domain(self.find_domain(&session, domain_name).await?.id)
+            }
+            Some(Requested::ParentBlockId(block_id)) => {
+                Parent::Block(self.find_block(&session, &block_id).await?.id)
+            }
+            None => {
+                let mut errors = ValidationErrors::new();
+                errors.push("parent", ValidationError::Missing);
+
+                return Err(status::problem(&ValidationProblem::from(errors)));
+            }
+        };
+        let blocks = BlockRepository::from_ref(session)
+            .list_children(&[parent])
+            .await
+            .map_err(|error| status::database(&error))?;
+
+        Ok(Response::new(pb::ListBlocksResponse {
+            blocks: blocks.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    #[tracing::instrument(name = "grpc.create_block", skip_all)]
+    async fn create_block(
+        &self,
+        request: Request<pb::CreateBlockRequest>,
+    ) -> Result<Response<pb::Block>, Status> {
+        let session = self.session(&request);
+        let request = request.into_inner();
+        let domain = self.find_domain(&session, request.domain_name).await?;
+        let blocks = BlockRepository::from_ref(session.clone());
+
+        let builder = if request.parent_block_id.is_empty() {
+            Block::builder().domain(domain.id)
+        } else {
+            let parent = uuid("parent_block_id", &request.parent_block_id)?;
+            let root = blocks
+                .get_root_domain_id(&parent)
+                .await
+                .map_err(|error| status::database(&error))?;
+            if root != Some(domain.id) {
+                return Err(status::problem(&BlockError::ParentNotFound(parent)));
+            }
+            Block::builder().block(parent)
+        };
+        let builder = if request.name.is_empty() {
+            builder
+        } else {
+            builder.name(&request.name)
+        };
+        let block = builder
+            .finalize()
+            .map_err(|errors| status::problem(&ValidationProblem::from(errors)))?;
+        blocks
+            .insert_block(&block)
+            .await
+            .map_err(|error| status::database(&error))?;
+
+        Ok(respond(&session, block.into()).await)
+    }
+
+    type WatchChangesStream = ReceiverStream<Result<pb::Change, Status>>;
+
+    #[tracing::instrument(name = "grpc.watch_changes", skip_all)]
+    async fn watch_changes(
+        &self,
+        _: Request<pb::WatchChangesRequest>,
+    ) -> Result<Response<Self::WatchChangesStream>, Status> {
+        let Some(mut subscription) = self.changes.subscribe() else {
+            return Err(status::problem(&LoadError::Overloaded));
+        };
+        // The changes are already buffered by the subscription.
+        let (sender, receiver) = mpsc::channel(1);
+        let shutdown = self.shutdown.clone();
+        let timeout = self.watch_timeout;
+
+        tokio::spawn(async move {
+            // Without a timeout, the stream only ends with the shutdown.
+            let deadline = tokio::time::sleep(timeout.unwrap_or(Duration::MAX));
+            tokio::pin!(deadline);
+
+            loop {
+                let change = tokio::select! {
+                    _ = shutdown.triggered() => break,
+                    _ = sender.closed() => break,
+                    _ = &mut deadline => {
+                        let message = format!(
+                            "the stream ended after {:?}, call again to keep watching the changes",
+                            timeout.unwrap_or_default()
+                        );
+                        let _ = sender.send(Err(Status::deadline_exceeded(message))).await;
+                        break;
+                    }
+                    change = subscription.recv() => change,
+                };
+                let item = match change {
+                    Ok(change) => Ok(change.into()),
+                    // The listener lost its connection or the client missed
+                    // changes: it has to call again, and read what changed
+                    // in the meantime.
+                    Err(FeedError::Interrupted(error)) => Err(status::database(&error)),
+                    Err(error @ FeedError::Lagged(_)) => Err(Status::aborted(error.to_string())),
+                    Err(FeedError::Closed) => break,
+                };
+                let failed = item.is_err();
+                if sender.send(item).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
@@ -0,0 +1,52 @@
+use http::StatusCode;
+use metadata_http_utils::{Problem, SqlProblem};
+use tonic::{metadata::MetadataValue, Code, Status};
+
+/// The name of the problem type of a conflict with an existing resource,
+/// the other conflicts being failed preconditions.
+const ALREADY_EXISTS: &str = "/sql/unique-violation";
+
+/// The gRPC code matching the HTTP status of a problem, or its type when
+/// the status is ambiguous.
+fn code(problem: &impl Problem) -> Code {
+    let Some(status) = problem.status() else {
+        return Code::Unknown;
+    };
+
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::CONFLICT if problem.ty().ends_with(ALREADY_EXISTS) => Code::AlreadyExists,
+        StatusCode::CONFLICT => Code::FailedPrecondition,
+        StatusCode::PRECONDITION_FAILED => Code::FailedPrecondition,
+        StatusCode::PAYLOAD_TOO_LARGE | StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::NOT_IMPLEMENTED => Code::Unimplemented,
+        StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        status if status.is_server_error() => Code::Internal,
+        _ => Code::Unknown,
+    }
+}
+
+/// Describe a problem as a gRPC status, whose message is the detail of
+/// the problem. Its type and title are sent as the `problem-type` and
+/// `problem-title` metadata.
+pub(crate) fn problem(problem: &impl Problem) -> Status {
+    let mut status = Status::new(code(problem), problem.detail());
+    for (key, value) in [
+        ("problem-type", problem.ty()),
+        ("problem-title", problem.title()),
+    ] {
+        if let Ok(value) = MetadataValue::try_from(value) {
+            status.metadata_mut().insert(key, value);
+        }
+    }
+
+    status
+}
+
+pub(crate) fn database(error: &sqlx::Error) -> Status {
+    problem(&SqlProblem::from(error))
+}
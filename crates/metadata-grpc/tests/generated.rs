@@ -0,0 +1,23 @@
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// The generated code records the hash of the definition it was generated
+/// from, which must be the one of the current definition.
+#[test]
+fn generated_code_matches_the_definition() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let definition = std::fs::read(root.join("proto/backbone/metadata/v1/metadata.proto"))
+        .expect("the definition of the service");
+    let generated = std::fs::read_to_string(root.join("src/pb/backbone.metadata.v1.rs"))
+        .expect("the generated code");
+
+    let recorded = generated
+        .lines()
+        .find_map(|line| line.strip_prefix("// proto-sha256: "))
+        .expect("the hash of the definition in the generated code");
+    assert_eq!(
+        recorded,
+        format!("{:x}", Sha256::digest(definition)),
+        "the definition changed: run `crates/metadata-grpc/generate.sh`"
+    );
+}
@@ -0,0 +1,180 @@
+use metadata_data_layer_utils::PoolState;
+use metadata_grpc::{
+    pb::{self, metadata_service_server::MetadataService},
+    Authenticator, Metadata,
+};
+use metadata_http::{ApiKeys, Principal, Shutdown};
+use std::time::Duration;
+use tokio_stream::StreamExt;
+use tonic::{service::Interceptor, Code, Request, Status};
+
+/// A service whose pool connects lazily to a port where no database
+/// listens, as the calls below are answered without reading it.
+fn service(max_watchers: usize) -> Metadata {
+    let pool = PoolState::builder().host("127.0.0.1").port(1).finalize();
+
+    Metadata::new(pool, max_watchers, Shutdown::new())
+}
+
+/// Check the code of a status and the problem type of its metadata.
+fn assert_problem(status: &Status, code: Code, problem_type: &str) {
+    assert_eq!(status.code(), code, "{status:?}");
+    let ty = status
+        .metadata()
+        .get("problem-type")
+        .expect("a problem type");
+    assert!(
+        ty.to_str().unwrap().ends_with(problem_type),
+        "{ty:?} is not {problem_type}"
+    );
+}
+
+#[tokio::test]
+async fn invalid_arguments_are_validation_problems() {
+    let service = service(1);
+
+    let request = Request::new(pb::GetBlockRequest {
+        id: "not-a-uuid".to_owned(),
+    });
+    let status = service.get_block(request).await.unwrap_err();
+    assert_problem(
+        &status,
+        Code::InvalidArgument,
+        "/validation/invalid-resource",
+    );
+
+    let request = Request::new(pb::CreateDomainRequest {
+        name: "-invalid".to_owned(),
+    });
+    let status = service.create_domain(request).await.unwrap_err();
+    assert_problem(
+        &status,
+        Code::InvalidArgument,
+        "/validation/invalid-resource",
+    );
+
+    let request = Request::new(pb::ListBlocksRequest { parent: None });
+    let status = service.list_blocks(request).await.unwrap_err();
+    assert_problem(
+        &status,
+        Code::InvalidArgument,
+        "/validation/invalid-resource",
+    );
+}
+
+#[tokio::test]
+async fn watchers_above_the_maximum_are_rejected() {
+    let service = service(1);
+
+    let watching = service
+        .watch_changes(Request::new(pb::WatchChangesRequest {}))
+        .await
+        .expect("a change stream");
+    let status = service
+        .watch_changes(Request::new(pb::WatchChangesRequest {}))
+        .await
+        .unwrap_err();
+    assert_problem(&status, Code::Unavailable, "/server/overloaded");
+
+    // The slot of a stream is released once the client drops it.
+    drop(watching);
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while service
+            .watch_changes(Request::new(pb::WatchChangesRequest {}))
+            .await
+            .is_err()
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the slot of the dropped stream");
+}
+
+#[tokio::test]
+async fn the_change_streams_end_after_the_watch_timeout() {
+    let service = service(1).with_watch_timeout(Duration::from_millis(100));
+
+    let mut stream = service
+        .watch_changes(Request::new(pb::WatchChangesRequest {}))
+        .await
+        .expect("a change stream")
+        .into_inner();
+    let status = tokio::time::timeout(Duration::from_secs(5), stream.next())
+        .await
+        .expect("the end of the stream")
+        .expect("an error")
+        .unwrap_err();
+    assert_eq!(status.code(), Code::DeadlineExceeded, "{status:?}");
+    assert!(stream.next().await.is_none());
+
+    // The slot of the stream is released once it ended.
+    drop(stream);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while service
+            .watch_changes(Request::new(pb::WatchChangesRequest {}))
+            .await
+            .is_err()
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the slot of the ended stream");
+}
+
+#[test]
+fn calls_are_authenticated_with_the_api_keys() {
+    let mut authenticator = Authenticator::new(ApiKeys::new([("ci", "secret")]));
+
+    let status = authenticator.call(Request::new(())).unwrap_err();
+    assert_problem(&status, Code::Unauthenticated, "/auth/missing-api-key");
+
+    let mut request = Request::new(());
+    request
+        .metadata_mut()
+        .insert("authorization", "Bearer guessed".parse().unwrap());
+    let status = authenticator.call(request).unwrap_err();
+    assert_problem(&status, Code::Unauthenticated, "/auth/invalid-api-key");
+
+    let mut request = Request::new(());
+    request
+        .metadata_mut()
+        .insert("x-api-key", "secret".parse().unwrap());
+    let request = authenticator.call(request).expect("an authenticated call");
+    assert_eq!(request.extensions().get::<Principal>().unwrap().0, "ci");
+}
+
+#[tokio::test]
+#[ignore = "requires a PostgreSQL database, configured with the POSTGRES_* variables"]
+async fn missing_blocks_are_not_found_by_id() {
+    let service = Metadata::new(PoolState::from_env().finalize(), 1, Shutdown::new());
+
+    let request = Request::new(pb::GetBlockRequest {
+        id: "00000000-0000-0000-0000-000000000000".to_owned(),
+    });
+    let status = service.get_block(request).await.unwrap_err();
+    assert_problem(&status, Code::NotFound, "/blocks/not-found-by-id");
+    assert!(status
+        .message()
+        .contains("00000000-0000-0000-0000-000000000000"));
+}
+
+#[tokio::test]
+#[ignore = "requires a PostgreSQL database, configured with the POSTGRES_* variables"]
+async fn duplicated_domains_already_exist() {
+    let service = Metadata::new(PoolState::from_env().finalize(), 1, Shutdown::new());
+
+    // The domain is kept between the runs, so the first creation may
+    // conflict already.
+    let request = Request::new(pb::CreateDomainRequest {
+        name: "grpc-duplicated".to_owned(),
+    });
+    let _ = service.create_domain(request).await;
+
+    let request = Request::new(pb::CreateDomainRequest {
+        name: "grpc-duplicated".to_owned(),
+    });
+    let status = service.create_domain(request).await.unwrap_err();
+    assert_problem(&status, Code::AlreadyExists, "/sql/unique-violation");
+}
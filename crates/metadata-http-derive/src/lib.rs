@@ -0,0 +1,270 @@
+//! A derive macro implementing the `Problem` and `ProblemTypes` traits of
+//! `metadata_http_utils` for error values.
+//!
+//! Each variant of an enum, or the struct itself, describes its problem
+//! type with a `#[problem(...)]` attribute:
+//!
+//! - `type`, the name of the problem type, resolved against the
+//!   `namespace` of the enum, if any;
+//! - `title`, a short, human-readable summary of the problem type;
+//! - `status`, the HTTP status code of the responses;
+//! - `description`, an optional explanation of the problem type, shown
+//!   on its documentation page. The title is used when it's missing.
+//!
+//! The `detail` of a problem is the [std::fmt::Display] representation
+//! of the error value. The type itself accepts the following options:
+//!
+//! - `namespace`, the prefix of the names of its problem types;
+//! - `headers`, the name of a `fn(&self) -> Option<HeaderMap>` method
+//!   providing the HTTP headers of the responses;
+//! - `extensions`, the name of a `fn(&self) -> Extensions` method
+//!   providing the extension members of the problems.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use metadata_http_utils::Problem;
+//! use thiserror::Error;
+//!
+//! #[derive(Debug, Error, Problem)]
+//! #[problem(namespace = "blocks")]
+//! enum BlockError {
+//!     #[error("Block '{0}' is not found.")]
+//!     #[problem(type = "not-found", title = "Block Not Found.", status = 404)]
+//!     NotFoundByName(String),
+//! }
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    meta::ParseNestedMeta, parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput,
+    Error, Ident, LitInt, LitStr, Result,
+};
+
+#[proc_macro_derive(Problem, attributes(problem))]
+pub fn derive_problem(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// The options of the `#[problem(...)]` attributes, whether they are set
+/// on the type or on one of its variants.
+#[derive(Default)]
+struct Options {
+    namespace: Option<LitStr>,
+    headers: Option<Ident>,
+    extensions: Option<Ident>,
+    ty: Option<LitStr>,
+    title: Option<LitStr>,
+    status: Option<LitInt>,
+    description: Option<LitStr>,
+}
+
+impl Options {
+    fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut options = Self::default();
+
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("problem")) {
+            attr.parse_nested_meta(|meta| {
+                fn value<T: syn::parse::Parse>(
+                    meta: &ParseNestedMeta<'_>,
+                    option: &mut Option<T>,
+                ) -> Result<()> {
+                    if option.is_some() {
+                        return Err(meta.error("duplicate problem option"));
+                    }
+                    *option = Some(meta.value()?.parse()?);
+                    Ok(())
+                }
+
+                if meta.path.is_ident("namespace") {
+                    value(&meta, &mut options.namespace)
+                } else if meta.path.is_ident("headers") {
+                    let name: LitStr = meta.value()?.parse()?;
+                    options.headers = Some(name.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("extensions") {
+                    let name: LitStr = meta.value()?.parse()?;
+                    options.extensions = Some(name.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("type") {
+                    value(&meta, &mut options.ty)
+                } else if meta.path.is_ident("title") {
+                    value(&meta, &mut options.title)
+                } else if meta.path.is_ident("status") {
+                    value(&meta, &mut options.status)
+                } else if meta.path.is_ident("description") {
+                    value(&meta, &mut options.description)
+                } else {
+                    Err(meta.error("unknown problem option"))
+                }
+            })?;
+        }
+
+        Ok(options)
+    }
+
+    /// Build the expression of the `ProblemType` described by the options
+    /// of a variant or of a struct.
+    fn problem_type(
+        &self,
+        namespace: Option<&LitStr>,
+        span: proc_macro2::Span,
+    ) -> Result<TokenStream2> {
+        let missing = |option: &str| Error::new(span, format!("missing problem option `{option}`"));
+
+        let ty = self.ty.as_ref().ok_or_else(|| missing("type"))?;
+        let title = self.title.as_ref().ok_or_else(|| missing("title"))?;
+        let status = self.status.as_ref().ok_or_else(|| missing("status"))?;
+        let description = self.description.as_ref().unwrap_or(title);
+
+        let code = status.base10_parse::<u16>()?;
+        if !(100..1000).contains(&code) {
+            return Err(Error::new(status.span(), "invalid HTTP status code"));
+        }
+
+        let name = match namespace {
+            Some(namespace) => format!("{}/{}", namespace.value(), ty.value()),
+            None => ty.value(),
+        };
+
+        Ok(quote! {
+            ::metadata_http_utils::ProblemType::new(
+                #name,
+                #title,
+                match ::metadata_http_utils::__private::StatusCode::from_u16(#code) {
+                    Ok(status) => status,
+                    Err(_) => panic!("invalid HTTP status code"),
+                },
+                #description,
+            )
+        })
+    }
+
+    fn reject_type_options(&self, what: &str) -> Result<()> {
+        let options = [&self.ty, &self.title, &self.description];
+        if let Some(option) = options.into_iter().flatten().next() {
+            return Err(Error::new(
+                option.span(),
+                format!("this problem option is not allowed on {what}"),
+            ));
+        }
+        if let Some(status) = &self.status {
+            return Err(Error::new(
+                status.span(),
+                format!("this problem option is not allowed on {what}"),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let options = Options::parse(&input.attrs)?;
+
+    let (types, index) = match &input.data {
+        Data::Struct(_) => (
+            vec![options.problem_type(None, input.ident.span())?],
+            quote!(0),
+        ),
+        Data::Enum(data) => {
+            options.reject_type_options("an enum, use its variants")?;
+            if data.variants.is_empty() {
+                return Err(Error::new(
+                    input.ident.span(),
+                    "Problem can't be derived for an empty enum",
+                ));
+            }
+
+            let mut types = Vec::new();
+            let mut arms = Vec::new();
+            for (index, variant) in data.variants.iter().enumerate() {
+                let variant_options = Options::parse(&variant.attrs)?;
+                if variant_options.namespace.is_some()
+                    || variant_options.headers.is_some()
+                    || variant_options.extensions.is_some()
+                {
+                    return Err(Error::new(
+                        variant.span(),
+                        "`namespace`, `headers` and `extensions` are only allowed on the enum",
+                    ));
+                }
+
+                types.push(
+                    variant_options.problem_type(options.namespace.as_ref(), variant.span())?,
+                );
+                let ident = &variant.ident;
+                arms.push(quote!(Self::#ident { .. } => #index));
+            }
+
+            (types, quote!(match self { #(#arms,)* }))
+        }
+        Data::Union(_) => {
+            return Err(Error::new(
+                input.ident.span(),
+                "Problem can't be derived for a union",
+            ))
+        }
+    };
+    if let (Data::Struct(_), Some(namespace)) = (&input.data, &options.namespace) {
+        return Err(Error::new(
+            namespace.span(),
+            "`namespace` is only allowed on an enum",
+        ));
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let problem_type = quote! {
+        <Self as ::metadata_http_utils::ProblemTypes>::TYPES[#index]
+    };
+
+    let headers = options.headers.map(|method| {
+        quote! {
+            fn headers(&self) -> Option<::metadata_http_utils::__private::HeaderMap> {
+                Self::#method(self)
+            }
+        }
+    });
+    let extensions = options.extensions.map(|method| {
+        quote! {
+            fn extensions(&self) -> ::metadata_http_utils::Extensions {
+                Self::#method(self)
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::metadata_http_utils::ProblemTypes for #ident #ty_generics #where_clause {
+            const TYPES: &'static [::metadata_http_utils::ProblemType] = &[#(#types),*];
+        }
+
+        impl #impl_generics ::metadata_http_utils::Problem for #ident #ty_generics #where_clause {
+            fn ty(&self) -> String {
+                #problem_type.uri()
+            }
+
+            fn title(&self) -> String {
+                #problem_type.title.to_string()
+            }
+
+            fn detail(&self) -> String {
+                format!("{self}")
+            }
+
+            fn status(&self) -> Option<::metadata_http_utils::__private::StatusCode> {
+                Some(#problem_type.status)
+            }
+
+            #headers
+
+            #extensions
+        }
+    })
+}
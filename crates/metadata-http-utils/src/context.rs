@@ -0,0 +1,80 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+use http::header;
+
+/// The HTTP header carrying the identifier of a request, either received
+/// from the client or generated by the server.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The maximum length of the request identifiers sent by the clients.
+pub const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Whether a request identifier sent by a client can be logged and
+/// returned in the problem details as is: at most
+/// [MAX_REQUEST_ID_LENGTH] ASCII alphanumerics, `-`, `_`, `.` or `:`.
+pub fn is_valid_request_id(id: &[u8]) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .iter()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b':'))
+}
+
+/// Remove the `X-Request-Id` header of a request when it's not a valid
+/// identifier, so the server generates one in its place.
+pub fn sanitize_request_id<B>(mut request: http::Request<B>) -> http::Request<B> {
+    if let Some(id) = request.headers().get(REQUEST_ID_HEADER) {
+        if !is_valid_request_id(id.as_bytes()) {
+            request.headers_mut().remove(REQUEST_ID_HEADER);
+        }
+    }
+
+    request
+}
+
+/// Information about the request being processed, available to the code
+/// that doesn't have access to the request itself, like
+/// [IntoResponse](axum::response::IntoResponse) implementations.
+#[derive(Clone, Debug, Default)]
+pub struct RequestContext {
+    /// The identifier of the request, taken from the `X-Request-Id`
+    /// header when it's valid.
+    pub id: Option<String>,
+    /// The media types accepted by the client, taken from the `Accept`
+    /// header. It's used to negotiate the format of the problem details.
+    pub accept: Option<String>,
+}
+
+tokio::task_local! {
+    static CONTEXT: RequestContext;
+}
+
+impl RequestContext {
+    fn from_request(request: &Request) -> Self {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+
+        Self {
+            id: header(REQUEST_ID_HEADER).filter(|id| is_valid_request_id(id.as_bytes())),
+            accept: header(header::ACCEPT.as_str()),
+        }
+    }
+
+    /// Returns the context of the request currently being processed, or
+    /// an empty context when called outside of [scope].
+    pub fn current() -> Self {
+        CONTEXT.try_with(Clone::clone).unwrap_or_default()
+    }
+}
+
+/// A middleware making the [RequestContext] of each request available
+/// while it's being processed.
+pub async fn scope(request: Request, next: Next) -> Response {
+    let context = RequestContext::from_request(&request);
+
+    CONTEXT.scope(context, next.run(request)).await
+}
@@ -1,11 +1,14 @@
-use crate::problems::{self, Problem};
+use crate::{
+    context::RequestContext,
+    negotiate,
+    problems::{self, Problem},
+    sql::SqlProblem,
+};
 use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use http::{header, StatusCode};
-use serde_json::json;
-use std::borrow::Cow;
+use http::{header, HeaderValue, StatusCode};
 
 /// A generic HTTP error that can be emitted during the application
 /// runtime. It can be transformed into a [axum::response::Response]
@@ -13,6 +16,9 @@ use std::borrow::Cow;
 ///
 /// It can be created from either an error value that is implementing
 /// [Problem] trait or a [sqlx::Error].
+///
+/// When the problem doesn't define its own `instance`, the identifier
+/// of the request from the current [RequestContext] is used instead.
 #[derive(Debug)]
 pub enum HttpError {
     /// An error that is described by an error value implementing
@@ -23,9 +29,11 @@ pub enum HttpError {
     /// [RFC 9547]: https://datatracker.ietf.org/doc/html/rfc9457
     ProblemError(Box<dyn Problem>),
 
-    /// An error emitted by the SQL backend. It's generally transformed
-    /// into a HTTP response representing a server error, that we'll
-    /// be formatted into a problem details as defined in [RFC 9457].
+    /// An error emitted by the SQL backend. It's transformed into a
+    /// problem details as defined in [RFC 9457], according to the
+    /// SQLSTATE code of the error. The code and the name of the violated
+    /// constraint, if any, are exposed as the `code` and `constraint`
+    /// extension members.
     ///
     /// [RFC 9457]: https://datatracker.ietf.org/doc/html/rfc9457
     SQLError(sqlx::Error),
@@ -46,107 +54,59 @@ where
     }
 }
 
-impl IntoResponse for HttpError {
-    fn into_response(self) -> Response {
-        match self {
-            Self::ProblemError(problem) => {
-                let (ty, title, detail, status, instance, headers) = problem.parts();
+/// The standard members of a problem details, which can't be overridden
+/// by its extension members.
+const STANDARD_MEMBERS: [&str; 5] = ["type", "title", "detail", "status", "instance"];
 
-                let headers = if let Some(mut headers) = headers {
-                    if let Some(content_type) = headers.get_mut(header::CONTENT_TYPE) {
-                        *content_type = problems::CONTENT_TYPE;
-                    } else {
-                        headers.append(header::CONTENT_TYPE, problems::CONTENT_TYPE);
-                    }
+/// Format a problem into a HTTP response, whose body is the problem
+/// details serialized to JSON or, when the client prefers it, to XML.
+fn problem_response(problem: &dyn Problem) -> Response {
+    let context = RequestContext::current();
+    let status = problem.status();
+    let instance = problem.instance().or(context.id);
 
-                    headers
-                } else {
-                    problems::default_headers()
-                };
-
-                let body = Json(match (status, instance) {
-                    (Some(status), Some(instance)) => json!({
-                        "type": ty,
-                        "title": title,
-                        "detail": detail,
-                        "status": status.as_u16(),
-                        "instance": instance,
-                    }),
-                    (Some(status), None) => json!({
-                        "type": ty,
-                        "title": title,
-                        "detail": detail,
-                        "status": status.as_u16(),
-                    }),
-                    (None, Some(instance)) => json!({
-                        "type": ty,
-                        "title": title,
-                        "detail": detail,
-                        "instance": instance,
-                    }),
-                    _ => json!({
-                        "type": ty,
-                        "title": title,
-                        "detail": detail,
-                    }),
-                });
-
-                (status.unwrap_or(StatusCode::BAD_REQUEST), headers, body).into_response()
+    let mut headers = problems::default_headers();
+    if let Some(extra) = problem.headers() {
+        for (name, value) in &extra {
+            if name != header::CONTENT_TYPE {
+                headers.insert(name, value.clone());
             }
-            Self::SQLError(error) => {
-                use sqlx::Error;
+        }
+    }
+    headers.append(header::VARY, HeaderValue::from_static("accept"));
 
-                let mut headers = problems::default_headers();
-                let (status_code, body) = match error {
-                    Error::Database(error) => {
-                        let code = error.code();
+    let mut body = problem.extensions().into_inner();
+    body.retain(|name, _| !STANDARD_MEMBERS.contains(&name.as_str()));
+    body.insert("type".to_owned(), problem.ty().into());
+    body.insert("title".to_owned(), problem.title().into());
+    body.insert("detail".to_owned(), problem.detail().into());
+    if let Some(status) = status {
+        body.insert("status".to_owned(), status.as_u16().into());
+    }
+    if let Some(instance) = instance {
+        body.insert("instance".to_owned(), instance.into());
+    }
 
-                        match code {
-                            Some(Cow::Borrowed("28P01")) => (
-                                StatusCode::BAD_GATEWAY,
-                                json!({
-                                    "type": "https://errors.taster.com/metadata/sql/bad-connection",
-                                    "title": "Bad SQL Connection.",
-                                    "detail": "Unable to establish connection with the database.",
-                                }),
-                            ),
-                            // TODO(rigma): we'll likely have more SQLSTATE error codes to handle in the future.
-                            _ => (
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                json!({
-                                    "type": "https://errors.taster.com/sql/database-error",
-                                    "title": "Database Error.",
-                                    "detail": "An error with the database occured while processing a query.",
-                                }),
-                            ),
-                        }
-                    }
-                    Error::PoolClosed | Error::PoolTimedOut => {
-                        // TODO(rigma): arbitrary value used here
-                        headers.append(header::RETRY_AFTER, 120.into());
+    let status = status.unwrap_or(StatusCode::BAD_REQUEST);
+    let media_type = context
+        .accept
+        .as_deref()
+        .and_then(|accept| negotiate::preferred(accept, &problems::MEDIA_TYPES));
 
-                        (
-                            StatusCode::GATEWAY_TIMEOUT,
-                            json!({
-                                "type": "https://errors.taster.com/metadata/sql/connection-closed",
-                                "title": "Database Connection Closed.",
-                                "detail": format!("{error}"),
-                            }),
-                        )
-                    }
-                    // TODO(rigma): you should support more SQLx errors in the future
-                    _ => (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        json!({
-                            "type": "https://errors.taster.com/metadata/sql/unknown-error",
-                            "title": "Unknown database error",
-                            "detail": format!("{error}"),
-                        }),
-                    ),
-                };
+    match media_type {
+        Some(media_type) if media_type.ends_with("xml") => {
+            headers.insert(header::CONTENT_TYPE, problems::XML_CONTENT_TYPE);
+            (status, headers, problems::to_xml(&body)).into_response()
+        }
+        _ => (status, headers, Json(body)).into_response(),
+    }
+}
 
-                (status_code, headers, Json(body)).into_response()
-            }
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::ProblemError(problem) => problem_response(problem.as_ref()),
+            Self::SQLError(error) => problem_response(&SqlProblem::from(&error)),
         }
     }
 }
@@ -0,0 +1,91 @@
+//! Wrappers of the [axum] extractors rejecting the invalid requests with
+//! problem details, rather than with the plain-text responses of [axum].
+
+use crate::{rejection::RejectionError, HttpError};
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Request},
+};
+use http::request::Parts;
+use serde::de::DeserializeOwned;
+
+/// An extractor deserializing the parameters of the path, like
+/// [axum::extract::Path].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Path::from_request_parts(parts, state).await {
+            Ok(axum::extract::Path(value)) => Ok(Self(value)),
+            Err(rejection) => Err(RejectionError::from(rejection).into()),
+        }
+    }
+}
+
+/// An extractor deserializing the query string, like
+/// [axum::extract::Query].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Query::from_request_parts(parts, state).await {
+            Ok(axum::extract::Query(value)) => Ok(Self(value)),
+            Err(rejection) => Err(RejectionError::from(rejection).into()),
+        }
+    }
+}
+
+/// An extractor buffering the raw request body, like [axum::body::Bytes].
+#[derive(Clone, Debug, Default)]
+pub struct Bytes(pub axum::body::Bytes);
+
+#[async_trait]
+impl<S> FromRequest<S> for Bytes
+where
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::body::Bytes::from_request(request, state).await {
+            Ok(value) => Ok(Self(value)),
+            Err(rejection) => Err(RejectionError::from(rejection).into()),
+        }
+    }
+}
+
+/// An extractor deserializing a JSON request body, like [axum::Json].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::Json::from_request(request, state).await {
+            Ok(axum::Json(value)) => Ok(Self(value)),
+            Err(rejection) => Err(RejectionError::from(rejection).into()),
+        }
+    }
+}
@@ -7,8 +7,29 @@
 //!
 //! [RFC 9457]: https://datatracker.ietf.org/doc/html/rfc9457
 
+// The code generated by the `Problem` derive macro refers to this crate
+// by its name, including within the crate itself.
+extern crate self as metadata_http_utils;
+
+pub mod context;
 mod error;
-pub(crate) mod problems;
+pub mod extract;
+pub mod negotiate;
+pub mod problems;
+pub mod rejection;
+mod sql;
 
+pub use context::RequestContext;
 pub use error::HttpError;
-pub use problems::Problem;
+pub use metadata_http_derive::Problem;
+pub use negotiate::Format;
+pub use problems::{Extensions, InvalidParam, Problem, ProblemType, ProblemTypes, Registry};
+pub use rejection::RejectionError;
+pub use sql::SqlProblem;
+
+/// The items used by the code generated by the [Problem](macro@Problem)
+/// derive macro. It's not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use http::{HeaderMap, StatusCode};
+}
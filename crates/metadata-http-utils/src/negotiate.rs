@@ -0,0 +1,186 @@
+//! Content negotiation of the responses, driven by the `Accept` header
+//! of the requests.
+
+use crate::{HttpError, Problem};
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    response::{IntoResponse, Response},
+};
+use http::{header, request::Parts, HeaderValue};
+use serde::Serialize;
+use std::convert::Infallible;
+use thiserror::Error;
+
+/// Returns the media type of `available` the client prefers according to
+/// the value of its `Accept` header, or `None` if it accepts none of
+/// them.
+///
+/// The media types with the same quality are preferred in the order of
+/// `available`, so its first item is picked for `*/*`.
+pub fn preferred<'a>(accept: &str, available: &[&'a str]) -> Option<&'a str> {
+    let ranges = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parameters = range.split(';').map(str::trim);
+            let media_range = parameters.next().filter(|range| !range.is_empty())?;
+            let quality = parameters
+                .filter_map(|parameter| parameter.strip_prefix("q="))
+                .find_map(|quality| quality.parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            Some((media_range.to_ascii_lowercase(), quality))
+        })
+        .collect::<Vec<_>>();
+
+    let quality = |media_type: &str| {
+        let (ty, _) = media_type.split_once('/').unwrap_or((media_type, ""));
+        let wildcard = format!("{ty}/*");
+
+        // The most specific media range matching the type applies.
+        ranges
+            .iter()
+            .find(|(range, _)| range == media_type)
+            .or_else(|| ranges.iter().find(|(range, _)| *range == wildcard))
+            .or_else(|| ranges.iter().find(|(range, _)| range == "*/*"))
+            .map(|(_, quality)| *quality)
+    };
+
+    let mut preferred: Option<(&str, f32)> = None;
+    for media_type in available {
+        let Some(quality) = quality(media_type).filter(|quality| *quality > 0.0) else {
+            continue;
+        };
+        if !matches!(preferred, Some((_, best)) if best >= quality) {
+            preferred = Some((media_type, quality));
+        }
+    }
+
+    preferred.map(|(media_type, _)| media_type)
+}
+
+/// The representations of the resources returned by the successful
+/// responses, picked from the `Accept` header of the requests.
+///
+/// JSON is used when the client doesn't send an `Accept` header or when
+/// it accepts none of the supported media types, so the older clients
+/// keep working.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Json,
+    Yaml,
+    MessagePack,
+}
+
+impl Format {
+    const MEDIA_TYPES: [(&'static str, Self); 6] = [
+        ("application/json", Self::Json),
+        ("application/yaml", Self::Yaml),
+        ("application/x-yaml", Self::Yaml),
+        ("text/yaml", Self::Yaml),
+        ("application/msgpack", Self::MessagePack),
+        ("application/x-msgpack", Self::MessagePack),
+    ];
+
+    /// Returns the format of the successful responses to a request
+    /// sending the given `Accept` header.
+    pub fn from_accept(accept: Option<&str>) -> Self {
+        let available = Self::MEDIA_TYPES.map(|(media_type, _)| media_type);
+
+        accept
+            .and_then(|accept| preferred(accept, &available))
+            .and_then(|media_type| {
+                Self::MEDIA_TYPES
+                    .iter()
+                    .find(|(candidate, _)| *candidate == media_type)
+            })
+            .map_or(Self::Json, |(_, format)| *format)
+    }
+
+    pub fn content_type(self) -> HeaderValue {
+        HeaderValue::from_static(match self {
+            Self::Json => "application/json",
+            Self::Yaml => "application/yaml",
+            Self::MessagePack => "application/msgpack",
+        })
+    }
+
+    /// Serialize a value to the format, and make it the body of a
+    /// response.
+    pub fn respond<T: Serialize>(self, value: T) -> Response {
+        let body = match self {
+            Self::Json => {
+                serde_json::to_vec(&value).map_err(|error| FormatError::new("JSON", error))
+            }
+            Self::Yaml => serde_yaml::to_string(&value)
+                .map(String::into_bytes)
+                .map_err(|error| FormatError::new("YAML", error)),
+            Self::MessagePack => {
+                // The values are serialized like with the other formats,
+                // e.g. the UUIDs as strings rather than as byte arrays.
+                let mut body = Vec::new();
+                let mut serializer = rmp_serde::Serializer::new(&mut body)
+                    .with_struct_map()
+                    .with_human_readable();
+                value
+                    .serialize(&mut serializer)
+                    .map(|_| body)
+                    .map_err(|error| FormatError::new("MessagePack", error))
+            }
+        };
+
+        match body {
+            Ok(body) => (
+                [
+                    (header::CONTENT_TYPE, self.content_type()),
+                    (header::VARY, HeaderValue::from_static("accept")),
+                ],
+                body,
+            )
+                .into_response(),
+            Err(error) => HttpError::from(error).into_response(),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Format
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let accept = parts
+            .headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok());
+
+        Ok(Self::from_accept(accept))
+    }
+}
+
+/// An error emitted when a resource can't be serialized to the format
+/// negotiated with the client.
+#[derive(Debug, Error, Problem)]
+#[error("The resource can't be serialized to {format}: {reason}")]
+#[problem(
+    type = "negotiation/serialization-error",
+    title = "Serialization Error.",
+    status = 500,
+    description = "The resource can't be represented in the requested format."
+)]
+pub struct FormatError {
+    format: &'static str,
+    reason: String,
+}
+
+impl FormatError {
+    fn new(format: &'static str, reason: impl ToString) -> Self {
+        Self {
+            format,
+            reason: reason.to_string(),
+        }
+    }
+}
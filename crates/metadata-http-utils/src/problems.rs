@@ -1,6 +1,80 @@
+//! The [RFC 9457] problem details of the application: the [Problem]
+//! trait implemented by the error values, and the [ProblemType]
+//! descriptors gathered into a [Registry] so they can be documented.
+//!
+//! [RFC 9457]: https://datatracker.ietf.org/doc/html/rfc9457
+
 use http::{header, HeaderMap, HeaderValue, StatusCode};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, sync::OnceLock};
 
 pub(crate) const CONTENT_TYPE: HeaderValue = HeaderValue::from_static("application/problem+json");
+pub(crate) const XML_CONTENT_TYPE: HeaderValue =
+    HeaderValue::from_static("application/problem+xml");
+
+/// The media types the problem details can be represented with, the XML
+/// ones being only used when the client prefers them.
+pub(crate) const MEDIA_TYPES: [&str; 5] = [
+    "application/problem+json",
+    "application/json",
+    "application/problem+xml",
+    "application/xml",
+    "text/xml",
+];
+
+/// The base URI of the problem types, used until [set_base_uri] is
+/// called.
+pub const DEFAULT_BASE_URI: &str = "https://errors.taster.com/metadata";
+
+static BASE_URI: OnceLock<String> = OnceLock::new();
+
+/// Set the base URI the problem type names are resolved against, e.g.
+/// `https://metadata.example.com/problems` to point the clients to the
+/// pages served by the application. Only the first call has an effect,
+/// it's expected to be made once at startup.
+pub fn set_base_uri(uri: impl Into<String>) {
+    let uri = uri.into();
+    let _ = BASE_URI.set(uri.trim_end_matches('/').to_owned());
+}
+
+/// Returns the base URI of the problem types.
+pub fn base_uri() -> &'static str {
+    BASE_URI.get().map_or(DEFAULT_BASE_URI, String::as_str)
+}
+
+/// The extension members of a problem details, added alongside the
+/// standard members as permitted by [RFC 9457](https://datatracker.ietf.org/doc/html/rfc9457#name-extension-members).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Extensions(Map<String, Value>);
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an extension member. A value that can't be serialized to
+    /// JSON is replaced by `null`.
+    pub fn with(mut self, name: impl Into<String>, value: impl Serialize) -> Self {
+        self.insert(name, value);
+        self
+    }
+
+    /// Add an extension member. A value that can't be serialized to
+    /// JSON is replaced by `null`.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Serialize) {
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+        self.0.insert(name.into(), value);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn into_inner(self) -> Map<String, Value> {
+        self.0
+    }
+}
 
 /// `Problem` is a trait to help define the problem details format
 /// described in [RFC 9457] to structures that are implementing
@@ -64,6 +138,26 @@ pub(crate) const CONTENT_TYPE: HeaderValue = HeaderValue::from_static("applicati
 /// }
 /// ```
 ///
+/// The trait, along with [ProblemTypes], is usually implemented with
+/// the [derive macro](macro@crate::Problem) instead, which describes
+/// each problem type with a `#[problem(...)]` attribute:
+///
+/// ```ignore
+/// # use metadata_http_utils::Problem;
+/// use thiserror::Error;
+///
+/// #[derive(Debug, Error, Problem)]
+/// #[problem(namespace = "tacos")]
+/// enum AppError {
+///     #[error("Foo is good enough")]
+///     #[problem(type = "foo", title = "Foo", status = 400)]
+///     Foo,
+///     #[error("But bar has something to say: {0}")]
+///     #[problem(type = "bar", title = "Bar", status = 409)]
+///     Bar(String),
+/// }
+/// ```
+///
 /// [RFC 9457]: https://datatracker.ietf.org/doc/html/rfc9457
 /// [thiserror]: https://github.com/dtolnay/thiserror
 pub trait Problem: std::error::Error {
@@ -106,6 +200,13 @@ pub trait Problem: std::error::Error {
         None
     }
 
+    /// The extension members of this occurence of the problem, such
+    /// as the list of the invalid parameters of a request. The members
+    /// named after a standard member are ignored.
+    fn extensions(&self) -> Extensions {
+        Extensions::default()
+    }
+
     /// A provided trait method that is the values returned
     /// by the others methods in a tuple.
     fn parts(
@@ -129,6 +230,162 @@ pub trait Problem: std::error::Error {
     }
 }
 
+/// A parameter of a request that is invalid, listed in the
+/// `invalid_params` extension member of a problem details as in the
+/// examples of [RFC 9457](https://datatracker.ietf.org/doc/html/rfc9457#name-extension-members).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct InvalidParam {
+    /// The name of the parameter, e.g. the name of a field of the body.
+    pub name: String,
+    /// Why the value of the parameter is invalid.
+    pub reason: String,
+}
+
+impl InvalidParam {
+    pub fn new(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// The description of a problem type, used to build its URI and to
+/// document it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProblemType {
+    /// The name of the type, relative to the [base URI](base_uri), e.g.
+    /// `"blocks/not-found"`.
+    pub name: &'static str,
+    /// A short, human-readable summary of the problem type.
+    pub title: &'static str,
+    /// The HTTP response status code of the occurences of the problem.
+    pub status: StatusCode,
+    /// A human-readable explanation of when the problem occurs and how
+    /// a client can recover from it.
+    pub description: &'static str,
+}
+
+impl ProblemType {
+    pub const fn new(
+        name: &'static str,
+        title: &'static str,
+        status: StatusCode,
+        description: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            title,
+            status,
+            description,
+        }
+    }
+
+    /// The URI identifying the problem type, made of the
+    /// [base URI](base_uri) and the name of the type.
+    pub fn uri(&self) -> String {
+        format!("{}/{}", base_uri(), self.name)
+    }
+}
+
+/// A trait implemented by the error values to list the problem types
+/// they can be formatted into, so they can be added to a [Registry].
+pub trait ProblemTypes {
+    const TYPES: &'static [ProblemType];
+}
+
+/// A collection of problem types, indexed by name.
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    types: BTreeMap<&'static str, ProblemType>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the problem types of an error value to the registry.
+    pub fn register<P: ProblemTypes>(mut self) -> Self {
+        for ty in P::TYPES {
+            self.types.insert(ty.name, *ty);
+        }
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ProblemType> {
+        self.types.get(name)
+    }
+
+    /// Iterate over the registered problem types, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = &ProblemType> {
+        self.types.values()
+    }
+}
+
+/// Serialize the members of a problem details to the XML format described
+/// in [RFC 9457](https://datatracker.ietf.org/doc/html/rfc9457#name-xml-format).
+///
+/// The arrays are represented by a sequence of `i` elements and the
+/// objects by nested elements, as suggested by the RFC.
+pub(crate) fn to_xml(members: &Map<String, Value>) -> String {
+    fn escape(value: &str) -> String {
+        value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    /// Replace the characters that are not allowed in XML element names.
+    fn element_name(name: &str) -> String {
+        let name = name
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | '.' => c,
+                _ => '_',
+            })
+            .collect::<String>();
+
+        match name.chars().next() {
+            Some('a'..='z' | 'A'..='Z' | '_') => name,
+            _ => format!("_{name}"),
+        }
+    }
+
+    fn write_element(xml: &mut String, name: &str, value: &Value) {
+        let name = element_name(name);
+        match value {
+            Value::Null => xml.push_str(&format!("<{name}/>")),
+            Value::String(value) => xml.push_str(&format!("<{name}>{}</{name}>", escape(value))),
+            Value::Array(items) => {
+                xml.push_str(&format!("<{name}>"));
+                for item in items {
+                    write_element(xml, "i", item);
+                }
+                xml.push_str(&format!("</{name}>"));
+            }
+            Value::Object(members) => {
+                xml.push_str(&format!("<{name}>"));
+                for (name, value) in members {
+                    write_element(xml, name, value);
+                }
+                xml.push_str(&format!("</{name}>"));
+            }
+            value => xml.push_str(&format!("<{name}>{value}</{name}>")),
+        }
+    }
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<problem xmlns=\"urn:ietf:rfc:7807\">",
+    );
+    for (name, value) in members {
+        write_element(&mut xml, name, value);
+    }
+    xml.push_str("</problem>\n");
+
+    xml
+}
+
 /// Returns the default HTTP headers that should be used for an HTTP
 /// response that is containing the problem details.
 pub(crate) fn default_headers() -> HeaderMap {
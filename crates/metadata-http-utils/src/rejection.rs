@@ -0,0 +1,182 @@
+//! The problems describing the requests rejected by the extractors or
+//! by the router, so the clients only have to handle problem details.
+
+use crate::{
+    problems::{Extensions, InvalidParam},
+    HttpError, Problem,
+};
+use axum::extract::rejection::{BytesRejection, JsonRejection, PathRejection, QueryRejection};
+use http::{Method, StatusCode, Uri};
+use thiserror::Error;
+
+/// An error emitted when a request is rejected before reaching its
+/// handler.
+#[derive(Debug, Error, Problem)]
+#[problem(namespace = "requests", extensions = "invalid_params")]
+pub enum RejectionError {
+    #[error("{detail}")]
+    #[problem(
+        type = "invalid-path",
+        title = "Invalid Path Parameter.",
+        status = 400,
+        description = "A segment of the request path can't be parsed. The `invalid_params` \
+                       member lists the parameters at fault, when they are known."
+    )]
+    InvalidPath {
+        detail: String,
+        params: Vec<InvalidParam>,
+    },
+    #[error("{0}")]
+    #[problem(
+        type = "invalid-query",
+        title = "Invalid Query String.",
+        status = 400,
+        description = "The query string of the request can't be parsed."
+    )]
+    InvalidQuery(String),
+    #[error("{0}")]
+    #[problem(
+        type = "malformed-body",
+        title = "Malformed Request Body.",
+        status = 400,
+        description = "The body of the request is not syntactically valid."
+    )]
+    MalformedBody(String),
+    #[error("{0}")]
+    #[problem(
+        type = "invalid-body",
+        title = "Invalid Request Body.",
+        status = 422,
+        description = "The body of the request is well-formed, but doesn't describe the \
+                       expected resource, e.g. a field is missing or has the wrong type."
+    )]
+    InvalidBody(String),
+    #[error("{0}")]
+    #[problem(
+        type = "unsupported-media-type",
+        title = "Unsupported Media Type.",
+        status = 415,
+        description = "The body of the request must be sent with the `application/json` \
+                       content type."
+    )]
+    UnsupportedMediaType(String),
+    #[error("{0}")]
+    #[problem(
+        type = "payload-too-large",
+        title = "Payload Too Large.",
+        status = 413,
+        description = "The body of the request is larger than the service accepts."
+    )]
+    PayloadTooLarge(String),
+    #[error("No route matches the path '{0}'.")]
+    #[problem(
+        type = "route-not-found",
+        title = "Route Not Found.",
+        status = 404,
+        description = "The path of the request doesn't match any route of the service."
+    )]
+    RouteNotFound(String),
+    #[error("The method {method} is not allowed on '{path}'.")]
+    #[problem(
+        type = "method-not-allowed",
+        title = "Method Not Allowed.",
+        status = 405,
+        description = "The route doesn't support the method of the request. The `Allow` \
+                       header lists the supported methods."
+    )]
+    MethodNotAllowed { method: Method, path: String },
+    #[error("{0}")]
+    #[problem(
+        type = "internal-error",
+        title = "Internal Error.",
+        status = 500,
+        description = "The request can't be processed because of a defect of the service."
+    )]
+    Internal(String),
+}
+
+impl RejectionError {
+    fn invalid_params(&self) -> Extensions {
+        match self {
+            Self::InvalidPath { params, .. } if !params.is_empty() => {
+                Extensions::new().with("invalid_params", params)
+            }
+            _ => Extensions::new(),
+        }
+    }
+}
+
+impl From<PathRejection> for RejectionError {
+    fn from(rejection: PathRejection) -> Self {
+        use axum::extract::path::ErrorKind;
+
+        match rejection {
+            PathRejection::FailedToDeserializePathParams(error) => {
+                let params = match error.kind() {
+                    ErrorKind::ParseErrorAtKey {
+                        key, expected_type, ..
+                    } => vec![InvalidParam::new(key, format!("expected {expected_type}"))],
+                    ErrorKind::InvalidUtf8InPathParam { key } => {
+                        vec![InvalidParam::new(key, "invalid UTF-8")]
+                    }
+                    _ => Vec::new(),
+                };
+
+                Self::InvalidPath {
+                    detail: error.body_text(),
+                    params,
+                }
+            }
+            rejection => Self::Internal(rejection.body_text()),
+        }
+    }
+}
+
+impl From<QueryRejection> for RejectionError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::InvalidQuery(rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for RejectionError {
+    fn from(rejection: JsonRejection) -> Self {
+        let detail = rejection.body_text();
+
+        match rejection {
+            JsonRejection::JsonDataError(_) => Self::InvalidBody(detail),
+            JsonRejection::MissingJsonContentType(_) => Self::UnsupportedMediaType(detail),
+            rejection if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+                Self::PayloadTooLarge(detail)
+            }
+            _ => Self::MalformedBody(detail),
+        }
+    }
+}
+
+impl From<BytesRejection> for RejectionError {
+    fn from(rejection: BytesRejection) -> Self {
+        let detail = rejection.body_text();
+
+        if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            Self::PayloadTooLarge(detail)
+        } else {
+            Self::MalformedBody(detail)
+        }
+    }
+}
+
+/// A router fallback responding with a problem to the requests that
+/// don't match any route.
+pub async fn route_not_found(uri: Uri) -> HttpError {
+    RejectionError::RouteNotFound(uri.path().to_owned()).into()
+}
+
+/// A router fallback responding with a problem to the requests whose
+/// method is not supported by the matched route.
+pub async fn method_not_allowed(method: Method, uri: Uri) -> HttpError {
+    RejectionError::MethodNotAllowed {
+        method,
+        path: uri.path().to_owned(),
+    }
+    .into()
+}
@@ -0,0 +1,295 @@
+use crate::problems::{Extensions, Problem, ProblemType, ProblemTypes};
+use http::{header, HeaderMap, StatusCode};
+use sqlx::error::DatabaseError;
+use thiserror::Error;
+
+/// A problem details describing an error emitted by the SQL backend.
+///
+/// The SQLSTATE code of the error and the name of the violated
+/// constraint, if any, are exposed as the `code` and `constraint`
+/// extension members.
+#[derive(Debug, Error)]
+#[error("{detail}")]
+pub struct SqlProblem {
+    ty: &'static ProblemType,
+    detail: String,
+    /// The number of seconds the client should wait before retrying, when
+    /// the request can be retried as is.
+    retry_after: Option<u32>,
+    code: Option<String>,
+    constraint: Option<String>,
+}
+
+impl SqlProblem {
+    const UNIQUE_VIOLATION: ProblemType = ProblemType::new(
+        "sql/unique-violation",
+        "Resource Already Exists.",
+        StatusCode::CONFLICT,
+        "The resource conflicts with an existing one, e.g. it has the same name. \
+         The `constraint` member names the unique constraint that was violated.",
+    );
+    const FOREIGN_KEY_VIOLATION: ProblemType = ProblemType::new(
+        "sql/foreign-key-violation",
+        "Referenced Resource Not Found.",
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "The resource references another resource that doesn't exist.",
+    );
+    const NOT_NULL_VIOLATION: ProblemType = ProblemType::new(
+        "sql/not-null-violation",
+        "Missing Value.",
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "A required value of the resource is missing.",
+    );
+    const CHECK_VIOLATION: ProblemType = ProblemType::new(
+        "sql/check-violation",
+        "Invalid Resource.",
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "The resource doesn't satisfy a constraint of the database. The \
+         `constraint` member names the constraint that was violated.",
+    );
+    const SERIALIZATION_FAILURE: ProblemType = ProblemType::new(
+        "sql/serialization-failure",
+        "Concurrent Update.",
+        StatusCode::SERVICE_UNAVAILABLE,
+        "The query conflicted with a concurrent transaction. The request can be \
+         retried as is after the delay of the `Retry-After` header.",
+    );
+    const DEADLOCK_DETECTED: ProblemType = ProblemType::new(
+        "sql/deadlock-detected",
+        "Concurrent Update.",
+        StatusCode::SERVICE_UNAVAILABLE,
+        "The query was aborted to resolve a deadlock. The request can be retried \
+         as is after the delay of the `Retry-After` header.",
+    );
+    const QUERY_CANCELED: ProblemType = ProblemType::new(
+        "sql/query-canceled",
+        "Database Query Timed Out.",
+        StatusCode::GATEWAY_TIMEOUT,
+        "The query was canceled before its completion, likely because it took \
+         too long.",
+    );
+    const DATABASE_UNAVAILABLE: ProblemType = ProblemType::new(
+        "sql/database-unavailable",
+        "Database Unavailable.",
+        StatusCode::SERVICE_UNAVAILABLE,
+        "The database is not able to process queries at the moment, e.g. it's \
+         restarting or has too many connections.",
+    );
+    const BAD_CONNECTION: ProblemType = ProblemType::new(
+        "sql/bad-connection",
+        "Bad SQL Connection.",
+        StatusCode::BAD_GATEWAY,
+        "The service is unable to establish a connection with the database.",
+    );
+    const CONNECTION_CLOSED: ProblemType = ProblemType::new(
+        "sql/connection-closed",
+        "Database Connection Closed.",
+        StatusCode::GATEWAY_TIMEOUT,
+        "No connection to the database could be acquired in time.",
+    );
+    const DATABASE_ERROR: ProblemType = ProblemType::new(
+        "sql/database-error",
+        "Database Error.",
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "The database emitted an unexpected error while processing a query.",
+    );
+    const UNKNOWN_ERROR: ProblemType = ProblemType::new(
+        "sql/unknown-error",
+        "Unknown database error",
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "An unexpected error occured while communicating with the database.",
+    );
+
+    fn new(ty: &'static ProblemType, detail: impl ToString) -> Self {
+        Self {
+            ty,
+            detail: detail.to_string(),
+            retry_after: None,
+            code: None,
+            constraint: None,
+        }
+    }
+
+    /// The number of seconds to wait before retrying a transaction
+    /// aborted because of a concurrent one, which is usually committed by
+    /// then.
+    const CONFLICT_RETRY_AFTER: u32 = 1;
+
+    /// The number of seconds to wait before retrying when the database
+    /// refuses the queries, the time for it to finish restarting or for
+    /// connections to be released.
+    const UNAVAILABLE_RETRY_AFTER: u32 = 5;
+
+    /// The number of seconds to wait before retrying when no connection
+    /// could be acquired from the pool. The pool was saturated for as long
+    /// as its acquire timeout, 30 seconds by default, so the requests
+    /// retried sooner would likely wait in the same queue.
+    const POOL_TIMEOUT_RETRY_AFTER: u32 = 30;
+
+    fn retry_after(mut self, seconds: u32) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+
+    /// Map an error emitted by the database to a problem, according to
+    /// its SQLSTATE code.
+    ///
+    /// The message of the database isn't forwarded to the client, as it
+    /// may leak details about the schema or the queries.
+    fn from_database_error(error: &dyn DatabaseError) -> Self {
+        let code = error.code().map(|code| code.into_owned());
+        let class = code.as_deref().and_then(|code| code.get(..2));
+
+        let problem = match (code.as_deref(), class) {
+            (Some("23505"), _) => Self::new(
+                &Self::UNIQUE_VIOLATION,
+                "The resource conflicts with an existing one.",
+            ),
+            // The deletions cascade to the referencing rows, so a violated
+            // foreign key is always a reference to a missing row.
+            (Some("23503"), _) => Self::new(
+                &Self::FOREIGN_KEY_VIOLATION,
+                "The resource references a resource that doesn't exist.",
+            ),
+            (Some("23502"), _) => Self::new(
+                &Self::NOT_NULL_VIOLATION,
+                "A required value of the resource is missing.",
+            ),
+            (Some("23514"), _) => Self::new(
+                &Self::CHECK_VIOLATION,
+                "The resource doesn't satisfy a constraint of the database.",
+            ),
+            (Some("40001"), _) => Self::new(
+                &Self::SERIALIZATION_FAILURE,
+                "The query conflicted with a concurrent transaction and can be retried.",
+            )
+            .retry_after(Self::CONFLICT_RETRY_AFTER),
+            (Some("40P01"), _) => Self::new(
+                &Self::DEADLOCK_DETECTED,
+                "The query was aborted to resolve a deadlock and can be retried.",
+            )
+            .retry_after(Self::CONFLICT_RETRY_AFTER),
+            (Some("57014"), _) => Self::new(
+                &Self::QUERY_CANCELED,
+                "The query was canceled before its completion, likely because it took too long.",
+            ),
+            (Some("53300" | "57P01" | "57P02" | "57P03"), _) => Self::new(
+                &Self::DATABASE_UNAVAILABLE,
+                "The database is not able to process queries at the moment.",
+            )
+            .retry_after(Self::UNAVAILABLE_RETRY_AFTER),
+            (Some("28000" | "28P01"), _) | (_, Some("08")) => Self::new(
+                &Self::BAD_CONNECTION,
+                "Unable to establish connection with the database.",
+            ),
+            _ => Self::new(
+                &Self::DATABASE_ERROR,
+                "An error with the database occured while processing a query.",
+            ),
+        };
+
+        Self {
+            code,
+            constraint: error.constraint().map(ToString::to_string),
+            ..problem
+        }
+    }
+
+    /// Map an error of the database, or of the connection to it, to a
+    /// problem.
+    fn from_error(error: &sqlx::Error) -> Self {
+        use sqlx::Error;
+
+        match error {
+            Error::Database(error) => {
+                let problem = Self::from_database_error(error.as_ref());
+                if problem.ty.status.is_server_error() {
+                    tracing::error!(%error, "the database failed to process a query");
+                }
+                problem
+            }
+            Error::PoolClosed | Error::PoolTimedOut => Self::new(
+                &Self::CONNECTION_CLOSED,
+                "No connection to the database could be acquired in time.",
+            )
+            .retry_after(Self::POOL_TIMEOUT_RETRY_AFTER),
+            Error::Io(_) | Error::Tls(_) => {
+                tracing::error!(%error, "unable to connect to the database");
+                Self::new(
+                    &Self::BAD_CONNECTION,
+                    "Unable to establish connection with the database.",
+                )
+            }
+            _ => {
+                // The other errors, e.g. the ones decoding the rows, tell the
+                // columns of the queries, so they are only logged.
+                tracing::error!(%error, "unexpected database error");
+                Self::new(
+                    &Self::UNKNOWN_ERROR,
+                    "An unexpected error occured while communicating with the database.",
+                )
+            }
+        }
+    }
+}
+
+impl From<&sqlx::Error> for SqlProblem {
+    fn from(error: &sqlx::Error) -> Self {
+        Self::from_error(error)
+    }
+}
+
+impl Problem for SqlProblem {
+    fn ty(&self) -> String {
+        self.ty.uri()
+    }
+
+    fn title(&self) -> String {
+        self.ty.title.to_string()
+    }
+
+    fn detail(&self) -> String {
+        self.detail.clone()
+    }
+
+    fn status(&self) -> Option<StatusCode> {
+        Some(self.ty.status)
+    }
+
+    fn headers(&self) -> Option<HeaderMap> {
+        let retry_after = self.retry_after?;
+        let mut headers = HeaderMap::new();
+        headers.append(header::RETRY_AFTER, retry_after.into());
+
+        Some(headers)
+    }
+
+    fn extensions(&self) -> Extensions {
+        let mut extensions = Extensions::new();
+        if let Some(code) = &self.code {
+            extensions.insert("code", code);
+        }
+        if let Some(constraint) = &self.constraint {
+            extensions.insert("constraint", constraint);
+        }
+
+        extensions
+    }
+}
+
+impl ProblemTypes for SqlProblem {
+    const TYPES: &'static [ProblemType] = &[
+        Self::UNIQUE_VIOLATION,
+        Self::FOREIGN_KEY_VIOLATION,
+        Self::NOT_NULL_VIOLATION,
+        Self::CHECK_VIOLATION,
+        Self::SERIALIZATION_FAILURE,
+        Self::DEADLOCK_DETECTED,
+        Self::QUERY_CANCELED,
+        Self::DATABASE_UNAVAILABLE,
+        Self::BAD_CONNECTION,
+        Self::CONNECTION_CLOSED,
+        Self::DATABASE_ERROR,
+        Self::UNKNOWN_ERROR,
+    ];
+}
@@ -0,0 +1,50 @@
+use http::Request;
+use metadata_http_utils::context::{
+    is_valid_request_id, sanitize_request_id, MAX_REQUEST_ID_LENGTH, REQUEST_ID_HEADER,
+};
+
+#[test]
+fn accepts_the_usual_request_ids() {
+    for id in [
+        "0192c6a4-8f4e-7c2b-9a3e-5d1f0b7e6a21",
+        "4bf92f3577b34da6a3ce929d0e0e4736",
+        "req_01HB.retry:2",
+    ] {
+        assert!(is_valid_request_id(id.as_bytes()), "{id}");
+    }
+
+    let longest = "a".repeat(MAX_REQUEST_ID_LENGTH);
+    assert!(is_valid_request_id(longest.as_bytes()));
+}
+
+#[test]
+fn refuses_the_request_ids_which_are_unsafe_to_echo() {
+    let too_long = "a".repeat(MAX_REQUEST_ID_LENGTH + 1);
+    for id in [
+        "",
+        too_long.as_str(),
+        "id with spaces",
+        "<script>alert(1)</script>",
+        "https://example.com/",
+        "id\"}",
+        "identifiant-généré",
+    ] {
+        assert!(!is_valid_request_id(id.as_bytes()), "{id}");
+    }
+}
+
+#[test]
+fn removes_the_invalid_request_ids() {
+    let request = |id: &str| {
+        Request::builder()
+            .header(REQUEST_ID_HEADER, id)
+            .body(())
+            .unwrap()
+    };
+
+    let valid = sanitize_request_id(request("0192c6a4-8f4e-7c2b"));
+    assert_eq!(valid.headers()[REQUEST_ID_HEADER], "0192c6a4-8f4e-7c2b");
+
+    let invalid = sanitize_request_id(request("<script>"));
+    assert!(invalid.headers().get(REQUEST_ID_HEADER).is_none());
+}
@@ -0,0 +1,111 @@
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use metadata_http_utils::{problems, Extensions, Problem, ProblemType, ProblemTypes};
+use thiserror::Error;
+
+#[derive(Debug, Error, Problem)]
+#[problem(namespace = "tacos", headers = "headers", extensions = "extensions")]
+enum TacoError {
+    #[error("Taco '{0}' is not found.")]
+    #[problem(type = "not-found", title = "Taco Not Found.", status = 404)]
+    NotFound(String),
+    #[error("Too many tacos, {eaten} were already eaten.")]
+    #[problem(
+        type = "too-many",
+        title = "Too Many Tacos.",
+        status = 429,
+        description = "The tacos are eaten faster than they're cooked."
+    )]
+    TooMany { eaten: u32 },
+}
+
+impl TacoError {
+    fn headers(&self) -> Option<HeaderMap> {
+        match self {
+            Self::NotFound(_) => None,
+            Self::TooMany { .. } => {
+                let mut headers = HeaderMap::new();
+                headers.insert(header::RETRY_AFTER, HeaderValue::from_static("60"));
+                Some(headers)
+            }
+        }
+    }
+
+    fn extensions(&self) -> Extensions {
+        match self {
+            Self::NotFound(name) => Extensions::new().with("name", name),
+            Self::TooMany { eaten } => Extensions::new().with("eaten", eaten),
+        }
+    }
+}
+
+#[derive(Debug, Error, Problem)]
+#[error("The salsa is too spicy.")]
+#[problem(type = "spicy-salsa", title = "Spicy Salsa.", status = 422)]
+struct SalsaError;
+
+#[test]
+fn lists_the_problem_types_of_the_variants() {
+    assert_eq!(
+        TacoError::TYPES,
+        [
+            ProblemType::new(
+                "tacos/not-found",
+                "Taco Not Found.",
+                StatusCode::NOT_FOUND,
+                "Taco Not Found."
+            ),
+            ProblemType::new(
+                "tacos/too-many",
+                "Too Many Tacos.",
+                StatusCode::TOO_MANY_REQUESTS,
+                "The tacos are eaten faster than they're cooked."
+            ),
+        ]
+    );
+    assert_eq!(
+        SalsaError::TYPES,
+        [ProblemType::new(
+            "spicy-salsa",
+            "Spicy Salsa.",
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Spicy Salsa."
+        )]
+    );
+}
+
+#[test]
+fn describes_each_variant() {
+    let error = TacoError::NotFound("al pastor".to_owned());
+    assert_eq!(
+        error.ty(),
+        format!("{}/tacos/not-found", problems::base_uri())
+    );
+    assert_eq!(error.title(), "Taco Not Found.");
+    assert_eq!(error.detail(), "Taco 'al pastor' is not found.");
+    assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+    assert_eq!(error.instance(), None);
+    assert_eq!(error.headers(), None);
+    assert_eq!(
+        Problem::extensions(&error),
+        Extensions::new().with("name", "al pastor")
+    );
+
+    let error = TacoError::TooMany { eaten: 12 };
+    assert_eq!(error.ty(), TacoError::TYPES[1].uri());
+    assert_eq!(error.detail(), "Too many tacos, 12 were already eaten.");
+    assert_eq!(error.status(), Some(StatusCode::TOO_MANY_REQUESTS));
+    let headers = Problem::headers(&error).unwrap();
+    assert_eq!(headers[header::RETRY_AFTER], "60");
+}
+
+#[test]
+fn describes_the_structs() {
+    let error = SalsaError;
+
+    assert_eq!(error.ty(), SalsaError::TYPES[0].uri());
+    assert_eq!(error.title(), "Spicy Salsa.");
+    assert_eq!(error.detail(), "The salsa is too spicy.");
+    assert_eq!(error.status(), Some(StatusCode::UNPROCESSABLE_ENTITY));
+    assert_eq!(Problem::headers(&error), None);
+    assert_eq!(Problem::extensions(&error), Extensions::new());
+}
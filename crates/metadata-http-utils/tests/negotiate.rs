@@ -0,0 +1,153 @@
+use axum::{body::Body, middleware, routing::get, Router};
+use http::{header, Request, StatusCode};
+use metadata_http_utils::{context, negotiate::preferred, Extensions, Format, HttpError, Problem};
+use thiserror::Error;
+use tower::ServiceExt;
+
+const AVAILABLE: [&str; 3] = ["application/json", "application/yaml", "text/yaml"];
+
+#[test]
+fn prefers_the_media_types_of_the_highest_quality() {
+    for (accept, media_type) in [
+        ("application/yaml", Some("application/yaml")),
+        ("application/json;q=0.5, text/yaml", Some("text/yaml")),
+        (
+            "application/yaml; q=0.2, application/json; q=0.8",
+            Some("application/json"),
+        ),
+        ("APPLICATION/YAML", Some("application/yaml")),
+        ("text/html", None),
+        ("", None),
+    ] {
+        assert_eq!(preferred(accept, &AVAILABLE), media_type, "{accept}");
+    }
+}
+
+#[test]
+fn applies_the_most_specific_media_ranges() {
+    for (accept, media_type) in [
+        // The media types of the same quality are picked in order.
+        ("*/*", Some("application/json")),
+        ("application/*", Some("application/json")),
+        ("text/*", Some("text/yaml")),
+        ("application/*;q=0.5, text/*", Some("text/yaml")),
+        ("*/*;q=0.5, application/yaml", Some("application/yaml")),
+        // A quality of zero excludes the media types.
+        ("application/json;q=0, */*", Some("application/yaml")),
+        ("application/*;q=0, */*", Some("text/yaml")),
+        ("*/*;q=0", None),
+    ] {
+        assert_eq!(preferred(accept, &AVAILABLE), media_type, "{accept}");
+    }
+}
+
+#[test]
+fn picks_the_format_of_the_responses() {
+    for (accept, format) in [
+        (None, Format::Json),
+        (Some("text/html"), Format::Json),
+        (Some("*/*"), Format::Json),
+        (Some("application/yaml"), Format::Yaml),
+        (Some("application/x-yaml"), Format::Yaml),
+        (Some("text/yaml"), Format::Yaml),
+        (Some("application/msgpack"), Format::MessagePack),
+        (Some("application/x-msgpack"), Format::MessagePack),
+        (
+            Some("application/json;q=0.1, application/msgpack"),
+            Format::MessagePack,
+        ),
+    ] {
+        assert_eq!(Format::from_accept(accept), format, "{accept:?}");
+    }
+}
+
+#[derive(Debug, Error, Problem)]
+#[error("The <taco> & the burrito can't be mixed.")]
+#[problem(
+    type = "mixed-dishes",
+    title = "Mixed Dishes.",
+    status = 409,
+    extensions = "extensions"
+)]
+struct MixedDishes;
+
+impl MixedDishes {
+    fn extensions(&self) -> Extensions {
+        Extensions::new()
+            .with("dishes", ["taco", "burrito"])
+            .with("order", serde_json::json!({"id": 1, "note": null}))
+            .with("2nd-try", true)
+    }
+}
+
+/// Request a route failing with [MixedDishes], returning the content
+/// type and the body of the response.
+async fn problem(accept: Option<&str>) -> (String, String) {
+    let app = Router::new()
+        .route(
+            "/",
+            get(|| async { Err::<(), _>(HttpError::from(MixedDishes)) }),
+        )
+        .layer(middleware::from_fn(context::scope));
+
+    let mut request = Request::get("/");
+    if let Some(accept) = accept {
+        request = request.header(header::ACCEPT, accept);
+    }
+    let response = app
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(response.headers()[header::VARY], "accept");
+
+    let content_type = response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    (content_type, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn formats_the_problems_to_xml_when_preferred() {
+    let (content_type, body) = problem(Some("application/xml")).await;
+
+    assert_eq!(content_type, "application/problem+xml");
+    assert_eq!(
+        body,
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <problem xmlns=\"urn:ietf:rfc:7807\">\
+             <_2nd-try>true</_2nd-try>\
+             <detail>The &lt;taco&gt; &amp; the burrito can't be mixed.</detail>\
+             <dishes><i>taco</i><i>burrito</i></dishes>\
+             <order><id>1</id><note/></order>\
+             <status>409</status>\
+             <title>Mixed Dishes.</title>\
+             <type>{}</type>\
+             </problem>\n",
+            MixedDishes.ty()
+        )
+    );
+}
+
+#[tokio::test]
+async fn formats_the_problems_to_json_otherwise() {
+    for accept in [
+        None,
+        Some("*/*"),
+        Some("text/html"),
+        Some("application/xml;q=0.5, application/json"),
+    ] {
+        let (content_type, body) = problem(accept).await;
+
+        assert_eq!(content_type, "application/problem+json", "{accept:?}");
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["dishes"], serde_json::json!(["taco", "burrito"]));
+        assert_eq!(body["status"], 409);
+    }
+}
@@ -0,0 +1,181 @@
+use http::{header, StatusCode};
+use metadata_http_utils::{Extensions, Problem, SqlProblem};
+use sqlx::error::{DatabaseError, ErrorKind};
+use std::{borrow::Cow, error::Error, fmt};
+
+/// An error of the database, as PostgreSQL would report it.
+#[derive(Debug)]
+struct PgError {
+    code: &'static str,
+    constraint: Option<&'static str>,
+}
+
+/// The message of the errors, which must never reach the clients.
+const MESSAGE: &str = "relation \"secret_table\" violates \"secret_constraint\"";
+
+impl fmt::Display for PgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(MESSAGE)
+    }
+}
+
+impl Error for PgError {}
+
+impl DatabaseError for PgError {
+    fn message(&self) -> &str {
+        MESSAGE
+    }
+
+    fn code(&self) -> Option<Cow<'_, str>> {
+        Some(Cow::Borrowed(self.code))
+    }
+
+    fn as_error(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn constraint(&self) -> Option<&str> {
+        self.constraint
+    }
+
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+fn database_error(code: &'static str) -> sqlx::Error {
+    sqlx::Error::Database(Box::new(PgError {
+        code,
+        constraint: None,
+    }))
+}
+
+/// The name of the type of a problem, e.g. `sql/unique-violation`.
+fn type_name(problem: &SqlProblem) -> String {
+    let ty = problem.ty();
+    let (_, name) = ty.rsplit_once("/sql/").expect("a SQL problem type");
+
+    format!("sql/{name}")
+}
+
+fn retry_after(problem: &SqlProblem) -> Option<String> {
+    let headers = problem.headers()?;
+    let value = headers.get(header::RETRY_AFTER)?;
+
+    Some(value.to_str().unwrap().to_owned())
+}
+
+#[test]
+fn maps_the_sqlstate_codes_to_problems() {
+    let cases = [
+        ("23505", "sql/unique-violation", StatusCode::CONFLICT),
+        (
+            "23503",
+            "sql/foreign-key-violation",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            "23502",
+            "sql/not-null-violation",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            "23514",
+            "sql/check-violation",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            "40001",
+            "sql/serialization-failure",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        (
+            "40P01",
+            "sql/deadlock-detected",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        ("57014", "sql/query-canceled", StatusCode::GATEWAY_TIMEOUT),
+        (
+            "53300",
+            "sql/database-unavailable",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        (
+            "57P01",
+            "sql/database-unavailable",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        ("28P01", "sql/bad-connection", StatusCode::BAD_GATEWAY),
+        ("08006", "sql/bad-connection", StatusCode::BAD_GATEWAY),
+        (
+            "42P01",
+            "sql/database-error",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    ];
+
+    for (code, ty, status) in cases {
+        let problem = SqlProblem::from(&database_error(code));
+
+        assert_eq!(type_name(&problem), ty, "{code}");
+        assert_eq!(problem.status(), Some(status), "{code}");
+        assert_eq!(problem.extensions(), Extensions::new().with("code", code));
+    }
+}
+
+#[test]
+fn the_retryable_problems_tell_when_to_retry() {
+    for (code, delay) in [("40001", "1"), ("40P01", "1"), ("57P03", "5")] {
+        let problem = SqlProblem::from(&database_error(code));
+        assert_eq!(retry_after(&problem).as_deref(), Some(delay), "{code}");
+    }
+
+    let problem = SqlProblem::from(&sqlx::Error::PoolTimedOut);
+    assert_eq!(type_name(&problem), "sql/connection-closed");
+    assert_eq!(retry_after(&problem).as_deref(), Some("30"));
+
+    let problem = SqlProblem::from(&database_error("23505"));
+    assert_eq!(retry_after(&problem), None);
+}
+
+#[test]
+fn exposes_the_violated_constraint() {
+    let error = sqlx::Error::Database(Box::new(PgError {
+        code: "23505",
+        constraint: Some("domains_name_key"),
+    }));
+    let problem = SqlProblem::from(&error);
+
+    assert_eq!(
+        problem.extensions(),
+        Extensions::new()
+            .with("code", "23505")
+            .with("constraint", "domains_name_key")
+    );
+}
+
+#[test]
+fn never_forwards_the_messages_of_the_database() {
+    let errors = [
+        database_error("23505"),
+        database_error("23503"),
+        database_error("42P01"),
+        database_error("XX000"),
+        sqlx::Error::ColumnNotFound("secret_column".to_owned()),
+        sqlx::Error::Protocol("secret protocol error".to_owned()),
+        sqlx::Error::PoolTimedOut,
+    ];
+
+    for error in &errors {
+        let detail = SqlProblem::from(error).detail();
+        assert!(!detail.contains("secret"), "{error}: {detail}");
+    }
+}
@@ -0,0 +1,144 @@
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use metadata_http_utils::{HttpError, Problem};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt::Write, sync::Arc};
+use thiserror::Error;
+
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// The set of API keys allowed to query the service. When it is empty,
+/// the requests are not authenticated.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeys {
+    inner: Arc<HashMap<String, String>>,
+}
+
+impl ApiKeys {
+    /// Build the set of API keys from pairs of owner names and keys.
+    pub fn new<I, N, K>(keys: I) -> Self
+    where
+        I: IntoIterator<Item = (N, K)>,
+        N: Into<String>,
+        K: Into<String>,
+    {
+        let inner = keys
+            .into_iter()
+            .map(|(name, key)| (key.into(), name.into()))
+            .collect();
+
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns the name of the owner of an API key, if it's known.
+    pub fn owner(&self, key: &str) -> Option<&str> {
+        self.inner.get(key).map(String::as_str)
+    }
+}
+
+/// The authenticated owner of a request. It's available in the request
+/// extensions once the authentication middleware accepted it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Principal(pub String);
+
+/// The identity of a client which authenticated itself with a TLS
+/// certificate. It's available in the request extensions when mutual
+/// TLS is enabled and the client presented a valid certificate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientIdentity {
+    /// The distinguished name of the certificate subject, e.g.
+    /// `"CN=catalog-sync, O=Platform"`.
+    pub subject: String,
+}
+
+#[derive(Clone, Debug, Error, Problem)]
+#[problem(namespace = "auth", headers = "challenge")]
+pub enum AuthError {
+    #[error("The request does not provide an API key.")]
+    #[problem(
+        type = "missing-api-key",
+        title = "Missing API Key.",
+        status = 401,
+        description = "The request must be authenticated, either with an API key sent as a \
+                       bearer token or in the `X-Api-Key` header, or with a client certificate."
+    )]
+    MissingApiKey,
+    #[error("The API key provided with the request is not valid.")]
+    #[problem(
+        type = "invalid-api-key",
+        title = "Invalid API Key.",
+        status = 401,
+        description = "The API key sent with the request is not known by the service."
+    )]
+    InvalidApiKey,
+}
+
+impl AuthError {
+    /// The challenge telling the client how to authenticate.
+    fn challenge(&self) -> Option<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.append(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+
+        Some(headers)
+    }
+}
+
+/// Extract the API key of a request, either from a bearer token in the
+/// `Authorization` header or from the `X-Api-Key` header.
+pub(crate) fn api_key(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    bearer.or_else(|| {
+        headers
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+    })
+}
+
+/// A digest of an API key, telling the keys apart without holding them,
+/// e.g. in the buckets of the rate limits.
+pub(crate) fn fingerprint(key: &str) -> String {
+    Sha256::digest(key.as_bytes())[..16]
+        .iter()
+        .fold(String::with_capacity(32), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// A middleware rejecting the requests which are not carrying one of the
+/// API keys registered in the application state. The clients which are
+/// authenticated by a TLS certificate don't need an API key.
+pub(crate) async fn authenticate(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, HttpError> {
+    let principal = if let Some(identity) = request.extensions().get::<ClientIdentity>() {
+        Principal(identity.subject.clone())
+    } else {
+        let key = api_key(request.headers()).ok_or(AuthError::MissingApiKey)?;
+        let owner = state.api_keys.owner(key).ok_or(AuthError::InvalidApiKey)?;
+
+        Principal(owner.to_owned())
+    };
+    tracing::debug!(principal = %principal.0, "request authenticated");
+    request.extensions_mut().insert(principal);
+
+    Ok(next.run(request).await)
+}
@@ -0,0 +1,56 @@
+//! The consistency tokens, which let the clients read their own writes
+//! across requests while the reads are sent to the replicas.
+
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use metadata_data_layer_utils::ConsistencyToken;
+
+/// The header carrying the consistency token, in the responses to the
+/// requests which wrote and in the following requests of the client.
+pub const CONSISTENCY_TOKEN: HeaderName = HeaderName::from_static("x-consistency-token");
+
+/// A middleware opening the session of the request, which the
+/// repositories extracted by its handler share.
+///
+/// The reads of a request carrying a consistency token are served by the
+/// replicas which replayed it only, and the ones of a request carrying an
+/// invalid token by the primary. The response to a request which wrote
+/// carries the token of its writes.
+pub(crate) async fn session(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let mut session = state.pool.session();
+    if let Some(value) = request.headers().get(&CONSISTENCY_TOKEN) {
+        match value.to_str().ok().map(str::parse::<ConsistencyToken>) {
+            Some(Ok(token)) => session = session.with_token(token),
+            _ => {
+                tracing::debug!(
+                    ?value,
+                    "invalid consistency token, reading from the primary"
+                );
+                session.pin();
+            }
+        }
+    }
+    request.extensions_mut().insert(session.clone());
+
+    let mut response = next.run(request).await;
+
+    match session.token().await {
+        Ok(Some(token)) => {
+            let value = HeaderValue::from_str(&token.to_string()).expect("a valid header value");
+            response.headers_mut().insert(CONSISTENCY_TOKEN, value);
+        }
+        Ok(None) => {}
+        Err(error) => tracing::warn!(%error, "unable to read the consistency token"),
+    }
+
+    response
+}
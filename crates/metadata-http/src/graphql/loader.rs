@@ -0,0 +1,135 @@
+//! Batching of the queries issued while resolving a GraphQL request, so
+//! resolving a field of every item of a list doesn't query the database
+//! once per item.
+
+use async_graphql::dataloader;
+use metadata_data_layer::{
+    models::{Block, ChildrenPage, Domain},
+    repositories::{BlockRepository, DomainRepository},
+};
+use metadata_data_layer_utils::{Repository, Session};
+use std::{collections::HashMap, sync::Arc};
+use uuid::Uuid;
+
+/// The identifier of a domain to load.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct DomainId(pub Uuid);
+
+/// The identifier of a block to load.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct BlockId(pub Uuid);
+
+/// The page of the direct children of a parent to load.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct ChildrenOf(pub ChildrenPage);
+
+/// The block whose ancestors are loaded, the nearest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct AncestorsOf(pub Uuid);
+
+/// Loads the resources requested while resolving a GraphQL request, each
+/// kind of key being loaded with a single query per batch.
+pub(crate) struct Loader {
+    pool: Session,
+}
+
+pub(crate) type DataLoader = dataloader::DataLoader<Loader>;
+
+impl Loader {
+    /// Build the loader of a single request, as it caches the resources
+    /// it loaded.
+    pub(crate) fn data_loader(pool: Session) -> DataLoader {
+        let loader = Self { pool };
+
+        dataloader::DataLoader::new(loader, tokio::spawn)
+    }
+}
+
+impl dataloader::Loader<DomainId> for Loader {
+    type Value = Domain;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[DomainId]) -> Result<HashMap<DomainId, Domain>, Self::Error> {
+        let ids = keys.iter().map(|key| key.0).collect::<Vec<_>>();
+        let domains = DomainRepository::from_ref(self.pool.clone())
+            .get_domains(&ids)
+            .await?;
+
+        Ok(domains
+            .into_iter()
+            .map(|domain| (DomainId(domain.id), domain))
+            .collect())
+    }
+}
+
+impl dataloader::Loader<BlockId> for Loader {
+    type Value = Block;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[BlockId]) -> Result<HashMap<BlockId, Block>, Self::Error> {
+        let ids = keys.iter().map(|key| key.0).collect::<Vec<_>>();
+        let blocks = BlockRepository::from_ref(self.pool.clone())
+            .get_blocks(&ids)
+            .await?;
+
+        Ok(blocks
+            .into_iter()
+            .map(|block| (BlockId(block.id), block))
+            .collect())
+    }
+}
+
+impl dataloader::Loader<ChildrenOf> for Loader {
+    type Value = Vec<Block>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(
+        &self,
+        keys: &[ChildrenOf],
+    ) -> Result<HashMap<ChildrenOf, Vec<Block>>, Self::Error> {
+        let pages = keys.iter().map(|key| key.0.clone()).collect::<Vec<_>>();
+        let rows = BlockRepository::from_ref(self.pool.clone())
+            .list_children_pages(&pages)
+            .await?;
+
+        // Every key is answered, so the empty pages are cached too.
+        let mut children = keys
+            .iter()
+            .map(|key| (key.clone(), Vec::new()))
+            .collect::<HashMap<_, _>>();
+        for (page, block) in rows {
+            if let Some(siblings) = keys.get(page).and_then(|key| children.get_mut(key)) {
+                siblings.push(block);
+            }
+        }
+
+        Ok(children)
+    }
+}
+
+impl dataloader::Loader<AncestorsOf> for Loader {
+    type Value = Vec<Block>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(
+        &self,
+        keys: &[AncestorsOf],
+    ) -> Result<HashMap<AncestorsOf, Vec<Block>>, Self::Error> {
+        let ids = keys.iter().map(|key| key.0).collect::<Vec<_>>();
+        let rows = BlockRepository::from_ref(self.pool.clone())
+            .list_ancestors(&ids)
+            .await?;
+
+        let mut ancestors = keys
+            .iter()
+            .map(|key| (*key, Vec::new()))
+            .collect::<HashMap<_, _>>();
+        for (id, block) in rows {
+            if let Some(blocks) = ancestors.get_mut(&AncestorsOf(id)) {
+                blocks.push(block);
+            }
+        }
+
+        Ok(ancestors)
+    }
+}
@@ -0,0 +1,70 @@
+//! A GraphQL schema over the domains and their trees of blocks, so the
+//! clients can walk a tree in a single request.
+//!
+//! The fields of the items of a list are resolved with batched queries,
+//! through the [Loader] of the request.
+
+use async_graphql::{EmptyMutation, EmptySubscription, Error, ErrorExtensions, Schema};
+use metadata_http_utils::{Problem, SqlProblem};
+use std::fmt;
+
+mod loader;
+mod query;
+
+pub(crate) use loader::Loader;
+use query::Query;
+
+/// The number of items of a page when the `first` argument is missing.
+const DEFAULT_PAGE_SIZE: usize = 20;
+/// The maximum of the `first` argument of the paginated fields.
+const MAX_PAGE_SIZE: usize = 100;
+
+/// The maximum depth of the queries, by default. The `edges` and `node`
+/// fields of the connections each count for one level.
+pub const DEFAULT_MAX_DEPTH: usize = 15;
+/// The maximum complexity of the queries, by default. Each field counts
+/// for one, multiplied by the size of the pages of the paginated ones.
+pub const DEFAULT_MAX_COMPLEXITY: usize = 2000;
+
+/// The schema of the API, along with its limits.
+#[derive(Clone)]
+pub(crate) struct GraphqlSchema(Schema<Query, EmptyMutation, EmptySubscription>);
+
+impl GraphqlSchema {
+    pub(crate) fn new(max_depth: usize, max_complexity: usize) -> Self {
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .limit_depth(max_depth)
+            .limit_complexity(max_complexity)
+            .finish();
+
+        Self(schema)
+    }
+
+    pub(crate) async fn execute(&self, request: async_graphql::Request) -> async_graphql::Response {
+        self.0.execute(request).await
+    }
+}
+
+impl fmt::Debug for GraphqlSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GraphqlSchema").finish_non_exhaustive()
+    }
+}
+
+/// Describe a problem as a GraphQL error, the members of its problem
+/// details being exposed as extensions.
+fn problem(problem: &impl Problem) -> Error {
+    let (ty, title, status) = (problem.ty(), problem.title(), problem.status());
+
+    Error::new(problem.detail()).extend_with(|_, extensions| {
+        extensions.set("type", ty);
+        extensions.set("title", title);
+        if let Some(status) = status {
+            extensions.set("status", status.as_u16());
+        }
+    })
+}
+
+fn database(error: &sqlx::Error) -> Error {
+    problem(&SqlProblem::from(error))
+}
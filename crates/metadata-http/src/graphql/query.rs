@@ -0,0 +1,270 @@
+use super::{
+    database,
+    loader::{AncestorsOf, BlockId, ChildrenOf, DataLoader, DomainId},
+    DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+};
+use async_graphql::{
+    connection::{self, Connection, Edge, OpaqueCursor},
+    Context, Error, Object, Result,
+};
+use chrono::{DateTime, Utc};
+use metadata_data_layer::{
+    models::{Block, ChildrenPage, Domain, Parent},
+    repositories::DomainRepository,
+};
+use metadata_data_layer_utils::{Repository, Session};
+use uuid::Uuid;
+
+/// The number of items of a page, checked against the maximum.
+fn page_size(first: Option<i32>) -> Result<usize> {
+    match first {
+        None => Ok(DEFAULT_PAGE_SIZE),
+        Some(first) if first > MAX_PAGE_SIZE as i32 => Err(Error::new(format!(
+            "The \"first\" parameter must be at most {MAX_PAGE_SIZE}"
+        ))),
+        // The negative values are rejected by `connection::query`.
+        Some(first) => Ok(first.max(0) as usize),
+    }
+}
+
+/// Paginate the children of a parent, the page being batched with the
+/// pages of the children of the other parents of the request.
+async fn children(
+    context: &Context<'_>,
+    parent: Parent,
+    first: Option<i32>,
+    after: Option<String>,
+) -> Result<Connection<OpaqueCursor<(String, Uuid)>, BlockNode>> {
+    let size = page_size(first)?;
+
+    connection::query(
+        after,
+        None,
+        first,
+        None,
+        |after: Option<OpaqueCursor<(String, Uuid)>>, _, _, _| async move {
+            // One more child is fetched to know whether there's a next
+            // page.
+            let page = ChildrenPage {
+                parent,
+                after: after.as_ref().map(|cursor| cursor.0.clone()),
+                limit: size as i64 + 1,
+            };
+            let mut children = context
+                .data_unchecked::<DataLoader>()
+                .load_one(ChildrenOf(page))
+                .await
+                .map_err(|error| database(&error))?
+                .unwrap_or_default();
+            let has_next_page = children.len() > size;
+            children.truncate(size);
+
+            let mut connection = Connection::new(after.is_some(), has_next_page);
+            connection.edges.extend(children.into_iter().map(|block| {
+                Edge::new(
+                    OpaqueCursor((block.name.clone(), block.id)),
+                    BlockNode(block),
+                )
+            }));
+
+            Ok::<_, Error>(connection)
+        },
+    )
+    .await
+}
+
+pub(crate) struct DomainNode(Domain);
+
+/// A domain, at the root of a tree of blocks.
+#[Object(name = "Domain")]
+impl DomainNode {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.0.updated_at
+    }
+
+    /// The blocks at the root of the domain, ordered by name.
+    #[graphql(
+        complexity = "first.unwrap_or(DEFAULT_PAGE_SIZE as i32).max(0) as usize * child_complexity"
+    )]
+    async fn blocks(
+        &self,
+        context: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<(String, Uuid)>, BlockNode>> {
+        children(context, Parent::Domain(self.0.id), first, after).await
+    }
+}
+
+pub(crate) struct BlockNode(Block);
+
+/// A block, under a domain or under another block.
+#[Object(name = "Block")]
+impl BlockNode {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.0.updated_at
+    }
+
+    /// The parent block, or `null` for the blocks at the root of a domain.
+    async fn parent(&self, context: &Context<'_>) -> Result<Option<BlockNode>> {
+        let Parent::Block(id) = self.0.parent else {
+            return Ok(None);
+        };
+        let block = context
+            .data_unchecked::<DataLoader>()
+            .load_one(BlockId(id))
+            .await
+            .map_err(|error| database(&error))?;
+
+        Ok(block.map(BlockNode))
+    }
+
+    /// The domain at the root of the tree of the block.
+    async fn domain(&self, context: &Context<'_>) -> Result<DomainNode> {
+        let loader = context.data_unchecked::<DataLoader>();
+        let root = match self.0.parent {
+            Parent::Domain(_) => self.0.clone(),
+            Parent::Block(_) => loader
+                .load_one(AncestorsOf(self.0.id))
+                .await
+                .map_err(|error| database(&error))?
+                .and_then(|ancestors| ancestors.last().cloned())
+                .ok_or_else(|| Error::new("The block has been moved or deleted"))?,
+        };
+        let Parent::Domain(id) = root.parent else {
+            return Err(Error::new("The block has been moved or deleted"));
+        };
+
+        let domain = loader
+            .load_one(DomainId(id))
+            .await
+            .map_err(|error| database(&error))?;
+        domain
+            .map(DomainNode)
+            .ok_or_else(|| Error::new("The block has been moved or deleted"))
+    }
+
+    /// The ancestors of the block, from its parent up to the root of its
+    /// tree.
+    async fn ancestors(&self, context: &Context<'_>) -> Result<Vec<BlockNode>> {
+        if let Parent::Domain(_) = self.0.parent {
+            return Ok(Vec::new());
+        }
+        let ancestors = context
+            .data_unchecked::<DataLoader>()
+            .load_one(AncestorsOf(self.0.id))
+            .await
+            .map_err(|error| database(&error))?
+            .unwrap_or_default();
+
+        Ok(ancestors.into_iter().map(BlockNode).collect())
+    }
+
+    /// The children of the block, ordered by name.
+    #[graphql(
+        complexity = "first.unwrap_or(DEFAULT_PAGE_SIZE as i32).max(0) as usize * child_complexity"
+    )]
+    async fn children(
+        &self,
+        context: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<(String, Uuid)>, BlockNode>> {
+        children(context, Parent::Block(self.0.id), first, after).await
+    }
+}
+
+pub(crate) struct Query;
+
+#[Object]
+impl Query {
+    /// The domains, ordered by name.
+    #[graphql(
+        complexity = "first.unwrap_or(DEFAULT_PAGE_SIZE as i32).max(0) as usize * child_complexity"
+    )]
+    async fn domains(
+        &self,
+        context: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<String>, DomainNode>> {
+        let size = page_size(first)?;
+        let session = context.data_unchecked::<Session>();
+        let repository = DomainRepository::from_ref(session.clone());
+
+        connection::query(
+            after,
+            None,
+            first,
+            None,
+            |after: Option<OpaqueCursor<String>>, _, _, _| async move {
+                // One more domain is fetched to know whether there's a
+                // next page.
+                let mut domains = repository
+                    .list_domains_page(
+                        after.as_ref().map(|cursor| cursor.0.as_str()),
+                        size as i64 + 1,
+                    )
+                    .await
+                    .map_err(|error| database(&error))?;
+                let has_next_page = domains.len() > size;
+                domains.truncate(size);
+
+                let mut connection = Connection::new(after.is_some(), has_next_page);
+                connection.edges.extend(domains.into_iter().map(|domain| {
+                    Edge::new(OpaqueCursor(domain.name.clone()), DomainNode(domain))
+                }));
+
+                Ok::<_, Error>(connection)
+            },
+        )
+        .await
+    }
+
+    /// The domain with the given name.
+    async fn domain(&self, context: &Context<'_>, name: String) -> Result<Option<DomainNode>> {
+        let session = context.data_unchecked::<Session>();
+        let repository = DomainRepository::from_ref(session.clone());
+        let domain = repository
+            .get_domain_by_name(&name)
+            .await
+            .map_err(|error| database(&error))?;
+
+        Ok(domain.map(DomainNode))
+    }
+
+    /// The block with the given identifier.
+    async fn block(&self, context: &Context<'_>, id: Uuid) -> Result<Option<BlockNode>> {
+        let block = context
+            .data_unchecked::<DataLoader>()
+            .load_one(BlockId(id))
+            .await
+            .map_err(|error| database(&error))?;
+
+        Ok(block.map(BlockNode))
+    }
+}
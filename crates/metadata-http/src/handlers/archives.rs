@@ -0,0 +1,151 @@
+use super::domains::DomainError;
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use metadata_data_layer::{
+    archive::{Action, Archive, ArchiveError, ArchiveFormat, ImportMode},
+    repositories::ArchiveRepository,
+};
+use metadata_data_layer_utils::extract::Repository;
+use metadata_http_utils::{
+    extract::{Bytes, Path, Query},
+    negotiate, Format, HttpError, Problem,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+#[derive(Debug, Error, Problem)]
+#[problem(namespace = "archives")]
+pub(crate) enum ArchiveProblem {
+    #[error("{0}")]
+    #[problem(
+        type = "malformed-archive",
+        title = "Malformed Archive.",
+        status = 400,
+        description = "The body of the request is neither a JSON nor an NDJSON archive."
+    )]
+    Malformed(String),
+    #[error("{0}")]
+    #[problem(
+        type = "invalid-archive",
+        title = "Invalid Archive.",
+        status = 422,
+        description = "The archive can't be imported: its version is not supported, a name \
+                       is invalid, or its blocks don't form a tree."
+    )]
+    Invalid(String),
+    #[error("{0}")]
+    #[problem(
+        type = "conflict",
+        title = "Archive Conflict.",
+        status = 409,
+        description = "The archive conflicts with the stored resources, e.g. its domain \
+                       already exists with another ID."
+    )]
+    Conflict(String),
+}
+
+/// Describe an error of the archives as a problem, the database errors
+/// being handled like everywhere else.
+fn problem(error: ArchiveError) -> HttpError {
+    match error {
+        ArchiveError::Database(error) => error.into(),
+        ArchiveError::Malformed(_) => ArchiveProblem::Malformed(error.to_string()).into(),
+        ArchiveError::Conflict(detail) => ArchiveProblem::Conflict(detail).into(),
+        error => ArchiveProblem::Invalid(error.to_string()).into(),
+    }
+}
+
+/// The query string of the requests importing an archive.
+#[derive(Debug, Deserialize)]
+pub(super) struct ImportParams {
+    #[serde(default)]
+    mode: ImportMode,
+    /// Only report what the import would change.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[tracing::instrument(name = "export_domain", skip(repository, headers))]
+pub(super) async fn export(
+    Path(domain_name): Path<String>,
+    Repository(repository): Repository<ArchiveRepository>,
+    headers: HeaderMap,
+) -> Result<Response, HttpError> {
+    let Some(archive) = repository.export_domain(&domain_name).await? else {
+        return Err(DomainError::NotFoundByName(domain_name).into());
+    };
+
+    let formats = [ArchiveFormat::Json, ArchiveFormat::Ndjson];
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    let format = accept
+        .and_then(|accept| negotiate::preferred(accept, &formats.map(ArchiveFormat::content_type)))
+        .and_then(|media_type| {
+            formats
+                .into_iter()
+                .find(|format| format.content_type() == media_type)
+        })
+        .unwrap_or_default();
+
+    let mut body = Vec::new();
+    archive
+        .write(format, &mut body)
+        .expect("an archive is always serializable");
+    let disposition = format!(
+        "attachment; filename=\"{}.{}\"",
+        archive.domain.name,
+        format.extension()
+    );
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(format.content_type()),
+            ),
+            (header::VARY, HeaderValue::from_static("accept")),
+            (
+                header::CONTENT_DISPOSITION,
+                HeaderValue::try_from(disposition).expect("domain names are valid header values"),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "import_domain", skip(repository, format, body))]
+pub(super) async fn import(
+    Query(params): Query<ImportParams>,
+    Repository(repository): Repository<ArchiveRepository>,
+    format: Format,
+    Bytes(body): Bytes,
+) -> Result<Response, HttpError> {
+    let archive = Archive::parse(&body).map_err(problem)?;
+    if params.dry_run {
+        let plan = repository
+            .plan_import(&archive, params.mode)
+            .await
+            .map_err(problem)?;
+        return Ok(format.respond(plan));
+    }
+
+    let plan = repository
+        .import(&archive, params.mode)
+        .await
+        .map_err(problem)?;
+    if plan.domain.action == Action::Create {
+        let location = format!("/{}", plan.domain.name);
+        Ok((
+            StatusCode::CREATED,
+            [(header::LOCATION, location)],
+            format.respond(plan),
+        )
+            .into_response())
+    } else {
+        Ok(format.respond(plan))
+    }
+}
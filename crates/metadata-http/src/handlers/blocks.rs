@@ -1,50 +1,182 @@
-use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
-use metadata_data_layer::repositories::BlockRepository;
+use super::domains::DomainError;
+use crate::{validation::ValidationProblem, AppState};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use metadata_data_layer::{
+    models::Block,
+    repositories::{BlockRepository, DomainRepository},
+};
 use metadata_data_layer_utils::extract::Repository;
-use metadata_http_utils::{HttpError, Problem};
+use metadata_http_utils::{
+    extract::{Json, Path},
+    Format, HttpError, Problem,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
+use uuid::Uuid;
 
-#[derive(Clone, Debug, Error)]
+/// The maximum number of blocks read by a batch, by default.
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+
+#[derive(Clone, Debug, Error, Problem)]
+#[problem(namespace = "blocks")]
 pub enum BlockError {
     #[error("Block '{0}' is not found.")]
+    #[problem(
+        type = "not-found",
+        title = "Block Not Found.",
+        status = 404,
+        description = "No block with the requested name exists in the domain."
+    )]
     NotFoundByName(String),
+    #[error("Block {0} is not found.")]
+    #[problem(
+        type = "not-found-by-id",
+        title = "Block Not Found.",
+        status = 404,
+        description = "No block with the requested identifier exists."
+    )]
+    NotFoundById(Uuid),
+    #[error("Block '{0}' is not found in the domain.")]
+    #[problem(
+        type = "parent-not-found",
+        title = "Parent Block Not Found.",
+        status = 422,
+        description = "The parent of the new block must be an existing block of the same domain."
+    )]
+    ParentNotFound(Uuid),
+    #[error("At most {0} blocks can be read at once.")]
+    #[problem(
+        type = "batch-too-large",
+        title = "Too Many Blocks Requested.",
+        status = 422,
+        description = "The request lists more identifiers than the number of blocks a batch \
+                       can read."
+    )]
+    BatchTooLarge(usize),
 }
 
-impl Problem for BlockError {
-    fn ty(&self) -> String {
-        let sub_type = match self {
-            Self::NotFoundByName(_) => "not-found",
-        };
+/// The body of the requests creating a block.
+#[derive(Debug, Deserialize)]
+pub(super) struct NewBlock {
+    name: Option<String>,
+    /// The identifier of the parent block. The block is created at the
+    /// root of the domain when it's missing.
+    parent: Option<Uuid>,
+}
 
-        format!("https://errors.taster.com/metadata/blocks/{sub_type}")
-    }
+/// The body of the requests reading many blocks at once.
+#[derive(Debug, Deserialize)]
+pub(super) struct BatchGet {
+    ids: Vec<Uuid>,
+}
 
-    fn title(&self) -> String {
-        match self {
-            Self::NotFoundByName(_) => "Block Not Found.".to_string(),
-        }
-    }
+/// The blocks read by a batch, in the order of their identifiers in the
+/// request, and the identifiers of the blocks which don't exist.
+#[derive(Debug, Serialize)]
+struct Batch {
+    blocks: Vec<Block>,
+    missing: Vec<Uuid>,
+}
 
-    fn detail(&self) -> String {
-        format!("{self}")
+#[tracing::instrument(name = "show_block", skip(domains, blocks, format))]
+pub(super) async fn show(
+    Path((domain_name, block_name)): Path<(String, String)>,
+    Repository(domains): Repository<DomainRepository>,
+    Repository(blocks): Repository<BlockRepository>,
+    format: Format,
+) -> Result<impl IntoResponse, HttpError> {
+    let Some(domain) = domains.get_domain_by_name(&domain_name).await? else {
+        return Err(DomainError::NotFoundByName(domain_name).into());
+    };
+
+    let block = blocks.get_block_by_name(&domain.id, &block_name).await?;
+    if let Some(block) = block {
+        Ok(format.respond(block))
+    } else {
+        Err(BlockError::NotFoundByName(block_name).into())
     }
+}
+
+#[tracing::instrument(name = "create_block", skip_all, fields(domain_name))]
+pub(super) async fn create(
+    Path(domain_name): Path<String>,
+    Repository(domains): Repository<DomainRepository>,
+    Repository(blocks): Repository<BlockRepository>,
+    format: Format,
+    Json(new_block): Json<NewBlock>,
+) -> Result<impl IntoResponse, HttpError> {
+    let Some(domain) = domains.get_domain_by_name(&domain_name).await? else {
+        return Err(DomainError::NotFoundByName(domain_name).into());
+    };
 
-    fn status(&self) -> Option<StatusCode> {
-        match self {
-            Self::NotFoundByName(_) => Some(StatusCode::NOT_FOUND),
+    let builder = match new_block.parent {
+        Some(parent) => {
+            if blocks.get_root_domain_id(&parent).await? != Some(domain.id) {
+                return Err(BlockError::ParentNotFound(parent).into());
+            }
+            Block::builder().block(parent)
         }
-    }
+        None => Block::builder().domain(domain.id),
+    };
+    let builder = match &new_block.name {
+        Some(name) => builder.name(name),
+        None => builder,
+    };
+    let block = builder.finalize().map_err(ValidationProblem::from)?;
+    blocks.insert_block(&block).await?;
+
+    let location = format!("/{}/{}", domain.name, block.name);
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        format.respond(block),
+    ))
 }
 
-#[tracing::instrument(name = "show_block", skip(repository))]
-pub(super) async fn show(
-    Path((_, block_name)): Path<(String, String)>,
+/// Read many blocks at once, with a single query. The identifiers
+/// repeated in the request are only read once.
+#[tracing::instrument(name = "batch_get_blocks", skip_all)]
+pub(super) async fn batch_get(
+    State(state): State<AppState>,
     Repository(repository): Repository<BlockRepository>,
+    format: Format,
+    Json(batch): Json<BatchGet>,
 ) -> Result<impl IntoResponse, HttpError> {
-    let block = repository.get_block_by_name(&block_name).await?;
-    if let Some(block) = block {
-        Ok(Json(block))
+    let mut seen = HashSet::new();
+    let ids = batch
+        .ids
+        .into_iter()
+        .filter(|id| seen.insert(*id))
+        .collect::<Vec<_>>();
+    if ids.len() > state.max_batch_size {
+        return Err(BlockError::BatchTooLarge(state.max_batch_size).into());
+    }
+
+    let mut found = if ids.is_empty() {
+        HashMap::new()
     } else {
-        Err(BlockError::NotFoundByName(block_name).into())
+        repository
+            .get_blocks(&ids)
+            .await?
+            .into_iter()
+            .map(|block| (block.id, block))
+            .collect()
+    };
+    let mut response = Batch {
+        blocks: Vec::with_capacity(found.len()),
+        missing: Vec::new(),
+    };
+    for id in ids {
+        match found.remove(&id) {
+            Some(block) => response.blocks.push(block),
+            None => response.missing.push(id),
+        }
     }
+
+    Ok(format.respond(response))
 }
@@ -0,0 +1,53 @@
+use crate::{openapi, AppState};
+use axum::{
+    extract::State,
+    http::header,
+    response::{Html, IntoResponse},
+    Json,
+};
+
+/// The version of [Redoc](https://redocly.com/redoc) rendering the OpenAPI
+/// document, pinned for its integrity to be checked.
+pub const REDOC_VERSION: &str = "2.1.5";
+
+/// The page rendering the OpenAPI document, loading Redoc from a CDN. The
+/// browser refuses the script when it doesn't match the integrity.
+fn page(integrity: Option<&str>) -> String {
+    let integrity = integrity
+        .map(|integrity| format!(r#" integrity="{integrity}""#))
+        .unwrap_or_default();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>backbone-metadata</title>
+</head>
+<body>
+<redoc spec-url="openapi.json"></redoc>
+<script src="https://cdn.jsdelivr.net/npm/redoc@{REDOC_VERSION}/bundles/redoc.standalone.js"{integrity} crossorigin="anonymous"></script>
+</body>
+</html>
+"#
+    )
+}
+
+/// Describe the routes of the service, with an OpenAPI document.
+pub(super) async fn openapi(State(state): State<AppState>) -> impl IntoResponse {
+    // The document only changes with the releases and the configuration
+    // of the service.
+    (
+        [(header::CACHE_CONTROL, "public, max-age=3600")],
+        Json(openapi::document(&state)),
+    )
+}
+
+/// Render the OpenAPI document, for the developers of the clients.
+pub(super) async fn ui(State(state): State<AppState>) -> impl IntoResponse {
+    let page = page(state.redoc_integrity.as_deref());
+    (
+        [(header::CACHE_CONTROL, "public, max-age=3600")],
+        Html(page),
+    )
+}
@@ -1,50 +1,63 @@
-use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
-use metadata_data_layer::repositories::DomainRepository;
+use crate::validation::ValidationProblem;
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use metadata_data_layer::{models::Domain, repositories::DomainRepository};
 use metadata_data_layer_utils::extract::Repository;
-use metadata_http_utils::{HttpError, Problem};
+use metadata_http_utils::{
+    extract::{Json, Path},
+    Format, HttpError, Problem,
+};
+use serde::Deserialize;
 use thiserror::Error;
 
-#[derive(Clone, Debug, Error)]
-enum DomainError {
+#[derive(Clone, Debug, Error, Problem)]
+#[problem(namespace = "domains")]
+pub enum DomainError {
     #[error("Domain '{0}' is not found.")]
+    #[problem(
+        type = "not-found",
+        title = "Domain Not Found.",
+        status = 404,
+        description = "No domain with the requested name exists."
+    )]
     NotFoundByName(String),
 }
 
-impl Problem for DomainError {
-    fn ty(&self) -> String {
-        let sub_type = match self {
-            Self::NotFoundByName(_) => "not-found",
-        };
-
-        format!("https://errors.taster.com/metadata/domains/{sub_type}")
-    }
-
-    fn title(&self) -> String {
-        match self {
-            Self::NotFoundByName(_) => "Domain Not Found.".to_string(),
-        }
-    }
-
-    fn detail(&self) -> String {
-        format!("{self}")
-    }
-
-    fn status(&self) -> Option<StatusCode> {
-        match self {
-            Self::NotFoundByName(_) => Some(StatusCode::NOT_FOUND),
-        }
-    }
+/// The body of the requests creating a domain.
+#[derive(Debug, Deserialize)]
+pub(super) struct NewDomain {
+    name: String,
 }
 
-#[tracing::instrument(name = "show_domain", skip(repository))]
+#[tracing::instrument(name = "show_domain", skip(repository, format))]
 pub(super) async fn show(
     Path(domain_name): Path<String>,
     Repository(repository): Repository<DomainRepository>,
+    format: Format,
 ) -> Result<impl IntoResponse, HttpError> {
     let domain = repository.get_domain_by_name(&domain_name).await?;
     if let Some(domain) = domain {
-        Ok(Json(domain))
+        Ok(format.respond(domain))
     } else {
         Err(DomainError::NotFoundByName(domain_name).into())
     }
 }
+
+#[tracing::instrument(name = "create_domain", skip_all)]
+pub(super) async fn create(
+    Repository(repository): Repository<DomainRepository>,
+    format: Format,
+    Json(new_domain): Json<NewDomain>,
+) -> Result<impl IntoResponse, HttpError> {
+    let domain = Domain::new(new_domain.name).map_err(ValidationProblem::from)?;
+    repository.insert_domain(&domain).await?;
+
+    let location = format!("/{}", domain.name);
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        format.respond(domain),
+    ))
+}
@@ -0,0 +1,22 @@
+use crate::{graphql::Loader, AppState};
+use axum::{
+    extract::{Extension, State},
+    Json,
+};
+use metadata_data_layer_utils::Session;
+use metadata_http_utils::extract;
+
+/// Execute a GraphQL query. The errors of the query are part of the
+/// response, which is always successful once the request is parsed.
+#[tracing::instrument(name = "graphql", skip_all)]
+pub(super) async fn execute(
+    State(state): State<AppState>,
+    Extension(session): Extension<Session>,
+    extract::Json(request): extract::Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let request = request
+        .data(session.clone())
+        .data(Loader::data_loader(session));
+
+    Json(state.graphql.execute(request).await)
+}
@@ -0,0 +1,132 @@
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use metadata_data_layer::{migrations, repositories::HealthRepository};
+use metadata_data_layer_utils::extract::Repository;
+use serde::Serialize;
+use std::time::Duration;
+
+/// How long the database is given to answer a readiness check.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Serialize)]
+struct DatabaseCheck {
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct MigrationsCheck {
+    status: Status,
+    expected_version: Option<i64>,
+    applied_version: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Checks {
+    database: DatabaseCheck,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    migrations: Option<MigrationsCheck>,
+}
+
+#[derive(Debug, Serialize)]
+struct Readiness {
+    status: Status,
+    checks: Checks,
+}
+
+/// Reports that the process is alive and able to answer requests. It
+/// doesn't check any dependency of the service.
+#[tracing::instrument(name = "liveness")]
+pub(super) async fn healthz() -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(serde_json::json!({ "status": Status::Up })),
+    )
+}
+
+/// Reports whether the service is ready to handle requests: the database
+/// must be reachable and its schema must be at the version expected by
+/// this build.
+#[tracing::instrument(name = "readiness", skip(repository))]
+pub(super) async fn readyz(
+    Repository(repository): Repository<HealthRepository>,
+) -> impl IntoResponse {
+    let version = tokio::time::timeout(CHECK_TIMEOUT, async {
+        repository.ping().await?;
+        repository.schema_version().await
+    })
+    .await;
+
+    let checks = match version {
+        Ok(Ok(applied_version)) => {
+            let expected_version = migrations::expected_version();
+            let status = if applied_version == expected_version {
+                Status::Up
+            } else {
+                Status::Down
+            };
+
+            Checks {
+                database: DatabaseCheck {
+                    status: Status::Up,
+                    error: None,
+                },
+                migrations: Some(MigrationsCheck {
+                    status,
+                    expected_version,
+                    applied_version,
+                }),
+            }
+        }
+        Ok(Err(error)) => {
+            // The probe is not authenticated, so the error, which may tell
+            // the hosts or the users of the database, is only logged.
+            tracing::warn!(%error, "the database is not ready");
+            Checks {
+                database: DatabaseCheck {
+                    status: Status::Down,
+                    error: Some("the database could not be queried".to_owned()),
+                },
+                migrations: None,
+            }
+        }
+        Err(_) => {
+            tracing::warn!(timeout = ?CHECK_TIMEOUT, "the database did not answer in time");
+            Checks {
+                database: DatabaseCheck {
+                    status: Status::Down,
+                    error: Some("the database did not answer in time".to_owned()),
+                },
+                migrations: None,
+            }
+        }
+    };
+
+    let ready = checks.database.status == Status::Up
+        && checks
+            .migrations
+            .as_ref()
+            .is_some_and(|migrations| migrations.status == Status::Up);
+    let (status_code, status) = if ready {
+        (StatusCode::OK, Status::Up)
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Status::Down)
+    };
+
+    (
+        status_code,
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(Readiness { status, checks }),
+    )
+}
@@ -1,12 +1,212 @@
-use crate::AppState;
-use axum::{routing::get, Router};
+use crate::{auth, consistency, metrics, rate_limit, AppState, RouteClass};
+use axum::{
+    extract::{DefaultBodyLimit, Request},
+    handler::Handler,
+    http::Method,
+    middleware,
+    response::IntoResponse,
+    routing::{on, MethodFilter, MethodRouter},
+    Router,
+};
+use metadata_http_utils::{context, rejection};
+use tower::ServiceExt;
 
+mod archives;
 mod blocks;
+mod docs;
 mod domains;
+mod graphql;
+mod health;
+pub(crate) mod problems;
+mod trees;
+
+pub use blocks::{BlockError, DEFAULT_MAX_BATCH_SIZE};
+pub use docs::REDOC_VERSION;
+pub use domains::DomainError;
+
+/// The path of the routes of a domain.
+const DOMAIN_PATH: &str = "/:domain_name";
+
+/// The path of the custom method reading many blocks at once. The router
+/// reads its colon as the start of a parameter, so it can't be registered
+/// as is: its requests are handed to it by the routes of the domains.
+const BATCH_GET_PATH: &str = "/blocks:batchGet";
+
+/// A route of the router, served for a single method.
+struct Route {
+    method: Method,
+    path: &'static str,
+    handler: MethodRouter<AppState>,
+    /// The class of the route, whose quota limits the rate of its
+    /// requests. It's derived from the method by default.
+    class: RouteClass,
+}
+
+impl Route {
+    /// Mark the route as reading many resources at once.
+    fn search(mut self) -> Self {
+        self.class = RouteClass::Search;
+        self
+    }
+}
+
+fn route<H, T>(method: Method, path: &'static str, handler: H) -> Route
+where
+    H: Handler<T, AppState>,
+    T: 'static,
+{
+    let filter = MethodFilter::try_from(method.clone()).expect("a standard method");
+    let class = if method == Method::GET {
+        RouteClass::Read
+    } else {
+        RouteClass::Write
+    };
+
+    Route {
+        method,
+        path,
+        handler: on(filter, handler),
+        class,
+    }
+}
+
+/// The routes of the API, authenticated when API keys are configured.
+fn api_routes() -> Vec<Route> {
+    vec![
+        route(Method::POST, "/", domains::create),
+        route(Method::GET, DOMAIN_PATH, domains::show),
+        route(Method::POST, DOMAIN_PATH, blocks::create),
+        route(Method::GET, "/:domain_name/:block_name", blocks::show),
+        route(Method::POST, BATCH_GET_PATH, blocks::batch_get).search(),
+        route(Method::POST, "/archives", archives::import),
+        route(Method::GET, "/archives/:domain_name", archives::export),
+        route(Method::GET, "/trees/:domain_name", trees::show).search(),
+        route(Method::POST, "/graphql", graphql::execute).search(),
+    ]
+}
+
+/// The routes that are never authenticated: the probes, as the
+/// orchestrators calling them are not expected to own an API key, and
+/// the documentation.
+fn public_routes(state: &AppState) -> Vec<Route> {
+    let mut routes = vec![
+        route(Method::GET, "/healthz", health::healthz),
+        route(Method::GET, "/readyz", health::readyz),
+        route(Method::GET, "/problems", problems::index),
+        route(Method::GET, "/problems/*name", problems::show),
+        route(Method::GET, "/openapi.json", docs::openapi),
+    ];
+    if state.openapi_ui {
+        routes.push(route(Method::GET, "/docs", docs::ui));
+    }
+
+    routes
+}
+
+/// List the method and the path of every route served by the router
+/// built from the state, e.g. to check that they are all documented.
+pub fn routes(state: &AppState) -> Vec<(Method, &'static str)> {
+    api_routes()
+        .into_iter()
+        .chain(public_routes(state))
+        .map(|route| (route.method, route.path))
+        .collect()
+}
+
+/// Apply the limits of the routes of the API to the handler of a route.
+fn limit(state: &AppState, route: Route) -> MethodRouter<AppState> {
+    // The rate limits are applied once the request is authenticated, so
+    // the clients can be told apart by their principal, and before the
+    // request limits, so the rejected requests are never in flight.
+    let handler = state.request_limits.apply(route.handler);
+    match &state.rate_limits {
+        Some(limits) => handler.layer(middleware::from_fn_with_state(
+            (limits.clone(), route.class),
+            rate_limit::limit,
+        )),
+        None => handler,
+    }
+}
+
+/// Hand the requests to the path of the custom method reading many blocks
+/// to its handler, and the other ones to the handler of a domain route.
+fn batch_get_or(
+    method: Method,
+    batch_get: MethodRouter,
+    handler: MethodRouter,
+) -> MethodRouter<AppState> {
+    let filter = MethodFilter::try_from(method).expect("a standard method");
+
+    on(filter, move |request: Request| {
+        let (batch_get, handler) = (batch_get.clone(), handler.clone());
+        async move {
+            if request.uri().path() != BATCH_GET_PATH {
+                return handler.oneshot(request).await.into_response();
+            }
+
+            let mut response = batch_get.oneshot(request).await.into_response();
+            response
+                .extensions_mut()
+                .insert(metrics::RoutePath(BATCH_GET_PATH));
+            response
+        }
+    })
+}
 
 pub fn init_router(state: AppState) -> Router {
-    Router::new()
-        .route("/:domain_name", get(domains::show))
-        .route("/:domain_name/:block_name", get(blocks::show))
+    let mut routes = api_routes();
+    let batch_get = routes
+        .iter()
+        .position(|route| route.path == BATCH_GET_PATH)
+        .map(|index| routes.remove(index))
+        .expect("the route of the batches");
+    let batch_get = limit(&state, batch_get)
+        .fallback(rejection::method_not_allowed)
+        .with_state(state.clone());
+
+    let mut router = Router::new();
+    for route in routes {
+        let (method, path) = (route.method.clone(), route.path);
+        let mut handler = limit(&state, route);
+        if path == DOMAIN_PATH {
+            handler = batch_get_or(method, batch_get.clone(), handler.with_state(state.clone()));
+        }
+        router = router.route(path, handler);
+    }
+
+    let mut router = if state.api_keys.is_empty() {
+        router
+    } else {
+        router.route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::authenticate,
+        ))
+    };
+    // The addresses are limited before the authentication, for the
+    // requests with invalid or missing keys to be limited too.
+    if let Some(limits) = state
+        .rate_limits
+        .as_ref()
+        .filter(|limits| limits.limits_addresses())
+    {
+        router = router.route_layer(middleware::from_fn_with_state(
+            limits.clone(),
+            rate_limit::limit_address,
+        ));
+    }
+    for route in public_routes(&state) {
+        router = router.route(route.path, route.handler);
+    }
+
+    router
+        .method_not_allowed_fallback(rejection::method_not_allowed)
+        .fallback(rejection::route_not_found)
+        .layer(DefaultBodyLimit::max(state.request_limits.max_body_size))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            consistency::session,
+        ))
+        .layer(middleware::from_fn(metrics::track))
+        .layer(middleware::from_fn(context::scope))
         .with_state(state)
 }
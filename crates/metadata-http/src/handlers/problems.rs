@@ -0,0 +1,108 @@
+use crate::{
+    auth::AuthError, limits::LoadError, rate_limit::RateLimitError, validation::ValidationProblem,
+};
+use axum::{
+    http::header,
+    response::{Html, IntoResponse},
+};
+use metadata_http_utils::{
+    extract::Path, negotiate::FormatError, problems, HttpError, Problem, Registry, RejectionError,
+    SqlProblem,
+};
+use std::{fmt::Write, sync::OnceLock};
+use thiserror::Error;
+
+use super::{archives::ArchiveProblem, blocks::BlockError, domains::DomainError};
+
+/// The problem types the application can respond with.
+pub(crate) fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| {
+        Registry::new()
+            .register::<ArchiveProblem>()
+            .register::<AuthError>()
+            .register::<BlockError>()
+            .register::<DomainError>()
+            .register::<FormatError>()
+            .register::<LoadError>()
+            .register::<ProblemError>()
+            .register::<RateLimitError>()
+            .register::<RejectionError>()
+            .register::<SqlProblem>()
+            .register::<ValidationProblem>()
+    })
+}
+
+#[derive(Clone, Debug, Error, Problem)]
+#[problem(namespace = "problems")]
+enum ProblemError {
+    #[error("Problem type '{0}' is not found.")]
+    #[problem(
+        type = "not-found",
+        title = "Problem Type Not Found.",
+        status = 404,
+        description = "The requested problem type is not emitted by the service."
+    )]
+    NotFound(String),
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn page(title: &str, content: &str) -> impl IntoResponse {
+    let html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n</head>\n<body>\n{content}</body>\n</html>\n",
+        title = escape(title),
+    );
+
+    // The problem types only change with the releases of the service.
+    (
+        [(header::CACHE_CONTROL, "public, max-age=3600")],
+        Html(html),
+    )
+}
+
+/// List the problem types the service can respond with.
+pub(super) async fn index() -> impl IntoResponse {
+    let mut content = String::from("<h1>Problem types</h1>\n<ul>\n");
+    for ty in registry().iter() {
+        let _ = writeln!(
+            content,
+            "<li><a href=\"{uri}\"><code>{name}</code></a>: {title}</li>",
+            uri = escape(&ty.uri()),
+            name = escape(ty.name),
+            title = escape(ty.title),
+        );
+    }
+    content.push_str("</ul>\n");
+
+    page("Problem types", &content)
+}
+
+/// Describe a problem type, so its URI can be dereferenced by the
+/// developers of the clients.
+pub(super) async fn show(Path(name): Path<String>) -> Result<impl IntoResponse, HttpError> {
+    let Some(ty) = registry().get(&name) else {
+        return Err(ProblemError::NotFound(name).into());
+    };
+
+    let content = format!(
+        "<h1>{title}</h1>\n<dl>\n<dt>Type</dt><dd><code>{uri}</code></dd>\n\
+         <dt>Status</dt><dd>{status}</dd>\n</dl>\n<p>{description}</p>\n\
+         <p><a href=\"{base_uri}\">All problem types</a></p>\n",
+        title = escape(ty.title),
+        uri = escape(&ty.uri()),
+        status = ty.status,
+        description = escape(ty.description),
+        base_uri = escape(problems::base_uri()),
+    );
+
+    Ok(page(ty.title, &content))
+}
@@ -0,0 +1,171 @@
+use super::{blocks::BlockError, domains::DomainError};
+use crate::{RequestLimits, Shutdown};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use futures_util::{stream, StreamExt};
+use metadata_data_layer::repositories::{BlockRepository, DomainRepository};
+use metadata_data_layer_utils::extract::Repository;
+use metadata_http_utils::{
+    extract::{Path, Query},
+    HttpError,
+};
+use serde::Deserialize;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::{sync::mpsc, time::Instant};
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// The media type of the streamed trees.
+const NDJSON: &str = "application/x-ndjson";
+
+/// The size above which the buffered lines are sent to the client.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// The number of chunks read from the database ahead of the client.
+const CHANNEL_CAPACITY: usize = 4;
+
+/// The errors ending a tree stream once its response has started: they
+/// can't be described by a problem anymore, so the response is aborted
+/// for the client to notice it's truncated.
+#[derive(Debug, Error)]
+enum TreeError {
+    #[error("the server is shutting down")]
+    ShuttingDown,
+    #[error("the stream didn't complete within {0:?}")]
+    TimedOut(Duration),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// The query string of the requests streaming a tree.
+#[derive(Debug, Deserialize)]
+pub(super) struct TreeParams {
+    /// The name of the block at the root of the subtree to stream. The
+    /// whole tree of the domain is streamed when it's missing.
+    root: Option<String>,
+}
+
+/// Stream the blocks of a domain, or of the subtree of one of its blocks,
+/// as newline-delimited JSON. Each line holds a block and its depth, the
+/// blocks being walked depth-first: every block comes right before its
+/// descendants, and the siblings are ordered by name.
+///
+/// The blocks are read from the database while the response is written,
+/// so the tree is never held in memory. When the stream fails, times out,
+/// or the server shuts down before its end, the response is aborted
+/// rather than ended, for the client not to mistake it for a whole tree.
+///
+/// The stream counts as a request in flight until its end, as it holds a
+/// connection to the database.
+#[tracing::instrument(name = "stream_tree", skip(shutdown, limits, domains, blocks))]
+pub(super) async fn show(
+    State(shutdown): State<Shutdown>,
+    State(limits): State<RequestLimits>,
+    Path(domain_name): Path<String>,
+    Query(params): Query<TreeParams>,
+    Repository(domains): Repository<DomainRepository>,
+    Repository(blocks): Repository<BlockRepository>,
+) -> Result<Response, HttpError> {
+    // The slot of the stream is taken before reading the database, for an
+    // overloaded service not to do it for nothing.
+    let permit = limits.stream_permit()?;
+    let deadline = limits
+        .stream_timeout
+        .map(|timeout| (Instant::now() + timeout, timeout));
+
+    let Some(domain) = domains.get_domain_by_name(&domain_name).await? else {
+        return Err(DomainError::NotFoundByName(domain_name).into());
+    };
+
+    let root_id = match params.root {
+        Some(root_name) => match blocks.get_block_by_name(&domain.id, &root_name).await? {
+            Some(root) => Some(root.id),
+            None => return Err(BlockError::NotFoundByName(root_name).into()),
+        },
+        None => None,
+    };
+
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    tokio::spawn(read_tree(blocks, domain.id, root_id, sender).in_current_span());
+
+    // The shutdown and the deadline are watched by the body rather than by
+    // the task reading the tree, which may be waiting for the client to
+    // read a chunk. The slot of the stream is released with the body.
+    let state = (receiver, shutdown, deadline, permit);
+    let body = stream::unfold(Some(state), |state| async move {
+        let (mut receiver, shutdown, deadline, permit) = state?;
+        let timed_out = async {
+            match deadline {
+                Some((deadline, _)) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            () = shutdown.triggered() => Some((Err(TreeError::ShuttingDown), None)),
+            () = timed_out => {
+                let timeout = deadline.map(|(_, timeout)| timeout).unwrap_or_default();
+                tracing::warn!(?timeout, "tree stream timed out");
+                Some((Err(TreeError::TimedOut(timeout)), None))
+            }
+            chunk = receiver.recv() => {
+                let done = !matches!(chunk, Some(Ok(_)));
+                let state = (receiver, shutdown, deadline, permit);
+                chunk.map(|chunk| (chunk, (!done).then_some(state)))
+            }
+        }
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, HeaderValue::from_static(NDJSON))],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+/// Read the tree from the database and send its lines by chunks, until
+/// its end or until the response is dropped.
+async fn read_tree(
+    blocks: BlockRepository,
+    domain_id: Uuid,
+    root_id: Option<Uuid>,
+    sender: mpsc::Sender<Result<Vec<u8>, TreeError>>,
+) {
+    let mut entries = blocks.stream_tree(&domain_id, root_id.as_ref());
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+
+    loop {
+        let entry = tokio::select! {
+            () = sender.closed() => return,
+            entry = entries.next() => entry,
+        };
+
+        match entry {
+            Some(Ok(entry)) => {
+                serde_json::to_writer(&mut chunk, &entry).expect("a block is always serializable");
+                chunk.push(b'\n');
+            }
+            Some(Err(error)) => {
+                tracing::error!(%error, "failed to read the tree");
+                let _ = sender.send(Err(error.into())).await;
+                return;
+            }
+            None => break,
+        }
+
+        if chunk.len() >= CHUNK_SIZE {
+            let full = std::mem::replace(&mut chunk, Vec::with_capacity(CHUNK_SIZE));
+            if sender.send(Ok(full)).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    if !chunk.is_empty() {
+        let _ = sender.send(Ok(chunk)).await;
+    }
+}
@@ -1,5 +1,22 @@
+mod auth;
+mod consistency;
+pub mod graphql;
 mod handlers;
+mod limits;
+mod metrics;
+pub mod openapi;
+mod rate_limit;
+mod shutdown;
 mod state;
+mod validation;
 
-pub use handlers::init_router;
+pub use auth::{ApiKeys, AuthError, ClientIdentity, Principal};
+pub use consistency::CONSISTENCY_TOKEN;
+pub use handlers::{
+    init_router, routes, BlockError, DomainError, DEFAULT_MAX_BATCH_SIZE, REDOC_VERSION,
+};
+pub use limits::{LoadError, RequestLimits, DEFAULT_MAX_BODY_SIZE};
+pub use rate_limit::{Quota, RateLimitKey, RateLimits, RouteClass};
+pub use shutdown::Shutdown;
 pub use state::AppState;
+pub use validation::ValidationProblem;
@@ -0,0 +1,211 @@
+//! The limits keeping the service responsive under load: rather than
+//! queueing the requests until the pool times out, the slow ones are
+//! cut short and the ones above the capacity of the service are shed.
+
+use crate::AppState;
+use axum::{
+    extract::FromRef,
+    http::{header, HeaderMap, HeaderValue},
+    routing::MethodRouter,
+    BoxError,
+};
+use metadata_http_utils::{HttpError, Problem};
+use std::{sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tower::{
+    layer::util::Identity,
+    limit::GlobalConcurrencyLimitLayer,
+    load_shed::{error::Overloaded, LoadShedLayer},
+    timeout::{error::Elapsed, TimeoutLayer},
+    ServiceBuilder,
+};
+
+/// The maximum size of the request bodies, by default.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+/// The delay after which the shed requests can be retried.
+const RETRY_AFTER: u64 = 1;
+
+/// The problems of the requests which the service is too busy or too slow
+/// to handle.
+#[derive(Clone, Debug, Error, Problem)]
+#[problem(namespace = "server", headers = "retry_after")]
+pub enum LoadError {
+    #[error("The request didn't complete within {0:?}.")]
+    #[problem(
+        type = "timeout",
+        title = "Request Timed Out.",
+        status = 504,
+        description = "The request took longer than the service allows, e.g. because the \
+                       database is slow. The changes it requested may have been applied."
+    )]
+    Timeout(Duration),
+    #[error("The service is handling as many requests as it can, retry later.")]
+    #[problem(
+        type = "overloaded",
+        title = "Service Overloaded.",
+        status = 503,
+        description = "The service is handling as many requests as it allows, and rejects \
+                       the others rather than queueing them. The request was not processed, \
+                       and can be retried after the delay of the `Retry-After` header."
+    )]
+    Overloaded,
+}
+
+impl LoadError {
+    fn retry_after(&self) -> Option<HeaderMap> {
+        match self {
+            Self::Timeout(_) => None,
+            Self::Overloaded => {
+                let mut headers = HeaderMap::new();
+                headers.insert(header::RETRY_AFTER, HeaderValue::from(RETRY_AFTER));
+
+                Some(headers)
+            }
+        }
+    }
+}
+
+/// The limits of the requests to the routes of the API. The probes and
+/// the documentation are only subject to the maximum body size.
+#[derive(Clone, Debug)]
+pub struct RequestLimits {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) stream_timeout: Option<Duration>,
+    pub(crate) max_body_size: usize,
+    in_flight: Option<Arc<Semaphore>>,
+    load_shedding: bool,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            stream_timeout: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            in_flight: None,
+            load_shedding: false,
+        }
+    }
+}
+
+impl RequestLimits {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Respond with a `504 Gateway Timeout` problem to the requests whose
+    /// response is not ready after the timeout. The bodies streamed once
+    /// the response is ready are limited by the stream timeout instead.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Abort the streamed responses which are still streaming after
+    /// `timeout`, counted from the start of the request, so a slow client
+    /// can't hold a connection to the database for ever.
+    pub fn with_stream_timeout(mut self, timeout: Duration) -> Self {
+        self.stream_timeout = Some(timeout);
+        self
+    }
+
+    /// Respond with a `413 Payload Too Large` problem to the requests
+    /// whose body is larger than `max_body_size` bytes.
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Handle at most `max_in_flight` requests at once, the next ones
+    /// waiting for one of them to complete.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.in_flight = Some(Arc::new(Semaphore::new(max_in_flight)));
+        self
+    }
+
+    /// Reject with a `503 Service Unavailable` problem the requests above
+    /// the maximum number of requests in flight, instead of having them
+    /// wait.
+    pub fn with_load_shedding(mut self, enabled: bool) -> Self {
+        self.load_shedding = enabled;
+        self
+    }
+
+    /// Whether requests can be shed, which requires a maximum number of
+    /// requests in flight.
+    pub(crate) fn sheds_load(&self) -> bool {
+        self.load_shedding && self.in_flight.is_some()
+    }
+
+    /// Take a slot of the requests in flight for the body of a streamed
+    /// response, held until the body is dropped, as the slot of its
+    /// request is released once the response starts. The handler holding
+    /// its own slot meanwhile, the stream is rejected rather than waiting
+    /// when no other slot is free.
+    pub(crate) fn stream_permit(&self) -> Result<Option<OwnedSemaphorePermit>, LoadError> {
+        let Some(in_flight) = &self.in_flight else {
+            return Ok(None);
+        };
+
+        match Arc::clone(in_flight).try_acquire_owned() {
+            Ok(permit) => Ok(Some(permit)),
+            Err(_) => {
+                metrics::counter!("http_requests_shed_total").increment(1);
+                Err(LoadError::Overloaded)
+            }
+        }
+    }
+
+    /// Apply the limits to the handler of a route. The requests in flight
+    /// are counted across every route the limits are applied to.
+    pub(crate) fn apply(&self, handler: MethodRouter<AppState>) -> MethodRouter<AppState> {
+        if self.timeout.is_none() && self.in_flight.is_none() {
+            return handler;
+        }
+
+        // The layers are applied one by one, the innermost first, for the
+        // missing ones not to change the type of the errors. The load is
+        // shed by the same layer as the one limiting the concurrency, the
+        // routes of the router being always ready.
+        let mut handler: MethodRouter<AppState, BoxError> = match self.in_flight.clone() {
+            Some(in_flight) if self.load_shedding => handler.layer(
+                ServiceBuilder::new()
+                    .layer(LoadShedLayer::new())
+                    .layer(GlobalConcurrencyLimitLayer::with_semaphore(in_flight)),
+            ),
+            Some(in_flight) => {
+                handler.layer(GlobalConcurrencyLimitLayer::with_semaphore(in_flight))
+            }
+            None => handler.layer(Identity::new()),
+        };
+        if let Some(timeout) = self.timeout {
+            handler = handler.layer(TimeoutLayer::new(timeout));
+        }
+
+        let timeout = self.timeout.unwrap_or_default();
+        handler.handle_error(move |error: BoxError| async move { problem(error, timeout) })
+    }
+}
+
+/// Describe the error of a limit as a problem.
+fn problem(error: BoxError, timeout: Duration) -> HttpError {
+    if error.is::<Elapsed>() {
+        tracing::warn!(?timeout, "request timed out");
+        LoadError::Timeout(timeout).into()
+    } else if error.is::<Overloaded>() {
+        metrics::counter!("http_requests_shed_total").increment(1);
+        LoadError::Overloaded.into()
+    } else {
+        tracing::error!(%error, "request failed");
+        metadata_http_utils::RejectionError::Internal(error.to_string()).into()
+    }
+}
+
+impl FromRef<AppState> for RequestLimits {
+    fn from_ref(input: &AppState) -> Self {
+        input.request_limits.clone()
+    }
+}
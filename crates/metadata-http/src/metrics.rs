@@ -0,0 +1,66 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use std::time::Instant;
+
+/// The label used for the requests that did not match any route, so
+/// unknown paths can't blow up the cardinality of the metrics.
+const UNMATCHED_ROUTE: &str = "<unmatched>";
+
+/// The label used for the requests with an extension method, which any
+/// client can make up.
+const OTHER_METHOD: &str = "other";
+
+/// The path of the route which handled a request, when it's not the one
+/// matched by the router, e.g. for the custom methods.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RoutePath(pub(crate) &'static str);
+
+/// A middleware recording the `http_requests_total` counter and the
+/// `http_request_duration_seconds` histogram, labelled by HTTP method,
+/// matched route and response status.
+pub(crate) async fn track(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = method_label(request.method());
+    let matched = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned());
+
+    let response = next.run(request).await;
+
+    let route = match response.extensions().get::<RoutePath>() {
+        Some(RoutePath(path)) => path.to_string(),
+        None => matched.unwrap_or_else(|| UNMATCHED_ROUTE.to_owned()),
+    };
+
+    let labels = [
+        ("method", method.to_owned()),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!("http_request_duration_seconds", &labels).record(start.elapsed());
+
+    response
+}
+
+/// The label of the method of a request, one of the standard methods or
+/// [`OTHER_METHOD`].
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::OPTIONS => "OPTIONS",
+        Method::CONNECT => "CONNECT",
+        Method::TRACE => "TRACE",
+        _ => OTHER_METHOD,
+    }
+}
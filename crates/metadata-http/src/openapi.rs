@@ -0,0 +1,861 @@
+//! The [OpenAPI 3.1] description of the service, covering every route of
+//! the router built by [init_router](crate::init_router), the resources
+//! it serves and every problem type it can respond with.
+//!
+//! The operations are described next to the routes rather than derived
+//! from the handlers, so the `openapi` integration test checks that each
+//! route returned by [routes](crate::routes) is documented, and validates
+//! the serialized resources and the responses of the routes against their
+//! schemas.
+//!
+//! [OpenAPI 3.1]: https://spec.openapis.org/oas/v3.1.0
+
+use crate::{auth::AuthError, handlers::problems::registry, rate_limit::RateLimitError, AppState};
+use axum::http::Method;
+use metadata_http_utils::{problems, ProblemType, ProblemTypes, SqlProblem};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// The representation of the body of a request or of a response.
+enum Body {
+    /// A resource, negotiated between JSON, YAML and MessagePack.
+    Resource(&'static str),
+    /// A JSON document.
+    Json(&'static str),
+    /// An archive, as a JSON document or as NDJSON.
+    Archive,
+    /// A tree of blocks, streamed as NDJSON.
+    Tree,
+    /// An HTML page.
+    Html,
+    /// An OpenAPI document.
+    OpenApi,
+}
+
+impl Body {
+    fn content(&self) -> Value {
+        let schema =
+            |name: &str| json!({ "schema": { "$ref": format!("#/components/schemas/{name}") } });
+
+        match self {
+            Self::Resource(name) => json!({
+                "application/json": schema(name),
+                "application/yaml": schema(name),
+                "application/msgpack": schema(name),
+            }),
+            Self::Json(name) => json!({ "application/json": schema(name) }),
+            Self::Archive => json!({
+                "application/json": schema("Archive"),
+                "application/x-ndjson": {
+                    "schema": {
+                        "type": "string",
+                        "description": "A first line holding the `version` and the `domain` \
+                                        of the archive, then one `ArchivedBlock` per line.",
+                    },
+                },
+            }),
+            Self::Tree => json!({
+                "application/x-ndjson": {
+                    "schema": {
+                        "type": "string",
+                        "description": "One `TreeEntry` per line, the blocks being walked \
+                                        depth-first with the siblings ordered by name.",
+                    },
+                },
+            }),
+            Self::Html => json!({ "text/html": { "schema": { "type": "string" } } }),
+            Self::OpenApi => json!({
+                "application/json": {
+                    "schema": {
+                        "type": "object",
+                        "externalDocs": { "url": "https://spec.openapis.org/oas/v3.1.0" },
+                    },
+                },
+            }),
+        }
+    }
+}
+
+struct Response {
+    status: u16,
+    description: &'static str,
+    body: Option<Body>,
+    headers: &'static [(&'static str, &'static str)],
+}
+
+impl Response {
+    fn new(status: u16, description: &'static str, body: Body) -> Self {
+        Self {
+            status,
+            description,
+            body: Some(body),
+            headers: &[],
+        }
+    }
+
+    fn with_headers(mut self, headers: &'static [(&'static str, &'static str)]) -> Self {
+        self.headers = headers;
+        self
+    }
+}
+
+/// The description of the `X-Consistency-Token` header of the responses.
+const TOKEN: &str = "The consistency token of the changes, to send in the following requests so \
+                     they read them.";
+
+const CREATED: &[(&str, &str)] = &[
+    ("Location", "The path of the created resource."),
+    ("X-Consistency-Token", TOKEN),
+];
+const CHANGED: &[(&str, &str)] = &[("X-Consistency-Token", TOKEN)];
+
+struct Operation {
+    method: Method,
+    /// The path, as an OpenAPI template.
+    path: &'static str,
+    id: &'static str,
+    summary: &'static str,
+    tag: &'static str,
+    /// Whether the operation requires an API key, when some are
+    /// configured.
+    authenticated: bool,
+    /// Whether the operation queries the database, so it can respond
+    /// with the problems describing its errors.
+    database: bool,
+    query: Vec<Value>,
+    request: Option<Body>,
+    responses: Vec<Response>,
+    /// The problem types specific to the operation. The ones shared by
+    /// every operation, e.g. the database problems, are added to them.
+    problems: &'static [&'static str],
+}
+
+impl Operation {
+    fn new(method: Method, path: &'static str, id: &'static str, summary: &'static str) -> Self {
+        Self {
+            method,
+            path,
+            id,
+            summary,
+            tag: "",
+            authenticated: false,
+            database: false,
+            query: Vec::new(),
+            request: None,
+            responses: Vec::new(),
+            problems: &[],
+        }
+    }
+
+    /// Mark the operation as part of the API, authenticated and backed by
+    /// the database.
+    fn api(mut self, tag: &'static str) -> Self {
+        self.tag = tag;
+        self.authenticated = true;
+        self.database = true;
+        self
+    }
+
+    fn tag(mut self, tag: &'static str) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    /// Mark the operation as requiring an API key, when some are
+    /// configured.
+    fn authenticated(mut self) -> Self {
+        self.authenticated = true;
+        self
+    }
+
+    fn query(mut self, name: &str, description: &str, schema: Value) -> Self {
+        self.query.push(json!({
+            "name": name,
+            "in": "query",
+            "description": description,
+            "schema": schema,
+        }));
+        self
+    }
+
+    fn request(mut self, body: Body) -> Self {
+        self.request = Some(body);
+        self
+    }
+
+    fn response(mut self, response: Response) -> Self {
+        self.responses.push(response);
+        self
+    }
+
+    fn problems(mut self, problems: &'static [&'static str]) -> Self {
+        self.problems = problems;
+        self
+    }
+
+    fn parameters(&self) -> Vec<&'static str> {
+        self.path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+            .collect()
+    }
+
+    /// The problem types the operation can respond with, including the
+    /// ones shared with the other operations.
+    fn problem_types(&self, state: &AppState) -> Vec<&'static ProblemType> {
+        let mut names = self.problems.to_vec();
+        if !self.parameters().is_empty() {
+            names.push("requests/invalid-path");
+        }
+        if !self.query.is_empty() {
+            names.push("requests/invalid-query");
+        }
+        if matches!(self.request, Some(Body::Json(_))) {
+            names.extend([
+                "requests/malformed-body",
+                "requests/invalid-body",
+                "requests/unsupported-media-type",
+                "requests/payload-too-large",
+            ]);
+        }
+        if self
+            .responses
+            .iter()
+            .any(|response| matches!(response.body, Some(Body::Resource(_))))
+        {
+            names.push("negotiation/serialization-error");
+        }
+        if self.database {
+            names.extend(SqlProblem::TYPES.iter().map(|ty| ty.name));
+        }
+        if self.authenticated && !state.api_keys.is_empty() {
+            names.extend(AuthError::TYPES.iter().map(|ty| ty.name));
+        }
+        // The routes of the API are the authenticated ones, and the only
+        // ones which are rate limited, timed out or shed.
+        if self.authenticated && state.rate_limits.is_some() {
+            names.extend(RateLimitError::TYPES.iter().map(|ty| ty.name));
+        }
+        if self.authenticated && state.request_limits.timeout.is_some() {
+            names.push("server/timeout");
+        }
+        if self.authenticated && state.request_limits.sheds_load() {
+            names.push("server/overloaded");
+        }
+
+        names
+            .into_iter()
+            .map(|name| {
+                registry()
+                    .get(name)
+                    .unwrap_or_else(|| panic!("the problem type '{name}' is not registered"))
+            })
+            .collect()
+    }
+
+    fn document(&self, state: &AppState) -> Value {
+        let mut parameters = self
+            .parameters()
+            .into_iter()
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "description": parameter_description(name),
+                    "schema": { "type": "string" },
+                })
+            })
+            .collect::<Vec<_>>();
+        parameters.extend(self.query.iter().cloned());
+        if self.database {
+            parameters.push(json!({
+                "name": "X-Consistency-Token",
+                "in": "header",
+                "description": "The consistency token of the last changes of the client, for \
+                                the request to read them.",
+                "schema": { "type": "string", "example": "16/B374D848" },
+            }));
+        }
+
+        let mut responses = Map::new();
+        for response in &self.responses {
+            let mut value = json!({ "description": response.description });
+            if let Some(body) = &response.body {
+                value["content"] = body.content();
+            }
+            if !response.headers.is_empty() {
+                value["headers"] = response
+                    .headers
+                    .iter()
+                    .map(|(name, description)| {
+                        (
+                            name.to_string(),
+                            json!({ "description": description, "schema": { "type": "string" } }),
+                        )
+                    })
+                    .collect::<Map<_, _>>()
+                    .into();
+            }
+            responses.insert(response.status.to_string(), value);
+        }
+
+        let mut by_status = BTreeMap::<u16, Vec<&ProblemType>>::new();
+        for ty in self.problem_types(state) {
+            by_status.entry(ty.status.as_u16()).or_default().push(ty);
+        }
+        for (status, types) in by_status {
+            let titles = types.iter().map(|ty| ty.title).collect::<Vec<_>>();
+            let schemas = types
+                .iter()
+                .map(|ty| json!({ "$ref": format!("#/components/schemas/{}", schema_name(ty)) }))
+                .collect::<Vec<_>>();
+            responses.insert(
+                status.to_string(),
+                json!({
+                    "description": titles.join(" "),
+                    "content": {
+                        "application/problem+json": { "schema": { "oneOf": schemas } },
+                    },
+                }),
+            );
+        }
+
+        let mut operation = json!({
+            "operationId": self.id,
+            "summary": self.summary,
+            "tags": [self.tag],
+            "responses": responses,
+        });
+        if !parameters.is_empty() {
+            operation["parameters"] = parameters.into();
+        }
+        if let Some(body) = &self.request {
+            operation["requestBody"] = json!({ "required": true, "content": body.content() });
+        }
+        operation["security"] = if self.authenticated && !state.api_keys.is_empty() {
+            json!([{ "bearer": [] }, { "apiKey": [] }])
+        } else {
+            json!([])
+        };
+
+        operation
+    }
+}
+
+fn parameter_description(name: &str) -> &'static str {
+    match name {
+        "domain_name" => "The name of the domain.",
+        "block_name" => "The name of the block.",
+        "name" => "The name of the problem type, which may contain slashes.",
+        _ => "",
+    }
+}
+
+/// The name of the schema of a problem type, e.g. `Problem.domains.not-found`.
+fn schema_name(ty: &ProblemType) -> String {
+    format!("Problem.{}", ty.name.replace('/', "."))
+}
+
+/// The operations served by the router built from the state.
+fn operations(state: &AppState) -> Vec<Operation> {
+    let mut operations = vec![
+        Operation::new(Method::POST, "/", "createDomain", "Create a domain")
+            .api("domains")
+            .request(Body::Json("NewDomain"))
+            .response(
+                Response::new(201, "The created domain.", Body::Resource("Domain"))
+                    .with_headers(CREATED),
+            )
+            .problems(&["validation/invalid-resource"]),
+        Operation::new(Method::GET, "/{domain_name}", "showDomain", "Show a domain")
+            .api("domains")
+            .response(Response::new(200, "The domain.", Body::Resource("Domain")))
+            .problems(&["domains/not-found"]),
+        Operation::new(
+            Method::POST,
+            "/{domain_name}",
+            "createBlock",
+            "Create a block, at the root of a domain or under another block",
+        )
+        .api("blocks")
+        .request(Body::Json("NewBlock"))
+        .response(
+            Response::new(201, "The created block.", Body::Resource("Block")).with_headers(CREATED),
+        )
+        .problems(&[
+            "domains/not-found",
+            "blocks/parent-not-found",
+            "validation/invalid-resource",
+        ]),
+        Operation::new(
+            Method::GET,
+            "/{domain_name}/{block_name}",
+            "showBlock",
+            "Show a block",
+        )
+        .api("blocks")
+        .response(Response::new(
+            200,
+            "The block with the name nearest to the root of the domain.",
+            Body::Resource("Block"),
+        ))
+        .problems(&["domains/not-found", "blocks/not-found"]),
+        Operation::new(
+            Method::POST,
+            "/blocks:batchGet",
+            "batchGetBlocks",
+            "Read many blocks at once, by their IDs",
+        )
+        .api("blocks")
+        .request(Body::Json("BatchGetBlocks"))
+        .response(Response::new(
+            200,
+            "The blocks found, in the order of the request, and the IDs of the missing ones.",
+            Body::Resource("BlockBatch"),
+        ))
+        .problems(&["blocks/batch-too-large", "requests/invalid-body"]),
+        Operation::new(
+            Method::POST,
+            "/archives",
+            "importArchive",
+            "Import a domain, with its whole tree of blocks, from an archive",
+        )
+        .api("archives")
+        .query(
+            "mode",
+            "Whether the resources are created with new IDs, or keep the IDs of the \
+             archive and update the stored resources.",
+            json!({ "type": "string", "enum": ["new-ids", "preserve-ids"], "default": "new-ids" }),
+        )
+        .query(
+            "dry_run",
+            "Only report the changes the import would make.",
+            json!({ "type": "boolean", "default": false }),
+        )
+        .request(Body::Archive)
+        .response(
+            Response::new(
+                200,
+                "The changes made by the import, or that it would make with a dry run.",
+                Body::Resource("ImportPlan"),
+            )
+            .with_headers(CHANGED),
+        )
+        .response(
+            Response::new(
+                201,
+                "The changes made by the import, which created the domain.",
+                Body::Resource("ImportPlan"),
+            )
+            .with_headers(CREATED),
+        )
+        .problems(&[
+            "archives/malformed-archive",
+            "archives/invalid-archive",
+            "archives/conflict",
+            "requests/malformed-body",
+            "requests/payload-too-large",
+        ]),
+        Operation::new(
+            Method::GET,
+            "/archives/{domain_name}",
+            "exportArchive",
+            "Export a domain, with its whole tree of blocks, as an archive",
+        )
+        .api("archives")
+        .response(
+            Response::new(200, "The archive of the domain.", Body::Archive)
+                .with_headers(&[("Content-Disposition", "Names the archive after the domain.")]),
+        )
+        .problems(&["domains/not-found"]),
+        Operation::new(
+            Method::GET,
+            "/trees/{domain_name}",
+            "streamTree",
+            "Stream the tree of blocks of a domain, depth-first",
+        )
+        .api("blocks")
+        .query(
+            "root",
+            "The name of the block at the root of the subtree to stream. The whole tree of \
+             the domain is streamed when it's missing.",
+            json!({ "type": "string" }),
+        )
+        .response(Response::new(
+            200,
+            "The blocks of the tree, read while the response is written. The response is \
+             aborted, rather than ended, when the tree can't be read to its end.",
+            Body::Tree,
+        ))
+        .problems(&["domains/not-found", "blocks/not-found"]),
+        // The errors of the GraphQL queries, including the ones of the
+        // database, are part of the successful responses.
+        Operation::new(
+            Method::POST,
+            "/graphql",
+            "graphql",
+            "Execute a GraphQL query over the domains and their blocks",
+        )
+        .tag("graphql")
+        .authenticated()
+        .request(Body::Json("GraphQLRequest"))
+        .response(Response::new(
+            200,
+            "The result of the query, along with its errors.",
+            Body::Json("GraphQLResponse"),
+        )),
+        Operation::new(
+            Method::GET,
+            "/healthz",
+            "liveness",
+            "Check that the process is alive",
+        )
+        .tag("probes")
+        .response(Response::new(
+            200,
+            "The process is alive.",
+            Body::Json("Liveness"),
+        )),
+        Operation::new(
+            Method::GET,
+            "/readyz",
+            "readiness",
+            "Check that the service is ready to handle requests",
+        )
+        .tag("probes")
+        .response(Response::new(
+            200,
+            "The service is ready.",
+            Body::Json("Readiness"),
+        ))
+        .response(Response::new(
+            503,
+            "The database is not reachable, or its schema is not at the expected version.",
+            Body::Json("Readiness"),
+        )),
+        Operation::new(
+            Method::GET,
+            "/problems",
+            "listProblemTypes",
+            "List the problem types",
+        )
+        .tag("documentation")
+        .response(Response::new(
+            200,
+            "A page listing the problem types.",
+            Body::Html,
+        )),
+        Operation::new(
+            Method::GET,
+            "/problems/{name}",
+            "showProblemType",
+            "Describe a problem type",
+        )
+        .tag("documentation")
+        .response(Response::new(
+            200,
+            "A page describing the problem type.",
+            Body::Html,
+        ))
+        .problems(&["problems/not-found"]),
+        Operation::new(Method::GET, "/openapi.json", "openapi", "Describe the API")
+            .tag("documentation")
+            .response(Response::new(200, "This document.", Body::OpenApi)),
+    ];
+    if state.openapi_ui {
+        operations.push(
+            Operation::new(Method::GET, "/docs", "openapiUi", "Browse this document")
+                .tag("documentation")
+                .response(Response::new(
+                    200,
+                    "A page rendering this document.",
+                    Body::Html,
+                )),
+        );
+    }
+
+    operations
+}
+
+fn date_time() -> Value {
+    json!({ "type": "string", "format": "date-time" })
+}
+
+fn uuid() -> Value {
+    json!({ "type": "string", "format": "uuid" })
+}
+
+fn object(properties: Value, required: &[&str]) -> Value {
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+/// The schemas of the resources, matching their serializers.
+fn resource_schemas() -> Map<String, Value> {
+    let check = |statuses: &[&str]| json!({ "type": "string", "enum": statuses });
+
+    let schemas = json!({
+        "Domain": object(
+            json!({ "id": uuid(), "name": { "type": "string" }, "created_at": date_time(), "updated_at": date_time() }),
+            &["id", "name", "created_at", "updated_at"],
+        ),
+        "Parent": {
+            "oneOf": [
+                object(
+                    json!({ "type": { "const": "domain" }, "domain_uuid": uuid() }),
+                    &["type", "domain_uuid"],
+                ),
+                object(
+                    json!({ "type": { "const": "block" }, "block_uuid": uuid() }),
+                    &["type", "block_uuid"],
+                ),
+            ],
+        },
+        "Block": object(
+            json!({
+                "id": uuid(),
+                "parent": { "$ref": "#/components/schemas/Parent" },
+                "name": { "type": "string" },
+                "created_at": date_time(),
+                "updated_at": date_time(),
+            }),
+            &["id", "parent", "name", "created_at", "updated_at"],
+        ),
+        "BatchGetBlocks": object(
+            json!({
+                "ids": {
+                    "description": format!(
+                        "The IDs of the blocks, at most {} by default.",
+                        crate::DEFAULT_MAX_BATCH_SIZE,
+                    ),
+                    "type": "array",
+                    "items": uuid(),
+                },
+            }),
+            &["ids"],
+        ),
+        "BlockBatch": object(
+            json!({
+                "blocks": { "type": "array", "items": { "$ref": "#/components/schemas/Block" } },
+                "missing": {
+                    "description": "The IDs of the request which are not the ones of a block.",
+                    "type": "array",
+                    "items": uuid(),
+                },
+            }),
+            &["blocks", "missing"],
+        ),
+        "TreeEntry": object(
+            json!({
+                "depth": {
+                    "description": "The depth of the block, 0 for the root of the tree.",
+                    "type": "integer",
+                    "minimum": 0,
+                },
+                "block": { "$ref": "#/components/schemas/Block" },
+            }),
+            &["depth", "block"],
+        ),
+        "NewDomain": object(json!({ "name": { "type": "string" } }), &["name"]),
+        "NewBlock": object(
+            json!({
+                "name": { "type": "string" },
+                "parent": {
+                    "description": "The parent block, the block is created at the root of \
+                                    the domain when it's missing.",
+                    "type": "string",
+                    "format": "uuid",
+                },
+            }),
+            &["name"],
+        ),
+        "ArchivedDomain": object(
+            json!({ "id": uuid(), "name": { "type": "string" }, "created_at": date_time(), "updated_at": date_time() }),
+            &["id", "name", "created_at", "updated_at"],
+        ),
+        "ArchivedBlock": object(
+            json!({
+                "id": uuid(),
+                "parent": {
+                    "description": "The parent block, missing for the roots of the domain.",
+                    "type": "string",
+                    "format": "uuid",
+                },
+                "name": { "type": "string" },
+                "created_at": date_time(),
+                "updated_at": date_time(),
+            }),
+            &["id", "name", "created_at", "updated_at"],
+        ),
+        "Archive": object(
+            json!({
+                "version": { "const": metadata_data_layer::archive::ARCHIVE_VERSION },
+                "domain": { "$ref": "#/components/schemas/ArchivedDomain" },
+                "blocks": {
+                    "description": "The blocks of the domain, parents first.",
+                    "type": "array",
+                    "items": { "$ref": "#/components/schemas/ArchivedBlock" },
+                },
+            }),
+            &["version", "domain", "blocks"],
+        ),
+        "ImportChange": object(
+            json!({
+                "action": { "type": "string", "enum": ["create", "update", "unchanged"] },
+                "id": uuid(),
+                "name": { "type": "string" },
+                "parent": uuid(),
+                "previous": object(
+                    json!({ "name": { "type": "string" }, "parent": uuid() }),
+                    &["name"],
+                ),
+            }),
+            &["action", "id", "name"],
+        ),
+        "ImportPlan": object(
+            json!({
+                "mode": { "type": "string", "enum": ["new-ids", "preserve-ids"] },
+                "domain": { "$ref": "#/components/schemas/ImportChange" },
+                "blocks": {
+                    "type": "array",
+                    "items": { "$ref": "#/components/schemas/ImportChange" },
+                },
+            }),
+            &["mode", "domain", "blocks"],
+        ),
+        "GraphQLRequest": object(
+            json!({
+                "query": { "type": "string" },
+                "operationName": { "type": "string" },
+                "variables": { "type": "object" },
+            }),
+            &["query"],
+        ),
+        "GraphQLResponse": {
+            "type": "object",
+            "properties": {
+                "data": { "type": ["object", "null"] },
+                "errors": {
+                    "description": "The errors of the query. The ones described by a \
+                                    problem type have its `type`, `title` and `status` \
+                                    as extensions.",
+                    "type": "array",
+                    "items": { "type": "object" },
+                },
+            },
+        },
+        "Liveness": object(json!({ "status": { "const": "up" } }), &["status"]),
+        "Readiness": object(
+            json!({
+                "status": check(&["up", "down"]),
+                "checks": object(
+                    json!({
+                        "database": object(
+                            json!({ "status": check(&["up", "down"]), "error": { "type": "string" } }),
+                            &["status"],
+                        ),
+                        "migrations": object(
+                            json!({
+                                "status": check(&["up", "down"]),
+                                "expected_version": { "type": ["integer", "null"] },
+                                "applied_version": { "type": ["integer", "null"] },
+                            }),
+                            &["status", "expected_version", "applied_version"],
+                        ),
+                    }),
+                    &["database"],
+                ),
+            }),
+            &["status", "checks"],
+        ),
+        "InvalidParam": object(
+            json!({ "name": { "type": "string" }, "reason": { "type": "string" } }),
+            &["name", "reason"],
+        ),
+        "Problem": {
+            "description": "A problem details object, as defined by RFC 9457. It's also \
+                            available as XML, with the `application/problem+xml` media type.",
+            "type": "object",
+            "properties": {
+                "type": { "type": "string", "format": "uri" },
+                "title": { "type": "string" },
+                "status": { "type": "integer" },
+                "detail": { "type": "string" },
+                "instance": { "type": "string" },
+                "invalid_params": {
+                    "type": "array",
+                    "items": { "$ref": "#/components/schemas/InvalidParam" },
+                },
+            },
+            "required": ["type", "title", "status", "detail"],
+        },
+    });
+
+    match schemas {
+        Value::Object(schemas) => schemas,
+        _ => unreachable!(),
+    }
+}
+
+/// Build the OpenAPI document describing the router built from the
+/// state.
+pub fn document(state: &AppState) -> Value {
+    let mut paths = Map::new();
+    for operation in operations(state) {
+        let item = paths
+            .entry(operation.path)
+            .or_insert_with(|| Value::Object(Map::new()));
+        item[operation.method.as_str().to_lowercase()] = operation.document(state);
+    }
+
+    let mut schemas = resource_schemas();
+    for ty in registry().iter() {
+        schemas.insert(
+            schema_name(ty),
+            json!({
+                "title": ty.title,
+                "description": ty.description,
+                "allOf": [
+                    { "$ref": "#/components/schemas/Problem" },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "type": { "const": ty.uri() },
+                            "title": { "const": ty.title },
+                            "status": { "const": ty.status.as_u16() },
+                        },
+                    },
+                ],
+            }),
+        );
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "backbone-metadata",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": format!(
+                "Stores the domains of the catalog and their trees of blocks. The errors are \
+                 described by problem details, whose types are documented at {}.",
+                problems::base_uri(),
+            ),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "bearer": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "An API key sent as a bearer token.",
+                },
+                "apiKey": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": "X-Api-Key",
+                },
+            },
+        },
+    })
+}
@@ -0,0 +1,376 @@
+//! Rate limiting of the routes of the API, so a single client can't
+//! exhaust the connections of the pool.
+//!
+//! Each client is given a token bucket per class of routes: it can send
+//! as many requests at once as the bucket holds tokens, then the bucket
+//! refills at a constant rate. The requests sent while the bucket is
+//! empty are rejected with a `429 Too Many Requests` problem.
+//!
+//! The clients can also be given a bucket per IP address, counting their
+//! requests to every route of the API before they are authenticated, so
+//! the requests with invalid or missing keys are limited too.
+
+use crate::auth::{self, Principal};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use metadata_http_utils::{HttpError, Problem};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// The number of buckets above which the full ones are dropped, at least.
+const MIN_SWEEP_THRESHOLD: usize = 1024;
+
+/// The classes of routes, each limited by its own quota.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RouteClass {
+    /// The routes reading a single resource.
+    Read,
+    /// The routes creating or changing resources.
+    Write,
+    /// The routes reading many resources at once, e.g. the GraphQL
+    /// queries or the trees of blocks.
+    Search,
+}
+
+impl RouteClass {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Search => "search",
+        }
+    }
+}
+
+impl fmt::Display for RouteClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The requests counted by a bucket: the ones to a class of routes, or
+/// all the requests to the API of an IP address, counted before their
+/// authentication.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Scope {
+    Class(RouteClass),
+    Address,
+}
+
+impl Scope {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Class(class) => class.as_str(),
+            Self::Address => "API",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What identifies the client whose requests share the same buckets. The
+/// requests which can't be identified this way are identified by the IP
+/// address of the client.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RateLimitKey {
+    /// The authenticated principal: the owner of the API key, or the
+    /// subject of the client certificate.
+    #[default]
+    Principal,
+    /// The authenticated API key, so each key of an owner is limited on
+    /// its own. The keys are told apart by a digest, rather than kept.
+    ApiKey,
+    /// The IP address of the client.
+    Ip,
+}
+
+impl std::str::FromStr for RateLimitKey {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "principal" => Ok(Self::Principal),
+            "api-key" => Ok(Self::ApiKey),
+            "ip" => Ok(Self::Ip),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The quota of a class of routes: a client can send `burst` requests at
+/// once, and is given back `per_second` requests a second.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+impl Quota {
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        Self { burst, per_second }
+    }
+
+    /// How long an empty bucket takes to hold `tokens` tokens.
+    fn refill_time(&self, tokens: f64) -> Duration {
+        Duration::from_secs_f64((tokens / self.per_second).max(0.0))
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, quota: &Quota, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * quota.per_second).min(f64::from(quota.burst));
+        self.updated_at = now;
+    }
+}
+
+/// The state of a bucket once a request has been counted, described to
+/// the client by the `RateLimit-*` headers.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Outcome {
+    limit: u32,
+    remaining: u32,
+    /// How long the bucket takes to be full again.
+    reset: Duration,
+    /// How long the client must wait for its request to be accepted, when
+    /// it's rejected.
+    retry_after: Option<Duration>,
+}
+
+impl Outcome {
+    fn write_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(RATELIMIT_LIMIT, self.limit.into());
+        headers.insert(RATELIMIT_REMAINING, self.remaining.into());
+        headers.insert(RATELIMIT_RESET, seconds(self.reset).into());
+    }
+}
+
+/// A delay in whole seconds, rounded up so the client never retries too
+/// early.
+fn seconds(delay: Duration) -> u64 {
+    delay.as_secs() + u64::from(delay.subsec_nanos() > 0)
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    buckets: HashMap<(Scope, String), Bucket>,
+    sweep_threshold: usize,
+}
+
+/// The quotas of the classes of routes and the buckets of the clients.
+/// The routes of a class without a quota are not limited.
+#[derive(Clone, Debug)]
+pub struct RateLimits {
+    key: RateLimitKey,
+    quotas: HashMap<Scope, Quota>,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimits {
+    pub fn new(key: RateLimitKey) -> Self {
+        Self {
+            key,
+            quotas: HashMap::new(),
+            buckets: Arc::default(),
+        }
+    }
+
+    /// Limit the routes of the class with the quota.
+    pub fn with_quota(mut self, class: RouteClass, quota: Quota) -> Self {
+        self.quotas.insert(Scope::Class(class), quota);
+        self
+    }
+
+    /// Limit the requests of each IP address to the routes of the API
+    /// with the quota, before they are authenticated.
+    pub fn with_ip_quota(mut self, quota: Quota) -> Self {
+        self.quotas.insert(Scope::Address, quota);
+        self
+    }
+
+    /// Whether the requests are limited by IP address before they are
+    /// authenticated.
+    pub(crate) fn limits_addresses(&self) -> bool {
+        self.quotas.contains_key(&Scope::Address)
+    }
+
+    /// Identify the client sending a request.
+    fn client(&self, request: &Request) -> String {
+        let principal = request.extensions().get::<Principal>();
+        let identity = match self.key {
+            RateLimitKey::Principal => {
+                principal.map(|principal| format!("principal:{}", principal.0))
+            }
+            // The keys are only trusted once authenticated, so a client
+            // can't get new buckets by sending new keys.
+            RateLimitKey::ApiKey => principal
+                .and(auth::api_key(request.headers()))
+                .map(|key| format!("api-key:{}", auth::fingerprint(key))),
+            RateLimitKey::Ip => None,
+        };
+
+        identity.unwrap_or_else(|| address(request))
+    }
+
+    /// Count a request of the client, returning `None` if its scope is
+    /// not limited.
+    fn acquire(&self, scope: Scope, client: String) -> Option<Outcome> {
+        let quota = self.quotas.get(&scope)?;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.buckets.len() >= buckets.sweep_threshold.max(MIN_SWEEP_THRESHOLD) {
+            // The full buckets are the ones of the clients which didn't
+            // send a request for a while, and are the same as new ones.
+            buckets.buckets.retain(|(scope, _), bucket| {
+                let quota = &self.quotas[scope];
+                bucket.refill(quota, now);
+                bucket.tokens < f64::from(quota.burst)
+            });
+            buckets.sweep_threshold = buckets.buckets.len() * 2;
+        }
+
+        let bucket = buckets
+            .buckets
+            .entry((scope, client))
+            .or_insert_with(|| Bucket {
+                tokens: f64::from(quota.burst),
+                updated_at: now,
+            });
+        bucket.refill(quota, now);
+
+        let retry_after = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(quota.refill_time(1.0 - bucket.tokens))
+        };
+
+        Some(Outcome {
+            limit: quota.burst,
+            remaining: bucket.tokens as u32,
+            reset: quota.refill_time(f64::from(quota.burst) - bucket.tokens),
+            retry_after,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Error, Problem)]
+#[problem(namespace = "rate-limit", headers = "headers")]
+pub(crate) enum RateLimitError {
+    #[error("Too many {scope} requests, retry in {retry_after} seconds.")]
+    #[problem(
+        type = "too-many-requests",
+        title = "Too Many Requests.",
+        status = 429,
+        description = "The client sent more requests to this class of routes than its quota \
+                       allows. It can retry once the delay of the `Retry-After` header has \
+                       elapsed."
+    )]
+    TooManyRequests {
+        scope: Scope,
+        retry_after: u64,
+        outcome: Outcome,
+    },
+}
+
+impl RateLimitError {
+    fn headers(&self) -> Option<HeaderMap> {
+        let Self::TooManyRequests {
+            retry_after,
+            outcome,
+            ..
+        } = self;
+        let mut headers = HeaderMap::new();
+        outcome.write_headers(&mut headers);
+        headers.insert(header::RETRY_AFTER, HeaderValue::from(*retry_after));
+
+        Some(headers)
+    }
+}
+
+/// A middleware counting the requests of a class of routes, rejecting
+/// them once the client has exhausted its quota. The `RateLimit-*`
+/// headers tell every client how many requests it has left.
+pub(crate) async fn limit(
+    State((limits, class)): State<(RateLimits, RouteClass)>,
+    request: Request,
+    next: Next,
+) -> Result<Response, HttpError> {
+    let client = limits.client(&request);
+    count(&limits, Scope::Class(class), client, request, next).await
+}
+
+/// A middleware counting the requests to the API of each IP address,
+/// before they are authenticated.
+pub(crate) async fn limit_address(
+    State(limits): State<RateLimits>,
+    request: Request,
+    next: Next,
+) -> Result<Response, HttpError> {
+    let client = address(&request);
+    count(&limits, Scope::Address, client, request, next).await
+}
+
+/// Identify a client by its IP address.
+fn address(request: &Request) -> String {
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(address)) => format!("ip:{}", address.ip()),
+        None => "ip:unknown".to_owned(),
+    }
+}
+
+/// Count a request in the bucket of the client, rejecting it when the
+/// bucket is empty.
+async fn count(
+    limits: &RateLimits,
+    scope: Scope,
+    client: String,
+    request: Request,
+    next: Next,
+) -> Result<Response, HttpError> {
+    let Some(outcome) = limits.acquire(scope, client) else {
+        return Ok(next.run(request).await);
+    };
+
+    if let Some(retry_after) = outcome.retry_after {
+        metrics::counter!("http_rate_limited_total", "class" => scope.as_str()).increment(1);
+        return Err(RateLimitError::TooManyRequests {
+            scope,
+            retry_after: seconds(retry_after),
+            outcome,
+        }
+        .into());
+    }
+
+    let mut response = next.run(request).await;
+    outcome.write_headers(response.headers_mut());
+
+    Ok(response)
+}
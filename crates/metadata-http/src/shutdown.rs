@@ -0,0 +1,48 @@
+use crate::AppState;
+use axum::extract::FromRef;
+use tokio_util::sync::CancellationToken;
+
+/// A signal triggered when the server starts to shut down.
+///
+/// Handlers producing long-lived responses, like event streams, should
+/// extract it with [axum::extract::State] and end their response once
+/// it's triggered, so the connections can be drained before the drain
+/// timeout elapses.
+#[derive(Clone, Debug, Default)]
+pub struct Shutdown(CancellationToken);
+
+impl Shutdown {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trigger the signal, waking up every task waiting on it.
+    #[inline]
+    pub fn trigger(&self) {
+        self.0.cancel();
+    }
+
+    /// Returns `true` once the signal has been triggered.
+    #[inline]
+    pub fn is_triggered(&self) -> bool {
+        self.0.is_cancelled()
+    }
+
+    /// Wait until the signal is triggered.
+    pub async fn triggered(&self) {
+        self.0.cancelled().await
+    }
+
+    /// Returns a future that is resolved once the signal is triggered
+    /// and that is not borrowing the signal.
+    pub fn triggered_owned(&self) -> tokio_util::sync::WaitForCancellationFutureOwned {
+        self.0.clone().cancelled_owned()
+    }
+}
+
+impl FromRef<AppState> for Shutdown {
+    fn from_ref(input: &AppState) -> Self {
+        input.shutdown.clone()
+    }
+}
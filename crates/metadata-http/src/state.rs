@@ -1,3 +1,11 @@
+use crate::{
+    auth::ApiKeys,
+    graphql::{self, GraphqlSchema},
+    handlers::DEFAULT_MAX_BATCH_SIZE,
+    limits::RequestLimits,
+    rate_limit::RateLimits,
+    shutdown::Shutdown,
+};
 use axum::extract::FromRef;
 use metadata_data_layer_utils::PoolState;
 use std::sync::Arc;
@@ -5,14 +13,90 @@ use std::sync::Arc;
 #[derive(Clone, Debug)]
 pub struct AppState {
     pub(crate) pool: Arc<PoolState>,
+    pub(crate) api_keys: ApiKeys,
+    pub(crate) shutdown: Shutdown,
+    pub(crate) openapi_ui: bool,
+    pub(crate) redoc_integrity: Option<String>,
+    pub(crate) graphql: GraphqlSchema,
+    pub(crate) max_batch_size: usize,
+    pub(crate) rate_limits: Option<RateLimits>,
+    pub(crate) request_limits: RequestLimits,
 }
 
 impl AppState {
     pub fn new(pool: PoolState) -> Self {
         Self {
             pool: Arc::new(pool),
+            api_keys: ApiKeys::default(),
+            shutdown: Shutdown::new(),
+            openapi_ui: false,
+            redoc_integrity: None,
+            graphql: GraphqlSchema::new(
+                graphql::DEFAULT_MAX_DEPTH,
+                graphql::DEFAULT_MAX_COMPLEXITY,
+            ),
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            rate_limits: None,
+            request_limits: RequestLimits::default(),
         }
     }
+
+    /// Share a shutdown signal with the handlers, so they can end their
+    /// long-lived responses when the server shuts down.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Serve a page rendering the OpenAPI document at `/docs`.
+    pub fn with_openapi_ui(mut self, enabled: bool) -> Self {
+        self.openapi_ui = enabled;
+        self
+    }
+
+    /// Have the browsers check the Redoc script of the `/docs` page against
+    /// a [subresource integrity] such as `sha384-...`, computed from the
+    /// bundle of the pinned [REDOC_VERSION](crate::REDOC_VERSION).
+    ///
+    /// [subresource integrity]: https://developer.mozilla.org/docs/Web/Security/Subresource_Integrity
+    pub fn with_redoc_integrity(mut self, integrity: impl Into<String>) -> Self {
+        self.redoc_integrity = Some(integrity.into());
+        self
+    }
+
+    /// Reject the GraphQL queries nested deeper than `max_depth`, or
+    /// whose complexity is above `max_complexity`.
+    pub fn with_graphql_limits(mut self, max_depth: usize, max_complexity: usize) -> Self {
+        self.graphql = GraphqlSchema::new(max_depth, max_complexity);
+        self
+    }
+
+    /// Reject the batches reading more than `max_batch_size` blocks.
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size;
+        self
+    }
+
+    /// Limit the rate of the requests of each client to the routes of the
+    /// API. The probes and the documentation are never limited.
+    pub fn with_rate_limits(mut self, rate_limits: RateLimits) -> Self {
+        self.rate_limits = Some(rate_limits);
+        self
+    }
+
+    /// Limit the duration, the body size and the concurrency of the
+    /// requests, so the service sheds load instead of queueing it.
+    pub fn with_request_limits(mut self, request_limits: RequestLimits) -> Self {
+        self.request_limits = request_limits;
+        self
+    }
+
+    /// Require the requests to be authenticated with one of the given
+    /// API keys.
+    pub fn with_api_keys(mut self, api_keys: ApiKeys) -> Self {
+        self.api_keys = api_keys;
+        self
+    }
 }
 
 impl FromRef<AppState> for Arc<PoolState> {
@@ -0,0 +1,29 @@
+use metadata_data_layer::validation::ValidationErrors;
+use metadata_http_utils::{Extensions, InvalidParam, Problem};
+use thiserror::Error;
+
+/// The problem describing a resource sent by a client whose fields are
+/// invalid, each one being listed in the `invalid_params` member.
+#[derive(Debug, Error, Problem)]
+#[error("The resource is invalid: {0}.")]
+#[problem(
+    type = "validation/invalid-resource",
+    title = "Invalid Resource.",
+    status = 422,
+    extensions = "invalid_params",
+    description = "A field of the resource sent with the request is missing or invalid. \
+                   The `invalid_params` member lists every field at fault and why."
+)]
+pub struct ValidationProblem(#[from] ValidationErrors);
+
+impl ValidationProblem {
+    fn invalid_params(&self) -> Extensions {
+        let params = self
+            .0
+            .iter()
+            .map(|(field, error)| InvalidParam::new(field, error.to_string()))
+            .collect::<Vec<_>>();
+
+        Extensions::new().with("invalid_params", params)
+    }
+}
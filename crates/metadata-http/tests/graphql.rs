@@ -0,0 +1,101 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Method, Request, StatusCode},
+};
+use metadata_data_layer_utils::PoolState;
+use metadata_http::{init_router, AppState, RequestLimits};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tower::ServiceExt;
+
+/// Execute a query against a schema limited to the given depth and
+/// complexity, returning the status and the body of the response. The
+/// pool connects lazily to a port where no database listens, so the
+/// queries which are rejected answer at once, while the ones reading the
+/// database time out quickly.
+async fn execute(max_depth: usize, max_complexity: usize, query: &str) -> (StatusCode, Value) {
+    let pool = PoolState::builder().host("127.0.0.1").port(1).finalize();
+    let limits = RequestLimits::new().with_timeout(Duration::from_millis(100));
+    let router = init_router(
+        AppState::new(pool)
+            .with_request_limits(limits)
+            .with_graphql_limits(max_depth, max_complexity),
+    );
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/graphql")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "query": query }).to_string()))
+        .expect("a request");
+    let response = router.oneshot(request).await.expect("a response");
+
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("the body of the response");
+    (status, serde_json::from_slice(&body).expect("a JSON body"))
+}
+
+/// The messages of the errors of a response.
+fn errors(body: &Value) -> Vec<&str> {
+    body["errors"]
+        .as_array()
+        .map(|errors| {
+            errors
+                .iter()
+                .filter_map(|error| error["message"].as_str())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+const NESTED: &str = r#"{
+    domain(name: "billing") {
+        blocks(first: 2) { edges { node { children(first: 2) { edges { node { name } } } } } }
+    }
+}"#;
+
+#[tokio::test]
+async fn queries_within_the_limits_are_executed() {
+    let (status, body) = execute(3, 10, "{ __typename }").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], json!({"__typename": "Query"}));
+    assert!(errors(&body).is_empty(), "{body}");
+}
+
+#[tokio::test]
+async fn rejects_the_queries_nested_too_deep() {
+    // domain > blocks > edges > node > children > edges > node > name
+    let (status, body) = execute(7, 10_000, NESTED).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], Value::Null);
+    assert_eq!(errors(&body), ["Query is nested too deep."]);
+}
+
+#[tokio::test]
+async fn rejects_the_queries_too_complex() {
+    // The pages of 2 blocks each having pages of 2 children multiply the
+    // complexity of the nested fields.
+    let (status, body) = execute(100, 10, NESTED).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], Value::Null);
+    assert_eq!(errors(&body), ["Query is too complex."]);
+}
+
+#[tokio::test]
+async fn the_complexity_grows_with_the_size_of_the_pages() {
+    let query =
+        |first: usize| format!("{{ domains(first: {first}) {{ edges {{ node {{ name }} }} }} }}");
+
+    let (status, body) = execute(100, 20, &query(100)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(errors(&body), ["Query is too complex."]);
+
+    // The smaller page is accepted, and times out reading the database.
+    let (status, body) = execute(100, 20, &query(1)).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT, "{body}");
+}
@@ -0,0 +1,35 @@
+use axum::{
+    body::Body,
+    http::{Method, Request},
+};
+use metadata_data_layer_utils::PoolState;
+use metadata_http::{init_router, AppState};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn the_extension_methods_share_a_label() {
+    let handle = PrometheusBuilder::new()
+        .install_recorder()
+        .expect("the recorder to be installed");
+    let router = init_router(AppState::new(PoolState::builder().finalize()));
+
+    for method in ["GET", "PURGE", "X-RANDOM-1", "X-RANDOM-2"] {
+        let request = Request::builder()
+            .method(Method::from_bytes(method.as_bytes()).unwrap())
+            .uri("/healthz")
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request).await.expect("a response");
+    }
+
+    let metrics = handle.render();
+    let methods = metrics
+        .lines()
+        .filter(|line| line.starts_with("http_requests_total{"))
+        .filter_map(|line| line.split("method=\"").nth(1)?.split('"').next())
+        .collect::<Vec<_>>();
+    assert_eq!(methods.len(), 2, "{metrics}");
+    assert!(methods.contains(&"GET"), "{metrics}");
+    assert!(methods.contains(&"other"), "{metrics}");
+}
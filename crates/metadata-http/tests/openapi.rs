@@ -0,0 +1,524 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Method, Request, StatusCode},
+};
+use metadata_data_layer::{
+    archive::{Action, Archive, Change, ImportMode, ImportPlan, Previous},
+    models::{Block, Domain, Parent, TreeEntry},
+};
+use metadata_data_layer_utils::PoolState;
+use metadata_http::{
+    init_router, openapi, routes, ApiKeys, AppState, RateLimitKey, RateLimits, RequestLimits,
+    REDOC_VERSION,
+};
+use serde_json::{json, Value};
+use std::{collections::BTreeSet, time::Duration};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+/// The pool connects lazily, so no database is needed to build the
+/// router and its documentation.
+fn state() -> AppState {
+    AppState::new(PoolState::builder().finalize())
+}
+
+/// Convert a path of the router to an OpenAPI path template, e.g.
+/// `/:domain_name` to `/{domain_name}`.
+fn template(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.strip_prefix([':', '*']) {
+            Some(name) => format!("{{{name}}}"),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn routed(state: &AppState) -> BTreeSet<(String, String)> {
+    routes(state)
+        .into_iter()
+        .map(|(method, path)| (method.as_str().to_lowercase(), template(path)))
+        .collect()
+}
+
+fn documented(document: &Value) -> BTreeSet<(String, String)> {
+    document["paths"]
+        .as_object()
+        .expect("the paths of the document")
+        .iter()
+        .flat_map(|(path, item)| {
+            item.as_object()
+                .expect("a path item")
+                .keys()
+                .map(move |method| (method.clone(), path.clone()))
+        })
+        .collect()
+}
+
+/// Collect the `$ref` of a value and of all its descendants.
+fn references<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::String(reference)) = object.get("$ref") {
+                found.push(reference);
+            }
+            object.values().for_each(|value| references(value, found));
+        }
+        Value::Array(array) => array.iter().for_each(|value| references(value, found)),
+        _ => {}
+    }
+}
+
+/// Resolve the local reference of a schema, if it's one.
+fn resolve<'a>(document: &'a Value, schema: &'a Value) -> &'a Value {
+    match schema["$ref"].as_str() {
+        Some(reference) => {
+            let pointer = reference.strip_prefix('#').expect("a local reference");
+            let resolved = document
+                .pointer(pointer)
+                .unwrap_or_else(|| panic!("'{reference}' doesn't resolve"));
+            resolve(document, resolved)
+        }
+        None => schema,
+    }
+}
+
+/// The names of the properties of a schema, including the ones of the
+/// schemas it's made of.
+fn properties<'a>(document: &'a Value, schema: &'a Value, names: &mut BTreeSet<&'a str>) {
+    let schema = resolve(document, schema);
+    if let Some(properties) = schema["properties"].as_object() {
+        names.extend(properties.keys().map(String::as_str));
+    }
+    for schema in schema["allOf"].as_array().into_iter().flatten() {
+        properties(document, schema, names);
+    }
+}
+
+/// Check a value against a schema of the document, collecting the reasons
+/// it doesn't match. Only the keywords used by the document are checked,
+/// and the members of an object which are not properties of its schema
+/// are reported, for the schemas not to miss any.
+fn validate(document: &Value, schema: &Value, value: &Value, at: &str, errors: &mut Vec<String>) {
+    let schema = resolve(document, schema);
+    check(document, schema, value, at, errors);
+
+    let mut names = BTreeSet::new();
+    properties(document, schema, &mut names);
+    if let (Some(members), false) = (value.as_object(), names.is_empty()) {
+        for name in members.keys() {
+            if !names.contains(name.as_str()) {
+                errors.push(format!("{at}: the member '{name}' is not documented"));
+            }
+        }
+    }
+}
+
+/// Check a value against the keywords of a schema, the members of the
+/// objects being checked by [validate] once the schemas are combined.
+fn check(document: &Value, schema: &Value, value: &Value, at: &str, errors: &mut Vec<String>) {
+    let schema = resolve(document, schema);
+
+    if let Some(schemas) = schema["allOf"].as_array() {
+        for schema in schemas {
+            check(document, schema, value, at, errors);
+        }
+    }
+    if let Some(schemas) = schema["oneOf"].as_array() {
+        let matching = schemas
+            .iter()
+            .filter(|schema| {
+                let mut errors = Vec::new();
+                validate(document, schema, value, at, &mut errors);
+                errors.is_empty()
+            })
+            .count();
+        if matching != 1 {
+            errors.push(format!("{at}: {value} matches {matching} schemas of oneOf"));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            errors.push(format!("{at}: {value} is not {expected}"));
+        }
+    }
+    if let Some(values) = schema["enum"].as_array() {
+        if !values.contains(value) {
+            errors.push(format!("{at}: {value} is not one of {values:?}"));
+        }
+    }
+
+    let types = match &schema["type"] {
+        Value::String(ty) => vec![ty.as_str()],
+        Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let is = |ty: &str| match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => panic!("unknown type '{ty}'"),
+    };
+    if !types.is_empty() && !types.into_iter().any(is) {
+        errors.push(format!(
+            "{at}: {value} is not of the type {}",
+            schema["type"]
+        ));
+    }
+
+    if let (Some(minimum), Some(number)) = (schema["minimum"].as_f64(), value.as_f64()) {
+        if number < minimum {
+            errors.push(format!("{at}: {number} is below {minimum}"));
+        }
+    }
+    if let (Some(format), Some(string)) = (schema["format"].as_str(), value.as_str()) {
+        let valid = match format {
+            "uuid" => string.parse::<uuid::Uuid>().is_ok(),
+            "date-time" => chrono::DateTime::parse_from_rfc3339(string).is_ok(),
+            "uri" => string.contains(':'),
+            _ => panic!("unknown format '{format}'"),
+        };
+        if !valid {
+            errors.push(format!("{at}: '{string}' is not a {format}"));
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        if let Some(schema) = schema.get("items") {
+            for (index, item) in items.iter().enumerate() {
+                validate(document, schema, item, &format!("{at}/{index}"), errors);
+            }
+        }
+    }
+
+    if let Some(members) = value.as_object() {
+        for required in schema["required"].as_array().into_iter().flatten() {
+            let required = required.as_str().expect("a member name");
+            if !members.contains_key(required) {
+                errors.push(format!("{at}: the member '{required}' is missing"));
+            }
+        }
+        if let Some(properties) = schema["properties"].as_object() {
+            for (name, member) in members {
+                if let Some(schema) = properties.get(name) {
+                    validate(document, schema, member, &format!("{at}/{name}"), errors);
+                }
+            }
+        }
+    }
+}
+
+/// Check that a serialized resource matches the schema of the document
+/// named `schema`.
+fn assert_matches_schema(document: &Value, schema: &str, resource: &Value) {
+    let schema = json!({ "$ref": format!("#/components/schemas/{schema}") });
+    let mut errors = Vec::new();
+    validate(document, &schema, resource, "", &mut errors);
+    assert!(errors.is_empty(), "{resource}: {errors:#?}");
+}
+
+#[tokio::test]
+async fn every_route_is_documented() {
+    for state in [state(), state().with_openapi_ui(true)] {
+        let document = openapi::document(&state);
+
+        assert_eq!(routed(&state), documented(&document));
+    }
+}
+
+#[tokio::test]
+async fn every_reference_resolves() {
+    let state = state().with_api_keys(ApiKeys::new([("ci", "secret")]));
+    let document = openapi::document(&state);
+
+    let mut found = Vec::new();
+    references(&document, &mut found);
+    assert!(!found.is_empty());
+
+    for reference in found {
+        let pointer = reference
+            .strip_prefix('#')
+            .unwrap_or_else(|| panic!("'{reference}' is not a local reference"));
+        assert!(
+            document.pointer(pointer).is_some(),
+            "'{reference}' doesn't resolve"
+        );
+    }
+}
+
+#[tokio::test]
+async fn operations_are_secured_with_api_keys_only() {
+    let document = openapi::document(&state());
+    assert_eq!(
+        document["paths"]["/"]["post"]["security"],
+        serde_json::json!([])
+    );
+
+    let state = state().with_api_keys(ApiKeys::new([("ci", "secret")]));
+    let document = openapi::document(&state);
+    assert_ne!(
+        document["paths"]["/"]["post"]["security"],
+        serde_json::json!([])
+    );
+    assert_eq!(
+        document["paths"]["/healthz"]["get"]["security"],
+        serde_json::json!([])
+    );
+}
+
+#[tokio::test]
+async fn rate_limited_operations_document_their_problem() {
+    let document = openapi::document(&state());
+    assert!(document["paths"]["/"]["post"]["responses"]
+        .get("429")
+        .is_none());
+
+    let state = state().with_rate_limits(RateLimits::new(RateLimitKey::Ip));
+    let document = openapi::document(&state);
+    assert!(document["paths"]["/"]["post"]["responses"]
+        .get("429")
+        .is_some());
+    assert!(document["paths"]["/healthz"]["get"]["responses"]
+        .get("429")
+        .is_none());
+}
+
+/// The problem types an operation of the document can respond with.
+fn problem_schemas(document: &Value, path: &str, method: &str) -> BTreeSet<String> {
+    document["paths"][path][method]["responses"]
+        .as_object()
+        .expect("the responses of the operation")
+        .values()
+        .filter_map(|response| {
+            response["content"]["application/problem+json"]["schema"]["oneOf"].as_array()
+        })
+        .flatten()
+        .filter_map(|schema| schema["$ref"].as_str().map(str::to_string))
+        .collect()
+}
+
+#[tokio::test]
+async fn limited_operations_document_their_problems() {
+    let timeout = "#/components/schemas/Problem.server.timeout".to_string();
+    let overloaded = "#/components/schemas/Problem.server.overloaded".to_string();
+
+    let document = openapi::document(&state());
+    let problems = problem_schemas(&document, "/", "post");
+    assert!(!problems.contains(&timeout));
+    assert!(!problems.contains(&overloaded));
+
+    let limits = RequestLimits::new()
+        .with_timeout(Duration::from_secs(5))
+        .with_max_in_flight(8)
+        .with_load_shedding(true);
+    let document = openapi::document(&state().with_request_limits(limits));
+    let problems = problem_schemas(&document, "/", "post");
+    assert!(problems.contains(&timeout));
+    assert!(problems.contains(&overloaded));
+    assert!(!problem_schemas(&document, "/healthz", "get").contains(&timeout));
+}
+
+#[tokio::test]
+async fn schemas_match_the_resources() {
+    let document = openapi::document(&state());
+
+    let domain = Domain::new("billing").expect("a valid domain");
+    let domain_json = serde_json::to_value(&domain).expect("a serializable domain");
+    assert_matches_schema(&document, "Domain", &domain_json);
+
+    let root = Block::builder()
+        .domain(domain.id)
+        .name(&"invoices")
+        .finalize()
+        .expect("a valid block");
+    let child = Block::builder()
+        .block(root.id)
+        .name(&"lines")
+        .finalize()
+        .expect("a valid block");
+    for block in [&root, &child] {
+        let block = serde_json::to_value(block).expect("a serializable block");
+        assert_matches_schema(&document, "Block", &block);
+    }
+
+    let entry = TreeEntry {
+        depth: 1,
+        block: child.clone(),
+    };
+    let entry = serde_json::to_value(&entry).expect("a serializable entry");
+    assert_matches_schema(&document, "TreeEntry", &entry);
+
+    let archive = Archive::new(&domain, &[root.clone(), child.clone()]);
+    let archive = serde_json::to_value(&archive).expect("a serializable archive");
+    assert_matches_schema(&document, "Archive", &archive);
+
+    let change = |action, block: &Block, previous| Change {
+        action,
+        id: block.id,
+        name: block.name.clone(),
+        parent: match block.parent {
+            Parent::Block(parent) => Some(parent),
+            Parent::Domain(_) => None,
+        },
+        previous,
+        created_at: block.created_at,
+        updated_at: block.updated_at,
+    };
+    let plan = ImportPlan {
+        mode: ImportMode::PreserveIds,
+        domain: Change {
+            action: Action::Unchanged,
+            id: domain.id,
+            name: domain.name.clone(),
+            parent: None,
+            previous: None,
+            created_at: domain.created_at,
+            updated_at: domain.updated_at,
+        },
+        blocks: vec![
+            change(Action::Create, &root, None),
+            change(
+                Action::Update,
+                &child,
+                Some(Previous {
+                    name: "items".to_owned(),
+                    parent: Some(Uuid::now_v7()),
+                }),
+            ),
+        ],
+    };
+    let plan = serde_json::to_value(&plan).expect("a serializable plan");
+    assert_matches_schema(&document, "ImportPlan", &plan);
+}
+
+/// Send a request to the router built from the state, returning the
+/// status, the media type and the body of the response.
+async fn send(state: AppState, request: Request<Body>) -> (StatusCode, String, Value) {
+    let response = init_router(state)
+        .oneshot(request)
+        .await
+        .expect("a response");
+
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    let body = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("the body of the response");
+    (
+        status,
+        content_type,
+        serde_json::from_slice(&body).expect("a JSON body"),
+    )
+}
+
+#[tokio::test]
+async fn schemas_match_the_responses() {
+    // The pool connects lazily to a port where no database listens, so
+    // the requests reading the database time out quickly.
+    let state = || {
+        let pool = PoolState::builder().host("127.0.0.1").port(1).finalize();
+        let limits = RequestLimits::new().with_timeout(Duration::from_millis(100));
+        AppState::new(pool).with_request_limits(limits)
+    };
+    let json = |method: Method, uri: &str, body: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_owned()))
+            .expect("a request")
+    };
+    let authenticated = || state().with_api_keys(ApiKeys::new([("ci", "secret")]));
+
+    let exchanges = [
+        (
+            state(),
+            json(Method::GET, "/healthz", ""),
+            "/healthz",
+            "get",
+        ),
+        (state(), json(Method::GET, "/readyz", ""), "/readyz", "get"),
+        (
+            state(),
+            json(Method::POST, "/blocks:batchGet", r#"{"ids": []}"#),
+            "/blocks:batchGet",
+            "post",
+        ),
+        (
+            state(),
+            json(Method::POST, "/", r#"{"name": ""}"#),
+            "/",
+            "post",
+        ),
+        (state(), json(Method::POST, "/", "{"), "/", "post"),
+        (
+            state(),
+            json(Method::GET, "/billing", ""),
+            "/{domain_name}",
+            "get",
+        ),
+        (
+            authenticated(),
+            json(Method::GET, "/billing", ""),
+            "/{domain_name}",
+            "get",
+        ),
+        (
+            state(),
+            json(Method::POST, "/graphql", r#"{"query": "{ __typename }"}"#),
+            "/graphql",
+            "post",
+        ),
+    ];
+
+    let document = openapi::document(&authenticated());
+    for (state, request, path, method) in exchanges {
+        let uri = request.uri().clone();
+        let (status, content_type, body) = send(state, request).await;
+
+        let response = &document["paths"][path][method]["responses"][status.as_str()];
+        let schema = &response["content"][content_type.as_str()]["schema"];
+        assert!(
+            schema.is_object(),
+            "{method} {uri}: the {status} {content_type} response is not documented"
+        );
+
+        let mut errors = Vec::new();
+        validate(&document, schema, &body, "", &mut errors);
+        assert!(errors.is_empty(), "{method} {uri}: {body}: {errors:#?}");
+    }
+}
+
+#[tokio::test]
+async fn the_documentation_page_pins_its_script() {
+    let state = state()
+        .with_openapi_ui(true)
+        .with_redoc_integrity("sha384-digest");
+    let request = Request::builder()
+        .uri("/docs")
+        .body(Body::empty())
+        .expect("a request");
+    let response = init_router(state)
+        .oneshot(request)
+        .await
+        .expect("a response");
+    let page = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("the page");
+    let page = String::from_utf8(page.to_vec()).expect("an HTML page");
+
+    assert!(page.contains(&format!("/redoc@{REDOC_VERSION}/")), "{page}");
+    assert!(
+        page.contains(r#"integrity="sha384-digest" crossorigin="anonymous""#),
+        "{page}"
+    );
+}
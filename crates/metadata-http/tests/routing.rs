@@ -0,0 +1,277 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use metadata_data_layer::validation::RESERVED_DOMAIN_NAMES;
+use metadata_data_layer_utils::PoolState;
+use metadata_http::{
+    init_router, routes, ApiKeys, AppState, Quota, RateLimitKey, RateLimits, RequestLimits,
+    RouteClass,
+};
+use serde_json::{json, Value};
+use std::{collections::BTreeSet, time::Duration};
+use tower::ServiceExt;
+
+/// A router whose pool connects lazily to a port where no database
+/// listens, the handlers reading the database timing out quickly.
+fn router() -> Router {
+    let pool = PoolState::builder().host("127.0.0.1").port(1).finalize();
+    let limits = RequestLimits::new().with_timeout(Duration::from_millis(100));
+
+    init_router(AppState::new(pool).with_request_limits(limits))
+}
+
+/// Whether the response is the problem of a request which timed out, i.e.
+/// which reached a handler reading the database.
+fn timed_out(status: StatusCode, body: &Value) -> bool {
+    status == StatusCode::GATEWAY_TIMEOUT && body["type"].as_str().unwrap().ends_with("/timeout")
+}
+
+/// Send a request with a JSON body, returning the status and the body of
+/// the response.
+async fn send(method: Method, path: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("a request");
+    let response = router().oneshot(request).await.expect("a response");
+
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("the body of the response");
+
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn batch_get_is_dispatched_to_its_handler() {
+    // The empty batches are answered without reading the database.
+    let (status, body) = send(Method::POST, "/blocks:batchGet", json!({"ids": []})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({"blocks": [], "missing": []}));
+
+    let (status, body) = send(Method::GET, "/blocks:batchGet", Value::Null).await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    assert!(body["type"]
+        .as_str()
+        .unwrap()
+        .ends_with("method-not-allowed"));
+}
+
+#[tokio::test]
+async fn the_problems_only_echo_the_valid_request_ids() {
+    let instance = |id: &'static str| async move {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("x-request-id", id)
+            .body(Body::from("{"))
+            .expect("a request");
+        let response = router().oneshot(request).await.expect("a response");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice::<Value>(&body).expect("a problem");
+
+        body.get("instance").cloned()
+    };
+
+    assert_eq!(
+        instance("0192c6a4-8f4e-7c2b").await,
+        Some(json!("0192c6a4-8f4e-7c2b"))
+    );
+    assert_eq!(instance("<script>alert(1)</script>").await, None);
+}
+
+#[tokio::test]
+async fn the_bodies_above_the_maximum_size_are_rejected() {
+    let pool = PoolState::builder().host("127.0.0.1").port(1).finalize();
+    let limits = RequestLimits::new().with_max_body_size(64);
+    let router = init_router(AppState::new(pool).with_request_limits(limits));
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "name": "a".repeat(64) }).to_string()))
+        .expect("a request");
+    let response = router.oneshot(request).await.expect("a response");
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = serde_json::from_slice::<Value>(&body).expect("a problem");
+    assert!(
+        body["type"]
+            .as_str()
+            .unwrap()
+            .ends_with("/requests/payload-too-large"),
+        "{body}"
+    );
+}
+
+#[tokio::test]
+async fn domains_named_after_blocks_reach_their_handlers() {
+    // A batch would be rejected for its missing identifiers, while the
+    // creation of a block reads the domain from the database.
+    let (status, body) = send(Method::POST, "/blocksmith", json!({"name": "anvil"})).await;
+    assert!(timed_out(status, &body), "{status}: {body}");
+
+    let (status, body) = send(Method::GET, "/blocksmith", Value::Null).await;
+    assert!(timed_out(status, &body), "{status}: {body}");
+
+    let (status, body) = send(Method::GET, "/blocksmith/anvil", Value::Null).await;
+    assert!(timed_out(status, &body), "{status}: {body}");
+}
+
+#[tokio::test]
+async fn the_reserved_domain_names_are_the_top_level_routes() {
+    let pool = PoolState::builder().finalize();
+    let state = AppState::new(pool).with_openapi_ui(true);
+
+    // The first segment of a path, without the custom method it may name.
+    let mut segments = routes(&state)
+        .into_iter()
+        .filter_map(|(_, path)| {
+            let segment = path.trim_start_matches('/').split('/').next()?;
+            let segment = segment.split(':').next()?;
+            (!segment.is_empty()).then_some(segment)
+        })
+        .collect::<BTreeSet<_>>();
+    // The metrics are served by the binary, on the listener of the API
+    // unless they have their own.
+    segments.insert("metrics");
+
+    assert_eq!(
+        segments,
+        RESERVED_DOMAIN_NAMES
+            .iter()
+            .copied()
+            .collect::<BTreeSet<_>>()
+    );
+}
+
+#[tokio::test]
+async fn the_streamed_trees_count_against_the_requests_in_flight() {
+    let tree = |max_in_flight: usize| async move {
+        let pool = PoolState::builder().host("127.0.0.1").port(1).finalize();
+        let limits = RequestLimits::new()
+            .with_timeout(Duration::from_millis(100))
+            .with_max_in_flight(max_in_flight);
+        let router = init_router(AppState::new(pool).with_request_limits(limits));
+
+        let request = Request::builder()
+            .uri("/trees/billing")
+            .body(Body::empty())
+            .expect("a request");
+        router.oneshot(request).await.expect("a response")
+    };
+
+    // The request holds the only slot, leaving none to its stream.
+    let response = tree(1).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+    // With a slot left, the stream goes on to read the database.
+    let response = tree(2).await;
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+/// Send a request to the API with an API key, returning its status.
+async fn send_with_key(router: &Router, key: &str) -> StatusCode {
+    let request = Request::builder()
+        .uri("/billing")
+        .header("x-api-key", key)
+        .body(Body::empty())
+        .expect("a request");
+
+    router
+        .clone()
+        .oneshot(request)
+        .await
+        .expect("a response")
+        .status()
+}
+
+#[tokio::test]
+async fn the_addresses_are_limited_before_the_authentication() {
+    let pool = PoolState::builder().host("127.0.0.1").port(1).finalize();
+    let limits = RateLimits::new(RateLimitKey::Principal).with_ip_quota(Quota::new(2, 0.001));
+    let router = init_router(
+        AppState::new(pool)
+            .with_api_keys(ApiKeys::new([("ci", "secret")]))
+            .with_rate_limits(limits),
+    );
+
+    assert_eq!(
+        send_with_key(&router, "guess-1").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        send_with_key(&router, "guess-2").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        send_with_key(&router, "guess-3").await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
+
+#[tokio::test]
+async fn the_api_keys_of_an_owner_are_limited_on_their_own() {
+    let pool = PoolState::builder().host("127.0.0.1").port(1).finalize();
+    let limits =
+        RateLimits::new(RateLimitKey::ApiKey).with_quota(RouteClass::Read, Quota::new(1, 0.001));
+    let router = init_router(
+        AppState::new(pool)
+            .with_api_keys(ApiKeys::new([("ci", "first"), ("ci", "second")]))
+            .with_request_limits(RequestLimits::new().with_timeout(Duration::from_millis(100)))
+            .with_rate_limits(limits),
+    );
+
+    assert_eq!(
+        send_with_key(&router, "first").await,
+        StatusCode::GATEWAY_TIMEOUT
+    );
+    assert_eq!(
+        send_with_key(&router, "first").await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(
+        send_with_key(&router, "second").await,
+        StatusCode::GATEWAY_TIMEOUT
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a PostgreSQL database, configured with the POSTGRES_* variables"]
+async fn the_readiness_probe_hides_the_errors_of_the_database() {
+    // The server rejects the connections to a database which doesn't
+    // exist, telling its name.
+    let pool = PoolState::from_env().dbname("missing-database").finalize();
+    let request = Request::builder()
+        .uri("/readyz")
+        .body(Body::empty())
+        .expect("a request");
+    let response = init_router(AppState::new(pool))
+        .oneshot(request)
+        .await
+        .expect("a response");
+
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("the body of the response");
+    let body: Value = serde_json::from_slice(&body).expect("a JSON body");
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        body["checks"]["database"],
+        json!({"status": "down", "error": "the database could not be queried"})
+    );
+}